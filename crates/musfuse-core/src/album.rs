@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+
+use crate::metadata::{AlbumId, AlbumMetadata, TagMap};
+use crate::track::TrackIndexEntry;
+
+pub const VARIOUS_ARTISTS: &str = "Various Artists";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlbumArtistResolver {
+    // Share of tracks (0.0..=1.0) the most common artist must exceed.
+    pub majority_threshold: f64,
+    pub various_artists: String,
+}
+
+impl Default for AlbumArtistResolver {
+    fn default() -> Self {
+        Self {
+            majority_threshold: 0.5,
+            various_artists: VARIOUS_ARTISTS.into(),
+        }
+    }
+}
+
+impl AlbumArtistResolver {
+    pub fn resolve<'a>(&self, artists: impl IntoIterator<Item = &'a str>) -> Option<String> {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        let mut order: Vec<&str> = Vec::new();
+        let mut total = 0usize;
+        for artist in artists {
+            total += 1;
+            let count = counts.entry(artist).or_insert(0);
+            if *count == 0 {
+                order.push(artist);
+            }
+            *count += 1;
+        }
+
+        if total == 0 {
+            return None;
+        }
+
+        // Ties go to the artist seen first so the result is stable.
+        let (leader, count) = order.iter().map(|artist| (*artist, counts[artist])).fold(
+            ("", 0),
+            |best, candidate| {
+                if candidate.1 > best.1 {
+                    candidate
+                } else {
+                    best
+                }
+            },
+        );
+
+        if count as f64 / total as f64 > self.majority_threshold {
+            Some(leader.to_string())
+        } else {
+            Some(self.various_artists.clone())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AlbumBuilder {
+    resolver: AlbumArtistResolver,
+}
+
+impl AlbumBuilder {
+    pub fn new(resolver: AlbumArtistResolver) -> Self {
+        Self { resolver }
+    }
+
+    pub fn build(
+        &self,
+        id: &AlbumId,
+        title: Option<&str>,
+        entries: &[TrackIndexEntry],
+    ) -> AlbumMetadata {
+        let tracks: Vec<_> = entries
+            .iter()
+            .filter(|entry| &entry.id.album == id)
+            .collect();
+
+        let album_artist = tracks
+            .iter()
+            .find_map(|entry| entry.metadata.album_artist.clone())
+            .or_else(|| {
+                self.resolver
+                    .resolve(tracks.iter().map(|entry| entry.metadata.artist.as_str()))
+            });
+
+        AlbumMetadata {
+            id: id.clone(),
+            title: title.map(str::to_string).unwrap_or_else(|| id.to_string()),
+            album_artist,
+            year: None,
+            tracks: tracks.iter().map(|entry| entry.id.clone()).collect(),
+            artwork: None,
+            tags: TagMap::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn entry(album: &AlbumId, index: u32, artist: &str) -> TrackIndexEntry {
+        let mut entry = fixtures::entry(&album.0, index, "/music/disc.flac");
+        entry.metadata.title = format!("Track {index}");
+        entry.metadata.artist = artist.into();
+        entry.metadata.duration_ms = 1_000;
+        entry
+    }
+
+    #[test]
+    fn majority_artist_becomes_album_artist() {
+        let album = AlbumId("album".into());
+        let entries = vec![
+            entry(&album, 1, "Artist"),
+            entry(&album, 2, "Artist"),
+            entry(&album, 3, "Guest"),
+            entry(&album, 4, "Artist"),
+        ];
+
+        let metadata = AlbumBuilder::default().build(&album, Some("Album"), &entries);
+        assert_eq!(metadata.album_artist.as_deref(), Some("Artist"));
+        assert_eq!(metadata.tracks.len(), 4);
+    }
+
+    #[test]
+    fn split_album_yields_various_artists() {
+        let album = AlbumId("compilation".into());
+        let entries = vec![
+            entry(&album, 1, "One"),
+            entry(&album, 2, "Two"),
+            entry(&album, 3, "One"),
+            entry(&album, 4, "Two"),
+        ];
+
+        let metadata = AlbumBuilder::default().build(&album, None, &entries);
+        assert_eq!(metadata.album_artist.as_deref(), Some(VARIOUS_ARTISTS));
+        assert_eq!(metadata.title, "compilation");
+    }
+
+    #[test]
+    fn threshold_is_configurable() {
+        let resolver = AlbumArtistResolver {
+            majority_threshold: 0.8,
+            various_artists: "VA".into(),
+        };
+        assert_eq!(
+            resolver.resolve(["A", "A", "A", "B"]).as_deref(),
+            Some("VA")
+        );
+        assert_eq!(resolver.resolve(std::iter::empty()), None);
+    }
+}
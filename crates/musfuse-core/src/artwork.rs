@@ -0,0 +1,200 @@
+use std::io::Cursor;
+
+use image::codecs::png::PngDecoder;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader};
+
+use crate::error::{MusFuseError, Result};
+use crate::hash::default_hasher;
+use crate::metadata::ArtworkRef;
+
+const UNKNOWN_MIME: &str = "application/octet-stream";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoverFormat {
+    Jpeg,
+    Png,
+}
+
+impl CoverFormat {
+    fn image_format(self) -> ImageFormat {
+        match self {
+            CoverFormat::Jpeg => ImageFormat::Jpeg,
+            CoverFormat::Png => ImageFormat::Png,
+        }
+    }
+}
+
+// Cover bytes as served, with the ref describing exactly those bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NormalizedCover {
+    pub artwork: ArtworkRef,
+    pub data: Vec<u8>,
+}
+
+impl NormalizedCover {
+    fn new(mime: &str, data: Vec<u8>) -> Self {
+        Self {
+            artwork: ArtworkRef::from_bytes(default_hasher().as_ref(), mime, &data),
+            data,
+        }
+    }
+}
+
+fn media_err(err: impl std::fmt::Display) -> MusFuseError {
+    MusFuseError::Media(err.to_string())
+}
+
+// Players often ignore the EXIF orientation flag, so rotate the pixels instead; the
+// re-encoded output carries no EXIF block and therefore no orientation tag.
+pub fn decode_upright(data: &[u8]) -> Result<DynamicImage> {
+    let mut decoder = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()?
+        .into_decoder()
+        .map_err(media_err)?;
+    let orientation = decoder.orientation().map_err(media_err)?;
+    let mut image = DynamicImage::from_decoder(decoder).map_err(media_err)?;
+    image.apply_orientation(orientation);
+    Ok(image)
+}
+
+pub fn thumbnail(data: &[u8], max_edge: u32, format: CoverFormat) -> Result<Vec<u8>> {
+    let image = decode_upright(data)?;
+    let image = if image.width().max(image.height()) > max_edge {
+        image.thumbnail(max_edge, max_edge)
+    } else {
+        image
+    };
+    encode(&image, format)
+}
+
+// Fits the cover within `max_dim` on its longest edge and re-encodes it as JPEG. Animated
+// images would lose every frame but the first, so they are kept as they are, like images
+// that fail to decode.
+pub fn normalize_cover(data: Vec<u8>, max_dim: u32) -> NormalizedCover {
+    if !is_animated(&data)
+        && let Ok(jpeg) = thumbnail(&data, max_dim, CoverFormat::Jpeg)
+    {
+        return NormalizedCover::new(ImageFormat::Jpeg.to_mime_type(), jpeg);
+    }
+    NormalizedCover::new(sniff_mime(&data), data)
+}
+
+pub fn sniff_mime(data: &[u8]) -> &'static str {
+    image::guess_format(data).map_or(UNKNOWN_MIME, |format| format.to_mime_type())
+}
+
+// Only PNG can carry animation among the enabled decoders; GIF and WebP fail to decode.
+fn is_animated(data: &[u8]) -> bool {
+    matches!(image::guess_format(data), Ok(ImageFormat::Png))
+        && PngDecoder::new(Cursor::new(data))
+            .and_then(|decoder| decoder.is_apng())
+            .unwrap_or(false)
+}
+
+fn encode(image: &DynamicImage, format: CoverFormat) -> Result<Vec<u8>> {
+    let mut out = Cursor::new(Vec::new());
+    match format {
+        // JPEG has no alpha channel.
+        CoverFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8())
+            .write_to(&mut out, format.image_format())
+            .map_err(media_err)?,
+        CoverFormat::Png => image
+            .write_to(&mut out, format.image_format())
+            .map_err(media_err)?,
+    }
+    Ok(out.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    // Big-endian TIFF with a single IFD entry: Orientation (0x0112) = 6, i.e. rotate 90° clockwise.
+    const EXIF_ROTATE_90: &[u8] = &[
+        b'E', b'x', b'i', b'f', 0, 0, b'M', b'M', 0, 42, 0, 0, 0, 8, 0, 1, 0x01, 0x12, 0, 3, 0, 0,
+        0, 1, 0, 6, 0, 0, 0, 0, 0, 0,
+    ];
+
+    fn rotated_jpeg() -> Vec<u8> {
+        let image = RgbImage::from_fn(16, 8, |x, _| {
+            if x < 8 {
+                Rgb([255, 0, 0])
+            } else {
+                Rgb([0, 0, 255])
+            }
+        });
+        let mut jpeg = Vec::new();
+        DynamicImage::ImageRgb8(image)
+            .write_to(&mut Cursor::new(&mut jpeg), ImageFormat::Jpeg)
+            .expect("encode jpeg");
+
+        let mut segment = vec![0xFF, 0xE1];
+        segment.extend_from_slice(&((EXIF_ROTATE_90.len() + 2) as u16).to_be_bytes());
+        segment.extend_from_slice(EXIF_ROTATE_90);
+        jpeg.splice(2..2, segment);
+        jpeg
+    }
+
+    fn is_red(pixel: &Rgb<u8>) -> bool {
+        pixel[0] > 200 && pixel[2] < 60
+    }
+
+    fn is_blue(pixel: &Rgb<u8>) -> bool {
+        pixel[2] > 200 && pixel[0] < 60
+    }
+
+    #[test]
+    fn exif_rotation_is_applied_and_stripped() {
+        let converted = normalize_cover(rotated_jpeg(), 100).data;
+
+        let mut decoder = ImageReader::new(Cursor::new(&converted))
+            .with_guessed_format()
+            .unwrap()
+            .into_decoder()
+            .unwrap();
+        assert_eq!(
+            decoder.orientation().unwrap(),
+            image::metadata::Orientation::NoTransforms
+        );
+
+        let image = DynamicImage::from_decoder(decoder).unwrap().to_rgb8();
+        assert_eq!(image.dimensions(), (8, 16));
+        // The left (red) half ends up on top after a clockwise quarter turn.
+        assert!(is_red(image.get_pixel(4, 3)));
+        assert!(is_blue(image.get_pixel(4, 12)));
+    }
+
+    #[test]
+    fn large_covers_are_scaled_to_jpeg_and_invalid_ones_kept() {
+        let image = RgbImage::from_fn(1600, 1200, |x, y| {
+            Rgb([(x % 256) as u8, (y % 256) as u8, 0])
+        });
+        let mut png = Vec::new();
+        DynamicImage::ImageRgb8(image)
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .unwrap();
+
+        let cover = normalize_cover(png, 400);
+        assert_eq!(image::guess_format(&cover.data).unwrap(), ImageFormat::Jpeg);
+        let scaled = image::load_from_memory(&cover.data).unwrap();
+        assert_eq!((scaled.width(), scaled.height()), (400, 300));
+        assert_eq!(cover.artwork.mime, "image/jpeg");
+        assert_eq!(cover.artwork.size, cover.data.len() as u64);
+        assert_eq!(
+            cover.artwork,
+            NormalizedCover::new("image/jpeg", cover.data.clone()).artwork
+        );
+
+        let invalid = normalize_cover(vec![1, 2, 3, 4], 400);
+        assert_eq!(invalid.data, [1, 2, 3, 4]);
+        assert_eq!(invalid.artwork.mime, UNKNOWN_MIME);
+    }
+
+    #[test]
+    fn thumbnail_keeps_orientation_and_bounds_size() {
+        let thumb = thumbnail(&rotated_jpeg(), 8, CoverFormat::Png).expect("thumbnail");
+        let image = image::load_from_memory(&thumb).unwrap();
+        assert_eq!((image.width(), image.height()), (4, 8));
+    }
+}
@@ -0,0 +1,521 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+
+use crate::artwork::sniff_mime;
+use crate::config::MountConfig;
+use crate::error::{MusFuseError, Result};
+use crate::hash::{ContentHasher, Sha256Hasher};
+use crate::kv::{KvBackend, KvKey, KvNamespace};
+use crate::media::TranscodeResult;
+use crate::metadata::{ArtworkRef, TrackId};
+
+const BLOB_DIR: &str = "blobs";
+// Distinct prefixes so switching `cache_dir` on or off never misreads an older entry.
+const FILE_PREFIX: &str = "file:";
+const INLINE_PREFIX: &str = "blob:";
+const STAMP_PREFIX: &str = "stamp:";
+
+// Numbers partial files, so concurrent writers of one blob never share one.
+static NEXT_PARTIAL: AtomicU64 = AtomicU64::new(0);
+
+// The source file's modification time when a transcode was stored.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+struct TranscodeStamp {
+    source_mtime_ns: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+struct BlobRecord {
+    file: String,
+    len: u64,
+}
+
+// Large binary payloads (transcodes, artwork). With a cache directory they live as files and
+// the KV only holds an index record, keeping the metadata DB small; otherwise they are
+// stored inline in the KV.
+pub struct BlobStore {
+    kv: Arc<dyn KvBackend>,
+    dir: Option<PathBuf>,
+}
+
+impl BlobStore {
+    pub fn new(kv: Arc<dyn KvBackend>, cache_dir: Option<PathBuf>) -> Self {
+        Self {
+            kv,
+            dir: cache_dir.map(|dir| dir.join(BLOB_DIR)),
+        }
+    }
+
+    pub fn from_config(kv: Arc<dyn KvBackend>, config: &MountConfig) -> Self {
+        Self::new(kv, config.cache_dir.clone())
+    }
+
+    pub fn is_file_backed(&self) -> bool {
+        self.dir.is_some()
+    }
+
+    fn file_name(namespace: KvNamespace, key: &str) -> String {
+        format!("{namespace}-{}.bin", blake3::hash(key.as_bytes()).to_hex())
+    }
+
+    pub async fn put(&self, namespace: KvNamespace, key: &str, data: &[u8]) -> Result<()> {
+        let mut writer = self.writer(namespace, key).await?;
+        writer.write(data).await?;
+        writer.commit().await
+    }
+
+    // Starts a blob that is written piece by piece; see `BlobWriter`.
+    pub async fn writer(&self, namespace: KvNamespace, key: &str) -> Result<BlobWriter> {
+        let target = match &self.dir {
+            None => BlobTarget::Inline(Vec::new()),
+            Some(dir) => {
+                tokio::fs::create_dir_all(dir).await?;
+                let name = Self::file_name(namespace, key);
+                let partial = dir.join(format!(
+                    "{name}.{}.partial",
+                    NEXT_PARTIAL.fetch_add(1, Ordering::Relaxed)
+                ));
+                BlobTarget::File {
+                    file: tokio::fs::File::create(&partial).await?,
+                    path: dir.join(&name),
+                    partial,
+                    name,
+                    len: 0,
+                }
+            }
+        };
+        Ok(BlobWriter {
+            kv: self.kv.clone(),
+            namespace,
+            key: key.to_string(),
+            target,
+            stamp: None,
+        })
+    }
+
+    pub async fn get(&self, namespace: KvNamespace, key: &str) -> Result<Option<Vec<u8>>> {
+        let Some(dir) = &self.dir else {
+            let inline = KvKey::new(namespace, format!("{INLINE_PREFIX}{key}"));
+            return self.kv.get(&inline).await;
+        };
+
+        let index = KvKey::new(namespace, format!("{FILE_PREFIX}{key}"));
+        let Some(value) = self.kv.get(&index).await? else {
+            return Ok(None);
+        };
+        let record: BlobRecord =
+            serde_json::from_slice(&value).map_err(|err| MusFuseError::Kv(err.to_string()))?;
+        match tokio::fs::read(dir.join(&record.file)).await {
+            Ok(data) if data.len() as u64 == record.len => Ok(Some(data)),
+            // A scratch disk may be wiped underneath us; treat that as a miss.
+            Ok(_) => Ok(None),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                self.kv.delete(&index).await?;
+                Ok(None)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    pub async fn remove(&self, namespace: KvNamespace, key: &str) -> Result<()> {
+        let Some(dir) = &self.dir else {
+            let inline = KvKey::new(namespace, format!("{INLINE_PREFIX}{key}"));
+            return self.kv.delete(&inline).await;
+        };
+
+        match tokio::fs::remove_file(dir.join(Self::file_name(namespace, key))).await {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+        self.kv
+            .delete(&KvKey::new(namespace, format!("{FILE_PREFIX}{key}")))
+            .await
+    }
+
+    fn transcode_key(id: &TrackId, format: &str) -> String {
+        format!("{id}.{format}")
+    }
+
+    pub async fn store_transcode(&self, result: &TranscodeResult) -> Result<()> {
+        let data: Vec<u8> = result
+            .chunks
+            .iter()
+            .flat_map(|chunk| chunk.data.iter().copied())
+            .collect();
+        let key = Self::transcode_key(&result.track_id, result.format);
+        self.put(KvNamespace::Cache, &key, &data).await
+    }
+
+    pub async fn load_transcode(&self, id: &TrackId, format: &str) -> Result<Option<Vec<u8>>> {
+        self.get(KvNamespace::Cache, &Self::transcode_key(id, format))
+            .await
+    }
+
+    fn stamp_key(id: &TrackId, format: &str) -> KvKey {
+        KvKey::new(
+            KvNamespace::Cache,
+            format!("{STAMP_PREFIX}{}", Self::transcode_key(id, format)),
+        )
+    }
+
+    // Like `store_transcode`, remembering which version of the source it was made from.
+    pub async fn store_stamped_transcode(
+        &self,
+        id: &TrackId,
+        format: &str,
+        data: &[u8],
+        source_mtime_ns: u64,
+    ) -> Result<()> {
+        let mut writer = self
+            .stamped_transcode_writer(id, format, source_mtime_ns)
+            .await?;
+        writer.write(data).await?;
+        writer.commit().await
+    }
+
+    // A stamped transcode written as it is produced; the stamp is stored on commit.
+    pub async fn stamped_transcode_writer(
+        &self,
+        id: &TrackId,
+        format: &str,
+        source_mtime_ns: u64,
+    ) -> Result<BlobWriter> {
+        let mut writer = self
+            .writer(KvNamespace::Cache, &Self::transcode_key(id, format))
+            .await?;
+        let stamp = TranscodeStamp { source_mtime_ns };
+        let value = serde_json::to_vec(&stamp).map_err(|err| MusFuseError::Kv(err.to_string()))?;
+        writer.stamp = Some((Self::stamp_key(id, format), value));
+        Ok(writer)
+    }
+
+    // A transcode made from a different version of the source is a miss.
+    pub async fn load_stamped_transcode(
+        &self,
+        id: &TrackId,
+        format: &str,
+        source_mtime_ns: u64,
+    ) -> Result<Option<Vec<u8>>> {
+        let Some(value) = self.kv.get(&Self::stamp_key(id, format)).await? else {
+            return Ok(None);
+        };
+        let stamp: TranscodeStamp =
+            serde_json::from_slice(&value).map_err(|err| MusFuseError::Kv(err.to_string()))?;
+        if stamp.source_mtime_ns != source_mtime_ns {
+            return Ok(None);
+        }
+        self.load_transcode(id, format).await
+    }
+
+    // Drops every stamped transcode of the track, whatever its format.
+    pub async fn remove_transcodes(&self, id: &TrackId) -> Result<()> {
+        let prefix = format!("{STAMP_PREFIX}{id}.");
+        for (key, _) in self.kv.scan_prefix(KvNamespace::Cache, &prefix).await? {
+            let key = key.to_string_lossy();
+            let Some(format) = key.strip_prefix(&prefix) else {
+                continue;
+            };
+            self.remove(KvNamespace::Cache, &Self::transcode_key(id, format))
+                .await?;
+            self.kv.delete(&Self::stamp_key(id, format)).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn store_artwork(&self, id: &TrackId, data: &[u8]) -> Result<()> {
+        self.put(KvNamespace::Artwork, &id.to_string(), data).await
+    }
+
+    pub async fn load_artwork(&self, id: &TrackId) -> Result<Option<Vec<u8>>> {
+        self.get(KvNamespace::Artwork, &id.to_string()).await
+    }
+}
+
+// A blob that only becomes visible once committed. File-backed stores append to a partial
+// file and rename it into place; inline stores have to collect the value, as the KV takes
+// it whole.
+pub struct BlobWriter {
+    kv: Arc<dyn KvBackend>,
+    namespace: KvNamespace,
+    key: String,
+    target: BlobTarget,
+    stamp: Option<(KvKey, Vec<u8>)>,
+}
+
+enum BlobTarget {
+    File {
+        file: tokio::fs::File,
+        partial: PathBuf,
+        path: PathBuf,
+        name: String,
+        len: u64,
+    },
+    Inline(Vec<u8>),
+}
+
+impl BlobWriter {
+    pub async fn write(&mut self, data: &[u8]) -> Result<()> {
+        match &mut self.target {
+            BlobTarget::File { file, len, .. } => {
+                file.write_all(data).await?;
+                *len += data.len() as u64;
+            }
+            BlobTarget::Inline(buffer) => buffer.extend_from_slice(data),
+        }
+        Ok(())
+    }
+
+    pub async fn commit(self) -> Result<()> {
+        match self.target {
+            BlobTarget::Inline(data) => {
+                let inline = KvKey::new(self.namespace, format!("{INLINE_PREFIX}{}", self.key));
+                self.kv.put(&inline, data).await?;
+            }
+            BlobTarget::File {
+                mut file,
+                partial,
+                path,
+                name,
+                len,
+            } => {
+                file.flush().await?;
+                drop(file);
+                tokio::fs::rename(&partial, &path).await?;
+                let record = BlobRecord { file: name, len };
+                let value =
+                    serde_json::to_vec(&record).map_err(|err| MusFuseError::Kv(err.to_string()))?;
+                self.kv
+                    .put(
+                        &KvKey::new(self.namespace, format!("{FILE_PREFIX}{}", self.key)),
+                        value,
+                    )
+                    .await?;
+            }
+        }
+        match self.stamp {
+            Some((key, value)) => self.kv.put(&key, value).await,
+            None => Ok(()),
+        }
+    }
+
+    // Drops what was written without storing anything.
+    pub async fn discard(self) -> Result<()> {
+        if let BlobTarget::File { file, partial, .. } = self.target {
+            drop(file);
+            match tokio::fs::remove_file(&partial).await {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(())
+    }
+}
+
+// Cover art keyed by its SHA-256, so every track of an album shares the one stored copy.
+pub struct ArtworkStore {
+    blobs: Arc<BlobStore>,
+}
+
+impl ArtworkStore {
+    pub fn new(blobs: Arc<BlobStore>) -> Self {
+        Self { blobs }
+    }
+
+    pub async fn store(&self, data: &[u8]) -> Result<ArtworkRef> {
+        let artwork = ArtworkRef {
+            hash: Sha256Hasher.hash(data),
+            mime: sniff_mime(data).to_string(),
+            size: data.len() as u64,
+        };
+        if self.load(&artwork).await?.is_none() {
+            self.blobs
+                .put(KvNamespace::Artwork, &artwork.hash, data)
+                .await?;
+        }
+        Ok(artwork)
+    }
+
+    pub async fn load(&self, artwork: &ArtworkRef) -> Result<Option<Vec<u8>>> {
+        self.blobs.get(KvNamespace::Artwork, &artwork.hash).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv::MemoryBackend;
+    use crate::media::AudioChunk;
+    use crate::metadata::AlbumId;
+    use bytes::Bytes;
+
+    fn sample_result() -> TranscodeResult {
+        TranscodeResult {
+            track_id: TrackId {
+                album: AlbumId("album".into()),
+                disc: 1,
+                index: 3,
+            },
+            format: "flac",
+            chunks: vec![
+                AudioChunk {
+                    data: Bytes::from_static(b"fLaC"),
+                    timestamp_ms: 0,
+                    is_end: false,
+                },
+                AudioChunk {
+                    data: Bytes::from_static(b"frames"),
+                    timestamp_ms: 10,
+                    is_end: true,
+                },
+            ],
+            artwork: None,
+            duration_ms: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn transcoded_blob_lands_under_cache_dir_when_configured() {
+        let cache = tempfile::tempdir().unwrap();
+        let kv: Arc<dyn KvBackend> = Arc::new(MemoryBackend::new());
+        let result = sample_result();
+
+        let store = BlobStore::new(kv.clone(), Some(cache.path().to_path_buf()));
+        store.store_transcode(&result).await.unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(cache.path().join(BLOB_DIR))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert_eq!(std::fs::read(&files[0]).unwrap(), b"fLaCframes");
+        assert!(
+            kv.get(&KvKey::new(
+                KvNamespace::Cache,
+                format!(
+                    "{INLINE_PREFIX}{}",
+                    BlobStore::transcode_key(&result.track_id, "flac")
+                ),
+            ))
+            .await
+            .unwrap()
+            .is_none()
+        );
+        assert_eq!(
+            store
+                .load_transcode(&result.track_id, "flac")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some(&b"fLaCframes"[..])
+        );
+
+        let inline = BlobStore::new(kv, None);
+        inline.store_transcode(&result).await.unwrap();
+        assert_eq!(
+            inline
+                .load_transcode(&result.track_id, "flac")
+                .await
+                .unwrap()
+                .as_deref(),
+            Some(&b"fLaCframes"[..])
+        );
+        assert_eq!(
+            std::fs::read_dir(cache.path().join(BLOB_DIR))
+                .unwrap()
+                .count(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn written_blobs_appear_only_once_committed() {
+        let cache = tempfile::tempdir().unwrap();
+        let kv: Arc<dyn KvBackend> = Arc::new(MemoryBackend::new());
+        let id = sample_result().track_id;
+
+        for cache_dir in [None, Some(cache.path().to_path_buf())] {
+            let store = BlobStore::new(kv.clone(), cache_dir);
+            let mut writer = store
+                .stamped_transcode_writer(&id, "flac", 7)
+                .await
+                .unwrap();
+            writer.write(b"fLaC").await.unwrap();
+            writer.write(b"frames").await.unwrap();
+            assert_eq!(
+                store.load_stamped_transcode(&id, "flac", 7).await.unwrap(),
+                None
+            );
+            writer.commit().await.unwrap();
+            assert_eq!(
+                store
+                    .load_stamped_transcode(&id, "flac", 7)
+                    .await
+                    .unwrap()
+                    .as_deref(),
+                Some(&b"fLaCframes"[..])
+            );
+
+            let mut abandoned = store.stamped_transcode_writer(&id, "mp3", 7).await.unwrap();
+            abandoned.write(b"ID3").await.unwrap();
+            abandoned.discard().await.unwrap();
+            assert_eq!(
+                store.load_stamped_transcode(&id, "mp3", 7).await.unwrap(),
+                None
+            );
+        }
+        // Only the committed file is left behind.
+        assert_eq!(
+            std::fs::read_dir(cache.path().join(BLOB_DIR))
+                .unwrap()
+                .count(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn identical_covers_share_one_stored_artwork_blob() {
+        let cache = tempfile::tempdir().unwrap();
+        let kv: Arc<dyn KvBackend> = Arc::new(MemoryBackend::new());
+        let cover = b"\xff\xd8\xff\xe0 album cover".to_vec();
+
+        for cache_dir in [None, Some(cache.path().to_path_buf())] {
+            let store = ArtworkStore::new(Arc::new(BlobStore::new(kv.clone(), cache_dir)));
+            let first = store.store(&cover).await.unwrap();
+            let second = store.store(&cover.clone()).await.unwrap();
+            assert_eq!(first, second);
+            assert!(first.hash.starts_with("sha256:"));
+            assert_eq!(first.mime, "image/jpeg");
+            assert_eq!(first.size, cover.len() as u64);
+            assert_eq!(store.load(&first).await.unwrap(), Some(cover.clone()));
+
+            let other = store.store(b"another cover").await.unwrap();
+            assert_ne!(other.hash, first.hash);
+            assert_eq!(
+                store.load(&other).await.unwrap().as_deref(),
+                Some(&b"another cover"[..])
+            );
+        }
+
+        // One record per distinct cover and storage mode, and one file per distinct cover.
+        let stored = kv.scan_prefix(KvNamespace::Artwork, "").await.unwrap();
+        assert_eq!(stored.len(), 4);
+        let files = std::fs::read_dir(cache.path().join(BLOB_DIR))
+            .unwrap()
+            .count();
+        assert_eq!(files, 2);
+
+        let missing = ArtworkRef {
+            hash: Sha256Hasher.hash(b"never stored"),
+            mime: "image/png".into(),
+            size: 12,
+        };
+        let store = ArtworkStore::new(Arc::new(BlobStore::new(kv, None)));
+        assert_eq!(store.load(&missing).await.unwrap(), None);
+    }
+}
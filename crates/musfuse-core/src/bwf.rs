@@ -0,0 +1,104 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::error::Result;
+use crate::metadata::{TagMap, TagValue};
+
+// Fixed-size fields at the start of a Broadcast Wave `bext` chunk (EBU Tech 3285).
+const DESCRIPTION_LEN: usize = 256;
+const ORIGINATOR_LEN: usize = 32;
+const ORIGINATOR_REFERENCE_LEN: usize = 32;
+const ORIGINATION_DATE_LEN: usize = 10;
+const ORIGINATION_TIME_LEN: usize = 8;
+const BEXT_HEADER_LEN: usize = DESCRIPTION_LEN
+    + ORIGINATOR_LEN
+    + ORIGINATOR_REFERENCE_LEN
+    + ORIGINATION_DATE_LEN
+    + ORIGINATION_TIME_LEN
+    + 8;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BextChunk {
+    pub description: Option<String>,
+    pub originator: Option<String>,
+    pub originator_reference: Option<String>,
+    pub origination_date: Option<String>,
+    pub origination_time: Option<String>,
+    // Sample count since midnight of the first sample, i.e. the timecode.
+    pub time_reference: u64,
+}
+
+impl BextChunk {
+    fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < BEXT_HEADER_LEN {
+            return None;
+        }
+        let mut offset = 0;
+        let mut field = |len: usize| {
+            let bytes = &data[offset..offset + len];
+            offset += len;
+            let end = bytes.iter().position(|&b| b == 0).unwrap_or(len);
+            let text = String::from_utf8_lossy(&bytes[..end]).trim().to_string();
+            (!text.is_empty()).then_some(text)
+        };
+        let description = field(DESCRIPTION_LEN);
+        let originator = field(ORIGINATOR_LEN);
+        let originator_reference = field(ORIGINATOR_REFERENCE_LEN);
+        let origination_date = field(ORIGINATION_DATE_LEN);
+        let origination_time = field(ORIGINATION_TIME_LEN);
+        let time_reference = u64::from_le_bytes(data[offset..offset + 8].try_into().ok()?);
+        Some(Self {
+            description,
+            originator,
+            originator_reference,
+            origination_date,
+            origination_time,
+            time_reference,
+        })
+    }
+
+    // Values already read from regular tags win over the broadcast header.
+    pub fn merge_into(&self, tags: &mut TagMap) {
+        let fields = [
+            ("BWF_DESCRIPTION", &self.description),
+            ("BWF_ORIGINATOR", &self.originator),
+            ("BWF_ORIGINATOR_REFERENCE", &self.originator_reference),
+            ("BWF_ORIGINATION_DATE", &self.origination_date),
+            ("BWF_ORIGINATION_TIME", &self.origination_time),
+        ];
+        for (key, value) in fields {
+            if let Some(value) = value
+                && tags.get(key).is_none()
+            {
+                tags.insert(key, TagValue::Text(value.clone()));
+            }
+        }
+        if self.time_reference > 0 && tags.get("BWF_TIME_REFERENCE").is_none() {
+            tags.insert(
+                "BWF_TIME_REFERENCE",
+                TagValue::Number(self.time_reference as i64),
+            );
+        }
+    }
+}
+
+pub fn read_bext(path: &Path) -> Result<Option<BextChunk>> {
+    let mut file = BufReader::new(File::open(path)?);
+    let mut riff = [0u8; 12];
+    if file.read_exact(&mut riff).is_err() || &riff[..4] != b"RIFF" || &riff[8..] != b"WAVE" {
+        return Ok(None);
+    }
+
+    let mut header = [0u8; 8];
+    while file.read_exact(&mut header).is_ok() {
+        let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as u64;
+        if &header[..4] == b"bext" {
+            let mut data = Vec::new();
+            file.by_ref().take(size).read_to_end(&mut data)?;
+            return Ok(BextChunk::parse(&data));
+        }
+        file.seek(SeekFrom::Current((size + (size & 1)) as i64))?;
+    }
+    Ok(None)
+}
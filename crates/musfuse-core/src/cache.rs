@@ -0,0 +1,246 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use bytes::Bytes;
+use parking_lot::Mutex;
+
+use crate::error::{MusFuseError, Result};
+use crate::kv::{KvBackend, KvKey, KvNamespace};
+use crate::metadata::TrackId;
+use crate::policy::TargetFormat;
+
+const PIN_PREFIX: &str = "pin:";
+
+// A track's converted output, per requested target; `None` is the policy's own format.
+pub type CacheKey = (TrackId, Option<TargetFormat>);
+
+struct CacheEntry {
+    data: Bytes,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<CacheKey, CacheEntry>,
+    pinned: HashSet<TrackId>,
+    used: usize,
+    tick: u64,
+}
+
+impl CacheState {
+    fn evict(&mut self, capacity: usize) {
+        while self.used > capacity {
+            let victim = self
+                .entries
+                .iter()
+                .filter(|((id, _), _)| !self.pinned.contains(id))
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            let Some(victim) = victim else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&victim) {
+                self.used -= entry.data.len();
+            }
+        }
+    }
+}
+
+// Whole converted outputs kept for ranged reads, least recently used first out once the byte
+// budget is exceeded. Pinned tracks are never evicted.
+pub struct TranscodeCache {
+    capacity_bytes: usize,
+    kv: Option<Arc<dyn KvBackend>>,
+    state: Mutex<CacheState>,
+}
+
+impl TranscodeCache {
+    pub fn new(capacity_bytes: usize) -> Self {
+        Self {
+            capacity_bytes,
+            kv: None,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    pub fn with_kv(mut self, kv: Arc<dyn KvBackend>) -> Self {
+        self.kv = Some(kv);
+        self
+    }
+
+    pub fn used_bytes(&self) -> usize {
+        self.state.lock().used
+    }
+
+    // Whether any output of the track is cached.
+    pub fn contains(&self, id: &TrackId) -> bool {
+        self.state
+            .lock()
+            .entries
+            .keys()
+            .any(|(track, _)| track == id)
+    }
+
+    pub fn is_pinned(&self, id: &TrackId) -> bool {
+        self.state.lock().pinned.contains(id)
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<Bytes> {
+        let mut state = self.state.lock();
+        state.tick += 1;
+        let tick = state.tick;
+        let entry = state.entries.get_mut(key)?;
+        entry.last_used = tick;
+        Some(entry.data.clone())
+    }
+
+    // Output larger than the whole budget is not kept at all.
+    pub fn insert(&self, key: CacheKey, data: Bytes) {
+        if data.len() > self.capacity_bytes {
+            return;
+        }
+        let mut state = self.state.lock();
+        state.tick += 1;
+        let size = data.len();
+        let entry = CacheEntry {
+            data,
+            last_used: state.tick,
+        };
+        if let Some(previous) = state.entries.insert(key, entry) {
+            state.used -= previous.data.len();
+        }
+        state.used += size;
+        state.evict(self.capacity_bytes);
+    }
+
+    // Drops every cached output of the track; a pin stays in place.
+    pub fn remove_track(&self, id: &TrackId) {
+        let mut state = self.state.lock();
+        let state = &mut *state;
+        let used = &mut state.used;
+        state.entries.retain(|(track, _), entry| {
+            let keep = track != id;
+            if !keep {
+                *used -= entry.data.len();
+            }
+            keep
+        });
+    }
+
+    pub async fn load_pins(&self) -> Result<()> {
+        let Some(kv) = &self.kv else {
+            return Ok(());
+        };
+        let mut pinned = HashSet::new();
+        for (_, value) in kv.scan_prefix(KvNamespace::Policy, PIN_PREFIX).await? {
+            let id: TrackId =
+                serde_json::from_slice(&value).map_err(|err| MusFuseError::Kv(err.to_string()))?;
+            pinned.insert(id);
+        }
+        self.state.lock().pinned.extend(pinned);
+        Ok(())
+    }
+
+    pub async fn pin(&self, id: &TrackId) -> Result<()> {
+        if let Some(kv) = &self.kv {
+            let value = serde_json::to_vec(id).map_err(|err| MusFuseError::Kv(err.to_string()))?;
+            kv.put(&Self::pin_key(id), value).await?;
+        }
+        self.state.lock().pinned.insert(id.clone());
+        Ok(())
+    }
+
+    pub async fn unpin(&self, id: &TrackId) -> Result<()> {
+        if let Some(kv) = &self.kv {
+            kv.delete(&Self::pin_key(id)).await?;
+        }
+        let mut state = self.state.lock();
+        state.pinned.remove(id);
+        state.evict(self.capacity_bytes);
+        Ok(())
+    }
+
+    fn pin_key(id: &TrackId) -> KvKey {
+        id.prefixed_kv_key(KvNamespace::Policy, PIN_PREFIX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::PolicyConfig;
+    use crate::filesystem::MediaEngine;
+    use crate::fixtures;
+    use crate::kv::MemoryBackend;
+    use crate::media::{
+        AudioChunk, AudioReader, DefaultCoverExtractor, FormatTranscoder, TranscodeRequest,
+        TranscodeResult,
+    };
+    use crate::track::SourceTrack;
+    use async_trait::async_trait;
+
+    struct FixedSizeTranscoder;
+
+    #[async_trait]
+    impl FormatTranscoder for FixedSizeTranscoder {
+        async fn transcode(&self, request: &TranscodeRequest) -> Result<TranscodeResult> {
+            Ok(TranscodeResult {
+                track_id: request.track.id.clone(),
+                format: "flac",
+                chunks: vec![AudioChunk {
+                    data: Bytes::from(vec![0u8; 100]),
+                    timestamp_ms: 0,
+                    is_end: true,
+                }],
+                artwork: None,
+                duration_ms: None,
+            })
+        }
+    }
+
+    struct NoReader;
+
+    #[async_trait]
+    impl AudioReader for NoReader {
+        async fn read(&self, _track: &SourceTrack) -> Result<Vec<AudioChunk>> {
+            Ok(Vec::new())
+        }
+    }
+
+    #[tokio::test]
+    async fn pinned_track_survives_eviction() {
+        let kv: Arc<dyn KvBackend> = Arc::new(MemoryBackend::new());
+        let cache = Arc::new(TranscodeCache::new(300).with_kv(kv.clone()));
+        let engine = MediaEngine::new(
+            Arc::new(NoReader),
+            Arc::new(FixedSizeTranscoder),
+            Arc::new(DefaultCoverExtractor::new()),
+            PolicyConfig::default(),
+        )
+        .with_cache(cache.clone());
+        let flac = Some(TargetFormat::Flac);
+        let entry = |index: u32| fixtures::entry("album", index, format!("{index:02}.wav"));
+
+        let favourite = entry(1);
+        engine.pin_track(&favourite, flac).await.expect("pin");
+        assert!(cache.contains(&favourite.id));
+
+        for index in 2..=6 {
+            engine
+                .read_range(&entry(index), flac, 0, 10)
+                .await
+                .expect("read");
+        }
+
+        assert!(cache.contains(&favourite.id));
+        assert!(!cache.contains(&entry(2).id));
+        assert!(cache.used_bytes() <= 300);
+
+        let reloaded = TranscodeCache::new(300).with_kv(kv);
+        reloaded.load_pins().await.expect("load pins");
+        assert!(reloaded.is_pinned(&favourite.id));
+
+        engine.unpin_track(&favourite.id).await.expect("unpin");
+        assert!(!cache.is_pinned(&favourite.id));
+    }
+}
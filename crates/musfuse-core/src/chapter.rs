@@ -0,0 +1,267 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::cue::{frames_to_ms, ms_to_frames};
+use crate::error::{MusFuseError, Result};
+use crate::metadata::{AlbumId, TagMap, TrackId, TrackMetadata};
+use crate::probe::StreamFormat;
+use crate::track::{SourceTrack, TrackIndex, TrackIndexEntry};
+
+// Nero `chpl` start times are in 100 ns units.
+const CHPL_TIMESCALE: u64 = 10_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chapter {
+    pub title: Option<String>,
+    pub start_ms: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChapterList {
+    pub chapters: Vec<Chapter>,
+    // Movie length from `mvhd`, used to size the final chapter.
+    pub duration_ms: Option<u64>,
+}
+
+pub fn is_chaptered(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("m4b") || ext.eq_ignore_ascii_case("m4a"))
+}
+
+// Only the Nero `moov/udta/chpl` list is read; QuickTime text-track chapters are not.
+pub fn read_chapters(path: &Path) -> Result<ChapterList> {
+    let mut file = BufReader::new(File::open(path)?);
+    let end = file.seek(SeekFrom::End(0))?;
+    file.seek(SeekFrom::Start(0))?;
+
+    let mut list = ChapterList::default();
+    let Some((moov_start, moov_end)) = find_box(&mut file, 0, end, b"moov")? else {
+        return Ok(list);
+    };
+    if let Some((start, box_end)) = find_box(&mut file, moov_start, moov_end, b"mvhd")? {
+        list.duration_ms = read_mvhd(&mut file, start, box_end)?;
+    }
+    if let Some((udta_start, udta_end)) = find_box(&mut file, moov_start, moov_end, b"udta")?
+        && let Some((start, box_end)) = find_box(&mut file, udta_start, udta_end, b"chpl")?
+    {
+        list.chapters = read_chpl(&mut file, start, box_end)?;
+    }
+    Ok(list)
+}
+
+// Returns the payload range of the first child box named `name` within `start..end`.
+fn find_box(
+    file: &mut (impl Read + Seek),
+    start: u64,
+    end: u64,
+    name: &[u8; 4],
+) -> Result<Option<(u64, u64)>> {
+    let mut pos = start;
+    while pos + 8 <= end {
+        file.seek(SeekFrom::Start(pos))?;
+        let mut header = [0u8; 8];
+        file.read_exact(&mut header)?;
+        let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let (header_len, size) = match size {
+            0 => (8, end - pos),
+            1 => {
+                let mut large = [0u8; 8];
+                file.read_exact(&mut large)?;
+                (16, u64::from_be_bytes(large))
+            }
+            size => (8, size),
+        };
+        if size < header_len || pos + size > end {
+            return Err(MusFuseError::Media(format!(
+                "malformed mp4 box at offset {pos}"
+            )));
+        }
+        if &header[4..] == name {
+            return Ok(Some((pos + header_len, pos + size)));
+        }
+        pos += size;
+    }
+    Ok(None)
+}
+
+fn read_payload(file: &mut (impl Read + Seek), start: u64, end: u64) -> Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(start))?;
+    let mut data = Vec::with_capacity((end - start) as usize);
+    file.by_ref().take(end - start).read_to_end(&mut data)?;
+    Ok(data)
+}
+
+fn be32(data: &[u8], at: usize) -> Option<u64> {
+    let bytes = data.get(at..at.checked_add(4)?)?;
+    Some(u32::from_be_bytes(bytes.try_into().ok()?) as u64)
+}
+
+fn be64(data: &[u8], at: usize) -> Option<u64> {
+    let bytes = data.get(at..at.checked_add(8)?)?;
+    Some(u64::from_be_bytes(bytes.try_into().ok()?))
+}
+
+fn read_mvhd(file: &mut (impl Read + Seek), start: u64, end: u64) -> Result<Option<u64>> {
+    let data = read_payload(file, start, end)?;
+    let malformed = || MusFuseError::Media("truncated mvhd box".into());
+    let (timescale, duration) = match data.first() {
+        Some(0) => (be32(&data, 12), be32(&data, 16)),
+        Some(1) => (be32(&data, 20), be64(&data, 24)),
+        // Versions this reader does not know leave the length unknown.
+        Some(_) => return Ok(None),
+        None => return Err(malformed()),
+    };
+    let (timescale, duration) = (
+        timescale.ok_or_else(malformed)?,
+        duration.ok_or_else(malformed)?,
+    );
+    Ok((timescale > 0).then(|| duration * 1000 / timescale))
+}
+
+fn read_chpl(file: &mut (impl Read + Seek), start: u64, end: u64) -> Result<Vec<Chapter>> {
+    let data = read_payload(file, start, end)?;
+    let malformed = || MusFuseError::Media("truncated chpl box".into());
+    // version, flags, and an extra reserved word in version 1.
+    let mut pos = if data.first().copied().unwrap_or(0) > 0 {
+        8
+    } else {
+        4
+    };
+    let count = *data.get(pos).ok_or_else(malformed)?;
+    pos += 1;
+
+    let mut chapters = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let start = be64(&data, pos).ok_or_else(malformed)?;
+        let len = *data.get(pos + 8).ok_or_else(malformed)? as usize;
+        let title = data.get(pos + 9..pos + 9 + len).ok_or_else(malformed)?;
+        pos += 9 + len;
+        let title = String::from_utf8_lossy(title).trim().to_string();
+        chapters.push(Chapter {
+            title: (!title.is_empty()).then_some(title),
+            start_ms: start * 1000 / CHPL_TIMESCALE,
+        });
+    }
+    chapters.sort_by_key(|chapter| chapter.start_ms);
+    Ok(chapters)
+}
+
+// Splits a single audiobook file into one track per chapter, the way `TrackMapper::from_cue`
+// splits a disc image. Offsets use the same CD-frame units as cue-derived sources.
+pub struct ChapterMapper;
+
+impl ChapterMapper {
+    pub fn from_file(path: &Path, album_id: &AlbumId) -> Result<TrackIndex> {
+        let list = read_chapters(path)?;
+        // Chapters of a file whose audio cannot be probed are still listed.
+        let format = crate::probe::stream_format(path).unwrap_or_default();
+        Ok(Self::from_chapters(&list, path, album_id, format))
+    }
+
+    pub fn from_chapters(
+        list: &ChapterList,
+        path: &Path,
+        album_id: &AlbumId,
+        format: StreamFormat,
+    ) -> TrackIndex {
+        let mut entries = Vec::new();
+        let mut iter = list.chapters.iter().enumerate().peekable();
+        while let Some((position, chapter)) = iter.next() {
+            let end_ms = iter
+                .peek()
+                .map(|(_, next)| next.start_ms)
+                .or(list.duration_ms)
+                .unwrap_or(chapter.start_ms);
+            let offset_frames = ms_to_frames(chapter.start_ms);
+            let length_frames = ms_to_frames(end_ms).saturating_sub(offset_frames);
+            let number = position as u32 + 1;
+
+            let track_id = TrackId {
+                album: album_id.clone(),
+                disc: 1,
+                index: number,
+            };
+            let metadata = TrackMetadata {
+                id: track_id.clone(),
+                title: chapter
+                    .title
+                    .clone()
+                    .unwrap_or_else(|| format!("Chapter {number:02}")),
+                artist: "Unknown Artist".into(),
+                album_artist: None,
+                duration_ms: frames_to_ms(length_frames),
+                tags: TagMap::default(),
+                artwork: None,
+                lyrics: None,
+            };
+            let source = SourceTrack {
+                id: track_id.clone(),
+                path: path.to_path_buf(),
+                cue_path: None,
+                offset_frames,
+                length_frames,
+                sample_rate: format.sample_rate.unwrap_or(44_100),
+                channels: format.channels.unwrap_or(2),
+            };
+            entries.push(TrackIndexEntry {
+                id: track_id,
+                metadata,
+                source,
+            });
+        }
+        TrackIndex { entries }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{mp4_box, write_m4b};
+
+    #[test]
+    fn chapters_become_tracks_with_offsets() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.m4b");
+        write_m4b(&path, &[(0, "Prologue"), (90_000, "Chapter One")], 250_000);
+        assert!(is_chaptered(&path));
+
+        let album = AlbumId("book".into());
+        let index = ChapterMapper::from_file(&path, &album).unwrap();
+
+        assert_eq!(index.entries.len(), 2);
+        let (first, second) = (&index.entries[0], &index.entries[1]);
+        assert_eq!(first.metadata.title, "Prologue");
+        assert_eq!(first.source.offset_frames, 0);
+        assert_eq!(first.metadata.duration_ms, 90_000);
+        assert_eq!(second.metadata.title, "Chapter One");
+        assert_eq!(second.id.index, 2);
+        assert_eq!(second.source.offset_frames, 90 * 75);
+        assert_eq!(second.source.length_frames, 160 * 75);
+        assert_eq!(second.metadata.duration_ms, 160_000);
+        assert_eq!(second.source.path, path);
+        // Offsets are CD frames, so they land on exact sample positions at any rate.
+        assert_eq!(
+            second.source.sample_span(48_000),
+            (90 * 48_000, Some(250 * 48_000))
+        );
+    }
+
+    #[test]
+    fn truncated_boxes_are_errors_not_panics() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("short.m4b");
+
+        let moov = mp4_box(b"mvhd", &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        std::fs::write(&path, mp4_box(b"moov", &moov)).unwrap();
+        let err = read_chapters(&path).unwrap_err();
+        assert!(matches!(err, MusFuseError::Media(message) if message.contains("mvhd")));
+
+        let chpl = [1u8, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let moov = mp4_box(b"udta", &mp4_box(b"chpl", &chpl));
+        std::fs::write(&path, mp4_box(b"moov", &moov)).unwrap();
+        let err = read_chapters(&path).unwrap_err();
+        assert!(matches!(err, MusFuseError::Media(message) if message.contains("chpl")));
+    }
+}
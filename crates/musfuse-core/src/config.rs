@@ -1,58 +1,228 @@
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
 
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+use crate::policy::AudioFormatPolicy;
+use crate::query::TagQuery;
+
+// Config structs reject unknown keys so a misspelt option fails loudly instead of
+// silently falling back to its default.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
 pub struct MountConfig {
     pub sources: Vec<SourceConfig>,
+    // Defaulted so a missing key is reported by `validate` as an invalid mount point.
+    #[serde(default)]
     pub mount_point: PathBuf,
     pub cache_dir: Option<PathBuf>,
     pub kv_backend: KvBackendKind,
     pub policies: PolicyConfig,
     pub scan_mode: ScanMode,
+    #[serde(default)]
+    pub allow_empty: bool,
+    #[serde(default)]
+    pub auto_select_free_drive: bool,
+    #[serde(default)]
+    pub smart_folders: Vec<SmartFolderConfig>,
+    // Operations slower than this are logged with their duration; unset disables the check.
+    #[serde(default)]
+    pub slow_op_threshold_ms: Option<u64>,
+    // Tracks converted ahead of playback when an album folder is opened; 0 turns it off.
+    #[serde(default)]
+    pub prewarm_tracks: usize,
+    // Transcodes allowed to run at once; unset keeps the transcoder's default.
+    #[serde(default)]
+    pub max_concurrent_transcodes: Option<usize>,
+    // Serve the first source byte-for-byte instead of the virtual library tree.
+    #[serde(default)]
+    pub passthrough: bool,
+    // Passthrough listings put subdirectories before files instead of sorting them together.
+    #[serde(default)]
+    pub directories_first: bool,
 }
 
 impl MountConfig {
+    pub fn from_toml_str(content: &str) -> Result<Self, ConfigValidationError> {
+        let config: MountConfig =
+            toml::from_str(content).map_err(|err| ConfigValidationError::Parse(err.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> crate::error::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(Self::from_toml_str(&content)?)
+    }
+
     pub fn validate(&self) -> Result<(), ConfigValidationError> {
-        if self.sources.is_empty() {
+        if self.sources.is_empty() && !self.allow_empty {
             return Err(ConfigValidationError::EmptySources);
         }
         if self.mount_point.as_os_str().is_empty() {
             return Err(ConfigValidationError::InvalidMountPoint);
         }
+        self.validate_sources()
+    }
+
+    // Sources may not exist yet, so they are compared as normalized paths rather than
+    // canonicalized on disk. A source inside another would be scanned twice.
+    fn validate_sources(&self) -> Result<(), ConfigValidationError> {
+        let mut seen: Vec<PathBuf> = Vec::with_capacity(self.sources.len());
+        for source in &self.sources {
+            if source.path.as_os_str().is_empty() {
+                return Err(ConfigValidationError::EmptySourcePath);
+            }
+            let path = normalize_lexically(&source.path);
+            for other in &seen {
+                if *other == path {
+                    return Err(ConfigValidationError::DuplicateSource(source.path.clone()));
+                }
+                if path.starts_with(other) || other.starts_with(&path) {
+                    return Err(ConfigValidationError::NestedSource(source.path.clone()));
+                }
+            }
+            seen.push(path);
+        }
         Ok(())
     }
 }
 
+// Drops `.` components and folds `..` into its parent without touching the disk.
+fn normalize_lexically(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push(component);
+                }
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SmartFolderConfig {
+    pub name: String,
+    pub query: TagQuery,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
 pub struct SourceConfig {
     pub path: PathBuf,
     pub recursive: bool,
     pub watch: bool,
+    #[serde(default = "default_follow_symlinks")]
+    pub follow_symlinks: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
 pub enum KvBackendKind {
+    #[default]
     Sled,
     RocksDb,
     Sqlite,
     Redis,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
 pub enum ScanMode {
     Eager,
+    #[default]
     Lazy,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
 pub struct PolicyConfig {
     pub lossless_strategy: LosslessStrategy,
     pub lossy_passthrough: bool,
+    #[serde(default = "default_multi_value_separator")]
+    pub multi_value_separator: String,
+    #[serde(default)]
+    pub source_preference: SourcePreference,
+    #[serde(default)]
+    pub missing_files: MissingFilePolicy,
+    // Per-extension overrides, consulted before the lossy/lossless defaults.
+    #[serde(default)]
+    pub format_policies: HashMap<String, AudioFormatPolicy>,
+    #[serde(default)]
+    pub cue_track_order: CueTrackOrder,
+    // List each whole-file track a second time under its source extension, served byte-exact.
+    #[serde(default)]
+    pub expose_originals: bool,
+    // Also write tag edits into the source files instead of only the KV overlay.
+    #[serde(default)]
+    pub write_through_tags: bool,
+    // Sample rate converted output is resampled to, e.g. 48000 for fixed-rate DACs.
+    #[serde(default)]
+    pub resample_to: Option<u32>,
+    // Bit depth converted lossless output is dithered down to, e.g. 16.
+    #[serde(default)]
+    pub target_bits: Option<u16>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        Self {
+            lossless_strategy: LosslessStrategy::default(),
+            lossy_passthrough: true,
+            multi_value_separator: default_multi_value_separator(),
+            source_preference: SourcePreference::default(),
+            missing_files: MissingFilePolicy::default(),
+            format_policies: HashMap::new(),
+            cue_track_order: CueTrackOrder::default(),
+            expose_originals: false,
+            write_through_tags: false,
+            resample_to: None,
+            target_bits: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum SourcePreference {
+    PreferCueSplit,
+    #[default]
+    PreferPerTrack,
+}
+
+// What to do with tracks whose backing audio file (e.g. a cue's FILE) no longer exists.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum MissingFilePolicy {
+    #[default]
+    Hide,
+    ShowStub,
+    Error,
+}
+
+// What to do when a FILE's tracks do not start in increasing INDEX 01 order, which would
+// otherwise give some tracks zero length.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum CueTrackOrder {
+    Reject,
+    #[default]
+    Reorder,
+    Clamp,
+}
+
+fn default_follow_symlinks() -> bool {
+    true
+}
+
+fn default_multi_value_separator() -> String {
+    crate::metadata::DEFAULT_MULTI_VALUE_SEPARATOR.to_string()
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
 pub enum LosslessStrategy {
+    #[default]
     Passthrough,
     ConvertToFlac,
 }
@@ -63,4 +233,127 @@ pub enum ConfigValidationError {
     EmptySources,
     #[error("mount point must be provided")]
     InvalidMountPoint,
+    #[error("source path must not be empty")]
+    EmptySourcePath,
+    #[error("source {0} is listed more than once")]
+    DuplicateSource(PathBuf),
+    #[error("source {0} overlaps another source")]
+    NestedSource(PathBuf),
+    #[error("failed to parse config: {0}")]
+    Parse(String),
+    #[error("{0} cannot change while mounted; remount to apply it")]
+    UnsafeChange(&'static str),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty_config(allow_empty: bool) -> MountConfig {
+        MountConfig {
+            mount_point: PathBuf::from("M:"),
+            allow_empty,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn empty_sources_require_opt_in() {
+        assert_eq!(
+            empty_config(false).validate(),
+            Err(ConfigValidationError::EmptySources)
+        );
+        assert_eq!(empty_config(true).validate(), Ok(()));
+    }
+
+    fn with_sources(paths: &[&str]) -> MountConfig {
+        let mut config = empty_config(false);
+        config.sources = paths
+            .iter()
+            .map(|path| SourceConfig {
+                path: PathBuf::from(path),
+                recursive: true,
+                watch: false,
+                follow_symlinks: true,
+            })
+            .collect();
+        config
+    }
+
+    #[test]
+    fn overlapping_sources_are_rejected() {
+        assert_eq!(with_sources(&["/music", "/audiobooks"]).validate(), Ok(()));
+        // Sibling directories sharing a name prefix do not overlap.
+        assert_eq!(with_sources(&["/music", "/music-new"]).validate(), Ok(()));
+
+        assert_eq!(
+            with_sources(&["/music", "/music/./rock"]).validate(),
+            Err(ConfigValidationError::NestedSource(PathBuf::from(
+                "/music/./rock"
+            )))
+        );
+        assert_eq!(
+            with_sources(&["/music/rock", "/music"]).validate(),
+            Err(ConfigValidationError::NestedSource(PathBuf::from("/music")))
+        );
+        assert_eq!(
+            with_sources(&["/music", "/other/../music/"]).validate(),
+            Err(ConfigValidationError::DuplicateSource(PathBuf::from(
+                "/other/../music/"
+            )))
+        );
+        assert_eq!(
+            with_sources(&["/music", ""]).validate(),
+            Err(ConfigValidationError::EmptySourcePath)
+        );
+    }
+
+    #[test]
+    fn toml_round_trips_through_load() {
+        let mut config = empty_config(false);
+        config.sources.push(SourceConfig {
+            path: PathBuf::from("/music"),
+            recursive: true,
+            watch: true,
+            follow_symlinks: false,
+        });
+        config.cache_dir = Some(PathBuf::from("/var/cache/musfuse"));
+        config
+            .policies
+            .format_policies
+            .insert("ape".into(), AudioFormatPolicy::ConvertLossless);
+        config.slow_op_threshold_ms = Some(250);
+        config.max_concurrent_transcodes = Some(2);
+        config.directories_first = true;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("musfuse.toml");
+        std::fs::write(&path, toml::to_string(&config).unwrap()).unwrap();
+        assert_eq!(MountConfig::load(&path).unwrap(), config);
+    }
+
+    #[test]
+    fn missing_mount_point_and_unknown_keys_are_rejected() {
+        let sources = "[[sources]]\npath = \"/music\"\nrecursive = true\nwatch = false\n";
+        let rest = "kv_backend = \"Sled\"\nscan_mode = \"Lazy\"\n";
+        let policies =
+            "[policies]\nlossless_strategy = \"Passthrough\"\nlossy_passthrough = true\n";
+
+        let missing = format!("{rest}{sources}{policies}");
+        assert_eq!(
+            MountConfig::from_toml_str(&missing),
+            Err(ConfigValidationError::InvalidMountPoint)
+        );
+
+        let valid = format!("mount_point = \"M:\"\n{missing}");
+        assert!(MountConfig::from_toml_str(&valid).is_ok());
+
+        let typo = format!("{valid}lossy_pasthrough = false\n");
+        match MountConfig::from_toml_str(&typo) {
+            Err(ConfigValidationError::Parse(message)) => {
+                assert!(message.contains("lossy_pasthrough"), "{message}")
+            }
+            other => panic!("expected a parse error, got {other:?}"),
+        }
+    }
 }
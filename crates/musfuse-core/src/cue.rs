@@ -1,11 +1,22 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
 use std::path::{Path, PathBuf};
 
+use tracing::warn;
+
 use crate::error::Result;
+use crate::track::TrackIndexEntry;
+
+const ISRC_LEN: usize = 12;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct CueSheet {
     pub album_title: Option<String>,
     pub album_performer: Option<String>,
+    // Sheet-level `REM <KEY> <value>` comments (GENRE, DATE, DISCID, ...), keyed upper-case.
+    pub rem: BTreeMap<String, String>,
+    // The disc's UPC/EAN from `CATALOG`.
+    pub catalog: Option<String>,
     pub files: Vec<CueFile>,
 }
 
@@ -20,12 +31,38 @@ pub struct CueTrack {
     pub number: u32,
     pub title: Option<String>,
     pub performer: Option<String>,
-    pub index_01_frames: u64,
+    // Every `INDEX NN` by number: 00 opens the pregap, 01 starts the track, and higher
+    // numbers mark sub-track positions.
+    pub indices: BTreeMap<u32, u64>,
+    // `REM` comments inside the TRACK block; they override sheet-level keys for this track.
+    pub rem: BTreeMap<String, String>,
+    pub isrc: Option<String>,
+    // `PREGAP`/`POSTGAP` lengths. The gap frames are taken to be part of the FILE, the
+    // way rippers use PREGAP in place of an INDEX 00.
+    pub pregap_frames: Option<u64>,
+    pub postgap_frames: Option<u64>,
 }
 
 impl CueTrack {
+    pub fn index_00_frames(&self) -> Option<u64> {
+        self.indices.get(&0).copied()
+    }
+
+    // A track without INDEX 01 is treated as starting at the top of its file.
+    pub fn index_01_frames(&self) -> u64 {
+        self.indices.get(&1).copied().unwrap_or(0)
+    }
+
     pub fn start_ms(&self) -> u64 {
-        frames_to_ms(self.index_01_frames)
+        frames_to_ms(self.index_01_frames())
+    }
+
+    // Where the gap before INDEX 01 begins; an explicit INDEX 00 wins over PREGAP.
+    pub fn pregap_start_frames(&self) -> Option<u64> {
+        self.index_00_frames().or_else(|| {
+            self.pregap_frames
+                .map(|gap| self.index_01_frames().saturating_sub(gap))
+        })
     }
 }
 
@@ -46,10 +83,135 @@ impl CueParser {
     }
 }
 
+// Inverse of `CueParser`: FILE references are written relative to `base_dir`.
+pub struct CueWriter;
+
+impl CueWriter {
+    pub fn write_str(&self, sheet: &CueSheet, base_dir: &Path) -> String {
+        let mut out = String::new();
+        for (key, value) in &sheet.rem {
+            let _ = writeln!(out, "REM {key} \"{}\"", quote(value));
+        }
+        if let Some(catalog) = &sheet.catalog {
+            let _ = writeln!(out, "CATALOG {catalog}");
+        }
+        if let Some(performer) = &sheet.album_performer {
+            let _ = writeln!(out, "PERFORMER \"{}\"", quote(performer));
+        }
+        if let Some(title) = &sheet.album_title {
+            let _ = writeln!(out, "TITLE \"{}\"", quote(title));
+        }
+        for file in &sheet.files {
+            let _ = writeln!(
+                out,
+                "FILE \"{}\" WAVE",
+                quote(&file_reference(base_dir, &file.path))
+            );
+            for track in &file.tracks {
+                let _ = writeln!(out, "  TRACK {:02} AUDIO", track.number);
+                if let Some(gap) = track.pregap_frames {
+                    let _ = writeln!(out, "    PREGAP {}", frames_to_timestamp(gap));
+                }
+                if let Some(title) = &track.title {
+                    let _ = writeln!(out, "    TITLE \"{}\"", quote(title));
+                }
+                if let Some(performer) = &track.performer {
+                    let _ = writeln!(out, "    PERFORMER \"{}\"", quote(performer));
+                }
+                if let Some(isrc) = &track.isrc {
+                    let _ = writeln!(out, "    ISRC {isrc}");
+                }
+                for (key, value) in &track.rem {
+                    let _ = writeln!(out, "    REM {key} \"{}\"", quote(value));
+                }
+                let mut indices = track.indices.clone();
+                indices.entry(1).or_insert(0);
+                for (number, frames) in indices {
+                    let _ = writeln!(out, "    INDEX {number:02} {}", frames_to_timestamp(frames));
+                }
+                if let Some(gap) = track.postgap_frames {
+                    let _ = writeln!(out, "    POSTGAP {}", frames_to_timestamp(gap));
+                }
+            }
+        }
+        out
+    }
+
+    // Describes the tracks laid end to end in a single stream. Slices of one image keep
+    // their offset from the first slice; other tracks start at the summed durations, rounded
+    // once per index so the error does not accumulate.
+    pub fn sheet_for_stream(
+        entries: &[&TrackIndexEntry],
+        stream: &Path,
+        album_title: Option<&str>,
+    ) -> CueSheet {
+        let first = entries.first().map(|entry| &entry.source);
+        let sliced = first.is_some_and(|first| {
+            entries
+                .iter()
+                .all(|entry| entry.source.cue_path.is_some() && entry.source.path == first.path)
+        });
+        let base_frames = first.map_or(0, |first| first.offset_frames);
+        let mut elapsed_ms = 0u64;
+        let tracks = entries
+            .iter()
+            .enumerate()
+            .map(|(position, entry)| {
+                let start_frames = if sliced {
+                    entry.source.offset_frames.saturating_sub(base_frames)
+                } else {
+                    ms_to_frames(elapsed_ms)
+                };
+                elapsed_ms += entry.metadata.duration_ms;
+                CueTrack {
+                    number: position as u32 + 1,
+                    title: Some(entry.metadata.title.clone()),
+                    performer: Some(entry.metadata.artist.clone()),
+                    indices: BTreeMap::from([(1, start_frames)]),
+                    rem: BTreeMap::new(),
+                    isrc: None,
+                    pregap_frames: None,
+                    postgap_frames: None,
+                }
+            })
+            .collect();
+        CueSheet {
+            album_title: album_title.map(str::to_string),
+            album_performer: entries
+                .first()
+                .and_then(|entry| entry.metadata.album_artist.clone()),
+            rem: BTreeMap::new(),
+            catalog: None,
+            files: vec![CueFile {
+                path: stream.to_path_buf(),
+                tracks,
+            }],
+        }
+    }
+}
+
+// CUE has no escape sequence for quotes inside a quoted value.
+fn quote(value: &str) -> String {
+    value.replace('"', "'")
+}
+
+fn file_reference(base_dir: &Path, path: &Path) -> String {
+    let relative = path
+        .strip_prefix(base_dir)
+        .unwrap_or_else(|_| path.file_name().map(Path::new).unwrap_or(path));
+    relative
+        .iter()
+        .map(|component| component.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
 fn parse_cue(content: &str, base_dir: &Path) -> Result<CueSheet> {
     let mut sheet = CueSheet {
         album_title: None,
         album_performer: None,
+        rem: BTreeMap::new(),
+        catalog: None,
         files: Vec::new(),
     };
 
@@ -58,21 +220,57 @@ fn parse_cue(content: &str, base_dir: &Path) -> Result<CueSheet> {
 
     for line in content.lines() {
         let trimmed = line.trim();
-        if trimmed.is_empty() || trimmed.starts_with("REM") {
+        if trimmed.is_empty() {
+            continue;
+        }
+
+        if let Some(rest) = trimmed.strip_prefix("REM") {
+            if let Some((key, value)) = parse_rem(rest) {
+                match &mut current_track {
+                    Some(track) => track.rem.insert(key, value),
+                    None => sheet.rem.insert(key, value),
+                };
+            }
+            continue;
+        }
+
+        if let Some(rest) = trimmed.strip_prefix("CATALOG") {
+            sheet.catalog = code_value(rest);
+            continue;
+        }
+
+        if let Some(rest) = trimmed.strip_prefix("ISRC") {
+            // A malformed code is kept as written rather than failing the whole sheet.
+            if let Some(track) = &mut current_track
+                && let Some(isrc) = code_value(rest)
+            {
+                if isrc.len() != ISRC_LEN {
+                    warn!(track = track.number, isrc = %isrc, "ISRC is not 12 characters");
+                }
+                track.isrc = Some(isrc);
+            }
             continue;
         }
 
         if let Some(rest) = trimmed.strip_prefix("FILE") {
+            // The open track belongs to the previous FILE.
+            if let Some(track) = current_track.take()
+                && let Some(file) = &mut current_file
+            {
+                file.tracks.push(track);
+            }
             if let Some(file) = current_file.take() {
                 sheet.files.push(file);
             }
-            let parts = rest.trim().splitn(2, ' ').collect::<Vec<_>>();
-            let name = parts
-                .first()
-                .map(|s| s.trim_matches('"'))
-                .filter(|s| !s.is_empty())
-                .map(|s| base_dir.join(s))
-                .ok_or_else(|| crate::error::MusFuseError::Mount("invalid FILE entry".into()))?;
+            let rest = rest.trim();
+            let reference = if rest.starts_with('"') {
+                extract_quoted(rest)
+            } else {
+                rest.split_whitespace().next()
+            }
+            .filter(|s| !s.is_empty())
+            .ok_or_else(|| crate::error::MusFuseError::Mount("invalid FILE entry".into()))?;
+            let name = resolve_file_reference(base_dir, reference)?;
             current_file = Some(CueFile {
                 path: name,
                 tracks: Vec::new(),
@@ -81,12 +279,12 @@ fn parse_cue(content: &str, base_dir: &Path) -> Result<CueSheet> {
         }
 
         if let Some(rest) = trimmed.strip_prefix("TRACK") {
-            if let Some(track) = current_track.take() {
-                if let Some(file) = &mut current_file {
-                    file.tracks.push(track);
-                }
+            if let Some(track) = current_track.take()
+                && let Some(file) = &mut current_file
+            {
+                file.tracks.push(track);
             }
-            let mut parts = rest.trim().split_whitespace();
+            let mut parts = rest.split_whitespace();
             let number = parts
                 .next()
                 .ok_or_else(|| crate::error::MusFuseError::Mount("missing track number".into()))?
@@ -96,7 +294,11 @@ fn parse_cue(content: &str, base_dir: &Path) -> Result<CueSheet> {
                 number,
                 title: None,
                 performer: None,
-                index_01_frames: 0,
+                indices: BTreeMap::new(),
+                rem: BTreeMap::new(),
+                isrc: None,
+                pregap_frames: None,
+                postgap_frames: None,
             });
             continue;
         }
@@ -121,21 +323,43 @@ fn parse_cue(content: &str, base_dir: &Path) -> Result<CueSheet> {
             continue;
         }
 
-        if trimmed.starts_with("INDEX 01") {
-            let timestamp = trimmed.split_whitespace().last().ok_or_else(|| {
+        if let Some(rest) = trimmed.strip_prefix("PREGAP") {
+            if let Some(track) = &mut current_track {
+                track.pregap_frames = Some(gap_frames(rest)?);
+            }
+            continue;
+        }
+
+        if let Some(rest) = trimmed.strip_prefix("POSTGAP") {
+            if let Some(track) = &mut current_track {
+                track.postgap_frames = Some(gap_frames(rest)?);
+            }
+            continue;
+        }
+
+        if let Some(rest) = trimmed.strip_prefix("INDEX") {
+            let mut parts = rest.split_whitespace();
+            let number = parts
+                .next()
+                .ok_or_else(|| crate::error::MusFuseError::Mount("missing index number".into()))?
+                .parse::<u32>()
+                .map_err(|_| crate::error::MusFuseError::Mount("invalid index number".into()))?;
+            let timestamp = parts.next().ok_or_else(|| {
                 crate::error::MusFuseError::Mount("missing index timestamp".into())
             })?;
             if let Some(track) = &mut current_track {
-                track.index_01_frames = timestamp_to_frames(timestamp)?;
+                track
+                    .indices
+                    .insert(number, timestamp_to_frames(timestamp)?);
             }
             continue;
         }
     }
 
-    if let Some(track) = current_track.take() {
-        if let Some(file) = &mut current_file {
-            file.tracks.push(track);
-        }
+    if let Some(track) = current_track.take()
+        && let Some(file) = &mut current_file
+    {
+        file.tracks.push(track);
     }
     if let Some(file) = current_file.take() {
         sheet.files.push(file);
@@ -144,6 +368,73 @@ fn parse_cue(content: &str, base_dir: &Path) -> Result<CueSheet> {
     Ok(sheet)
 }
 
+// FILE tokens may use either separator; `..` is folded lexically and must stay inside base_dir.
+fn resolve_file_reference(base_dir: &Path, reference: &str) -> Result<PathBuf> {
+    let normalized = reference.replace('\\', "/");
+    if normalized.starts_with('/')
+        || normalized
+            .split('/')
+            .next()
+            .is_some_and(|c| c.ends_with(':'))
+    {
+        return Err(crate::error::MusFuseError::Mount(format!(
+            "FILE reference must be relative to the cue sheet: {reference}"
+        )));
+    }
+
+    let mut components: Vec<&str> = Vec::new();
+    for component in normalized.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                if components.pop().is_none() {
+                    return Err(crate::error::MusFuseError::Mount(format!(
+                        "FILE reference escapes the cue directory: {reference}"
+                    )));
+                }
+            }
+            other => components.push(other),
+        }
+    }
+
+    if components.is_empty() {
+        return Err(crate::error::MusFuseError::Mount(
+            "invalid FILE entry".into(),
+        ));
+    }
+
+    let mut path = base_dir.to_path_buf();
+    path.extend(components);
+    Ok(path)
+}
+
+fn parse_rem(rest: &str) -> Option<(String, String)> {
+    if !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let (key, value) = rest.trim().split_once(char::is_whitespace)?;
+    let value = value.trim();
+    let value = if value.starts_with('"') {
+        extract_quoted(value).unwrap_or(value.trim_matches('"'))
+    } else {
+        value
+    };
+    Some((key.to_ascii_uppercase(), value.to_string()))
+}
+
+fn gap_frames(rest: &str) -> Result<u64> {
+    let timestamp = rest
+        .split_whitespace()
+        .next()
+        .ok_or_else(|| crate::error::MusFuseError::Mount("missing gap length".into()))?;
+    timestamp_to_frames(timestamp)
+}
+
+fn code_value(rest: &str) -> Option<String> {
+    let value = rest.trim().trim_matches('"').trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
 fn extract_quoted(line: &str) -> Option<&str> {
     let start = line.find('"')? + 1;
     let end = line[start..].find('"')? + start;
@@ -173,6 +464,24 @@ pub fn frames_to_ms(frames: u64) -> u64 {
     (frames * 1000) / 75
 }
 
+pub fn ms_to_frames(ms: u64) -> u64 {
+    (ms * 75).div_ceil(1000)
+}
+
+// Cue frames are a fixed 1/75 s whatever the audio's rate.
+pub fn frames_to_samples(frames: u64, sample_rate: u32) -> u64 {
+    frames * sample_rate as u64 / 75
+}
+
+pub fn frames_to_timestamp(frames: u64) -> String {
+    format!(
+        "{:02}:{:02}:{:02}",
+        frames / (60 * 75),
+        (frames / 75) % 60,
+        frames % 75
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -198,6 +507,251 @@ mod tests {
         let file = &sheet.files[0];
         assert_eq!(file.path, Path::new("/music/disc.flac"));
         assert_eq!(file.tracks.len(), 2);
-        assert_eq!(file.tracks[1].index_01_frames, 3 * 60 * 75 + 15 * 75);
+        assert_eq!(file.tracks[1].index_01_frames(), 3 * 60 * 75 + 15 * 75);
+    }
+
+    #[test]
+    fn file_reference_in_subdirectory_is_resolved() {
+        let cue = r#"
+        FILE "CD1/disc one.flac" WAVE
+          TRACK 01 AUDIO
+            INDEX 01 00:00:00
+        FILE "CD2\disc two.flac" WAVE
+          TRACK 02 AUDIO
+            INDEX 01 00:00:00
+        "#;
+
+        let sheet = CueParser.parse_str(cue, Path::new("/music")).unwrap();
+        assert_eq!(sheet.files.len(), 2);
+        assert_eq!(
+            sheet.files[0].path,
+            Path::new("/music").join("CD1").join("disc one.flac")
+        );
+        assert_eq!(
+            sheet.files[1].path,
+            Path::new("/music").join("CD2").join("disc two.flac")
+        );
+    }
+
+    #[test]
+    fn written_cue_parses_back_to_the_same_sheet() {
+        let cue = r#"
+        PERFORMER "Artist"
+        TITLE "Album"
+        FILE "CD1/disc one.flac" WAVE
+          TRACK 01 AUDIO
+            TITLE "Intro"
+            PERFORMER "Guest"
+            INDEX 01 00:00:00
+          TRACK 02 AUDIO
+            TITLE "Song"
+            INDEX 00 03:13:00
+            INDEX 01 03:15:42
+        FILE "disc two.flac" WAVE
+          TRACK 03 AUDIO
+            INDEX 01 00:00:00
+        "#;
+        let base = Path::new("/music");
+        let sheet = CueParser.parse_str(cue, base).unwrap();
+
+        let written = CueWriter.write_str(&sheet, base);
+        assert!(written.contains("INDEX 01 03:15:42"));
+        let reparsed = CueParser.parse_str(&written, base).unwrap();
+        assert_eq!(reparsed, sheet);
+        assert_eq!(
+            reparsed
+                .files
+                .iter()
+                .map(|file| file.tracks.len())
+                .collect::<Vec<_>>(),
+            vec![2, 1]
+        );
+    }
+
+    #[test]
+    fn every_index_number_is_captured() {
+        let cue = r#"
+        FILE "disc.flac" WAVE
+          TRACK 01 AUDIO
+            INDEX 01 00:00:00
+          TRACK 02 AUDIO
+            INDEX 00 04:58:00
+            INDEX 01 05:00:00
+            INDEX 02 06:30:37
+        "#;
+
+        let sheet = CueParser.parse_str(cue, Path::new("/music")).unwrap();
+        let track = &sheet.files[0].tracks[1];
+        assert_eq!(
+            track.indices,
+            BTreeMap::from([
+                (0, (4 * 60 + 58) * 75),
+                (1, 5 * 60 * 75),
+                (2, (6 * 60 + 30) * 75 + 37),
+            ])
+        );
+        assert_eq!(track.index_00_frames(), Some((4 * 60 + 58) * 75));
+        assert_eq!(track.index_01_frames(), 5 * 60 * 75);
+
+        let written = CueWriter.write_str(&sheet, Path::new("/music"));
+        assert!(written.contains("INDEX 02 06:30:37"));
+        assert_eq!(
+            CueParser.parse_str(&written, Path::new("/music")).unwrap(),
+            sheet
+        );
+    }
+
+    #[test]
+    fn rem_lines_are_kept_as_sheet_comments() {
+        let cue = r#"
+        REM GENRE "Progressive Rock"
+        REM DATE 1973
+        REM DISCID 860B640B
+        REM COMMENT "ExactAudioCopy v1.6"
+        REM
+        REM DATE
+        TITLE "Album"
+        FILE "disc.flac" WAVE
+          TRACK 01 AUDIO
+            REM REPLAYGAIN_TRACK_GAIN -7.89 dB
+            REM COMMENT "Live take"
+            INDEX 01 00:00:00
+          TRACK 02 AUDIO
+            REM
+            INDEX 01 03:00:00
+        "#;
+
+        let sheet = CueParser.parse_str(cue, Path::new("/music")).unwrap();
+        let expected: BTreeMap<String, String> = [
+            ("COMMENT", "ExactAudioCopy v1.6"),
+            ("DATE", "1973"),
+            ("DISCID", "860B640B"),
+            ("GENRE", "Progressive Rock"),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+        assert_eq!(sheet.rem, expected);
+        let tracks = &sheet.files[0].tracks;
+        assert_eq!(
+            tracks[0].rem,
+            BTreeMap::from([
+                ("COMMENT".to_string(), "Live take".to_string()),
+                ("REPLAYGAIN_TRACK_GAIN".to_string(), "-7.89 dB".to_string()),
+            ])
+        );
+        assert!(tracks[1].rem.is_empty());
+
+        let written = CueWriter.write_str(&sheet, Path::new("/music"));
+        let reparsed = CueParser.parse_str(&written, Path::new("/music")).unwrap();
+        assert_eq!(reparsed, sheet);
+    }
+
+    #[test]
+    fn catalog_and_isrc_codes_are_captured() {
+        let cue = r#"
+        CATALOG 0724384260927
+        FILE "disc.flac" WAVE
+          TRACK 01 AUDIO
+            ISRC GBAYE7300001
+            INDEX 01 00:00:00
+          TRACK 02 AUDIO
+            ISRC "GBAYE73"
+            INDEX 01 03:00:00
+          TRACK 03 AUDIO
+            INDEX 01 06:00:00
+        "#;
+
+        let sheet = CueParser.parse_str(cue, Path::new("/music")).unwrap();
+        assert_eq!(sheet.catalog.as_deref(), Some("0724384260927"));
+        let isrcs: Vec<_> = sheet.files[0]
+            .tracks
+            .iter()
+            .map(|track| track.isrc.as_deref())
+            .collect();
+        assert_eq!(isrcs, vec![Some("GBAYE7300001"), Some("GBAYE73"), None]);
+
+        let written = CueWriter.write_str(&sheet, Path::new("/music"));
+        assert_eq!(
+            CueParser.parse_str(&written, Path::new("/music")).unwrap(),
+            sheet
+        );
+    }
+
+    #[test]
+    fn pregap_and_postgap_are_captured() {
+        let cue = r#"
+        FILE "disc.flac" WAVE
+          TRACK 01 AUDIO
+            INDEX 01 00:00:00
+            POSTGAP 00:01:00
+          TRACK 02 AUDIO
+            PREGAP 00:02:00
+            INDEX 01 03:00:00
+          TRACK 03 AUDIO
+            PREGAP 00:02:00
+            INDEX 00 05:59:00
+            INDEX 01 06:00:00
+        "#;
+
+        let sheet = CueParser.parse_str(cue, Path::new("/music")).unwrap();
+        let tracks = &sheet.files[0].tracks;
+        assert_eq!(tracks[0].postgap_frames, Some(75));
+        assert_eq!(tracks[1].pregap_frames, Some(150));
+        assert_eq!(tracks[1].pregap_start_frames(), Some(178 * 75));
+        // INDEX 00 is explicit and wins over PREGAP.
+        assert_eq!(tracks[2].pregap_start_frames(), Some(359 * 75));
+
+        let written = CueWriter.write_str(&sheet, Path::new("/music"));
+        assert!(written.contains("PREGAP 00:02:00"));
+        assert_eq!(
+            CueParser.parse_str(&written, Path::new("/music")).unwrap(),
+            sheet
+        );
+    }
+
+    #[test]
+    fn file_reference_dot_segments_are_folded() {
+        let path = resolve_file_reference(Path::new("/music"), r".\CD1\..\disc.flac").unwrap();
+        assert_eq!(path, Path::new("/music").join("disc.flac"));
+    }
+
+    #[test]
+    fn file_reference_escaping_base_dir_is_rejected() {
+        for reference in [
+            "../other/disc.flac",
+            r"CD1\..\..\disc.flac",
+            "/abs/disc.flac",
+        ] {
+            let err = resolve_file_reference(Path::new("/music"), reference)
+                .expect_err("escape should be rejected");
+            assert!(matches!(err, crate::error::MusFuseError::Mount(_)));
+        }
+    }
+
+    #[test]
+    fn stream_indices_follow_the_slice_offsets() {
+        // Each slice's rounded duration is a little over its length in frames, which summed
+        // over a long album would push every later index past the slice it names.
+        let entries: Vec<_> = (0..40u64)
+            .map(|position| {
+                let mut entry =
+                    crate::fixtures::entry("album", position as u32 + 1, "/m/disc.flac");
+                entry.source.cue_path = Some("/m/disc.cue".into());
+                entry.source.offset_frames = 150 + position * 13_501;
+                entry.source.length_frames = 13_501;
+                entry.metadata.duration_ms = frames_to_ms(13_501) + 1;
+                entry
+            })
+            .collect();
+        let refs: Vec<_> = entries.iter().collect();
+        let sheet = CueWriter::sheet_for_stream(&refs, Path::new("album.flac"), Some("Album"));
+        let starts: Vec<_> = sheet.files[0]
+            .tracks
+            .iter()
+            .map(CueTrack::index_01_frames)
+            .collect();
+        let expected: Vec<_> = (0..40u64).map(|position| position * 13_501).collect();
+        assert_eq!(starts, expected);
     }
 }
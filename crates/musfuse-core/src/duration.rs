@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use crate::error::{MusFuseError, Result};
+use crate::kv::{KvBackend, KvKey, KvNamespace};
+use crate::metadata::{TrackId, TrackMetadata};
+
+const DURATION_PREFIX: &str = "duration:";
+
+// Cue arithmetic only estimates a track's length (pregap rounding, guessed last track); the
+// first full decode records the real length so later metadata reads report it instead.
+pub struct DurationCorrections {
+    kv: Arc<dyn KvBackend>,
+}
+
+impl DurationCorrections {
+    pub fn new(kv: Arc<dyn KvBackend>) -> Self {
+        Self { kv }
+    }
+
+    fn key(id: &TrackId) -> KvKey {
+        id.prefixed_kv_key(KvNamespace::Track, DURATION_PREFIX)
+    }
+
+    pub async fn lookup(&self, id: &TrackId) -> Result<Option<u64>> {
+        match self.kv.get(&Self::key(id)).await? {
+            Some(bytes) => serde_json::from_slice(&bytes)
+                .map(Some)
+                .map_err(|err| MusFuseError::Kv(err.to_string())),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn record(&self, id: &TrackId, duration_ms: u64) -> Result<()> {
+        let value =
+            serde_json::to_vec(&duration_ms).map_err(|err| MusFuseError::Kv(err.to_string()))?;
+        self.kv.put(&Self::key(id), value).await
+    }
+
+    pub async fn apply(&self, metadata: &mut TrackMetadata) -> Result<bool> {
+        match self.lookup(&metadata.id).await? {
+            Some(duration_ms) => {
+                metadata.duration_ms = duration_ms;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use std::fmt;
 use std::io;
 
 use thiserror::Error;
@@ -14,8 +15,180 @@ pub enum MusFuseError {
     Io(#[from] io::Error),
     #[error("unsupported operation: {0}")]
     Unsupported(&'static str),
+    #[error("unsupported source format: {0}")]
+    UnsupportedFormat(String),
     #[error("media pipeline error: {0}")]
     Media(String),
+    // `reason` is set when the source itself is broken rather than the pipeline.
+    #[error("transcode failed during {stage}: {message}")]
+    Transcode {
+        stage: TranscodeStage,
+        message: String,
+        reason: Option<MalformedStream>,
+    },
+}
+
+// Structural defects that make a source undecodable. They are reported at the probe stage
+// so a broken file is not mistaken for a transcoder fault.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MalformedStream {
+    NoAudioTrack,
+    ZeroChannels,
+    MissingSampleRate,
+}
+
+impl fmt::Display for MalformedStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MalformedStream::NoAudioTrack => "malformed file: no audio track",
+            MalformedStream::ZeroChannels => "malformed file: zero channels",
+            MalformedStream::MissingSampleRate => "malformed file: missing sample rate",
+        })
+    }
+}
+
+// Platform-neutral classification that each adapter translates to its native status code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    NotFound,
+    PermissionDenied,
+    NotSupported,
+    IoError,
+    Corrupt,
+    Transient,
+}
+
+impl MusFuseError {
+    pub fn malformed(reason: MalformedStream) -> Self {
+        MusFuseError::Transcode {
+            stage: TranscodeStage::Probe,
+            message: reason.to_string(),
+            reason: Some(reason),
+        }
+    }
+
+    pub fn malformed_reason(&self) -> Option<MalformedStream> {
+        match self {
+            MusFuseError::Transcode { reason, .. } => *reason,
+            _ => None,
+        }
+    }
+
+    pub fn status_hint(&self) -> ErrorClass {
+        match self {
+            MusFuseError::Config(_) => ErrorClass::NotSupported,
+            MusFuseError::Kv(_) => ErrorClass::Transient,
+            MusFuseError::Mount(_) => ErrorClass::IoError,
+            MusFuseError::Io(err) => match err.kind() {
+                io::ErrorKind::NotFound => ErrorClass::NotFound,
+                io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem => {
+                    ErrorClass::PermissionDenied
+                }
+                io::ErrorKind::Unsupported => ErrorClass::NotSupported,
+                io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => ErrorClass::Corrupt,
+                io::ErrorKind::Interrupted
+                | io::ErrorKind::WouldBlock
+                | io::ErrorKind::TimedOut
+                | io::ErrorKind::ResourceBusy => ErrorClass::Transient,
+                _ => ErrorClass::IoError,
+            },
+            MusFuseError::Unsupported(_) | MusFuseError::UnsupportedFormat(_) => {
+                ErrorClass::NotSupported
+            }
+            MusFuseError::Media(_) => ErrorClass::Corrupt,
+            MusFuseError::Transcode { stage, .. } => match stage {
+                TranscodeStage::Probe | TranscodeStage::Decode => ErrorClass::Corrupt,
+                TranscodeStage::Encode => ErrorClass::IoError,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranscodeStage {
+    Probe,
+    Decode,
+    Encode,
+}
+
+impl fmt::Display for TranscodeStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TranscodeStage::Probe => "probe",
+            TranscodeStage::Decode => "decode",
+            TranscodeStage::Encode => "encode",
+        })
+    }
 }
 
 pub type Result<T, E = MusFuseError> = std::result::Result<T, E>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigValidationError;
+
+    #[test]
+    fn every_variant_has_a_status_class() {
+        let io = |kind| MusFuseError::Io(io::Error::from(kind));
+        let cases = [
+            (
+                MusFuseError::Config(ConfigValidationError::EmptySources),
+                ErrorClass::NotSupported,
+            ),
+            (MusFuseError::Kv("flush".into()), ErrorClass::Transient),
+            (MusFuseError::Mount("busy".into()), ErrorClass::IoError),
+            (io(io::ErrorKind::NotFound), ErrorClass::NotFound),
+            (
+                io(io::ErrorKind::PermissionDenied),
+                ErrorClass::PermissionDenied,
+            ),
+            (io(io::ErrorKind::InvalidData), ErrorClass::Corrupt),
+            (io(io::ErrorKind::TimedOut), ErrorClass::Transient),
+            (io(io::ErrorKind::Other), ErrorClass::IoError),
+            (MusFuseError::Unsupported("watch"), ErrorClass::NotSupported),
+            (
+                MusFuseError::UnsupportedFormat("xyz".into()),
+                ErrorClass::NotSupported,
+            ),
+            (MusFuseError::Media("bad frame".into()), ErrorClass::Corrupt),
+            (
+                MusFuseError::Transcode {
+                    stage: TranscodeStage::Decode,
+                    message: "truncated".into(),
+                    reason: None,
+                },
+                ErrorClass::Corrupt,
+            ),
+            (
+                MusFuseError::Transcode {
+                    stage: TranscodeStage::Encode,
+                    message: "encoder".into(),
+                    reason: None,
+                },
+                ErrorClass::IoError,
+            ),
+        ];
+
+        for (err, expected) in cases {
+            assert_eq!(err.status_hint(), expected, "{err}");
+        }
+    }
+
+    #[test]
+    fn malformed_reasons_are_carried_not_parsed_from_the_message() {
+        let err = MusFuseError::malformed(MalformedStream::ZeroChannels);
+        assert_eq!(err.malformed_reason(), Some(MalformedStream::ZeroChannels));
+        assert_eq!(
+            err.to_string(),
+            "transcode failed during probe: malformed file: zero channels"
+        );
+
+        let lookalike = MusFuseError::Transcode {
+            stage: TranscodeStage::Probe,
+            message: MalformedStream::ZeroChannels.to_string(),
+            reason: None,
+        };
+        assert_eq!(lookalike.malformed_reason(), None);
+    }
+}
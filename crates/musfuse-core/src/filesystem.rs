@@ -1,26 +1,81 @@
-use std::path::PathBuf;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
 
-use crate::config::PolicyConfig;
-use crate::error::Result;
-use crate::media::{AudioReader, CoverExtractor, FormatTranscoder, TranscodeRequest};
-use crate::metadata::{TagDelta, TrackId, TrackMetadata};
+use bytes::Bytes;
+use parking_lot::Mutex;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use crate::blob::{BlobStore, BlobWriter};
+use crate::cache::{CacheKey, TranscodeCache};
+use crate::config::{PolicyConfig, SmartFolderConfig};
+use crate::cue::CueWriter;
+use crate::duration::DurationCorrections;
+use crate::error::{MusFuseError, Result};
+use crate::lyrics::Lyrics;
+use crate::media::{
+    AudioChunk, AudioReader, ChunkReceiver, CoverExtractor, FormatTranscoder, TranscodeRequest,
+    TranscoderRegistry,
+};
+use crate::metadata::{AlbumId, DEFAULT_MULTI_VALUE_SEPARATOR, TagDelta, TrackId, TrackMetadata};
+use crate::mount::{AdapterCapabilities, MountStats};
+use crate::policy::{AudioFormatPolicy, TargetFormat, classify_source};
+use crate::probe::LengthTrail;
+use crate::readahead::{FileChunkSource, ReadAhead};
+use crate::sanitize::{MountPlatform, PathSanitizer};
+use crate::scanner::FsLibraryScanner;
 use crate::tag::TagOverlayService;
-use crate::track::TrackIndexEntry;
+use crate::timing::SlowOpThreshold;
+use crate::track::{TrackCollection, TrackIndexEntry};
+
+const SMART_ROOT: &str = "Smart";
+const COVER_NAME: &str = "cover.jpg";
+const FOLDER_COVER_NAME: &str = "folder.jpg";
+// Covers are served as `cover.jpg`, fitted within this many pixels on their longest edge.
+const COVER_MAX_DIM: u32 = 1200;
+const COVER_LABEL: &str = "cover";
+const DEFAULT_RANGE_CACHE_BYTES: usize = 256 * 1024 * 1024;
+const STREAM_CHANNEL_CAPACITY: usize = 4;
+// Converted files are listed at the size of 24-bit PCM plus room for headers and tags until
+// they are encoded; no supported output is larger.
+const ESTIMATE_BYTES_PER_SAMPLE: u64 = 3;
+const ESTIMATE_OVERHEAD_BYTES: u64 = 64 * 1024;
+// Originals are read in chunks of this size, prefetching a few ahead while playback is
+// sequential; only the most recently read originals keep a prefetch buffer.
+const ORIGINAL_CHUNK_BYTES: usize = 256 * 1024;
+const ORIGINAL_READ_AHEAD: usize = 4;
+const ORIGINAL_STREAMS: usize = 8;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum VirtualEntry {
     Directory(PathBuf),
-    TrackFile(TrackId),
+    TrackFile(TrackId, Option<TargetFormat>),
+    OriginalFile(TrackId),
     CoverImage(TrackId),
+    Lyrics(TrackId),
+    // A cue-split album joined back into one stream, and the cue sheet describing it.
+    AlbumStream(AlbumId),
+    AlbumCue(AlbumId),
 }
 
 #[allow(dead_code)]
 pub struct MediaEngine {
     reader: Arc<dyn AudioReader>,
     transcoder: Arc<dyn FormatTranscoder>,
+    registry: TranscoderRegistry,
     cover: Arc<dyn CoverExtractor>,
     policy: PolicyConfig,
+    encoded: Arc<TranscodeCache>,
+    blobs: Option<Arc<BlobStore>>,
+    durations: Option<Arc<DurationCorrections>>,
+    stats: MountStats,
+    // What each source's probed codec calls for, so the container is opened once per track.
+    classified: Mutex<HashMap<TrackId, AudioFormatPolicy>>,
+    // Encodes under way, so concurrent reads of one output share it.
+    inflight: Mutex<HashMap<CacheKey, Arc<tokio::sync::Mutex<()>>>>,
 }
 
 impl MediaEngine {
@@ -33,34 +88,455 @@ impl MediaEngine {
         Self {
             reader,
             transcoder,
+            registry: TranscoderRegistry::default(),
             cover,
             policy,
+            encoded: Arc::new(TranscodeCache::new(DEFAULT_RANGE_CACHE_BYTES)),
+            blobs: None,
+            durations: None,
+            stats: MountStats::default(),
+            classified: Mutex::new(HashMap::new()),
+            inflight: Mutex::new(HashMap::new()),
         }
     }
 
-    pub async fn stream_track(&self, entry: &TrackIndexEntry) -> Result<Vec<u8>> {
-        let policy = crate::policy::AudioFormatPolicy::from_extension("flac", &self.policy);
-        let request = TranscodeRequest {
+    // Targets with a registered transcoder are encoded by it; the rest use the default one.
+    pub fn with_registry(mut self, registry: TranscoderRegistry) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    // Persists converted output in the KV cache namespace so it survives remounts.
+    pub fn with_blob_cache(mut self, blobs: Arc<BlobStore>) -> Self {
+        self.blobs = Some(blobs);
+        self
+    }
+
+    // Records each track's decoded length the first time it is converted; a `TagOverlay`
+    // sharing the corrections reports it in place of the cue estimate.
+    pub fn with_duration_corrections(mut self, durations: Arc<DurationCorrections>) -> Self {
+        self.durations = Some(durations);
+        self
+    }
+
+    // Reports reads, transcodes and cache lookups into counters shared with the mount.
+    pub fn with_stats(mut self, stats: MountStats) -> Self {
+        self.stats = stats;
+        self
+    }
+
+    pub fn stats(&self) -> &MountStats {
+        &self.stats
+    }
+
+    pub fn with_range_cache_capacity(mut self, capacity_bytes: usize) -> Self {
+        self.encoded = Arc::new(TranscodeCache::new(capacity_bytes));
+        self
+    }
+
+    // Shares a cache built elsewhere, e.g. one whose pins are kept in KV across remounts.
+    pub fn with_cache(mut self, cache: Arc<TranscodeCache>) -> Self {
+        self.encoded = cache;
+        self
+    }
+
+    // The policy for this track's source, judged by the codec inside the container rather
+    // than the extension; see `classify_source`.
+    pub async fn source_policy(&self, entry: &TrackIndexEntry) -> Result<AudioFormatPolicy> {
+        if let Some(policy) = self.classified.lock().get(&entry.id) {
+            return Ok(policy.clone());
+        }
+        let path = entry.source.path.clone();
+        let config = self.policy.clone();
+        let policy = tokio::task::spawn_blocking(move || classify_source(&path, &config))
+            .await
+            .map_err(|err| MusFuseError::Media(err.to_string()))??;
+        self.classified
+            .lock()
+            .insert(entry.id.clone(), policy.clone());
+        Ok(policy)
+    }
+
+    // Converted output is recorded on the way through: the persisted copy is written as
+    // chunks pass, and the duration is read back from the header and last pages once the
+    // stream ends, so the whole output is never held here.
+    pub async fn stream_track(
+        &self,
+        entry: &TrackIndexEntry,
+        target_format: Option<TargetFormat>,
+    ) -> Result<ChunkReceiver> {
+        let policy = self.source_policy(entry).await?;
+        let raw = self.is_raw(entry, target_format, &policy);
+        // Only converted output is worth keeping; passthrough bytes are the source itself.
+        let persisted = match &self.blobs {
+            Some(blobs) if !raw => source_mtime_ns(&entry.source.path)
+                .await
+                .map(|mtime| (blobs, mtime)),
+            _ => None,
+        };
+        let label = cache_label(target_format, &self.policy);
+        if let Some((blobs, mtime)) = persisted {
+            let stored = blobs
+                .load_stamped_transcode(&entry.id, &label, mtime)
+                .await?;
+            self.stats.record_cache_lookup(stored.is_some());
+            if let Some(data) = stored {
+                let (sender, receiver) = mpsc::channel(1);
+                let _ = sender.try_send(Ok(AudioChunk {
+                    data: Bytes::from(data),
+                    timestamp_ms: 0,
+                    is_end: true,
+                }));
+                return Ok(receiver);
+            }
+        }
+
+        let request = self.request(entry, target_format, policy);
+        let transcoder = target_format
+            .and_then(|format| self.registry.get(format))
+            .unwrap_or(&self.transcoder);
+        self.stats.record_transcode();
+        let mut chunks = transcoder.transcode_stream(&request).await?;
+        if raw {
+            return Ok(chunks);
+        }
+
+        let stored = match persisted {
+            Some((blobs, mtime)) => Some(
+                blobs
+                    .stamped_transcode_writer(&entry.id, &label, mtime)
+                    .await?,
+            ),
+            None => None,
+        };
+        let mut recorder = OutputRecorder {
+            id: entry.id.clone(),
+            durations: self.durations.clone(),
+            trail: LengthTrail::default(),
+            stored,
+        };
+        let (sender, receiver) = mpsc::channel(STREAM_CHANNEL_CAPACITY);
+        tokio::spawn(async move {
+            while let Some(chunk) = chunks.recv().await {
+                let recorded = match chunk {
+                    Ok(chunk) => recorder.record(&chunk.data).await.map(|()| chunk),
+                    Err(err) => Err(err),
+                };
+                let chunk = match recorded {
+                    Ok(chunk) => chunk,
+                    Err(err) => {
+                        recorder.abandon().await;
+                        let _ = sender.send(Err(err)).await;
+                        return;
+                    }
+                };
+                // The end is only reported once the output is recorded, so a reader that
+                // drained the stream sees the recorded duration and persisted copy.
+                if chunk.is_end
+                    && let Err(err) = recorder.finish().await
+                {
+                    let _ = sender.send(Err(err)).await;
+                    return;
+                }
+                if sender.send(Ok(chunk)).await.is_err() {
+                    recorder.abandon().await;
+                    return;
+                }
+            }
+            recorder.abandon().await;
+        });
+        Ok(receiver)
+    }
+
+    // Forgets every cached output of the track, in memory and persisted.
+    pub async fn invalidate_cache(&self, id: &TrackId) -> Result<()> {
+        self.encoded.remove_track(id);
+        self.classified.lock().remove(id);
+        match &self.blobs {
+            Some(blobs) => blobs.remove_transcodes(id).await,
+            None => Ok(()),
+        }
+    }
+
+    // Serves `len` bytes from `offset` of the track as `stream_track` would produce it.
+    // Whole-file passthrough reads the source directly; converted output is encoded once
+    // and later ranges are sliced from the cached result.
+    pub async fn read_range(
+        &self,
+        entry: &TrackIndexEntry,
+        target_format: Option<TargetFormat>,
+        offset: u64,
+        len: usize,
+    ) -> Result<Vec<u8>> {
+        if self.is_raw(entry, target_format, &self.source_policy(entry).await?) {
+            let path = entry.source.path.clone();
+            return tokio::task::spawn_blocking(move || read_file_range(&path, offset, len))
+                .await
+                .map_err(|err| MusFuseError::Media(err.to_string()))?;
+        }
+
+        let data = self.encoded_output(entry, target_format).await?;
+        Ok(byte_range(&data, offset, len))
+    }
+
+    // The exact size of the file `stream_track` serves. Converted output has to be encoded to
+    // be measured; the result stays in the range cache for the reads that follow.
+    pub async fn content_length(
+        &self,
+        entry: &TrackIndexEntry,
+        target_format: Option<TargetFormat>,
+    ) -> Result<u64> {
+        if self.is_raw(entry, target_format, &self.source_policy(entry).await?) {
+            return Ok(tokio::fs::metadata(&entry.source.path).await?.len());
+        }
+        Ok(self.encoded_output(entry, target_format).await?.len() as u64)
+    }
+
+    // The size to list before a file is opened: exact when it is passthrough or already
+    // encoded, otherwise an upper bound from the track's length, so listing never encodes.
+    pub async fn listed_length(
+        &self,
+        entry: &TrackIndexEntry,
+        target_format: Option<TargetFormat>,
+    ) -> Result<u64> {
+        if self.is_raw(entry, target_format, &self.source_policy(entry).await?) {
+            return Ok(tokio::fs::metadata(&entry.source.path).await?.len());
+        }
+        match self.encoded.get(&(entry.id.clone(), target_format)) {
+            Some(data) => Ok(data.len() as u64),
+            None => self.estimated_length(entry).await,
+        }
+    }
+
+    pub async fn listed_album_length(&self, tracks: &[&TrackIndexEntry]) -> Result<u64> {
+        if let Some(first) = tracks.first()
+            && let Some(data) = self.encoded.get(&album_key(&first.id.album))
+        {
+            return Ok(data.len() as u64);
+        }
+        let mut total = 0;
+        for entry in tracks {
+            total += self.estimated_length(entry).await?;
+        }
+        Ok(total)
+    }
+
+    // Tracks of unknown length are assumed no larger than their source.
+    async fn estimated_length(&self, entry: &TrackIndexEntry) -> Result<u64> {
+        let duration_ms = entry.metadata.duration_ms;
+        if duration_ms == 0 {
+            return Ok(tokio::fs::metadata(&entry.source.path).await?.len());
+        }
+        let rate = u64::from(self.policy.resample_to.unwrap_or(entry.source.sample_rate));
+        let samples = duration_ms * rate / 1000 * u64::from(entry.source.channels.max(1));
+        Ok(samples * ESTIMATE_BYTES_PER_SAMPLE + ESTIMATE_OVERHEAD_BYTES)
+    }
+
+    async fn encoded_output(
+        &self,
+        entry: &TrackIndexEntry,
+        target_format: Option<TargetFormat>,
+    ) -> Result<Bytes> {
+        self.cached_output((entry.id.clone(), target_format), async {
+            collect(self.stream_track(entry, target_format).await?)
+                .await
+                .map(Bytes::from)
+        })
+        .await
+    }
+
+    // Serves `key` from the range cache, running `encode` only on a miss.
+    async fn cached_output(
+        &self,
+        key: CacheKey,
+        encode: impl Future<Output = Result<Bytes>>,
+    ) -> Result<Bytes> {
+        if let Some(data) = self.encoded.get(&key) {
+            self.stats.record_cache_lookup(true);
+            return Ok(data);
+        }
+        // Concurrent opens of one track wait for a single encode instead of starting their
+        // own; whoever waited finds the result cached.
+        let flight = self.inflight.lock().entry(key.clone()).or_default().clone();
+        let _encoding = flight.lock().await;
+        let cached = self.encoded.get(&key);
+        self.stats.record_cache_lookup(cached.is_some());
+        if let Some(data) = cached {
+            return Ok(data);
+        }
+        let encoded = encode.await;
+        if let Ok(data) = &encoded {
+            self.encoded.insert(key.clone(), data.clone());
+        }
+        self.inflight.lock().remove(&key);
+        encoded
+    }
+
+    // Keeps the track's converted output cached until it is unpinned, converting it now if it
+    // is not cached yet. Passthrough tracks are served from the source and need nothing.
+    pub async fn pin_track(
+        &self,
+        entry: &TrackIndexEntry,
+        target_format: Option<TargetFormat>,
+    ) -> Result<()> {
+        self.encoded.pin(&entry.id).await?;
+        if !self.is_raw(entry, target_format, &self.source_policy(entry).await?) {
+            self.encoded_output(entry, target_format).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn unpin_track(&self, id: &TrackId) -> Result<()> {
+        self.encoded.unpin(id).await
+    }
+
+    // Converts the first `ahead` of `tracks` into the cache so playback starts without
+    // waiting. Tracks run one after another, so prewarming never holds more than one of the
+    // transcoder's permits. Returns how many were converted.
+    pub async fn prewarm_album(
+        &self,
+        tracks: &[&TrackIndexEntry],
+        target_format: Option<TargetFormat>,
+        ahead: usize,
+    ) -> Result<usize> {
+        let mut converted = 0;
+        for entry in tracks.iter().take(ahead) {
+            let key = (entry.id.clone(), target_format);
+            if self.encoded.get(&key).is_some()
+                || self.is_raw(entry, target_format, &self.source_policy(entry).await?)
+            {
+                continue;
+            }
+            self.encoded_output(entry, target_format).await?;
+            converted += 1;
+        }
+        Ok(converted)
+    }
+
+    // A single FLAC stream of an album's cue-split tracks, bit-identical to the audio of the
+    // image they were cut from.
+    pub async fn stream_album_gapless(&self, album: &TrackCollection) -> Result<Vec<AudioChunk>> {
+        self.transcoder.transcode_album(album).await
+    }
+
+    // The gapless stream, encoded once and then served from the range cache like a track.
+    // Disc 0 never names a real track, so it keys the album's stream.
+    pub async fn album_output(&self, album: &TrackCollection) -> Result<Bytes> {
+        self.cached_output(album_key(&album.album), async {
+            let chunks = self.stream_album_gapless(album).await?;
+            let mut data = Vec::new();
+            for chunk in &chunks {
+                data.extend_from_slice(&chunk.data);
+            }
+            Ok(Bytes::from(data))
+        })
+        .await
+    }
+
+    pub(crate) fn request(
+        &self,
+        entry: &TrackIndexEntry,
+        target_format: Option<TargetFormat>,
+        policy: AudioFormatPolicy,
+    ) -> TranscodeRequest {
+        TranscodeRequest {
             track: entry.source.clone(),
             policy,
-        };
-        let result = self.transcoder.transcode(&request).await?;
-        let mut buffer = Vec::new();
-        for chunk in result.chunks {
-            buffer.extend_from_slice(&chunk.data);
+            target_format,
+            resample_to: self.policy.resample_to,
+            target_bits: self.policy.target_bits,
         }
-        Ok(buffer)
     }
 
+    // Whether the served bytes are exactly the source file's. The default transcoder plans
+    // its output through the same call; a registered transcoder always converts.
+    pub(crate) fn is_raw(
+        &self,
+        entry: &TrackIndexEntry,
+        target_format: Option<TargetFormat>,
+        policy: &AudioFormatPolicy,
+    ) -> bool {
+        target_format.is_none_or(|format| self.registry.get(format).is_none())
+            && self
+                .request(entry, target_format, policy.clone())
+                .is_passthrough()
+    }
+
+    // Art is re-encoded as upright JPEG once; with a blob store the result is kept until the
+    // source file changes.
     pub async fn cover_image(&self, entry: &TrackIndexEntry) -> Result<Option<Vec<u8>>> {
-        self.cover.extract(&entry.source).await
+        let persisted = match &self.blobs {
+            Some(blobs) => source_mtime_ns(&entry.source.path)
+                .await
+                .map(|mtime| (blobs, mtime)),
+            None => None,
+        };
+        if let Some((blobs, mtime)) = persisted
+            && let Some(data) = blobs
+                .load_stamped_transcode(&entry.id, COVER_LABEL, mtime)
+                .await?
+        {
+            return Ok(Some(data));
+        }
+        let Some(cover) = self
+            .cover
+            .extract_normalized(&entry.source, COVER_MAX_DIM)
+            .await?
+        else {
+            return Ok(None);
+        };
+        if let Some((blobs, mtime)) = persisted {
+            blobs
+                .store_stamped_transcode(&entry.id, COVER_LABEL, &cover.data, mtime)
+                .await?;
+        }
+        Ok(Some(cover.data))
+    }
+}
+
+// The tracks a lookup is confined to: the whole index, one smart folder or one album.
+#[derive(Clone, Copy)]
+enum Scope<'a> {
+    All,
+    Smart(&'a SmartFolderConfig),
+    Album(&'a AlbumId),
+}
+
+impl Scope<'_> {
+    fn contains(&self, entry: &TrackIndexEntry) -> bool {
+        match self {
+            Scope::All => true,
+            Scope::Smart(folder) => folder.query.matches(entry),
+            Scope::Album(album) => &entry.id.album == *album,
+        }
     }
 }
 
+// Name, id and album lookups over the index, built on first use. A router's index never
+// changes, so they stay valid until a builder changes how names are derived.
+#[derive(Default)]
+struct Lookup {
+    albums: Vec<AlbumId>,
+    album_by_name: HashMap<String, usize>,
+    album_tracks: HashMap<AlbumId, Vec<usize>>,
+    by_name: HashMap<String, Vec<usize>>,
+    by_id: HashMap<TrackId, usize>,
+    smart_by_name: HashMap<String, usize>,
+    smart_members: HashMap<String, Vec<usize>>,
+}
+
 pub struct FileRouter {
     index: Arc<Vec<TrackIndexEntry>>,
+    lookup: OnceLock<Lookup>,
     media: Arc<MediaEngine>,
     tags: Arc<dyn TagOverlayService>,
+    sanitizer: Arc<dyn PathSanitizer>,
+    smart_folders: Vec<SmartFolderConfig>,
+    slow_ops: SlowOpThreshold,
+    capabilities: AdapterCapabilities,
+    prewarm_tracks: usize,
+    originals: Mutex<HashMap<TrackId, Arc<ReadAhead<FileChunkSource>>>>,
+    watcher: Option<Arc<FsLibraryScanner>>,
 }
 
 impl FileRouter {
@@ -69,7 +545,267 @@ impl FileRouter {
         media: Arc<MediaEngine>,
         tags: Arc<dyn TagOverlayService>,
     ) -> Self {
-        Self { index, media, tags }
+        Self {
+            index,
+            lookup: OnceLock::new(),
+            media,
+            tags,
+            sanitizer: MountPlatform::current().sanitizer(),
+            smart_folders: Vec::new(),
+            slow_ops: SlowOpThreshold::disabled(),
+            capabilities: AdapterCapabilities::ALL,
+            prewarm_tracks: 0,
+            originals: Mutex::new(HashMap::new()),
+            watcher: None,
+        }
+    }
+
+    pub fn stats(&self) -> &MountStats {
+        self.media.stats()
+    }
+
+    // Set from the mounting adapter; a read-only adapter makes tag edits unavailable.
+    pub fn with_capabilities(mut self, capabilities: AdapterCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    pub fn with_slow_op_threshold(mut self, threshold: SlowOpThreshold) -> Self {
+        self.slow_ops = threshold;
+        self
+    }
+
+    // How many tracks `prewarm_dir` converts ahead of playback.
+    pub fn with_prewarm(mut self, tracks: usize) -> Self {
+        self.prewarm_tracks = tracks;
+        self
+    }
+
+    // Keeps the scanner watching this router's sources alive for as long as it serves them.
+    pub fn with_watcher(mut self, watcher: Arc<FsLibraryScanner>) -> Self {
+        self.watcher = Some(watcher);
+        self
+    }
+
+    pub fn with_smart_folders(mut self, folders: Vec<SmartFolderConfig>) -> Self {
+        self.smart_folders = folders;
+        self.lookup = OnceLock::new();
+        self
+    }
+
+    pub fn with_sanitizer(mut self, sanitizer: Arc<dyn PathSanitizer>) -> Self {
+        self.sanitizer = sanitizer;
+        self.lookup = OnceLock::new();
+        self
+    }
+
+    fn lookup(&self) -> &Lookup {
+        self.lookup.get_or_init(|| {
+            let mut lookup = Lookup::default();
+            for (position, entry) in self.index.iter().enumerate() {
+                let album = &entry.id.album;
+                if !lookup.album_tracks.contains_key(album) {
+                    lookup
+                        .album_by_name
+                        .entry(self.album_dir_name(album))
+                        .or_insert(lookup.albums.len());
+                    lookup.albums.push(album.clone());
+                }
+                lookup
+                    .album_tracks
+                    .entry(album.clone())
+                    .or_default()
+                    .push(position);
+                lookup
+                    .by_name
+                    .entry(self.entry_name(&entry.id))
+                    .or_default()
+                    .push(position);
+                lookup.by_id.entry(entry.id.clone()).or_insert(position);
+            }
+            for tracks in lookup.album_tracks.values_mut() {
+                tracks.sort_by_key(|&position| {
+                    let id = &self.index[position].id;
+                    (id.disc, id.index)
+                });
+            }
+            for (position, folder) in self.smart_folders.iter().enumerate() {
+                let name = self.sanitizer.sanitize_component(&folder.name);
+                let members = self
+                    .index
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, entry)| folder.query.matches(entry))
+                    .map(|(member, _)| member)
+                    .collect();
+                if !lookup.smart_by_name.contains_key(&name) {
+                    lookup.smart_by_name.insert(name.clone(), position);
+                    lookup.smart_members.insert(name, members);
+                }
+            }
+            lookup
+        })
+    }
+
+    fn entry(&self, id: &TrackId) -> Option<&TrackIndexEntry> {
+        let position = *self.lookup().by_id.get(id)?;
+        Some(&self.index[position])
+    }
+
+    pub fn entry_name(&self, id: &TrackId) -> String {
+        self.sanitizer.sanitize_component(&id.to_string())
+    }
+
+    pub fn track_file_name(&self, id: &TrackId, format: TargetFormat) -> String {
+        format!("{}.{}", self.entry_name(id), format.extension())
+    }
+
+    // Extension an original is exposed under. Cue-split tracks have no file of their own, and
+    // sources already in a served format would collide with the converted name.
+    fn original_extension<'a>(&self, entry: &'a TrackIndexEntry) -> Option<&'a str> {
+        if !self.media.policy.expose_originals || entry.source.cue_path.is_some() {
+            return None;
+        }
+        let ext = entry.source.path.extension()?.to_str()?;
+        TargetFormat::from_extension(ext).is_none().then_some(ext)
+    }
+
+    // Tracks are listed in the policy's format, or as FLAC when originals sit next to them.
+    fn listed_format(&self) -> Option<TargetFormat> {
+        self.media
+            .policy
+            .expose_originals
+            .then_some(TargetFormat::Flac)
+    }
+
+    fn track_entries(&self, entry: &TrackIndexEntry) -> Vec<VirtualEntry> {
+        let mut entries = vec![VirtualEntry::TrackFile(
+            entry.id.clone(),
+            self.listed_format(),
+        )];
+        if self.original_extension(entry).is_some() {
+            entries.push(VirtualEntry::OriginalFile(entry.id.clone()));
+        }
+        if entry.metadata.lyrics.is_some()
+            || lyrics_sidecar(entry).is_some_and(|path| path.is_file())
+        {
+            entries.push(VirtualEntry::Lyrics(entry.id.clone()));
+        }
+        entries
+    }
+
+    pub fn album_dir_name(&self, album: &AlbumId) -> String {
+        self.sanitizer.sanitize_component(&album.0)
+    }
+
+    // Name a listed entry is shown under inside its directory.
+    pub fn virtual_name(&self, entry: &VirtualEntry) -> String {
+        match entry {
+            VirtualEntry::Directory(path) => path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            VirtualEntry::TrackFile(id, Some(format)) => self.track_file_name(id, *format),
+            VirtualEntry::TrackFile(id, None) => self.entry_name(id),
+            VirtualEntry::OriginalFile(id) => {
+                let ext = self
+                    .entry(id)
+                    .and_then(|entry| self.original_extension(entry));
+                match ext {
+                    Some(ext) => format!("{}.{ext}", self.entry_name(id)),
+                    None => self.entry_name(id),
+                }
+            }
+            VirtualEntry::CoverImage(_) => COVER_NAME.to_string(),
+            VirtualEntry::Lyrics(id) => format!("{}.lrc", self.entry_name(id)),
+            VirtualEntry::AlbumStream(album) => self.album_stream_name(album),
+            VirtualEntry::AlbumCue(album) => self.album_cue_name(album),
+        }
+    }
+
+    // Albums in index order, each once.
+    fn albums(&self) -> &[AlbumId] {
+        &self.lookup().albums
+    }
+
+    fn find_album(&self, name: &str) -> Option<&AlbumId> {
+        let lookup = self.lookup();
+        Some(&lookup.albums[*lookup.album_by_name.get(name)?])
+    }
+
+    // Tracks of one album in disc and track order; the first one supplies the folder cover.
+    fn album_tracks(&self, album: &AlbumId) -> Vec<&TrackIndexEntry> {
+        self.positions(self.lookup().album_tracks.get(album))
+    }
+
+    fn positions(&self, positions: Option<&Vec<usize>>) -> Vec<&TrackIndexEntry> {
+        positions
+            .into_iter()
+            .flatten()
+            .map(|&position| &self.index[position])
+            .collect()
+    }
+
+    // Only albums split from a single image can be joined back sample-exactly.
+    fn has_album_stream(&self, album: &AlbumId) -> bool {
+        let tracks = self.album_tracks(album);
+        tracks.first().is_some_and(|first| {
+            tracks.iter().all(|entry| {
+                entry.source.cue_path.is_some() && entry.source.path == first.source.path
+            })
+        })
+    }
+
+    pub fn album_stream_name(&self, album: &AlbumId) -> String {
+        format!("{}.flac", self.album_dir_name(album))
+    }
+
+    pub fn album_cue_name(&self, album: &AlbumId) -> String {
+        format!("{}.cue", self.album_dir_name(album))
+    }
+
+    // Cue sheet placed next to an album exposed as one concatenated stream.
+    pub fn album_cue(&self, album: &AlbumId) -> Option<String> {
+        if !self.has_album_stream(album) {
+            return None;
+        }
+        let tracks = self.album_tracks(album);
+        let stream = self.album_stream_name(album);
+        let title = tracks
+            .first()
+            .and_then(|first| first.metadata.tags.get_ignore_case("ALBUM"))
+            .map(|title| title.join_text(DEFAULT_MULTI_VALUE_SEPARATOR))
+            .unwrap_or_else(|| self.album_dir_name(album));
+        let sheet = CueWriter::sheet_for_stream(&tracks, Path::new(&stream), Some(&title));
+        Some(CueWriter.write_str(&sheet, Path::new("")))
+    }
+
+    fn find_by_name(&self, name: &str, scope: Scope<'_>) -> Option<&TrackIndexEntry> {
+        self.positions(self.lookup().by_name.get(name))
+            .into_iter()
+            .find(|entry| scope.contains(entry))
+    }
+
+    fn find_smart_folder(&self, name: &str) -> Option<&SmartFolderConfig> {
+        Some(&self.smart_folders[*self.lookup().smart_by_name.get(name)?])
+    }
+
+    // Members are matched once against the index, which a router never swaps out.
+    fn smart_members(&self, name: &str) -> Option<Vec<&TrackIndexEntry>> {
+        let members = self.lookup().smart_members.get(name)?;
+        Some(self.positions(Some(members)))
+    }
+
+    fn smart_path<'a>(&self, path: &'a str) -> Option<&'a str> {
+        if self.smart_folders.is_empty() {
+            return None;
+        }
+        let rest = path.strip_prefix(SMART_ROOT)?;
+        if rest.is_empty() {
+            Some(rest)
+        } else {
+            rest.strip_prefix('/')
+        }
     }
 
     pub fn resolve(&self, path: &str) -> Option<VirtualEntry> {
@@ -78,38 +814,1677 @@ impl FileRouter {
             return Some(VirtualEntry::Directory(PathBuf::from("/")));
         }
 
-        let candidate = path.strip_suffix(".flac").unwrap_or(path);
+        let Some(rest) = self.smart_path(path) else {
+            return self.resolve_album_path(path);
+        };
+        match rest.split_once('/') {
+            None if rest.is_empty() => Some(VirtualEntry::Directory(PathBuf::from(path))),
+            None => self
+                .find_smart_folder(rest)
+                .map(|_| VirtualEntry::Directory(PathBuf::from(path))),
+            Some((folder, name)) => {
+                self.resolve_track(name, Scope::Smart(self.find_smart_folder(folder)?))
+            }
+        }
+    }
 
-        self.index
-            .iter()
-            .find(|entry| entry.id.to_string() == candidate)
-            .map(|entry| VirtualEntry::TrackFile(entry.id.clone()))
+    // `<album>/<track>` and `<album>/cover.jpg`; flat track names at the root still resolve
+    // so existing links keep working.
+    fn resolve_album_path(&self, path: &str) -> Option<VirtualEntry> {
+        let Some((album_name, name)) = path.split_once('/') else {
+            if let Some(album) = self.find_album(path) {
+                return Some(VirtualEntry::Directory(PathBuf::from(
+                    self.album_dir_name(album),
+                )));
+            }
+            return self.resolve_track(path, Scope::All);
+        };
+        let album = self.find_album(album_name)?;
+        if self.has_album_stream(album) {
+            if name == self.album_stream_name(album) {
+                return Some(VirtualEntry::AlbumStream(album.clone()));
+            }
+            if name == self.album_cue_name(album) {
+                return Some(VirtualEntry::AlbumCue(album.clone()));
+            }
+        }
+        if name == COVER_NAME || name == FOLDER_COVER_NAME {
+            return self
+                .album_tracks(album)
+                .first()
+                .map(|entry| VirtualEntry::CoverImage(entry.id.clone()));
+        }
+        self.resolve_track(name, Scope::Album(album))
+    }
+
+    fn resolve_track(&self, path: &str, scope: Scope<'_>) -> Option<VirtualEntry> {
+        if path.contains('/') {
+            return None;
+        }
+        if let Some(candidate) = path.strip_suffix(".lrc") {
+            return self
+                .find_by_name(candidate, scope)
+                .map(|entry| VirtualEntry::Lyrics(entry.id.clone()));
+        }
+        if let Some(candidate) = path.strip_suffix(".jpg")
+            && let Some(entry) = self.find_by_name(candidate, scope)
+        {
+            return Some(VirtualEntry::CoverImage(entry.id.clone()));
+        }
+
+        if let Some((stem, ext)) = path.rsplit_once('.')
+            && let Some(entry) = self.find_by_name(stem, scope)
+            && self
+                .original_extension(entry)
+                .is_some_and(|original| original.eq_ignore_ascii_case(ext))
+        {
+            return Some(VirtualEntry::OriginalFile(entry.id.clone()));
+        }
+
+        let (candidate, target_format) = match path.rsplit_once('.') {
+            Some((stem, ext)) => match TargetFormat::from_extension(ext) {
+                Some(format) => (stem, Some(format)),
+                None => (path, None),
+            },
+            None => (path, None),
+        };
+
+        self.find_by_name(candidate, scope)
+            .map(|entry| VirtualEntry::TrackFile(entry.id.clone(), target_format))
+    }
+
+    // The root holds one folder per album (plus the smart folder root); an album folder
+    // holds its tracks and a cover. `None` when `path` is not a directory.
+    pub fn list_dir(&self, path: &str) -> Option<Vec<VirtualEntry>> {
+        self.slow_ops
+            .time("read_dir", path, || self.collect_dir(path))
+    }
+
+    pub fn list_dir_names(&self, path: &str) -> Option<Vec<String>> {
+        self.list_dir(path).map(|entries| {
+            entries
+                .iter()
+                .map(|entry| self.virtual_name(entry))
+                .collect()
+        })
+    }
+
+    fn collect_dir(&self, path: &str) -> Option<Vec<VirtualEntry>> {
+        let path = path.trim_matches('/');
+        if path.is_empty() {
+            let mut entries: Vec<VirtualEntry> = self
+                .albums()
+                .iter()
+                .map(|album| VirtualEntry::Directory(PathBuf::from(self.album_dir_name(album))))
+                .collect();
+            if !self.smart_folders.is_empty() {
+                entries.push(VirtualEntry::Directory(PathBuf::from(SMART_ROOT)));
+            }
+            return Some(entries);
+        }
+
+        let Some(rest) = self.smart_path(path) else {
+            let album = self.find_album(path)?;
+            let tracks = self.album_tracks(album);
+            let mut entries: Vec<VirtualEntry> = tracks
+                .iter()
+                .flat_map(|entry| self.track_entries(entry))
+                .collect();
+            entries.extend(
+                tracks
+                    .first()
+                    .map(|entry| VirtualEntry::CoverImage(entry.id.clone())),
+            );
+            if self.has_album_stream(album) {
+                entries.push(VirtualEntry::AlbumStream(album.clone()));
+                entries.push(VirtualEntry::AlbumCue(album.clone()));
+            }
+            return Some(entries);
+        };
+        if rest.is_empty() {
+            return Some(
+                self.smart_folders
+                    .iter()
+                    .map(|folder| {
+                        VirtualEntry::Directory(
+                            Path::new(SMART_ROOT)
+                                .join(self.sanitizer.sanitize_component(&folder.name)),
+                        )
+                    })
+                    .collect(),
+            );
+        }
+        Some(
+            self.smart_members(rest)?
+                .into_iter()
+                .flat_map(|entry| self.track_entries(entry))
+                .collect(),
+        )
+    }
+
+    // Called when an album folder is opened: converts its first tracks in the format they
+    // are listed in, so playback starts without waiting. Other paths are left alone.
+    pub async fn prewarm_dir(&self, path: &str) -> Result<usize> {
+        if self.prewarm_tracks == 0 {
+            return Ok(0);
+        }
+        let Some(album) = self.find_album(path.trim_matches('/')) else {
+            return Ok(0);
+        };
+        let tracks = self.album_tracks(album);
+        self.media
+            .prewarm_album(&tracks, self.listed_format(), self.prewarm_tracks)
+            .await
+    }
+
+    fn album_collection(&self, album: &AlbumId) -> Result<TrackCollection> {
+        if !self.has_album_stream(album) {
+            return Err(MusFuseError::Mount(
+                "album has no single-stream image".into(),
+            ));
+        }
+        Ok(TrackCollection {
+            album: album.clone(),
+            tracks: self
+                .album_tracks(album)
+                .into_iter()
+                .map(|entry| entry.source.clone())
+                .collect(),
+        })
+    }
+
+    async fn album_output(&self, album: &AlbumId) -> Result<Bytes> {
+        let collection = self.album_collection(album)?;
+        self.slow_ops
+            .time_async("read_file", &album.0, self.media.album_output(&collection))
+            .await
+    }
+
+    // The album's cue-split tracks joined back into one FLAC stream; see `album_cue`.
+    pub async fn read_album_stream(&self, album: &AlbumId) -> Result<Vec<u8>> {
+        let data = self.album_output(album).await?;
+        self.stats().record_read(data.len());
+        Ok(data.to_vec())
+    }
+
+    pub async fn read_album_range(
+        &self,
+        album: &AlbumId,
+        offset: u64,
+        len: usize,
+    ) -> Result<Vec<u8>> {
+        let data = byte_range(&self.album_output(album).await?, offset, len);
+        self.stats().record_bytes(data.len());
+        Ok(data)
+    }
+
+    pub async fn album_stream_length(&self, album: &AlbumId) -> Result<u64> {
+        Ok(self.album_output(album).await?.len() as u64)
+    }
+
+    // See `MediaEngine::listed_length`.
+    pub async fn listed_album_stream_length(&self, album: &AlbumId) -> Result<u64> {
+        self.media
+            .listed_album_length(&self.album_tracks(album))
+            .await
     }
 
     pub async fn read_track(&self, id: &TrackId) -> Result<Vec<u8>> {
+        self.read_track_as(id, None).await
+    }
+
+    pub async fn read_track_as(
+        &self,
+        id: &TrackId,
+        target_format: Option<TargetFormat>,
+    ) -> Result<Vec<u8>> {
         let entry = self
-            .index
-            .iter()
-            .find(|entry| &entry.id == id)
+            .entry(id)
             .ok_or_else(|| crate::error::MusFuseError::Mount("track not found".into()))?;
-        self.media.stream_track(entry).await
+        let data = self
+            .slow_ops
+            .time_async("read_file", id, async {
+                collect(self.media.stream_track(entry, target_format).await?).await
+            })
+            .await?;
+        self.stats().record_read(data.len());
+        Ok(data)
+    }
+
+    pub async fn read_track_range(
+        &self,
+        id: &TrackId,
+        target_format: Option<TargetFormat>,
+        offset: u64,
+        len: usize,
+    ) -> Result<Vec<u8>> {
+        let entry = self
+            .entry(id)
+            .ok_or_else(|| MusFuseError::Mount("track not found".into()))?;
+        let data = self
+            .slow_ops
+            .time_async(
+                "read_file",
+                id,
+                self.media.read_range(entry, target_format, offset, len),
+            )
+            .await?;
+        self.stats().record_bytes(data.len());
+        Ok(data)
+    }
+
+    pub async fn content_length(
+        &self,
+        id: &TrackId,
+        target_format: Option<TargetFormat>,
+    ) -> Result<u64> {
+        let entry = self
+            .entry(id)
+            .ok_or_else(|| MusFuseError::Mount("track not found".into()))?;
+        self.media.content_length(entry, target_format).await
+    }
+
+    // See `MediaEngine::listed_length`.
+    pub async fn listed_length(
+        &self,
+        id: &TrackId,
+        target_format: Option<TargetFormat>,
+    ) -> Result<u64> {
+        let entry = self
+            .entry(id)
+            .ok_or_else(|| MusFuseError::Mount("track not found".into()))?;
+        self.media.listed_length(entry, target_format).await
+    }
+
+    fn original_entry(&self, id: &TrackId) -> Result<&TrackIndexEntry> {
+        let entry = self
+            .entry(id)
+            .ok_or_else(|| MusFuseError::Mount("track not found".into()))?;
+        if self.original_extension(entry).is_none() {
+            return Err(MusFuseError::Mount("original file is not exposed".into()));
+        }
+        Ok(entry)
+    }
+
+    pub async fn read_original(&self, id: &TrackId) -> Result<Vec<u8>> {
+        let entry = self.original_entry(id)?;
+        self.slow_ops
+            .time_async("read_file", id, async {
+                Ok(tokio::fs::read(&entry.source.path).await?)
+            })
+            .await
+    }
+
+    pub async fn original_length(&self, id: &TrackId) -> Result<u64> {
+        let entry = self.original_entry(id)?;
+        Ok(tokio::fs::metadata(&entry.source.path).await?.len())
+    }
+
+    pub async fn read_original_range(
+        &self,
+        id: &TrackId,
+        offset: u64,
+        len: usize,
+    ) -> Result<Vec<u8>> {
+        let entry = self.original_entry(id)?;
+        let reader = {
+            let mut originals = self.originals.lock();
+            if !originals.contains_key(id) && originals.len() >= ORIGINAL_STREAMS {
+                originals.clear();
+            }
+            originals
+                .entry(id.clone())
+                .or_insert_with(|| {
+                    Arc::new(ReadAhead::new(
+                        Arc::new(FileChunkSource::new(
+                            &entry.source.path,
+                            ORIGINAL_CHUNK_BYTES,
+                        )),
+                        ORIGINAL_READ_AHEAD,
+                    ))
+                })
+                .clone()
+        };
+        let end = offset.saturating_add(len as u64);
+        let first = offset / ORIGINAL_CHUNK_BYTES as u64;
+        let mut data = Vec::with_capacity(len);
+        let read = async {
+            let mut index = first as usize;
+            while ((index * ORIGINAL_CHUNK_BYTES) as u64) < end {
+                let Some(chunk) = reader.read(index).await? else {
+                    break;
+                };
+                data.extend_from_slice(&chunk);
+                index += 1;
+            }
+            Ok::<_, MusFuseError>(())
+        };
+        self.slow_ops.time_async("read_file", id, read).await?;
+        let data = byte_range(&data, offset - first * ORIGINAL_CHUNK_BYTES as u64, len);
+        self.stats().record_bytes(data.len());
+        Ok(data)
+    }
+
+    // `Ok(None)` when the track has neither embedded nor external art, so the adapter can
+    // answer "not found" for the cover file.
+    pub async fn read_cover(&self, id: &TrackId) -> Result<Option<Vec<u8>>> {
+        let entry = self
+            .entry(id)
+            .ok_or_else(|| MusFuseError::Mount("track not found".into()))?;
+        self.slow_ops
+            .time_async("read_file", id, self.media.cover_image(entry))
+            .await
     }
 
     pub async fn read_tags(&self, id: &TrackId) -> Result<TrackMetadata> {
         let entry = self
-            .index
-            .iter()
-            .find(|entry| &entry.id == id)
+            .entry(id)
             .ok_or_else(|| crate::error::MusFuseError::Mount("track not found".into()))?;
         self.tags.read(id, &entry.source.path).await
     }
 
     pub async fn write_tags(&self, id: &TrackId, delta: &TagDelta) -> Result<TrackMetadata> {
+        if !self.capabilities.writes {
+            return Err(MusFuseError::Unsupported("tag writes on a read-only mount"));
+        }
         let entry = self
-            .index
-            .iter()
-            .find(|entry| &entry.id == id)
+            .entry(id)
             .ok_or_else(|| crate::error::MusFuseError::Mount("track not found".into()))?;
         self.tags.apply(id, &entry.source.path, delta).await
     }
+
+    pub async fn etag_for_track(&self, id: &TrackId) -> Result<String> {
+        self.etag_for_track_as(id, None).await
+    }
+
+    // Strong validator for a virtual track file: derived from everything the served bytes
+    // depend on (source file identity and mtime, the whole transcode request down to the
+    // resample and bit-depth targets, and the effective tags after overlay deltas), so it
+    // only changes when the output would.
+    pub async fn etag_for_track_as(
+        &self,
+        id: &TrackId,
+        target_format: Option<TargetFormat>,
+    ) -> Result<String> {
+        let entry = self
+            .entry(id)
+            .ok_or_else(|| MusFuseError::Mount("track not found".into()))?;
+        let source = tokio::fs::metadata(&entry.source.path).await?;
+        let modified = source
+            .modified()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        let metadata = self.tags.read(id, &entry.source.path).await?;
+        let tags =
+            serde_json::to_vec(&metadata).map_err(|err| MusFuseError::Kv(err.to_string()))?;
+
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(entry.source.path.to_string_lossy().as_bytes());
+        hasher.update(&source.len().to_le_bytes());
+        hasher.update(&modified.as_nanos().to_le_bytes());
+        let policy = self.media.source_policy(entry).await?;
+        let request = self.media.request(entry, target_format, policy);
+        hasher.update(format!("{request:?}").as_bytes());
+        hasher.update(&[u8::from(self.media.is_raw(
+            entry,
+            target_format,
+            &request.policy,
+        ))]);
+        hasher.update(&tags);
+        Ok(hasher.finalize().to_hex()[..32].to_string())
+    }
+
+    // Embedded lyrics win over a `.lrc` file next to the track.
+    pub async fn read_lyrics(&self, id: &TrackId) -> Result<Option<String>> {
+        let metadata = self.read_tags(id).await?;
+        if let Some(lyrics) = metadata.lyrics {
+            return Ok(Some(lyrics.to_lrc()));
+        }
+        let Some(sidecar) = self.entry(id).and_then(lyrics_sidecar) else {
+            return Ok(None);
+        };
+        match tokio::fs::read_to_string(&sidecar).await {
+            Ok(text) => Ok(Lyrics::parse(&text).map(|lyrics| lyrics.to_lrc())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+// Only whole-file tracks can have a sidecar; a cue image's `.lrc` would cover every track.
+fn lyrics_sidecar(entry: &TrackIndexEntry) -> Option<PathBuf> {
+    entry
+        .source
+        .cue_path
+        .is_none()
+        .then(|| entry.source.path.with_extension("lrc"))
+}
+
+fn album_key(album: &AlbumId) -> CacheKey {
+    (
+        TrackId {
+            album: album.clone(),
+            disc: 0,
+            index: 0,
+        },
+        Some(TargetFormat::Flac),
+    )
+}
+
+// Only the first conversion's duration is recorded; output without a length header (MP3)
+// records none.
+// What `stream_track` keeps of converted output while it passes: enough to read its length
+// back, and the persisted copy as it is written.
+struct OutputRecorder {
+    id: TrackId,
+    durations: Option<Arc<DurationCorrections>>,
+    trail: LengthTrail,
+    stored: Option<BlobWriter>,
+}
+
+impl OutputRecorder {
+    async fn record(&mut self, data: &[u8]) -> Result<()> {
+        self.trail.push(data);
+        if let Some(stored) = &mut self.stored {
+            stored.write(data).await?;
+        }
+        Ok(())
+    }
+
+    async fn finish(&mut self) -> Result<()> {
+        if let Some(durations) = &self.durations
+            && durations.lookup(&self.id).await?.is_none()
+            && let Some(length) = self.trail.frames()?
+        {
+            durations.record(&self.id, length.duration_ms()).await?;
+        }
+        match self.stored.take() {
+            Some(stored) => stored.commit().await,
+            None => Ok(()),
+        }
+    }
+
+    // Output that never reached its end is not persisted.
+    async fn abandon(&mut self) {
+        if let Some(stored) = self.stored.take()
+            && let Err(err) = stored.discard().await
+        {
+            warn!(track = %self.id, error = %err, "failed to remove a partial transcode");
+        }
+    }
+}
+
+fn byte_range(data: &[u8], offset: u64, len: usize) -> Vec<u8> {
+    let start = usize::try_from(offset)
+        .unwrap_or(usize::MAX)
+        .min(data.len());
+    let end = start.saturating_add(len).min(data.len());
+    data[start..end].to_vec()
+}
+
+// Drains a chunk stream into one buffer.
+pub async fn collect(mut chunks: ChunkReceiver) -> Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    while let Some(chunk) = chunks.recv().await {
+        buffer.extend_from_slice(&chunk?.data);
+    }
+    Ok(buffer)
+}
+
+// Requests without an explicit target are served in the policy's format.
+// Resampled or requantized output is stored apart from output in the source format.
+fn cache_label(target_format: Option<TargetFormat>, policy: &PolicyConfig) -> String {
+    let mut label = target_format
+        .map_or("policy", |format| format.extension())
+        .to_string();
+    if let Some(rate) = policy.resample_to {
+        label.push_str(&format!("@{rate}"));
+    }
+    if let Some(bits) = policy.target_bits {
+        label.push_str(&format!("-{bits}bit"));
+    }
+    label
+}
+
+// A source whose mtime cannot be read is never served from the persisted cache.
+async fn source_mtime_ns(path: &Path) -> Option<u64> {
+    let modified = tokio::fs::metadata(path).await.ok()?.modified().ok()?;
+    let since_epoch = modified.duration_since(std::time::UNIX_EPOCH).ok()?;
+    u64::try_from(since_epoch.as_nanos()).ok()
+}
+
+fn read_file_range(path: &Path, offset: u64, len: usize) -> Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut buffer = Vec::with_capacity(len);
+    file.take(len as u64).read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LosslessStrategy;
+    use crate::fixtures;
+    use crate::media::{DefaultCoverExtractor, DefaultFormatTranscoder};
+    use crate::metadata::{AlbumId, TagValue};
+    use crate::query::TagQuery;
+    use crate::track::SourceTrack;
+    use async_trait::async_trait;
+    use mockall::mock;
+    use std::path::Path;
+
+    mock! {
+        pub Reader {}
+
+        #[async_trait]
+        impl AudioReader for Reader {
+            async fn read(&self, track: &SourceTrack) -> Result<Vec<AudioChunk>>;
+        }
+    }
+
+    mock! {
+        pub Transcoder {}
+
+        #[async_trait]
+        impl FormatTranscoder for Transcoder {
+            async fn transcode(&self, request: &TranscodeRequest) -> Result<crate::media::TranscodeResult>;
+        }
+    }
+
+    mock! {
+        pub Tags {}
+
+        #[async_trait]
+        impl TagOverlayService for Tags {
+            async fn read(&self, track: &TrackId, source: &Path) -> Result<TrackMetadata>;
+            async fn apply(
+                &self,
+                track: &TrackId,
+                source: &Path,
+                delta: &TagDelta,
+            ) -> Result<TrackMetadata>;
+            async fn remove(&self, track: &TrackId) -> Result<()>;
+        }
+    }
+
+    fn rated_entry(index: u32, rating: i64) -> TrackIndexEntry {
+        let mut entry = fixtures::entry("album", index, format!("{index:02}.flac"));
+        entry
+            .metadata
+            .tags
+            .insert("RATING", TagValue::Number(rating));
+        entry
+    }
+
+    fn router(folders: Vec<SmartFolderConfig>) -> FileRouter {
+        let media = MediaEngine::new(
+            Arc::new(MockReader::new()),
+            Arc::new(DefaultFormatTranscoder::new()),
+            Arc::new(DefaultCoverExtractor::new()),
+            PolicyConfig::default(),
+        );
+        let index = vec![rated_entry(1, 5), rated_entry(2, 3)];
+        FileRouter::new(Arc::new(index), Arc::new(media), Arc::new(MockTags::new()))
+            .with_smart_folders(folders)
+    }
+
+    #[tokio::test]
+    async fn etag_is_stable_until_the_tags_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut entry = rated_entry(1, 5);
+        entry.source.path = dir.path().join("01.flac");
+        std::fs::write(&entry.source.path, b"fLaC").unwrap();
+        let id = entry.id.clone();
+
+        let current = Arc::new(parking_lot::Mutex::new(entry.metadata.clone()));
+        let served = current.clone();
+        let mut tags = MockTags::new();
+        tags.expect_read()
+            .returning(move |_, _| Ok(served.lock().clone()));
+        let router = FileRouter::new(
+            Arc::new(vec![entry]),
+            router(Vec::new()).media.clone(),
+            Arc::new(tags),
+        );
+
+        let first = router.etag_for_track(&id).await.unwrap();
+        assert_eq!(router.etag_for_track(&id).await.unwrap(), first);
+        let mp3 = router
+            .etag_for_track_as(&id, Some(TargetFormat::Mp3))
+            .await
+            .unwrap();
+        assert_ne!(mp3, first);
+
+        current.lock().tags.insert("RATING", TagValue::Number(4));
+        let edited = router.etag_for_track(&id).await.unwrap();
+        assert_ne!(edited, first);
+        assert_eq!(router.etag_for_track(&id).await.unwrap(), edited);
+    }
+
+    #[tokio::test]
+    async fn etag_changes_with_the_conversion_targets() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut entry = rated_entry(1, 5);
+        entry.source.path = dir.path().join("01.flac");
+        std::fs::write(&entry.source.path, b"fLaC").unwrap();
+        let id = entry.id.clone();
+
+        let etag = async |resample_to, target_bits| {
+            let metadata = entry.metadata.clone();
+            let mut tags = MockTags::new();
+            tags.expect_read()
+                .returning(move |_, _| Ok(metadata.clone()));
+            let media = MediaEngine::new(
+                Arc::new(MockReader::new()),
+                Arc::new(DefaultFormatTranscoder::new()),
+                Arc::new(DefaultCoverExtractor::new()),
+                PolicyConfig {
+                    lossless_strategy: LosslessStrategy::ConvertToFlac,
+                    resample_to,
+                    target_bits,
+                    ..Default::default()
+                },
+            );
+            FileRouter::new(
+                Arc::new(vec![entry.clone()]),
+                Arc::new(media),
+                Arc::new(tags),
+            )
+            .etag_for_track(&id)
+            .await
+            .unwrap()
+        };
+
+        let plain = etag(None, None).await;
+        assert_eq!(etag(None, None).await, plain);
+        let resampled = etag(Some(48_000), None).await;
+        assert_ne!(resampled, plain);
+        let requantized = etag(None, Some(16)).await;
+        assert_ne!(requantized, plain);
+        assert_ne!(requantized, resampled);
+    }
+
+    #[tokio::test]
+    async fn router_reads_are_counted_in_the_mount_stats() {
+        let mut transcoder = MockTranscoder::new();
+        transcoder.expect_transcode().returning(|request| {
+            Ok(crate::media::TranscodeResult {
+                track_id: request.track.id.clone(),
+                format: "flac",
+                chunks: vec![AudioChunk {
+                    data: bytes::Bytes::from_static(b"fLaC"),
+                    timestamp_ms: 0,
+                    is_end: true,
+                }],
+                artwork: None,
+                duration_ms: None,
+            })
+        });
+        let mut policy = router(Vec::new()).media.policy.clone();
+        policy.lossless_strategy = LosslessStrategy::ConvertToFlac;
+        let stats = MountStats::new();
+        let media = MediaEngine::new(
+            Arc::new(MockReader::new()),
+            Arc::new(transcoder),
+            Arc::new(DefaultCoverExtractor::new()),
+            policy,
+        )
+        .with_stats(stats.clone());
+        let first = rated_entry(1, 5);
+        let second = rated_entry(2, 3);
+        let router = FileRouter::new(
+            Arc::new(vec![first.clone(), second.clone()]),
+            Arc::new(media),
+            Arc::new(MockTags::new()),
+        );
+
+        router.read_track(&first.id).await.unwrap();
+        router.read_track(&second.id).await.unwrap();
+        assert_eq!(stats.track_reads(), 2);
+        assert_eq!(stats.bytes_read(), 8);
+        assert_eq!(stats.transcodes(), 2);
+
+        router
+            .read_track_range(&first.id, None, 1, 2)
+            .await
+            .unwrap();
+        router
+            .read_track_range(&first.id, None, 3, 2)
+            .await
+            .unwrap();
+        // Ranges of an already open track add bytes, not reads.
+        assert_eq!(stats.track_reads(), 2);
+        assert_eq!(stats.bytes_read(), 11);
+        assert_eq!(stats.transcodes(), 3);
+        assert_eq!((stats.cache_hits(), stats.cache_misses()), (1, 1));
+        router.stats().record_track_open();
+        assert_eq!(router.stats().track_reads(), 3);
+    }
+
+    #[tokio::test]
+    async fn originals_are_exposed_next_to_converted_tracks() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut entry = rated_entry(1, 5);
+        entry.source.path = dir.path().join("01.ape");
+        std::fs::write(&entry.source.path, b"MAC \x96\x0f").unwrap();
+        let id = entry.id.clone();
+
+        let mut transcoder = MockTranscoder::new();
+        transcoder.expect_transcode().returning(|request| {
+            Ok(crate::media::TranscodeResult {
+                track_id: request.track.id.clone(),
+                format: "flac",
+                chunks: vec![AudioChunk {
+                    data: bytes::Bytes::from_static(b"fLaC"),
+                    timestamp_ms: 0,
+                    is_end: true,
+                }],
+                artwork: None,
+                duration_ms: None,
+            })
+        });
+        let mut policy = router(Vec::new()).media.policy.clone();
+        policy.lossless_strategy = LosslessStrategy::ConvertToFlac;
+        policy.expose_originals = true;
+        let media = MediaEngine::new(
+            Arc::new(MockReader::new()),
+            Arc::new(transcoder),
+            Arc::new(DefaultCoverExtractor::new()),
+            policy,
+        );
+        let router = FileRouter::new(
+            Arc::new(vec![entry]),
+            Arc::new(media),
+            Arc::new(MockTags::new()),
+        );
+
+        let name = router.entry_name(&id);
+        let converted = format!("{name}.flac");
+        let original = format!("{name}.ape");
+        assert_eq!(
+            router.list_dir_names("/album").unwrap(),
+            vec![converted.clone(), original.clone(), "cover.jpg".to_string()]
+        );
+        assert_eq!(
+            router.resolve(&converted),
+            Some(VirtualEntry::TrackFile(
+                id.clone(),
+                Some(TargetFormat::Flac)
+            ))
+        );
+        assert_eq!(
+            router.resolve(&original),
+            Some(VirtualEntry::OriginalFile(id.clone()))
+        );
+        assert_eq!(
+            router
+                .read_track_as(&id, Some(TargetFormat::Flac))
+                .await
+                .unwrap(),
+            b"fLaC"
+        );
+        assert_eq!(router.read_original(&id).await.unwrap(), b"MAC \x96\x0f");
+    }
+
+    #[tokio::test]
+    async fn originals_are_read_in_ranges_across_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut entry = rated_entry(1, 5);
+        entry.source.path = dir.path().join("01.ape");
+        let bytes: Vec<u8> = (0..=255u8)
+            .cycle()
+            .take(ORIGINAL_CHUNK_BYTES * 2 + 100)
+            .collect();
+        std::fs::write(&entry.source.path, &bytes).unwrap();
+        let id = entry.id.clone();
+        let mut policy = router(Vec::new()).media.policy.clone();
+        policy.expose_originals = true;
+        let media = MediaEngine::new(
+            Arc::new(MockReader::new()),
+            Arc::new(MockTranscoder::new()),
+            Arc::new(DefaultCoverExtractor::new()),
+            policy,
+        );
+        let router = FileRouter::new(
+            Arc::new(vec![entry]),
+            Arc::new(media),
+            Arc::new(MockTags::new()),
+        );
+
+        assert_eq!(
+            router.original_length(&id).await.unwrap(),
+            bytes.len() as u64
+        );
+        let mut served = Vec::new();
+        let mut offset = 0;
+        loop {
+            let chunk = router
+                .read_original_range(&id, offset, 100_000)
+                .await
+                .unwrap();
+            if chunk.is_empty() {
+                break;
+            }
+            offset += chunk.len() as u64;
+            served.extend(chunk);
+        }
+        assert_eq!(served, bytes);
+        let straddling = ORIGINAL_CHUNK_BYTES as u64 - 10;
+        assert_eq!(
+            router
+                .read_original_range(&id, straddling, 20)
+                .await
+                .unwrap(),
+            bytes[straddling as usize..straddling as usize + 20]
+        );
+        assert_eq!(router.stats().bytes_read(), bytes.len() as u64 + 20);
+    }
+
+    #[tokio::test]
+    async fn ranged_reads_of_passthrough_files_come_from_the_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut entry = rated_entry(1, 5);
+        entry.source.path = dir.path().join("01.wav");
+        let bytes: Vec<u8> = (0..=255u8).cycle().take(4_096).collect();
+        std::fs::write(&entry.source.path, &bytes).unwrap();
+        let id = entry.id.clone();
+
+        // Any call into the transcoder would panic on the missing expectation.
+        let media = MediaEngine::new(
+            Arc::new(MockReader::new()),
+            Arc::new(MockTranscoder::new()),
+            Arc::new(DefaultCoverExtractor::new()),
+            router(Vec::new()).media.policy.clone(),
+        );
+        let router = FileRouter::new(
+            Arc::new(vec![entry]),
+            Arc::new(media),
+            Arc::new(MockTags::new()),
+        );
+
+        let middle = router
+            .read_track_range(&id, None, 1_000, 500)
+            .await
+            .unwrap();
+        assert_eq!(middle, &bytes[1_000..1_500]);
+        let tail = router
+            .read_track_range(&id, None, 4_000, 500)
+            .await
+            .unwrap();
+        assert_eq!(tail, &bytes[4_000..]);
+    }
+
+    #[tokio::test]
+    async fn passthrough_policies_ignore_conversion_targets_like_the_transcoder() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut entry = rated_entry(1, 5);
+        entry.source.path = dir.path().join("01.wav");
+        entry.source.sample_rate = 44_100;
+        std::fs::write(&entry.source.path, vec![7u8; 2_048]).unwrap();
+
+        let mut policy = router(Vec::new()).media.policy.clone();
+        policy.resample_to = Some(48_000);
+        policy.target_bits = Some(16);
+        let request = TranscodeRequest {
+            track: entry.source.clone(),
+            policy: AudioFormatPolicy::PassthroughLossless,
+            target_format: None,
+            resample_to: policy.resample_to,
+            target_bits: policy.target_bits,
+        };
+        assert!(request.is_passthrough());
+
+        // Any call into the transcoder would panic on the missing expectation.
+        let media = MediaEngine::new(
+            Arc::new(MockReader::new()),
+            Arc::new(MockTranscoder::new()),
+            Arc::new(DefaultCoverExtractor::new()),
+            policy,
+        );
+        assert_eq!(media.content_length(&entry, None).await.unwrap(), 2_048);
+        assert_eq!(
+            media.read_range(&entry, None, 2_000, 100).await.unwrap(),
+            vec![7u8; 48]
+        );
+    }
+
+    #[tokio::test]
+    async fn persisted_transcodes_follow_the_source_mtime() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut entry = rated_entry(1, 5);
+        entry.source.path = dir.path().join("01.wav");
+        std::fs::write(&entry.source.path, b"RIFF").unwrap();
+
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = calls.clone();
+        let mut transcoder = MockTranscoder::new();
+        transcoder.expect_transcode().returning(move |request| {
+            let call = counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(crate::media::TranscodeResult {
+                track_id: request.track.id.clone(),
+                format: "flac",
+                chunks: vec![AudioChunk {
+                    data: bytes::Bytes::from(format!("fLaC{call}")),
+                    timestamp_ms: 0,
+                    is_end: true,
+                }],
+                artwork: None,
+                duration_ms: None,
+            })
+        });
+        let blobs = BlobStore::new(Arc::new(crate::kv::MemoryBackend::new()), None);
+        let media = MediaEngine::new(
+            Arc::new(MockReader::new()),
+            Arc::new(transcoder),
+            Arc::new(DefaultCoverExtractor::new()),
+            router(Vec::new()).media.policy.clone(),
+        )
+        .with_blob_cache(Arc::new(blobs));
+        let flac = Some(TargetFormat::Flac);
+        let calls = || calls.load(std::sync::atomic::Ordering::SeqCst);
+
+        assert_eq!(
+            collect(media.stream_track(&entry, flac).await.unwrap())
+                .await
+                .unwrap(),
+            b"fLaC0"
+        );
+        assert_eq!(
+            collect(media.stream_track(&entry, flac).await.unwrap())
+                .await
+                .unwrap(),
+            b"fLaC0"
+        );
+        assert_eq!(calls(), 1);
+
+        let touched = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+        std::fs::File::options()
+            .write(true)
+            .open(&entry.source.path)
+            .unwrap()
+            .set_modified(touched)
+            .unwrap();
+        assert_eq!(
+            collect(media.stream_track(&entry, flac).await.unwrap())
+                .await
+                .unwrap(),
+            b"fLaC1"
+        );
+        assert_eq!(
+            collect(media.stream_track(&entry, flac).await.unwrap())
+                .await
+                .unwrap(),
+            b"fLaC1"
+        );
+        assert_eq!(calls(), 2);
+
+        media.invalidate_cache(&entry.id).await.unwrap();
+        assert_eq!(
+            collect(media.stream_track(&entry, flac).await.unwrap())
+                .await
+                .unwrap(),
+            b"fLaC2"
+        );
+        assert_eq!(calls(), 3);
+    }
+
+    #[tokio::test]
+    async fn ranged_reads_of_converted_tracks_reuse_the_encoded_output() {
+        let mut transcoder = MockTranscoder::new();
+        transcoder.expect_transcode().times(1).returning(|request| {
+            Ok(crate::media::TranscodeResult {
+                track_id: request.track.id.clone(),
+                format: "flac",
+                chunks: vec![AudioChunk {
+                    data: bytes::Bytes::from_static(b"fLaC0123456789"),
+                    timestamp_ms: 0,
+                    is_end: true,
+                }],
+                artwork: None,
+                duration_ms: None,
+            })
+        });
+        let mut entry = rated_entry(1, 5);
+        entry.source.path = PathBuf::from("01.wav");
+        let id = entry.id.clone();
+        let media = MediaEngine::new(
+            Arc::new(MockReader::new()),
+            Arc::new(transcoder),
+            Arc::new(DefaultCoverExtractor::new()),
+            router(Vec::new()).media.policy.clone(),
+        );
+        let router = FileRouter::new(
+            Arc::new(vec![entry]),
+            Arc::new(media),
+            Arc::new(MockTags::new()),
+        );
+
+        let flac = Some(TargetFormat::Flac);
+        assert_eq!(
+            router.read_track_range(&id, flac, 0, 4).await.unwrap(),
+            b"fLaC"
+        );
+        assert_eq!(
+            router.read_track_range(&id, flac, 10, 100).await.unwrap(),
+            b"6789"
+        );
+        assert!(
+            router
+                .read_track_range(&id, flac, 1_000, 4)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn concurrent_sizes_of_one_track_share_a_single_encode() {
+        let mut transcoder = MockTranscoder::new();
+        transcoder.expect_transcode().times(1).returning(|request| {
+            Ok(crate::media::TranscodeResult {
+                track_id: request.track.id.clone(),
+                format: "flac",
+                chunks: vec![AudioChunk {
+                    data: bytes::Bytes::from_static(b"fLaC0123"),
+                    timestamp_ms: 0,
+                    is_end: true,
+                }],
+                artwork: None,
+                duration_ms: None,
+            })
+        });
+        let mut entry = rated_entry(1, 5);
+        entry.source.path = PathBuf::from("01.wav");
+        let media = MediaEngine::new(
+            Arc::new(MockReader::new()),
+            Arc::new(transcoder),
+            Arc::new(DefaultCoverExtractor::new()),
+            router(Vec::new()).media.policy.clone(),
+        );
+
+        let flac = Some(TargetFormat::Flac);
+        let (first, second, range) = tokio::join!(
+            media.content_length(&entry, flac),
+            media.content_length(&entry, flac),
+            media.read_range(&entry, flac, 4, 4),
+        );
+        assert_eq!(first.unwrap(), 8);
+        assert_eq!(second.unwrap(), 8);
+        assert_eq!(range.unwrap(), b"0123");
+    }
+
+    #[tokio::test]
+    async fn listings_estimate_sizes_until_the_track_is_encoded() {
+        let mut transcoder = MockTranscoder::new();
+        transcoder.expect_transcode().times(1).returning(|request| {
+            Ok(crate::media::TranscodeResult {
+                track_id: request.track.id.clone(),
+                format: "flac",
+                chunks: vec![AudioChunk {
+                    data: bytes::Bytes::from_static(b"fLaC0123"),
+                    timestamp_ms: 0,
+                    is_end: true,
+                }],
+                artwork: None,
+                duration_ms: None,
+            })
+        });
+        let mut entry = rated_entry(1, 5);
+        entry.source.path = PathBuf::from("01.wav");
+        entry.metadata.duration_ms = 2_000;
+        let media = MediaEngine::new(
+            Arc::new(MockReader::new()),
+            Arc::new(transcoder),
+            Arc::new(DefaultCoverExtractor::new()),
+            router(Vec::new()).media.policy.clone(),
+        );
+
+        let flac = Some(TargetFormat::Flac);
+        // Two seconds of 44.1 kHz stereo at three bytes a sample, plus the header allowance.
+        let estimate = 2 * 44_100 * 2 * 3 + ESTIMATE_OVERHEAD_BYTES;
+        assert_eq!(media.listed_length(&entry, flac).await.unwrap(), estimate);
+        assert_eq!(media.listed_length(&entry, flac).await.unwrap(), estimate);
+        assert_eq!(media.content_length(&entry, flac).await.unwrap(), 8);
+        assert_eq!(media.listed_length(&entry, flac).await.unwrap(), 8);
+    }
+
+    #[tokio::test]
+    async fn registry_routes_matching_targets_to_custom_transcoders() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut entry = rated_entry(1, 5);
+        entry.source.path = dir.path().join("01.wav");
+        silent_wav(&entry.source.path);
+
+        let mut custom = MockTranscoder::new();
+        custom.expect_transcode().times(1).returning(|request| {
+            Ok(crate::media::TranscodeResult {
+                track_id: request.track.id.clone(),
+                format: "fake",
+                chunks: vec![AudioChunk {
+                    data: bytes::Bytes::from_static(b"FAKE"),
+                    timestamp_ms: 0,
+                    is_end: true,
+                }],
+                artwork: None,
+                duration_ms: None,
+            })
+        });
+        let fake = TargetFormat::Custom("fake");
+        let media = MediaEngine::new(
+            Arc::new(MockReader::new()),
+            Arc::new(DefaultFormatTranscoder::new()),
+            Arc::new(DefaultCoverExtractor::new()),
+            router(Vec::new()).media.policy.clone(),
+        )
+        .with_registry(TranscoderRegistry::new().with(fake, Arc::new(custom)));
+
+        assert_eq!(
+            collect(media.stream_track(&entry, Some(fake)).await.unwrap())
+                .await
+                .unwrap(),
+            b"FAKE"
+        );
+        let builtin = collect(
+            media
+                .stream_track(&entry, Some(TargetFormat::Flac))
+                .await
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+        assert!(builtin.starts_with(b"fLaC"));
+    }
+
+    #[tokio::test]
+    async fn converted_cue_tracks_report_their_decoded_duration() {
+        let dir = tempfile::tempdir().unwrap();
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 44_100,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(dir.path().join("disc.wav"), spec).unwrap();
+        for _ in 0..2 * (44_100 * 7 / 2) {
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+        let cue = "FILE \"disc.wav\" WAVE\n  TRACK 01 AUDIO\n    INDEX 01 00:00:00\n  TRACK 02 AUDIO\n    INDEX 01 00:02:00\n";
+        let sheet = crate::cue::CueParser.parse_str(cue, dir.path()).unwrap();
+        let index = crate::track::TrackMapper::from_cue(
+            &sheet,
+            &AlbumId("album".into()),
+            Some(&dir.path().join("disc.cue")),
+        )
+        .unwrap();
+        let last = index.entries[1].clone();
+        assert_eq!(last.metadata.duration_ms, 0);
+
+        let durations = Arc::new(DurationCorrections::new(Arc::new(
+            crate::kv::MemoryBackend::new(),
+        )));
+        let media = MediaEngine::new(
+            Arc::new(MockReader::new()),
+            Arc::new(DefaultFormatTranscoder::new()),
+            Arc::new(DefaultCoverExtractor::new()),
+            PolicyConfig::default(),
+        )
+        .with_duration_corrections(durations.clone());
+        struct EstimateReader(TrackMetadata);
+
+        #[async_trait]
+        impl crate::tag::TagReader for EstimateReader {
+            async fn read_from_file(
+                &self,
+                _track: &TrackId,
+                _path: &Path,
+            ) -> Result<TrackMetadata> {
+                Ok(self.0.clone())
+            }
+        }
+
+        let kv = Arc::new(crate::kv::MemoryBackend::new());
+        let tags = crate::tag::TagOverlay::new(
+            Arc::new(EstimateReader(last.metadata.clone())),
+            Arc::new(crate::tag::KvTagPersistence::new(crate::kv::KvStore::new(
+                kv,
+            ))),
+        )
+        .with_duration_corrections(durations.clone());
+        let router = FileRouter::new(Arc::new(index.entries), Arc::new(media), Arc::new(tags));
+
+        router.read_track(&last.id).await.unwrap();
+        assert_eq!(durations.lookup(&last.id).await.unwrap(), Some(1_500));
+        assert_eq!(router.read_tags(&last.id).await.unwrap().duration_ms, 1_500);
+    }
+
+    #[tokio::test]
+    async fn prewarm_converts_only_the_leading_uncached_tracks() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let counter = calls.clone();
+        let mut transcoder = MockTranscoder::new();
+        transcoder.expect_transcode().returning(move |request| {
+            counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            Ok(crate::media::TranscodeResult {
+                track_id: request.track.id.clone(),
+                format: "flac",
+                chunks: vec![AudioChunk {
+                    data: bytes::Bytes::from_static(b"fLaC"),
+                    timestamp_ms: 0,
+                    is_end: true,
+                }],
+                artwork: None,
+                duration_ms: None,
+            })
+        });
+        let cache = Arc::new(TranscodeCache::new(1 << 20));
+        let media = MediaEngine::new(
+            Arc::new(MockReader::new()),
+            Arc::new(transcoder),
+            Arc::new(DefaultCoverExtractor::new()),
+            PolicyConfig::default(),
+        )
+        .with_cache(cache.clone());
+        let entries: Vec<TrackIndexEntry> = (1..=3)
+            .map(|index| fixtures::entry("album", index, format!("/missing/{index:02}.wav")))
+            .collect();
+        let tracks: Vec<&TrackIndexEntry> = entries.iter().collect();
+        let flac = Some(TargetFormat::Flac);
+        let calls = || calls.load(std::sync::atomic::Ordering::SeqCst);
+
+        assert_eq!(media.prewarm_album(&tracks, flac, 2).await.unwrap(), 2);
+        assert_eq!(calls(), 2);
+        assert!(cache.contains(&entries[0].id));
+        assert!(cache.contains(&entries[1].id));
+        assert!(!cache.contains(&entries[2].id));
+
+        assert_eq!(media.prewarm_album(&tracks, flac, 2).await.unwrap(), 0);
+        assert_eq!(calls(), 2);
+    }
+
+    #[tokio::test]
+    async fn album_folders_prewarm_and_stream_their_cue_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 44_100,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(dir.path().join("disc.wav"), spec).unwrap();
+        for _ in 0..2 * 3 * 44_100 {
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+        let cue = "TITLE \"Disc Title\"\nFILE \"disc.wav\" WAVE\n  TRACK 01 AUDIO\n    INDEX 01 00:00:00\n  TRACK 02 AUDIO\n    INDEX 01 00:01:37\n";
+        let sheet = crate::cue::CueParser.parse_str(cue, dir.path()).unwrap();
+        let index = crate::track::TrackMapper::from_cue_with_durations(
+            &sheet,
+            &AlbumId("album".into()),
+            Some(&dir.path().join("disc.cue")),
+        )
+        .unwrap();
+        let cache = Arc::new(TranscodeCache::new(1 << 24));
+        let media = MediaEngine::new(
+            Arc::new(MockReader::new()),
+            Arc::new(DefaultFormatTranscoder::new()),
+            Arc::new(DefaultCoverExtractor::new()),
+            PolicyConfig::default(),
+        )
+        .with_cache(cache.clone());
+        let first = index.entries[0].id.clone();
+        let router = FileRouter::new(
+            Arc::new(index.entries),
+            Arc::new(media),
+            Arc::new(MockTags::new()),
+        )
+        .with_prewarm(1);
+
+        assert_eq!(router.prewarm_dir("/album").await.unwrap(), 1);
+        assert!(cache.contains(&first));
+        assert_eq!(router.prewarm_dir("/missing").await.unwrap(), 0);
+
+        let stream = dir.path().join("album.flac");
+        std::fs::write(
+            &stream,
+            router
+                .read_album_stream(&AlbumId("album".into()))
+                .await
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            crate::probe::decoded_frames(&stream).unwrap().frames,
+            3 * 44_100
+        );
+
+        let album = AlbumId("album".into());
+        let listing = router.list_dir("/album").unwrap();
+        assert_eq!(
+            listing[listing.len() - 2..],
+            [
+                VirtualEntry::AlbumStream(album.clone()),
+                VirtualEntry::AlbumCue(album.clone()),
+            ]
+        );
+        assert_eq!(
+            router.resolve("/album/album.flac"),
+            Some(VirtualEntry::AlbumStream(album.clone()))
+        );
+        assert_eq!(
+            router.resolve("/album/album.cue"),
+            Some(VirtualEntry::AlbumCue(album.clone()))
+        );
+        let cue = router.album_cue(&album).unwrap();
+        assert!(cue.contains("FILE \"album.flac\""));
+        let reparsed = crate::cue::CueParser.parse_str(&cue, dir.path()).unwrap();
+        assert_eq!(reparsed.album_title.as_deref(), Some("Disc Title"));
+        assert_eq!(reparsed.files[0].tracks[1].index_01_frames(), 112);
+    }
+
+    #[test]
+    fn root_lists_albums_and_album_folders_list_tracks_and_cover() {
+        let mut other = rated_entry(1, 4);
+        other.id.album = AlbumId("Other: Album".into());
+        other.metadata.id = other.id.clone();
+        other.source.id = other.id.clone();
+        let base = router(Vec::new());
+        let index = vec![rated_entry(2, 3), other.clone(), rated_entry(1, 5)];
+        let router = FileRouter::new(Arc::new(index), base.media.clone(), base.tags.clone())
+            .with_sanitizer(MountPlatform::Windows.sanitizer());
+
+        let other_dir = router.album_dir_name(&other.id.album);
+        assert_ne!(other_dir, "Other: Album");
+        assert_eq!(
+            router.list_dir("/").unwrap(),
+            vec![
+                VirtualEntry::Directory(PathBuf::from("album")),
+                VirtualEntry::Directory(PathBuf::from(&other_dir)),
+            ]
+        );
+
+        // Tracks come back in disc and track order whatever the index order.
+        let first = rated_entry(1, 5).id;
+        let second = rated_entry(2, 3).id;
+        assert_eq!(
+            router.list_dir("/album").unwrap(),
+            vec![
+                VirtualEntry::TrackFile(first.clone(), None),
+                VirtualEntry::TrackFile(second.clone(), None),
+                VirtualEntry::CoverImage(first.clone()),
+            ]
+        );
+        assert_eq!(
+            router.list_dir_names(&format!("/{other_dir}")).unwrap(),
+            vec![router.entry_name(&other.id), "cover.jpg".to_string()]
+        );
+        assert!(router.list_dir("/missing").is_none());
+    }
+
+    fn silent_wav(path: &Path) {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 8_000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).unwrap();
+        for _ in 0..800 {
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    #[tokio::test]
+    async fn content_length_matches_the_streamed_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut wav = rated_entry(1, 5);
+        wav.source.path = dir.path().join("01.wav");
+        silent_wav(&wav.source.path);
+        let mut flac = rated_entry(2, 5);
+        flac.source.path = dir.path().join("02.flac");
+        std::fs::write(&flac.source.path, b"fLaC not really").unwrap();
+        let (wav_id, flac_id) = (wav.id.clone(), flac.id.clone());
+        let base = router(Vec::new());
+        let router = FileRouter::new(
+            Arc::new(vec![wav, flac]),
+            base.media.clone(),
+            base.tags.clone(),
+        );
+
+        for (id, target) in [
+            (&wav_id, None),
+            (&wav_id, Some(TargetFormat::Flac)),
+            (&flac_id, Some(TargetFormat::Flac)),
+        ] {
+            let streamed = router.read_track_as(id, target).await.unwrap();
+            assert_eq!(
+                router.content_length(id, target).await.unwrap(),
+                streamed.len() as u64,
+                "{id} as {target:?}"
+            );
+        }
+        assert!(
+            router
+                .read_track_as(&wav_id, Some(TargetFormat::Flac))
+                .await
+                .unwrap()
+                .starts_with(b"fLaC")
+        );
+    }
+
+    #[tokio::test]
+    async fn covers_resolve_and_read_from_embedded_or_external_art() {
+        use lofty::{MimeType, Picture, PictureType, Tag, TagExt, TagType};
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut entries = Vec::new();
+        for (index, album) in ["embedded", "external", "bare"].into_iter().enumerate() {
+            let folder = dir.path().join(album);
+            std::fs::create_dir_all(&folder).unwrap();
+            let mut entry = rated_entry(index as u32 + 1, 5);
+            entry.id.album = AlbumId(album.into());
+            entry.source.id = entry.id.clone();
+            entry.source.path = folder.join("01.wav");
+            silent_wav(&entry.source.path);
+            entries.push(entry);
+        }
+        let mut tag = Tag::new(TagType::Id3v2);
+        tag.push_picture(Picture::new_unchecked(
+            PictureType::CoverFront,
+            MimeType::Jpeg,
+            None,
+            vec![1, 2, 3, 4],
+        ));
+        tag.save_to_path(&entries[0].source.path).unwrap();
+        std::fs::write(dir.path().join("external/folder.jpg"), [5u8, 6, 7]).unwrap();
+
+        let ids: Vec<TrackId> = entries.iter().map(|entry| entry.id.clone()).collect();
+        let base = router(Vec::new());
+        let router = FileRouter::new(Arc::new(entries), base.media.clone(), base.tags.clone());
+
+        for path in ["/embedded/cover.jpg", "/embedded/folder.jpg"] {
+            assert_eq!(
+                router.resolve(path),
+                Some(VirtualEntry::CoverImage(ids[0].clone())),
+                "{path}"
+            );
+        }
+        let per_track = format!("/external/{}.jpg", router.entry_name(&ids[1]));
+        assert_eq!(
+            router.resolve(&per_track),
+            Some(VirtualEntry::CoverImage(ids[1].clone()))
+        );
+
+        assert_eq!(
+            router.read_cover(&ids[0]).await.unwrap(),
+            Some(vec![1, 2, 3, 4])
+        );
+        assert_eq!(
+            router.read_cover(&ids[1]).await.unwrap(),
+            Some(vec![5, 6, 7])
+        );
+        assert_eq!(router.read_cover(&ids[2]).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn covers_are_served_as_bounded_jpeg_and_kept_in_the_blob_store() {
+        use image::{DynamicImage, ImageFormat, RgbImage};
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut entry = rated_entry(1, 5);
+        entry.source.path = dir.path().join("01.wav");
+        silent_wav(&entry.source.path);
+        let folder_art = dir.path().join("folder.jpg");
+        DynamicImage::ImageRgb8(RgbImage::new(2 * COVER_MAX_DIM, COVER_MAX_DIM))
+            .save_with_format(&folder_art, ImageFormat::Png)
+            .unwrap();
+        let blobs = BlobStore::new(Arc::new(crate::kv::MemoryBackend::new()), None);
+        let media = MediaEngine::new(
+            Arc::new(MockReader::new()),
+            Arc::new(DefaultFormatTranscoder::new()),
+            Arc::new(DefaultCoverExtractor::new()),
+            PolicyConfig::default(),
+        )
+        .with_blob_cache(Arc::new(blobs));
+
+        let cover = media.cover_image(&entry).await.unwrap().unwrap();
+        assert_eq!(image::guess_format(&cover).unwrap(), ImageFormat::Jpeg);
+        let served = image::load_from_memory(&cover).unwrap();
+        assert_eq!(
+            (served.width(), served.height()),
+            (COVER_MAX_DIM, COVER_MAX_DIM / 2)
+        );
+
+        // Later reads come from the store rather than the folder image.
+        std::fs::remove_file(&folder_art).unwrap();
+        assert_eq!(media.cover_image(&entry).await.unwrap(), Some(cover));
+    }
+
+    #[tokio::test]
+    async fn album_folders_list_lyrics_next_to_tracks_that_have_them() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut embedded = rated_entry(1, 5);
+        embedded.metadata.lyrics = Lyrics::parse("[00:01.00]Embedded");
+        let mut sidecar = rated_entry(2, 3);
+        sidecar.source.path = dir.path().join("02.flac");
+        std::fs::write(dir.path().join("02.lrc"), "[00:02.50]From the sidecar").unwrap();
+        let mut plain = rated_entry(3, 1);
+        plain.source.path = dir.path().join("03.flac");
+        let ids = [embedded.id.clone(), sidecar.id.clone(), plain.id.clone()];
+
+        let mut tags = MockTags::new();
+        let served = [embedded.clone(), sidecar.clone(), plain.clone()];
+        tags.expect_read().returning(move |id, _| {
+            Ok(served
+                .iter()
+                .find(|entry| &entry.id == id)
+                .unwrap()
+                .metadata
+                .clone())
+        });
+        let router = FileRouter::new(
+            Arc::new(vec![embedded, sidecar, plain]),
+            router(Vec::new()).media.clone(),
+            Arc::new(tags),
+        );
+
+        assert_eq!(
+            router.list_dir("/album").unwrap(),
+            vec![
+                VirtualEntry::TrackFile(ids[0].clone(), None),
+                VirtualEntry::Lyrics(ids[0].clone()),
+                VirtualEntry::TrackFile(ids[1].clone(), None),
+                VirtualEntry::Lyrics(ids[1].clone()),
+                VirtualEntry::TrackFile(ids[2].clone(), None),
+                VirtualEntry::CoverImage(ids[0].clone()),
+            ]
+        );
+        let lrc = format!("/album/{}.lrc", router.entry_name(&ids[1]));
+        assert_eq!(
+            router.resolve(&lrc),
+            Some(VirtualEntry::Lyrics(ids[1].clone()))
+        );
+        assert_eq!(
+            router.read_lyrics(&ids[0]).await.unwrap().as_deref(),
+            Some("[00:01.00]Embedded\n")
+        );
+        assert_eq!(
+            router.read_lyrics(&ids[1]).await.unwrap().as_deref(),
+            Some("[00:02.50]From the sidecar\n")
+        );
+        assert_eq!(router.read_lyrics(&ids[2]).await.unwrap(), None);
+    }
+
+    #[test]
+    fn nested_album_paths_resolve_to_tracks_and_covers() {
+        let router = router(Vec::new());
+        let first = rated_entry(1, 5).id;
+        let second = rated_entry(2, 3).id;
+
+        assert_eq!(
+            router.resolve("/album"),
+            Some(VirtualEntry::Directory(PathBuf::from("album")))
+        );
+        let nested = format!(
+            "/album/{}",
+            router.track_file_name(&second, TargetFormat::Flac)
+        );
+        assert_eq!(
+            router.resolve(&nested),
+            Some(VirtualEntry::TrackFile(
+                second.clone(),
+                Some(TargetFormat::Flac)
+            ))
+        );
+        assert_eq!(
+            router.resolve(&format!("/album/{}.lrc", router.entry_name(&first))),
+            Some(VirtualEntry::Lyrics(first.clone()))
+        );
+        assert_eq!(
+            router.resolve("/album/cover.jpg"),
+            Some(VirtualEntry::CoverImage(first.clone()))
+        );
+        // Flat names at the root keep resolving.
+        assert_eq!(
+            router.resolve(&router.entry_name(&second)),
+            Some(VirtualEntry::TrackFile(second.clone(), None))
+        );
+        let wrong_album = format!("/other/{}", router.entry_name(&second));
+        assert_eq!(router.resolve(&wrong_album), None);
+        let too_deep = format!("/album/extra/{}", router.entry_name(&second));
+        assert_eq!(router.resolve(&too_deep), None);
+    }
+
+    #[test]
+    fn smart_folder_lists_only_matching_tracks() {
+        let router = router(vec![SmartFolderConfig {
+            name: "5 Stars".into(),
+            query: TagQuery::tag_eq("RATING", TagValue::Number(5)),
+        }]);
+        let five = rated_entry(1, 5).id;
+        let three = rated_entry(2, 3).id;
+
+        assert!(
+            router
+                .list_dir_names("/")
+                .unwrap()
+                .contains(&"Smart".to_string())
+        );
+        assert_eq!(router.list_dir_names("/Smart").unwrap(), vec!["5 Stars"]);
+        assert_eq!(
+            router.list_dir_names("/Smart/5 Stars").unwrap(),
+            vec![router.entry_name(&five)]
+        );
+
+        let hit = format!("/Smart/5 Stars/{}.flac", router.entry_name(&five));
+        assert_eq!(
+            router.resolve(&hit),
+            Some(VirtualEntry::TrackFile(five, Some(TargetFormat::Flac)))
+        );
+        let miss = format!("/Smart/5 Stars/{}", router.entry_name(&three));
+        assert_eq!(router.resolve(&miss), None);
+        assert!(router.list_dir("/Smart/Unknown").is_none());
+    }
 }
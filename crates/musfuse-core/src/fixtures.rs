@@ -0,0 +1,72 @@
+// Index entries shared by the test suites; callers adjust the fields a test is about.
+use std::path::{Path, PathBuf};
+
+use crate::metadata::{AlbumId, TagMap, TrackId, TrackMetadata};
+use crate::track::{SourceTrack, TrackIndexEntry};
+
+pub fn track_id(album: &str, index: u32) -> TrackId {
+    TrackId {
+        album: AlbumId(album.into()),
+        disc: 1,
+        index,
+    }
+}
+
+// A whole-file 44.1 kHz stereo track titled "Track NN" by "Artist".
+pub fn entry(album: &str, index: u32, path: impl Into<PathBuf>) -> TrackIndexEntry {
+    let id = track_id(album, index);
+    TrackIndexEntry {
+        id: id.clone(),
+        metadata: TrackMetadata {
+            id: id.clone(),
+            title: format!("Track {index:02}"),
+            artist: "Artist".into(),
+            album_artist: None,
+            duration_ms: 0,
+            tags: TagMap::default(),
+            artwork: None,
+            lyrics: None,
+        },
+        source: SourceTrack {
+            id,
+            path: path.into(),
+            cue_path: None,
+            offset_frames: 0,
+            length_frames: 0,
+            sample_rate: 44_100,
+            channels: 2,
+        },
+    }
+}
+
+pub fn mp4_box(name: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut out = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+    out.extend_from_slice(name);
+    out.extend_from_slice(payload);
+    out
+}
+
+// An audiobook container holding only an `mvhd` length and a Nero `chpl` chapter list.
+pub fn write_m4b(path: &Path, chapters: &[(u64, &str)], duration_ms: u32) {
+    let mut mvhd = vec![0u8; 4];
+    mvhd.extend_from_slice(&[0; 8]);
+    mvhd.extend_from_slice(&1000u32.to_be_bytes());
+    mvhd.extend_from_slice(&duration_ms.to_be_bytes());
+    mvhd.extend_from_slice(&[0; 80]);
+
+    let mut chpl = vec![1, 0, 0, 0, 0, 0, 0, 0, chapters.len() as u8];
+    for (start_ms, title) in chapters {
+        chpl.extend_from_slice(&(start_ms * 10_000).to_be_bytes());
+        chpl.push(title.len() as u8);
+        chpl.extend_from_slice(title.as_bytes());
+    }
+
+    let udta = mp4_box(b"udta", &mp4_box(b"chpl", &chpl));
+    let mut moov = mp4_box(b"mvhd", &mvhd);
+    moov.extend(udta);
+
+    let mut data = mp4_box(b"ftyp", b"M4B \0\0\0\0M4B mp42");
+    data.extend(mp4_box(b"moov", &moov));
+    data.extend(mp4_box(b"mdat", &[0; 16]));
+    std::fs::write(path, data).unwrap();
+}
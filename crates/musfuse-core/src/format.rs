@@ -0,0 +1,166 @@
+use std::path::Path;
+
+// The single list of source formats MusFuse recognizes; scanner, policy, tag reader and
+// media engine all classify files through it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AudioFormat {
+    Flac,
+    Wav,
+    Aiff,
+    Alac,
+    Ape,
+    WavPack,
+    Tak,
+    Tta,
+    Mp3,
+    Ogg,
+    Opus,
+    M4a,
+    Aac,
+    Dsf,
+    Dff,
+}
+
+impl AudioFormat {
+    pub const ALL: [AudioFormat; 15] = [
+        AudioFormat::Flac,
+        AudioFormat::Wav,
+        AudioFormat::Aiff,
+        AudioFormat::Alac,
+        AudioFormat::Ape,
+        AudioFormat::WavPack,
+        AudioFormat::Tak,
+        AudioFormat::Tta,
+        AudioFormat::Mp3,
+        AudioFormat::Ogg,
+        AudioFormat::Opus,
+        AudioFormat::M4a,
+        AudioFormat::Aac,
+        AudioFormat::Dsf,
+        AudioFormat::Dff,
+    ];
+
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|format| format.extension().eq_ignore_ascii_case(ext))
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(Self::from_extension)
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            AudioFormat::Flac => "flac",
+            AudioFormat::Wav => "wav",
+            AudioFormat::Aiff => "aiff",
+            AudioFormat::Alac => "alac",
+            AudioFormat::Ape => "ape",
+            AudioFormat::WavPack => "wv",
+            AudioFormat::Tak => "tak",
+            AudioFormat::Tta => "tta",
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::Ogg => "ogg",
+            AudioFormat::Opus => "opus",
+            AudioFormat::M4a => "m4a",
+            AudioFormat::Aac => "aac",
+            AudioFormat::Dsf => "dsf",
+            AudioFormat::Dff => "dff",
+        }
+    }
+
+    // M4A may carry ALAC, but is treated as lossy like the AAC it usually holds.
+    pub fn is_lossless(&self) -> bool {
+        matches!(
+            self,
+            AudioFormat::Flac
+                | AudioFormat::Wav
+                | AudioFormat::Aiff
+                | AudioFormat::Alac
+                | AudioFormat::Ape
+                | AudioFormat::WavPack
+                | AudioFormat::Tak
+                | AudioFormat::Tta
+                | AudioFormat::Dsf
+                | AudioFormat::Dff
+        )
+    }
+
+    pub fn is_dsd(&self) -> bool {
+        matches!(self, AudioFormat::Dsf | AudioFormat::Dff)
+    }
+
+    pub fn mime(&self) -> &'static str {
+        match self {
+            AudioFormat::Flac => "audio/flac",
+            AudioFormat::Wav => "audio/wav",
+            AudioFormat::Aiff => "audio/aiff",
+            AudioFormat::Alac => "audio/mp4",
+            AudioFormat::Ape => "audio/x-ape",
+            AudioFormat::WavPack => "audio/x-wavpack",
+            AudioFormat::Tak => "audio/x-tak",
+            AudioFormat::Tta => "audio/x-tta",
+            AudioFormat::Mp3 => "audio/mpeg",
+            AudioFormat::Ogg => "audio/ogg",
+            AudioFormat::Opus => "audio/opus",
+            AudioFormat::M4a => "audio/mp4",
+            AudioFormat::Aac => "audio/aac",
+            AudioFormat::Dsf => "audio/x-dsf",
+            AudioFormat::Dff => "audio/x-dff",
+        }
+    }
+}
+
+pub fn is_audio_file(path: &Path) -> bool {
+    AudioFormat::from_path(path).is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{LosslessStrategy, PolicyConfig};
+    use crate::policy::AudioFormatPolicy;
+
+    #[test]
+    fn classification_is_consistent_across_the_api() {
+        let policy = PolicyConfig {
+            lossless_strategy: LosslessStrategy::ConvertToFlac,
+            ..PolicyConfig::default()
+        };
+        let cases = [
+            ("Album.FLAC", Some(AudioFormat::Flac), true, "audio/flac"),
+            ("song.mp3", Some(AudioFormat::Mp3), false, "audio/mpeg"),
+            ("voice.opus", Some(AudioFormat::Opus), false, "audio/opus"),
+        ];
+        for (name, expected, lossless, mime) in cases {
+            let path = Path::new(name);
+            let format = AudioFormat::from_path(path);
+            assert_eq!(format, expected, "{name}");
+            assert!(is_audio_file(path), "{name}");
+
+            let format = format.unwrap();
+            assert_eq!(
+                AudioFormat::from_extension(format.extension()),
+                Some(format)
+            );
+            assert_eq!(format.is_lossless(), lossless, "{name}");
+            assert_eq!(format.mime(), mime);
+            let expected_policy = if lossless {
+                AudioFormatPolicy::ConvertLossless
+            } else {
+                AudioFormatPolicy::PassthroughLossy
+            };
+            assert_eq!(
+                AudioFormatPolicy::from_extension(format.extension(), &policy).unwrap(),
+                expected_policy
+            );
+        }
+
+        assert_eq!(AudioFormat::from_extension("txt"), None);
+        assert!(!is_audio_file(Path::new("notes.txt")));
+        assert!(!is_audio_file(Path::new("no_extension")));
+    }
+}
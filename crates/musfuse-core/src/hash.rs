@@ -0,0 +1,198 @@
+use std::sync::Arc;
+
+use crate::metadata::ArtworkRef;
+
+// Hashes are rendered as `<algorithm>:<hex digest>` so refs produced by
+// different hashers can live side by side without colliding.
+pub trait ContentHasher: Send + Sync {
+    fn algorithm(&self) -> &'static str;
+    fn digest_hex(&self, bytes: &[u8]) -> String;
+
+    fn hash(&self, bytes: &[u8]) -> String {
+        format!("{}:{}", self.algorithm(), self.digest_hex(bytes))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Blake3Hasher;
+
+impl ContentHasher for Blake3Hasher {
+    fn algorithm(&self) -> &'static str {
+        "blake3"
+    }
+
+    fn digest_hex(&self, bytes: &[u8]) -> String {
+        blake3::hash(bytes).to_hex().to_string()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Xxh3Hasher;
+
+impl ContentHasher for Xxh3Hasher {
+    fn algorithm(&self) -> &'static str {
+        "xxh3"
+    }
+
+    fn digest_hex(&self, bytes: &[u8]) -> String {
+        format!("{:032x}", xxhash_rust::xxh3::xxh3_128(bytes))
+    }
+}
+
+// FIPS 180-4 SHA-256, for refs that other tools must be able to recompute. Slower than
+// blake3, which stays the default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sha256Hasher;
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+impl Sha256Hasher {
+    pub fn digest(bytes: &[u8]) -> [u8; 32] {
+        let mut state: [u32; 8] = [
+            0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+            0x5be0cd19,
+        ];
+        let mut message = bytes.to_vec();
+        message.push(0x80);
+        message.resize(message.len().next_multiple_of(64), 0);
+        if message.len() - bytes.len() < 9 {
+            message.resize(message.len() + 64, 0);
+        }
+        let bit_len = (bytes.len() as u64).wrapping_mul(8);
+        let end = message.len();
+        message[end - 8..].copy_from_slice(&bit_len.to_be_bytes());
+
+        for block in message.chunks_exact(64) {
+            let mut w = [0u32; 64];
+            for (word, chunk) in w.iter_mut().zip(block.chunks_exact(4)) {
+                *word = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            }
+            for i in 16..64 {
+                let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+                let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+                w[i] = w[i - 16]
+                    .wrapping_add(s0)
+                    .wrapping_add(w[i - 7])
+                    .wrapping_add(s1);
+            }
+
+            let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = state;
+            for i in 0..64 {
+                let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+                let choice = (e & f) ^ (!e & g);
+                let t1 = h
+                    .wrapping_add(s1)
+                    .wrapping_add(choice)
+                    .wrapping_add(SHA256_K[i])
+                    .wrapping_add(w[i]);
+                let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+                let majority = (a & b) ^ (a & c) ^ (b & c);
+                let t2 = s0.wrapping_add(majority);
+                h = g;
+                g = f;
+                f = e;
+                e = d.wrapping_add(t1);
+                d = c;
+                c = b;
+                b = a;
+                a = t1.wrapping_add(t2);
+            }
+            for (word, add) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+                *word = word.wrapping_add(add);
+            }
+        }
+
+        let mut digest = [0u8; 32];
+        for (out, word) in digest.chunks_exact_mut(4).zip(state) {
+            out.copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+}
+
+impl ContentHasher for Sha256Hasher {
+    fn algorithm(&self) -> &'static str {
+        "sha256"
+    }
+
+    fn digest_hex(&self, bytes: &[u8]) -> String {
+        Self::digest(bytes)
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+}
+
+pub fn default_hasher() -> Arc<dyn ContentHasher> {
+    Arc::new(Blake3Hasher)
+}
+
+pub fn algorithm_of(hash: &str) -> Option<&str> {
+    hash.split_once(':').map(|(algorithm, _)| algorithm)
+}
+
+impl ArtworkRef {
+    pub fn from_bytes(hasher: &dyn ContentHasher, mime: impl Into<String>, bytes: &[u8]) -> Self {
+        Self {
+            hash: hasher.hash(bytes),
+            mime: mime.into(),
+            size: bytes.len() as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashers_produce_distinct_prefixed_digests() {
+        let bytes = b"cover art bytes";
+        let strong = Blake3Hasher.hash(bytes);
+        let fast = Xxh3Hasher.hash(bytes);
+
+        assert!(strong.starts_with("blake3:"));
+        assert!(fast.starts_with("xxh3:"));
+        assert_ne!(strong, fast);
+        assert_eq!(strong, Blake3Hasher.hash(bytes));
+        assert_eq!(algorithm_of(&fast), Some("xxh3"));
+    }
+
+    #[test]
+    fn sha256_matches_the_fips_test_vectors() {
+        assert_eq!(
+            Sha256Hasher.digest_hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            Sha256Hasher.hash(b"abc"),
+            "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        // 56 bytes: the length no longer fits in the first block.
+        assert_eq!(
+            Sha256Hasher.digest_hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        assert_eq!(
+            Sha256Hasher.digest_hex(&[b'a'; 1000]),
+            "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3"
+        );
+    }
+
+    #[test]
+    fn artwork_ref_embeds_algorithm() {
+        let artwork = ArtworkRef::from_bytes(default_hasher().as_ref(), "image/jpeg", &[1, 2, 3]);
+        assert_eq!(algorithm_of(&artwork.hash), Some("blake3"));
+        assert_eq!(artwork.size, 3);
+        assert_eq!(artwork.mime, "image/jpeg");
+    }
+}
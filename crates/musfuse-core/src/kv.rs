@@ -1,13 +1,30 @@
+use std::borrow::Cow;
+#[cfg(feature = "sled")]
 use std::collections::HashMap;
+use std::path::Path;
 use std::sync::Arc;
 
 use async_trait::async_trait;
 use serde::{Serialize, de::DeserializeOwned};
 
-use crate::error::Result;
+use crate::config::KvBackendKind;
+use crate::error::{MusFuseError, Result};
+use crate::timing::SlowOpThreshold;
 
+mod memory_backend;
+#[cfg(feature = "rocksdb")]
+mod rocksdb_backend;
+#[cfg(feature = "sled")]
 mod sled_backend;
+#[cfg(feature = "sqlite")]
+mod sqlite_backend;
+pub use memory_backend::MemoryBackend;
+#[cfg(feature = "rocksdb")]
+pub use rocksdb_backend::{RocksDb, RocksDbBackend};
+#[cfg(feature = "sled")]
 pub use sled_backend::SledBackend;
+#[cfg(feature = "sqlite")]
+pub use sqlite_backend::{SqliteBackend, SqlitePool};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct KvKey {
@@ -28,6 +45,35 @@ impl KvKey {
     }
 }
 
+impl std::fmt::Display for KvKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.namespace, self.key)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KvKeyBytes(pub Vec<u8>);
+
+impl KvKeyBytes {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn as_utf8(&self) -> Option<&str> {
+        std::str::from_utf8(&self.0).ok()
+    }
+
+    pub fn to_string_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.0)
+    }
+}
+
+impl From<&KvKey> for KvKeyBytes {
+    fn from(key: &KvKey) -> Self {
+        Self(key.key.as_bytes().to_vec())
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum KvNamespace {
     Track,
@@ -60,24 +106,98 @@ pub trait KvBackend: Send + Sync + 'static {
     async fn get(&self, key: &KvKey) -> Result<Option<Vec<u8>>>;
     async fn put(&self, key: &KvKey, value: Vec<u8>) -> Result<()>;
     async fn delete(&self, key: &KvKey) -> Result<()>;
+    async fn delete_bytes(&self, namespace: KvNamespace, key: &KvKeyBytes) -> Result<()>;
     async fn scan_prefix(
         &self,
         namespace: KvNamespace,
         prefix: &str,
-    ) -> Result<Vec<(String, Vec<u8>)>>;
+    ) -> Result<Vec<(KvKeyBytes, Vec<u8>)>>;
+
+    // One call per key; backends that can serve a batch in one round trip override these.
+    async fn get_many(&self, keys: &[KvKey]) -> Result<Vec<Option<Vec<u8>>>> {
+        let mut values = Vec::with_capacity(keys.len());
+        for key in keys {
+            values.push(self.get(key).await?);
+        }
+        Ok(values)
+    }
+
+    async fn put_many(&self, entries: &[(KvKey, Vec<u8>)]) -> Result<()> {
+        for (key, value) in entries {
+            self.put(key, value.clone()).await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<()> {
+        Ok(())
+    }
 }
 
 pub trait KvCodec: Serialize + DeserializeOwned + Send + Sync + 'static {}
 
 impl<T> KvCodec for T where T: Serialize + DeserializeOwned + Send + Sync + 'static {}
 
-pub struct KvStore<B: KvBackend> {
+// Prefixes every bincode value, so a reader never mistakes one format for the other; the
+// last byte is the layout version.
+const BINCODE_TAG: &[u8] = b"MFKV\x01";
+
+// How KvStore serializes values. Only writes follow the choice: reads recognise bincode by
+// its tag and take anything else as JSON, so a store can switch codecs over existing data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Codec {
+    #[default]
+    Json,
+    Bincode,
+}
+
+impl Codec {
+    fn encode<T: KvCodec>(&self, value: &T) -> Result<Vec<u8>> {
+        match self {
+            Codec::Json => {
+                serde_json::to_vec(value).map_err(|err| MusFuseError::Kv(err.to_string()))
+            }
+            Codec::Bincode => {
+                let mut bytes = BINCODE_TAG.to_vec();
+                bincode::serialize_into(&mut bytes, value)
+                    .map_err(|err| MusFuseError::Kv(err.to_string()))?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    fn decode<T: KvCodec>(bytes: &[u8]) -> Result<T> {
+        match bytes.strip_prefix(BINCODE_TAG) {
+            Some(payload) => {
+                bincode::deserialize(payload).map_err(|err| MusFuseError::Kv(err.to_string()))
+            }
+            None => serde_json::from_slice(bytes).map_err(|err| MusFuseError::Kv(err.to_string())),
+        }
+    }
+}
+
+pub struct KvStore<B: KvBackend + ?Sized> {
     backend: Arc<B>,
+    codec: Codec,
+    slow_ops: SlowOpThreshold,
 }
 
-impl<B: KvBackend> KvStore<B> {
+impl<B: KvBackend + ?Sized> KvStore<B> {
     pub fn new(backend: Arc<B>) -> Self {
-        Self { backend }
+        Self::with_codec(backend, Codec::default())
+    }
+
+    pub fn with_codec(backend: Arc<B>, codec: Codec) -> Self {
+        Self {
+            backend,
+            codec,
+            slow_ops: SlowOpThreshold::disabled(),
+        }
+    }
+
+    pub fn with_slow_op_threshold(mut self, threshold: SlowOpThreshold) -> Self {
+        self.slow_ops = threshold;
+        self
     }
 
     pub fn backend(&self) -> &Arc<B> {
@@ -88,23 +208,77 @@ impl<B: KvBackend> KvStore<B> {
     where
         T: KvCodec,
     {
-        match self.backend.get(key).await? {
-            Some(bytes) => {
-                let value = serde_json::from_slice(&bytes)
-                    .map_err(|err| crate::error::MusFuseError::Kv(err.to_string()))?;
-                Ok(Some(value))
-            }
-            None => Ok(None),
-        }
+        let bytes = self
+            .slow_ops
+            .time_async("kv_get", key, self.backend.get(key))
+            .await?;
+        bytes.map(|bytes| Codec::decode(&bytes)).transpose()
     }
 
     pub async fn store<T>(&self, key: &KvKey, value: &T) -> Result<()>
     where
         T: KvCodec,
     {
-        let bytes = serde_json::to_vec(value)
-            .map_err(|err| crate::error::MusFuseError::Kv(err.to_string()))?;
-        self.backend.put(key, bytes).await
+        let bytes = self.codec.encode(value)?;
+        self.slow_ops
+            .time_async("kv_put", key, self.backend.put(key, bytes))
+            .await
+    }
+
+    // Values come back in the order of `keys`.
+    pub async fn load_many<T>(&self, keys: &[KvKey]) -> Result<Vec<Option<T>>>
+    where
+        T: KvCodec,
+    {
+        let values = self
+            .slow_ops
+            .time_async(
+                "kv_get_many",
+                format!("{} keys", keys.len()),
+                self.backend.get_many(keys),
+            )
+            .await?;
+        values
+            .into_iter()
+            .map(|bytes| bytes.map(|bytes| Codec::decode(&bytes)).transpose())
+            .collect()
+    }
+
+    pub async fn store_many<T>(&self, entries: &[(KvKey, T)]) -> Result<()>
+    where
+        T: KvCodec,
+    {
+        let encoded = entries
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), self.codec.encode(value)?)))
+            .collect::<Result<Vec<_>>>()?;
+        self.slow_ops
+            .time_async(
+                "kv_put_many",
+                format!("{} keys", entries.len()),
+                self.backend.put_many(&encoded),
+            )
+            .await
+    }
+
+    // Every value under `prefix`, in key order. Keys that are not valid UTF-8 come back
+    // lossily converted.
+    pub async fn scan<T>(&self, namespace: KvNamespace, prefix: &str) -> Result<Vec<(String, T)>>
+    where
+        T: KvCodec,
+    {
+        let entries = self
+            .slow_ops
+            .time_async(
+                "kv_scan",
+                format!("{namespace}:{prefix}"),
+                self.backend.scan_prefix(namespace, prefix),
+            )
+            .await?;
+        entries
+            .into_iter()
+            .map(|(key, bytes)| Ok((key.to_string_lossy().into_owned(), Codec::decode(&bytes)?)))
+            .collect()
     }
 
     pub async fn remove(&self, key: &KvKey) -> Result<()> {
@@ -112,10 +286,45 @@ impl<B: KvBackend> KvStore<B> {
     }
 }
 
+// Opens the backend a config names, storing its files under `dir`. Kinds whose feature
+// was not compiled in, and Redis, report Unsupported.
+#[cfg_attr(
+    not(any(feature = "sled", feature = "rocksdb", feature = "sqlite")),
+    allow(unused_variables)
+)]
+pub fn open_backend(kind: &KvBackendKind, dir: &Path) -> Result<Arc<dyn KvBackend>> {
+    match kind {
+        #[cfg(feature = "sled")]
+        KvBackendKind::Sled => Ok(Arc::new(SledBackend::open(dir)?)),
+        #[cfg(feature = "rocksdb")]
+        KvBackendKind::RocksDb => Ok(Arc::new(RocksDbBackend::open(dir)?)),
+        #[cfg(feature = "sqlite")]
+        KvBackendKind::Sqlite => {
+            std::fs::create_dir_all(dir)?;
+            Ok(Arc::new(SqliteBackend::open(dir.join("kv.sqlite3"))?))
+        }
+        #[allow(unreachable_patterns)]
+        KvBackendKind::Sled => Err(MusFuseError::Unsupported(
+            "sled kv backend (built without the sled feature)",
+        )),
+        #[allow(unreachable_patterns)]
+        KvBackendKind::RocksDb => Err(MusFuseError::Unsupported(
+            "rocksdb kv backend (built without the rocksdb feature)",
+        )),
+        #[allow(unreachable_patterns)]
+        KvBackendKind::Sqlite => Err(MusFuseError::Unsupported(
+            "sqlite kv backend (built without the sqlite feature)",
+        )),
+        KvBackendKind::Redis => Err(MusFuseError::Unsupported("redis kv backend")),
+    }
+}
+
+#[cfg(feature = "sled")]
 struct NamespaceCache {
     map: parking_lot::Mutex<HashMap<KvNamespace, sled::Tree>>,
 }
 
+#[cfg(feature = "sled")]
 impl NamespaceCache {
     fn new() -> Self {
         Self {
@@ -140,3 +349,116 @@ impl NamespaceCache {
         Ok(tree)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn open_backend_builds_sled_and_rejects_missing_kinds() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let store = KvStore::new(open_backend(&KvBackendKind::Sled, dir.path()).expect("sled"));
+        let key = KvKey::new(KvNamespace::Policy, "strategy");
+        store.store(&key, &7u32).await.expect("store");
+        assert_eq!(store.load::<u32>(&key).await.expect("load"), Some(7));
+
+        let err = open_backend(&KvBackendKind::Redis, dir.path()).err();
+        assert!(matches!(err, Some(MusFuseError::Unsupported(_))));
+        #[cfg(not(feature = "rocksdb"))]
+        assert!(matches!(
+            open_backend(&KvBackendKind::RocksDb, dir.path()).err(),
+            Some(MusFuseError::Unsupported(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn bincode_values_round_trip_and_json_values_stay_json() {
+        use crate::metadata::TagValue;
+
+        let backend = Arc::new(MemoryBackend::new());
+        let json = KvStore::new(backend.clone());
+        let bincode = KvStore::with_codec(backend.clone(), Codec::Bincode);
+        let value = vec![TagValue::Text("Artist".into()), TagValue::Number(7)];
+        let packed = KvKey::new(KvNamespace::Track, "packed");
+        let plain = KvKey::new(KvNamespace::Track, "plain");
+
+        bincode.store(&packed, &value).await.expect("store bincode");
+        let raw = backend.get(&packed).await.expect("get").expect("stored");
+        assert!(raw.starts_with(BINCODE_TAG));
+        assert_eq!(
+            bincode.load::<Vec<TagValue>>(&packed).await.expect("load"),
+            Some(value.clone())
+        );
+        // Either store reads either format.
+        assert_eq!(
+            json.load::<Vec<TagValue>>(&packed).await.expect("load"),
+            Some(value.clone())
+        );
+        json.store(&plain, &value).await.expect("store json");
+        assert_eq!(
+            bincode.load::<Vec<TagValue>>(&plain).await.expect("load"),
+            Some(value)
+        );
+
+        // Untagged bytes are never handed to bincode, even when they would decode.
+        backend
+            .put(&plain, 3u64.to_le_bytes().to_vec())
+            .await
+            .expect("put");
+        assert!(matches!(
+            bincode.load::<u64>(&plain).await,
+            Err(MusFuseError::Kv(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn scan_decodes_every_value_under_a_prefix() {
+        use crate::metadata::{AlbumId, TagMap, TrackId, TrackMetadata};
+
+        let store = KvStore::new(Arc::new(MemoryBackend::new()));
+        let track = |album: &str, index: u32| TrackMetadata {
+            id: TrackId {
+                album: AlbumId(album.into()),
+                disc: 1,
+                index,
+            },
+            title: format!("Track {index}"),
+            artist: "Artist".into(),
+            album_artist: None,
+            duration_ms: 1_000 * index as u64,
+            tags: TagMap::default(),
+            artwork: None,
+            lyrics: None,
+        };
+        for (album, index) in [("album1", 2), ("album1", 1), ("album2", 1)] {
+            let key = KvKey::new(KvNamespace::Track, format!("{album}-01-{index:02}"));
+            store
+                .store(&key, &track(album, index))
+                .await
+                .expect("store");
+        }
+
+        let scanned = store
+            .scan::<TrackMetadata>(KvNamespace::Track, "album1-")
+            .await
+            .expect("scan");
+        assert_eq!(
+            scanned,
+            vec![
+                ("album1-01-01".to_string(), track("album1", 1)),
+                ("album1-01-02".to_string(), track("album1", 2)),
+            ]
+        );
+
+        store
+            .backend()
+            .put(&KvKey::new(KvNamespace::Track, "album1-bad"), b"{".to_vec())
+            .await
+            .expect("put");
+        let err = store
+            .scan::<TrackMetadata>(KvNamespace::Track, "album1-")
+            .await
+            .err();
+        assert!(matches!(err, Some(MusFuseError::Kv(_))));
+    }
+}
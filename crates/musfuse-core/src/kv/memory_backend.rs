@@ -0,0 +1,72 @@
+use std::collections::{BTreeMap, HashMap};
+
+use async_trait::async_trait;
+use parking_lot::Mutex;
+
+use crate::error::Result;
+
+use super::{KvBackend, KvKey, KvKeyBytes, KvNamespace};
+
+type Tree = BTreeMap<Vec<u8>, Vec<u8>>;
+
+// Keeps every namespace in process memory; nothing survives a restart, which suits tests
+// and the self-test.
+#[derive(Default)]
+pub struct MemoryBackend {
+    trees: Mutex<HashMap<KvNamespace, Tree>>,
+}
+
+impl MemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl KvBackend for MemoryBackend {
+    async fn get(&self, key: &KvKey) -> Result<Option<Vec<u8>>> {
+        Ok(self
+            .trees
+            .lock()
+            .get(&key.namespace)
+            .and_then(|tree| tree.get(key.key.as_bytes()))
+            .cloned())
+    }
+
+    async fn put(&self, key: &KvKey, value: Vec<u8>) -> Result<()> {
+        self.trees
+            .lock()
+            .entry(key.namespace)
+            .or_default()
+            .insert(key.key.as_bytes().to_vec(), value);
+        Ok(())
+    }
+
+    async fn delete(&self, key: &KvKey) -> Result<()> {
+        self.delete_bytes(key.namespace, &KvKeyBytes::from(key))
+            .await
+    }
+
+    async fn delete_bytes(&self, namespace: KvNamespace, key: &KvKeyBytes) -> Result<()> {
+        if let Some(tree) = self.trees.lock().get_mut(&namespace) {
+            tree.remove(key.as_bytes());
+        }
+        Ok(())
+    }
+
+    async fn scan_prefix(
+        &self,
+        namespace: KvNamespace,
+        prefix: &str,
+    ) -> Result<Vec<(KvKeyBytes, Vec<u8>)>> {
+        let trees = self.trees.lock();
+        let Some(tree) = trees.get(&namespace) else {
+            return Ok(Vec::new());
+        };
+        Ok(tree
+            .range(prefix.as_bytes().to_vec()..)
+            .take_while(|(key, _)| key.starts_with(prefix.as_bytes()))
+            .map(|(key, value)| (KvKeyBytes(key.clone()), value.clone()))
+            .collect())
+    }
+}
@@ -0,0 +1,189 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use rocksdb::{BoundColumnFamily, DBWithThreadMode, IteratorMode, MultiThreaded, Options};
+use tokio::task::spawn_blocking;
+
+use crate::error::{MusFuseError, Result};
+
+use super::{KvBackend, KvKey, KvKeyBytes, KvNamespace};
+
+pub type RocksDb = DBWithThreadMode<MultiThreaded>;
+
+// Each namespace lives in its own column family, created on first use like sled trees.
+pub struct RocksDbBackend {
+    db: Arc<RocksDb>,
+}
+
+impl RocksDbBackend {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let mut options = Options::default();
+        options.create_if_missing(true);
+        options.create_missing_column_families(true);
+        // Every existing column family has to be named when opening; a new database has none.
+        let families = RocksDb::list_cf(&options, path).unwrap_or_default();
+        let db = RocksDb::open_cf(&options, path, families)
+            .map_err(|err| MusFuseError::Kv(format!("unable to open rocksdb: {err}")))?;
+        Ok(Self::from_db(db))
+    }
+
+    pub fn from_db(db: RocksDb) -> Self {
+        Self { db: Arc::new(db) }
+    }
+
+    async fn with_family<T, F>(&self, namespace: KvNamespace, op: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: for<'a> FnOnce(&'a RocksDb, Arc<BoundColumnFamily<'a>>) -> Result<T> + Send + 'static,
+    {
+        let db = self.db.clone();
+        spawn_blocking(move || {
+            let name = namespace.to_string();
+            if db.cf_handle(&name).is_none() {
+                db.create_cf(&name, &Options::default())
+                    .map_err(|err| MusFuseError::Kv(err.to_string()))?;
+            }
+            let family = db
+                .cf_handle(&name)
+                .ok_or_else(|| MusFuseError::Kv(format!("missing column family {name}")))?;
+            op(&db, family)
+        })
+        .await
+        .map_err(|err| MusFuseError::Kv(format!("task join error: {err}")))?
+    }
+}
+
+#[async_trait]
+impl KvBackend for RocksDbBackend {
+    async fn get(&self, key: &KvKey) -> Result<Option<Vec<u8>>> {
+        let key_bytes = key.key.clone();
+        self.with_family(key.namespace, move |db, family| {
+            db.get_cf(&family, key_bytes.as_bytes())
+                .map_err(|err| MusFuseError::Kv(err.to_string()))
+        })
+        .await
+    }
+
+    async fn put(&self, key: &KvKey, value: Vec<u8>) -> Result<()> {
+        let key_bytes = key.key.clone();
+        self.with_family(key.namespace, move |db, family| {
+            db.put_cf(&family, key_bytes.as_bytes(), value)
+                .map_err(|err| MusFuseError::Kv(err.to_string()))
+        })
+        .await
+    }
+
+    async fn delete(&self, key: &KvKey) -> Result<()> {
+        self.delete_bytes(key.namespace, &KvKeyBytes::from(key))
+            .await
+    }
+
+    async fn delete_bytes(&self, namespace: KvNamespace, key: &KvKeyBytes) -> Result<()> {
+        let key_bytes = key.0.clone();
+        self.with_family(namespace, move |db, family| {
+            db.delete_cf(&family, key_bytes)
+                .map_err(|err| MusFuseError::Kv(err.to_string()))
+        })
+        .await
+    }
+
+    async fn scan_prefix(
+        &self,
+        namespace: KvNamespace,
+        prefix: &str,
+    ) -> Result<Vec<(KvKeyBytes, Vec<u8>)>> {
+        let prefix = prefix.to_owned();
+        self.with_family(namespace, move |db, family| {
+            let start = IteratorMode::From(prefix.as_bytes(), rocksdb::Direction::Forward);
+            let mut results = Vec::new();
+            for item in db.iterator_cf(&family, start) {
+                let (key, value) = item.map_err(|err| MusFuseError::Kv(err.to_string()))?;
+                if !key.starts_with(prefix.as_bytes()) {
+                    break;
+                }
+                results.push((KvKeyBytes(key.to_vec()), value.to_vec()));
+            }
+            Ok(results)
+        })
+        .await
+    }
+
+    async fn flush(&self) -> Result<()> {
+        let db = self.db.clone();
+        spawn_blocking(move || db.flush().map_err(|err| MusFuseError::Kv(err.to_string())))
+            .await
+            .map_err(|err| MusFuseError::Kv(format!("task join error: {err}")))?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv::KvStore;
+    use crate::metadata::{AlbumId, TagMap, TrackId, TrackMetadata};
+
+    fn test_store(path: &Path) -> Result<KvStore<RocksDbBackend>> {
+        let backend = RocksDbBackend::open(path)?;
+        Ok(KvStore::new(Arc::new(backend)))
+    }
+
+    #[tokio::test]
+    async fn put_and_get_roundtrip() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let store = test_store(dir.path()).expect("create store");
+        let key = KvKey::new(KvNamespace::Track, "album1-01-01");
+
+        let metadata = TrackMetadata {
+            id: TrackId {
+                album: AlbumId("album1".into()),
+                disc: 1,
+                index: 1,
+            },
+            title: "Intro".into(),
+            artist: "Artist".into(),
+            album_artist: None,
+            duration_ms: 120_000,
+            tags: TagMap::default(),
+            artwork: None,
+            lyrics: None,
+        };
+
+        store.store(&key, &metadata).await.expect("store");
+        let fetched = store.load::<TrackMetadata>(&key).await.expect("load");
+        assert_eq!(fetched, Some(metadata));
+
+        // Column families created on first use are found again after a reopen.
+        drop(store);
+        let reopened = test_store(dir.path()).expect("reopen store");
+        assert!(
+            reopened
+                .load::<TrackMetadata>(&key)
+                .await
+                .expect("load")
+                .is_some()
+        );
+    }
+
+    #[tokio::test]
+    async fn scan_prefix_returns_matches() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let store = test_store(dir.path()).expect("create store");
+
+        for idx in 1..=3 {
+            let key = KvKey::new(KvNamespace::Track, format!("album1-01-{idx:02}"));
+            store.store(&key, &idx).await.expect("store");
+        }
+        let other = KvKey::new(KvNamespace::Track, "album2-01-01");
+        store.store(&other, &4).await.expect("store");
+
+        let backend = store.backend().clone();
+        let results = backend
+            .scan_prefix(KvNamespace::Track, "album1-01")
+            .await
+            .expect("scan");
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].0.as_utf8(), Some("album1-01-01"));
+    }
+}
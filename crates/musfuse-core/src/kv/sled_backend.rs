@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::path::Path;
 use std::sync::Arc;
 
@@ -6,7 +7,7 @@ use tokio::task::spawn_blocking;
 
 use crate::error::{MusFuseError, Result};
 
-use super::{KvBackend, KvKey, KvNamespace, NamespaceCache};
+use super::{KvBackend, KvKey, KvKeyBytes, KvNamespace, NamespaceCache};
 
 pub struct SledBackend {
     db: Arc<sled::Db>,
@@ -77,24 +78,89 @@ impl KvBackend for SledBackend {
         .map_err(|err| MusFuseError::Kv(format!("task join error: {err}")))?
     }
 
+    async fn delete_bytes(&self, namespace: KvNamespace, key: &KvKeyBytes) -> Result<()> {
+        let tree = self.tree(namespace).await?;
+        let key_bytes = key.0.clone();
+        spawn_blocking(move || {
+            tree.remove(key_bytes)
+                .map(|_| ())
+                .map_err(|err| MusFuseError::Kv(err.to_string()))
+        })
+        .await
+        .map_err(|err| MusFuseError::Kv(format!("task join error: {err}")))?
+    }
+
     async fn scan_prefix(
         &self,
         namespace: KvNamespace,
         prefix: &str,
-    ) -> Result<Vec<(String, Vec<u8>)>> {
+    ) -> Result<Vec<(KvKeyBytes, Vec<u8>)>> {
         let tree = self.tree(namespace).await?;
         let prefix = prefix.to_owned();
         spawn_blocking(move || {
             let mut results = Vec::new();
             for item in tree.scan_prefix(prefix.as_bytes()) {
                 let (key, value) = item.map_err(|err| MusFuseError::Kv(err.to_string()))?;
-                results.push((String::from_utf8_lossy(&key).into_owned(), value.to_vec()));
+                results.push((KvKeyBytes(key.to_vec()), value.to_vec()));
             }
             Ok(results)
         })
         .await
         .map_err(|err| MusFuseError::Kv(format!("task join error: {err}")))?
     }
+
+    // The whole batch runs in one blocking task; writes land as one sled batch per tree, so
+    // each namespace's share of them is applied atomically.
+    async fn get_many(&self, keys: &[KvKey]) -> Result<Vec<Option<Vec<u8>>>> {
+        let db = self.db.clone();
+        let cache = self.cache.clone();
+        let keys = keys.to_vec();
+        spawn_blocking(move || {
+            keys.iter()
+                .map(|key| {
+                    cache
+                        .get_or_insert(&db, key.namespace)?
+                        .get(key.key.as_bytes())
+                        .map(|opt| opt.map(|ivec| ivec.as_ref().to_vec()))
+                        .map_err(|err| MusFuseError::Kv(err.to_string()))
+                })
+                .collect()
+        })
+        .await
+        .map_err(|err| MusFuseError::Kv(format!("task join error: {err}")))?
+    }
+
+    async fn put_many(&self, entries: &[(KvKey, Vec<u8>)]) -> Result<()> {
+        let db = self.db.clone();
+        let cache = self.cache.clone();
+        let entries = entries.to_vec();
+        spawn_blocking(move || {
+            let mut batches: HashMap<KvNamespace, sled::Batch> = HashMap::new();
+            for (key, value) in entries {
+                batches
+                    .entry(key.namespace)
+                    .or_default()
+                    .insert(key.key.as_bytes(), value);
+            }
+            for (namespace, batch) in batches {
+                cache
+                    .get_or_insert(&db, namespace)?
+                    .apply_batch(batch)
+                    .map_err(|err| MusFuseError::Kv(err.to_string()))?;
+            }
+            Ok(())
+        })
+        .await
+        .map_err(|err| MusFuseError::Kv(format!("task join error: {err}")))?
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.db
+            .flush_async()
+            .await
+            .map(|_| ())
+            .map_err(|err| MusFuseError::Kv(err.to_string()))
+    }
 }
 
 #[cfg(test)]
@@ -126,6 +192,7 @@ mod tests {
             duration_ms: 120_000,
             tags: TagMap::default(),
             artwork: None,
+            lyrics: None,
         };
 
         store.store(&key, &metadata).await.expect("store");
@@ -149,5 +216,66 @@ mod tests {
             .await
             .expect("scan");
         assert_eq!(results.len(), 3);
+        assert_eq!(results[0].0.as_utf8(), Some("album1-01-01"));
+    }
+
+    #[tokio::test]
+    async fn non_utf8_keys_survive_scan_and_delete() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let db = sled::open(dir.path()).expect("open sled");
+        let raw_key = b"bin:\xff\xfe\x00".to_vec();
+        db.open_tree(KvNamespace::Cache.to_string())
+            .expect("tree")
+            .insert(&raw_key, b"payload".to_vec())
+            .expect("insert");
+        let backend = SledBackend::from_db(db);
+
+        let results = backend
+            .scan_prefix(KvNamespace::Cache, "bin:")
+            .await
+            .expect("scan");
+        assert_eq!(results.len(), 1);
+        let (key, value) = &results[0];
+        assert_eq!(key.as_bytes(), raw_key.as_slice());
+        assert!(key.as_utf8().is_none());
+        assert_eq!(value, b"payload");
+
+        backend
+            .delete_bytes(KvNamespace::Cache, key)
+            .await
+            .expect("delete");
+        let results = backend
+            .scan_prefix(KvNamespace::Cache, "bin:")
+            .await
+            .expect("scan");
+        assert!(results.is_empty());
+    }
+
+    #[tokio::test]
+    async fn batched_puts_read_back_in_key_order() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let store = test_store(dir.path()).expect("create store");
+        let entries: Vec<(KvKey, u32)> = (0..100u32)
+            .map(|n| {
+                let namespace = if n % 2 == 0 {
+                    KvNamespace::Track
+                } else {
+                    KvNamespace::Album
+                };
+                (KvKey::new(namespace, format!("key-{n:03}")), n)
+            })
+            .collect();
+        store.store_many(&entries).await.expect("store batch");
+
+        let mut keys: Vec<KvKey> = entries.iter().rev().map(|(key, _)| key.clone()).collect();
+        keys.push(KvKey::new(KvNamespace::Track, "missing"));
+        let raw = store.backend().get_many(&keys).await.expect("get batch");
+        assert_eq!(raw.len(), 101);
+        assert_eq!(raw[0].as_deref(), Some(b"99".as_slice()));
+        assert_eq!(raw[100], None);
+
+        let values = store.load_many::<u32>(&keys).await.expect("load batch");
+        let expected: Vec<Option<u32>> = (0..100u32).rev().map(Some).chain([None]).collect();
+        assert_eq!(values, expected);
     }
 }
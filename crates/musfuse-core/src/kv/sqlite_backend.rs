@@ -0,0 +1,221 @@
+use std::path::Path;
+
+use async_trait::async_trait;
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{OptionalExtension, params};
+use tokio::task::spawn_blocking;
+
+use crate::error::{MusFuseError, Result};
+
+use super::{KvBackend, KvKey, KvKeyBytes, KvNamespace};
+
+pub type SqlitePool = Pool<SqliteConnectionManager>;
+
+// LIKE is case-insensitive for ASCII by default, which would make prefix scans disagree
+// with the byte-wise matching of the other backends.
+const CONNECTION_INIT: &str = "PRAGMA journal_mode = WAL;
+PRAGMA busy_timeout = 5000;
+PRAGMA case_sensitive_like = ON;";
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS kv (
+    namespace TEXT NOT NULL,
+    key BLOB NOT NULL,
+    value BLOB NOT NULL,
+    PRIMARY KEY (namespace, key)
+) WITHOUT ROWID;";
+
+// All namespaces share one table keyed by (namespace, key). Calls draw connections from a
+// pool so concurrent KvStore operations do not queue behind a single connection.
+pub struct SqliteBackend {
+    pool: SqlitePool,
+}
+
+impl SqliteBackend {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let manager = SqliteConnectionManager::file(path.as_ref())
+            .with_init(|conn| conn.execute_batch(CONNECTION_INIT));
+        let pool = Pool::new(manager)
+            .map_err(|err| MusFuseError::Kv(format!("unable to open sqlite db: {err}")))?;
+        Self::from_pool(pool)
+    }
+
+    pub fn from_pool(pool: SqlitePool) -> Result<Self> {
+        let conn = pool
+            .get()
+            .map_err(|err| MusFuseError::Kv(err.to_string()))?;
+        conn.execute_batch(SCHEMA)
+            .map_err(|err| MusFuseError::Kv(err.to_string()))?;
+        Ok(Self { pool })
+    }
+
+    async fn with_conn<T, F>(&self, op: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&rusqlite::Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let pool = self.pool.clone();
+        spawn_blocking(move || {
+            let conn = pool
+                .get()
+                .map_err(|err| MusFuseError::Kv(err.to_string()))?;
+            op(&conn).map_err(|err| MusFuseError::Kv(err.to_string()))
+        })
+        .await
+        .map_err(|err| MusFuseError::Kv(format!("task join error: {err}")))?
+    }
+}
+
+// Escapes LIKE wildcards so a prefix is matched literally; pairs with `ESCAPE '\'`.
+fn like_prefix(prefix: &str) -> String {
+    let mut escaped = String::with_capacity(prefix.len());
+    for c in prefix.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[async_trait]
+impl KvBackend for SqliteBackend {
+    async fn get(&self, key: &KvKey) -> Result<Option<Vec<u8>>> {
+        let namespace = key.namespace.to_string();
+        let key_bytes = key.key.clone().into_bytes();
+        self.with_conn(move |conn| {
+            conn.query_row(
+                "SELECT value FROM kv WHERE namespace = ?1 AND key = ?2",
+                params![namespace, key_bytes],
+                |row| row.get(0),
+            )
+            .optional()
+        })
+        .await
+    }
+
+    async fn put(&self, key: &KvKey, value: Vec<u8>) -> Result<()> {
+        let namespace = key.namespace.to_string();
+        let key_bytes = key.key.clone().into_bytes();
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO kv (namespace, key, value) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (namespace, key) DO UPDATE SET value = excluded.value",
+                params![namespace, key_bytes, value],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    async fn delete(&self, key: &KvKey) -> Result<()> {
+        self.delete_bytes(key.namespace, &KvKeyBytes::from(key))
+            .await
+    }
+
+    async fn delete_bytes(&self, namespace: KvNamespace, key: &KvKeyBytes) -> Result<()> {
+        let namespace = namespace.to_string();
+        let key_bytes = key.0.clone();
+        self.with_conn(move |conn| {
+            conn.execute(
+                "DELETE FROM kv WHERE namespace = ?1 AND key = ?2",
+                params![namespace, key_bytes],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    async fn scan_prefix(
+        &self,
+        namespace: KvNamespace,
+        prefix: &str,
+    ) -> Result<Vec<(KvKeyBytes, Vec<u8>)>> {
+        let namespace = namespace.to_string();
+        let pattern = like_prefix(prefix);
+        self.with_conn(move |conn| {
+            let mut statement = conn.prepare_cached(
+                "SELECT key, value FROM kv
+                 WHERE namespace = ?1 AND key LIKE ?2 || '%' ESCAPE '\\'
+                 ORDER BY key",
+            )?;
+            let rows = statement.query_map(params![namespace, pattern], |row| {
+                Ok((KvKeyBytes(row.get(0)?), row.get(1)?))
+            })?;
+            rows.collect()
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv::KvStore;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn put_and_get_roundtrip() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let store = KvStore::new(Arc::new(
+            SqliteBackend::open(dir.path().join("kv.db")).expect("open"),
+        ));
+        let key = KvKey::new(KvNamespace::Track, "album1-01-01");
+
+        store
+            .store(&key, &"first".to_string())
+            .await
+            .expect("store");
+        store
+            .store(&key, &"second".to_string())
+            .await
+            .expect("overwrite");
+        assert_eq!(
+            store.load::<String>(&key).await.expect("load"),
+            Some("second".to_string())
+        );
+        // Namespaces do not see each other's keys.
+        let other = KvKey::new(KvNamespace::Album, "album1-01-01");
+        assert_eq!(store.load::<String>(&other).await.expect("load"), None);
+
+        store.remove(&key).await.expect("remove");
+        assert_eq!(store.load::<String>(&key).await.expect("load"), None);
+    }
+
+    #[tokio::test]
+    async fn scan_prefix_treats_wildcards_literally() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let backend = SqliteBackend::open(dir.path().join("kv.db")).expect("open");
+        for key in ["100%-a", "100%-b", "1000-c", "100_x", "100x", "ABC", "abc"] {
+            backend
+                .put(
+                    &KvKey::new(KvNamespace::Cache, key),
+                    key.as_bytes().to_vec(),
+                )
+                .await
+                .expect("put");
+        }
+
+        let keys = |results: Vec<(KvKeyBytes, Vec<u8>)>| {
+            results
+                .into_iter()
+                .map(|(key, _)| key.to_string_lossy().into_owned())
+                .collect::<Vec<_>>()
+        };
+        let percent = backend
+            .scan_prefix(KvNamespace::Cache, "100%")
+            .await
+            .expect("scan");
+        assert_eq!(keys(percent), vec!["100%-a", "100%-b"]);
+        let underscore = backend
+            .scan_prefix(KvNamespace::Cache, "100_")
+            .await
+            .expect("scan");
+        assert_eq!(keys(underscore), vec!["100_x"]);
+        let upper = backend
+            .scan_prefix(KvNamespace::Cache, "AB")
+            .await
+            .expect("scan");
+        assert_eq!(keys(upper), vec!["ABC"]);
+    }
+}
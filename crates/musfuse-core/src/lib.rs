@@ -1,22 +1,47 @@
+pub mod album;
+pub mod artwork;
+pub mod blob;
+pub mod bwf;
+pub mod cache;
+pub mod chapter;
 pub mod config;
 pub mod cue;
+pub mod duration;
 pub mod error;
 pub mod filesystem;
+#[cfg(any(test, feature = "test-util"))]
+pub mod fixtures;
+pub mod format;
+pub mod hash;
 pub mod kv;
+pub mod library;
+pub mod lyrics;
 pub mod media;
 pub mod metadata;
 pub mod mount;
 pub mod policy;
 pub mod prelude;
+pub mod probe;
+pub mod provider;
+pub mod query;
+pub mod readahead;
+pub mod reload;
+pub mod sanitize;
 pub mod scanner;
+pub mod selftest;
 pub mod tag;
+pub mod timing;
 pub mod track;
 
+pub use cache::TranscodeCache;
 pub use config::*;
 pub use error::*;
+pub use filesystem::MediaEngine;
 pub use media::{
-    AudioChunk, CoverExtractor, DefaultCoverExtractor, DefaultFormatTranscoder, FormatTranscoder,
-    MediaEngine, TranscodeRequest, TranscodeResult,
+    AudioChunk, CoverExtractor, CoverFilter, DecodeLimits, DefaultCoverExtractor,
+    DefaultFormatTranscoder, FormatTranscoder, MediaOptions, ProbeFallback, TranscodeRequest,
+    TranscodeResult, TranscoderRegistry,
 };
 pub use mount::*;
 pub use policy::*;
+pub use provider::AdapterMountProvider;
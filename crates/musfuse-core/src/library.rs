@@ -0,0 +1,679 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+use crate::blob::BlobStore;
+use crate::chapter::{self, ChapterMapper};
+use crate::config::PolicyConfig;
+use crate::cue::CueParser;
+use crate::error::{MusFuseError, Result};
+use crate::filesystem::{FileRouter, MediaEngine};
+use crate::format::is_audio_file;
+use crate::kv::{KvBackend, KvStore, MemoryBackend};
+use crate::media::{AudioChunk, AudioReader, DefaultCoverExtractor, DefaultFormatTranscoder};
+use crate::metadata::{AlbumId, TrackId};
+use crate::mount::{AdapterCapabilities, MountContext, MountEvent};
+use crate::scanner::{FsLibraryScanner, LibraryScanner, ScanRecord};
+use crate::tag::{KvTagPersistence, LoftyTagReader, TagOverlay, TagReader};
+use crate::timing::SlowOpThreshold;
+use crate::track::{CueMapOptions, SourceTrack, TrackIndex, TrackIndexEntry, TrackMapper};
+
+// Scans the mount's sources and builds the router serving them. Tag edits, converted tracks
+// and covers are kept in the mount's store, or only in memory when it has none; with a
+// cache_dir the larger blobs go to files there.
+pub async fn open_router(
+    ctx: &MountContext,
+    capabilities: AdapterCapabilities,
+) -> Result<FileRouter> {
+    let config = &ctx.config;
+    let scanner = FsLibraryScanner::new(config.sources.clone());
+    // Watching starts before the scan so a change made while it runs is still reported.
+    let watching = watch_sources(ctx, &scanner).await?;
+    let scan = scanner
+        .full_scan(config.scan_mode.clone(), &CancellationToken::new(), None)
+        .await?;
+    let index = IndexBuilder::new(config.policies.clone())
+        .build(&scan.records)
+        .await?;
+    info!(tracks = index.entries.len(), "library indexed");
+
+    let kv: Arc<dyn KvBackend> = match &ctx.kv {
+        Some(kv) => kv.clone(),
+        None => Arc::new(MemoryBackend::new()),
+    };
+    let blobs = Arc::new(BlobStore::from_config(kv.clone(), config));
+    let tags = TagOverlay::for_policy(
+        &config.policies,
+        Arc::new(KvTagPersistence::new(KvStore::new(kv))),
+    )
+    .with_shared_sources(shared_sources(&index.entries));
+    let mut transcoder = DefaultFormatTranscoder::new();
+    if let Some(permits) = config.max_concurrent_transcodes {
+        transcoder = transcoder.with_max_concurrency(permits);
+    }
+    let media = MediaEngine::new(
+        Arc::new(NoReader),
+        Arc::new(transcoder),
+        Arc::new(DefaultCoverExtractor::new()),
+        config.policies.clone(),
+    )
+    .with_blob_cache(blobs)
+    .with_stats(ctx.stats.clone());
+    let router = FileRouter::new(Arc::new(index.entries), Arc::new(media), Arc::new(tags))
+        .with_capabilities(capabilities)
+        .with_slow_op_threshold(SlowOpThreshold::from_ms(config.slow_op_threshold_ms))
+        .with_prewarm(config.prewarm_tracks)
+        .with_smart_folders(config.smart_folders.clone());
+    Ok(if watching {
+        router.with_watcher(Arc::new(scanner))
+    } else {
+        router
+    })
+}
+
+// Forwards settled changes under the watched sources to the mount's signal. Forwarding
+// ends once the scanner is dropped along with the router that holds it.
+async fn watch_sources(ctx: &MountContext, scanner: &FsLibraryScanner) -> Result<bool> {
+    if !ctx.config.sources.iter().any(|source| source.watch) {
+        return Ok(false);
+    }
+    let mut changes = scanner.subscribe();
+    scanner.watch().await?;
+    let signal = ctx.signal.clone();
+    tokio::spawn(async move {
+        loop {
+            match changes.recv().await {
+                Ok(change) => {
+                    let _ = signal.send(MountEvent::LibraryChanged(change));
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!(
+                        skipped,
+                        "library changes dropped before they were forwarded"
+                    );
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+    Ok(true)
+}
+
+// The engine holds a raw reader but serves every read through the transcoder.
+pub(crate) struct NoReader;
+
+#[async_trait]
+impl AudioReader for NoReader {
+    async fn read(&self, _track: &SourceTrack) -> Result<Vec<AudioChunk>> {
+        Err(MusFuseError::Unsupported("raw audio reads"))
+    }
+}
+
+// Turns scan records into the index a mount serves: one track per cue TRACK, per audiobook
+// chapter, or per standalone audio file. Files a cue sheet describes are only listed
+// through the sheet; tracks whose backing file is gone follow the missing-file policy, and a
+// track both split from an image and ripped on its own keeps the preferred source.
+pub struct IndexBuilder {
+    policy: PolicyConfig,
+    tags: Arc<dyn TagReader>,
+}
+
+impl IndexBuilder {
+    pub fn new(policy: PolicyConfig) -> Self {
+        let tags = Arc::new(LoftyTagReader::for_policy(&policy));
+        Self { policy, tags }
+    }
+
+    pub fn with_tag_reader(mut self, tags: Arc<dyn TagReader>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    pub async fn build(&self, records: &[ScanRecord]) -> Result<TrackIndex> {
+        let mut sheets = HashMap::new();
+        let mut imaged = HashSet::new();
+        for (position, record) in records.iter().enumerate() {
+            if !is_cue(&record.source) {
+                continue;
+            }
+            match self.map_cue(record).await {
+                Ok(index) => {
+                    imaged.extend(index.entries.iter().map(|entry| entry.source.path.clone()));
+                    sheets.insert(position, index.entries);
+                }
+                Err(err) => {
+                    warn!(path = %record.source.display(), error = %err, "skipping unreadable cue sheet");
+                }
+            }
+        }
+
+        let mut entries = Vec::new();
+        let mut numbered: HashMap<AlbumId, u32> = HashMap::new();
+        for (position, record) in records.iter().enumerate() {
+            if let Some(mapped) = sheets.remove(&position) {
+                entries.extend(mapped);
+                continue;
+            }
+            if !is_audio_file(&record.source) || imaged.contains(&record.source) {
+                continue;
+            }
+            let album = album_of(record);
+            if chapter::is_chaptered(&record.source) {
+                match chapters(&record.source, &album).await {
+                    Ok(index) if !index.entries.is_empty() => {
+                        entries.extend(index.entries);
+                        continue;
+                    }
+                    Ok(_) => {}
+                    Err(err) => {
+                        warn!(path = %record.source.display(), error = %err, "could not read chapters");
+                    }
+                }
+            }
+
+            // Untagged files are numbered in folder order after the ones before them.
+            let next = numbered.entry(album.clone()).or_default();
+            *next += 1;
+            let id = record.tracks.first().cloned().unwrap_or(TrackId {
+                album,
+                disc: 1,
+                index: *next,
+            });
+            match self.file_entry(id, &record.source).await {
+                Ok(entry) => entries.push(entry),
+                Err(err) => {
+                    warn!(path = %record.source.display(), error = %err, "skipping unreadable file");
+                }
+            }
+        }
+        let present =
+            TrackMapper::apply_missing_files(TrackIndex { entries }, self.policy.missing_files)?;
+        Ok(TrackMapper::merge_sources(
+            present.entries,
+            self.policy.source_preference,
+        ))
+    }
+
+    async fn map_cue(&self, record: &ScanRecord) -> Result<TrackIndex> {
+        let sheet = CueParser.parse_file(&record.source).await?;
+        let album = album_of(record);
+        let cue_path = record.source.clone();
+        let options = CueMapOptions {
+            probe_durations: true,
+            track_order: self.policy.cue_track_order,
+            ..CueMapOptions::default()
+        };
+        tokio::task::spawn_blocking(move || {
+            TrackMapper::from_cue_with_options(&sheet, &album, Some(&cue_path), options)
+        })
+        .await
+        .map_err(|err| MusFuseError::Media(err.to_string()))?
+    }
+
+    async fn file_entry(&self, id: TrackId, path: &Path) -> Result<TrackIndexEntry> {
+        let metadata = self.tags.read_from_file(&id, path).await?;
+        let owned = path.to_path_buf();
+        let format = tokio::task::spawn_blocking(move || crate::probe::stream_format(&owned))
+            .await
+            .map_err(|err| MusFuseError::Media(err.to_string()))?
+            .unwrap_or_default();
+        Ok(TrackIndexEntry {
+            id: id.clone(),
+            metadata,
+            source: SourceTrack {
+                id,
+                path: path.to_path_buf(),
+                cue_path: None,
+                offset_frames: 0,
+                length_frames: 0,
+                sample_rate: format.sample_rate.unwrap_or(44_100),
+                channels: format.channels.unwrap_or(2),
+            },
+        })
+    }
+}
+
+async fn chapters(path: &Path, album: &AlbumId) -> Result<TrackIndex> {
+    let path = path.to_path_buf();
+    let album = album.clone();
+    tokio::task::spawn_blocking(move || ChapterMapper::from_file(&path, &album))
+        .await
+        .map_err(|err| MusFuseError::Media(err.to_string()))?
+}
+
+// Sources behind more than one track, or behind a slice of a file, such as cue images and
+// chaptered audiobooks.
+fn shared_sources(entries: &[TrackIndexEntry]) -> HashSet<PathBuf> {
+    let mut seen = HashSet::new();
+    let mut shared = HashSet::new();
+    for entry in entries {
+        let source = &entry.source;
+        if !seen.insert(&source.path) || source.offset_frames != 0 || source.length_frames != 0 {
+            shared.insert(source.path.clone());
+        }
+    }
+    shared
+}
+
+fn is_cue(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("cue"))
+}
+
+fn album_of(record: &ScanRecord) -> AlbumId {
+    record.albums.first().cloned().unwrap_or_else(|| {
+        AlbumId(
+            record
+                .source
+                .parent()
+                .and_then(Path::file_name)
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{MissingFilePolicy, ScanMode, SourceConfig, SourcePreference};
+    use crate::fixtures::write_m4b;
+    use crate::scanner::{FsLibraryScanner, LibraryScanner};
+    use tokio_util::sync::CancellationToken;
+
+    fn write_wav(path: &Path, seconds: u32) {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 48_000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).unwrap();
+        for _ in 0..2 * 48_000 * seconds {
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    async fn scan(root: &Path) -> Vec<ScanRecord> {
+        let scanner = FsLibraryScanner::new(vec![SourceConfig {
+            path: root.to_path_buf(),
+            recursive: true,
+            watch: false,
+            follow_symlinks: false,
+        }]);
+        scanner
+            .full_scan(ScanMode::Lazy, &CancellationToken::new(), None)
+            .await
+            .unwrap()
+            .records
+    }
+
+    #[tokio::test]
+    async fn images_chapters_and_loose_files_each_become_tracks() {
+        let dir = tempfile::tempdir().unwrap();
+        let image = dir.path().join("Image");
+        let book = dir.path().join("Book");
+        let loose = dir.path().join("Loose");
+        for folder in [&image, &book, &loose] {
+            std::fs::create_dir(folder).unwrap();
+        }
+        write_wav(&image.join("disc.wav"), 3);
+        std::fs::write(
+            image.join("disc.cue"),
+            "FILE \"disc.wav\" WAVE\n  TRACK 01 AUDIO\n    INDEX 01 00:00:00\n  TRACK 02 AUDIO\n    INDEX 01 00:01:00\n",
+        )
+        .unwrap();
+        write_m4b(
+            &book.join("book.m4a"),
+            &[(0, "Opening"), (60_000, "Ending")],
+            120_000,
+        );
+        write_wav(&loose.join("a.wav"), 1);
+        write_wav(&loose.join("b.wav"), 1);
+
+        let index = IndexBuilder::new(PolicyConfig::default())
+            .build(&scan(dir.path()).await)
+            .await
+            .unwrap();
+
+        let summary: Vec<(String, u32, String)> = index
+            .entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.id.album.0.clone(),
+                    entry.id.index,
+                    entry
+                        .source
+                        .path
+                        .file_name()
+                        .unwrap()
+                        .to_string_lossy()
+                        .into_owned(),
+                )
+            })
+            .collect();
+        let expected = [
+            ("Book", 1, "book.m4a"),
+            ("Book", 2, "book.m4a"),
+            ("Image", 1, "disc.wav"),
+            ("Image", 2, "disc.wav"),
+            ("Loose", 1, "a.wav"),
+            ("Loose", 2, "b.wav"),
+        ];
+        assert_eq!(
+            summary,
+            expected
+                .iter()
+                .map(|(album, index, file)| (album.to_string(), *index, file.to_string()))
+                .collect::<Vec<_>>()
+        );
+
+        let chapter = &index.entries[1];
+        assert_eq!(chapter.metadata.title, "Ending");
+        assert_eq!(chapter.source.offset_frames, 60 * 75);
+        // The image's last track runs to the end of the probed file.
+        assert_eq!(index.entries[3].metadata.duration_ms, 2_000);
+        assert_eq!(index.entries[4].source.sample_rate, 48_000);
+        assert_eq!(index.entries[4].metadata.duration_ms, 1_000);
+    }
+
+    #[tokio::test]
+    async fn loose_files_keep_their_probed_channel_count() {
+        let dir = tempfile::tempdir().unwrap();
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 22_050,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(dir.path().join("mono.wav"), spec).unwrap();
+        for _ in 0..22_050 {
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+        write_wav(&dir.path().join("stereo.wav"), 1);
+
+        let index = IndexBuilder::new(PolicyConfig::default())
+            .build(&scan(dir.path()).await)
+            .await
+            .unwrap();
+        let formats: Vec<_> = index
+            .entries
+            .iter()
+            .map(|entry| (entry.source.channels, entry.source.sample_rate))
+            .collect();
+        assert_eq!(formats, vec![(1, 22_050), (2, 48_000)]);
+    }
+
+    #[tokio::test]
+    async fn mounts_open_a_router_over_their_scanned_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        let album = dir.path().join("Album");
+        std::fs::create_dir(&album).unwrap();
+        write_wav(&album.join("a.wav"), 1);
+        write_wav(&album.join("b.wav"), 1);
+        let ctx = MountContext::new(crate::config::MountConfig {
+            sources: vec![SourceConfig {
+                path: dir.path().to_path_buf(),
+                recursive: true,
+                watch: false,
+                follow_symlinks: false,
+            }],
+            mount_point: dir.path().join("mnt"),
+            smart_folders: vec![crate::config::SmartFolderConfig {
+                name: "Rated".into(),
+                query: crate::query::TagQuery::tag_eq(
+                    "RATING",
+                    crate::metadata::TagValue::Number(5),
+                ),
+            }],
+            ..Default::default()
+        });
+
+        let router = open_router(&ctx, AdapterCapabilities::READ_ONLY)
+            .await
+            .unwrap();
+        assert_eq!(router.list_dir_names("/").unwrap(), vec!["Album", "Smart"]);
+        assert_eq!(router.list_dir_names("/Smart").unwrap(), vec!["Rated"]);
+        let tracks = router
+            .list_dir("/Album")
+            .unwrap()
+            .into_iter()
+            .filter_map(|entry| match entry {
+                crate::filesystem::VirtualEntry::TrackFile(id, format) => Some((id, format)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(tracks.len(), 2);
+
+        let (id, format) = &tracks[0];
+        let read = router.read_track_range(id, *format, 0, 16).await.unwrap();
+        assert_eq!(ctx.stats.bytes_read(), read.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn write_through_mounts_refuse_edits_to_tracks_of_a_shared_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let image = dir.path().join("Image");
+        let loose = dir.path().join("Loose");
+        for folder in [&image, &loose] {
+            std::fs::create_dir(folder).unwrap();
+        }
+        write_wav(&image.join("disc.wav"), 3);
+        std::fs::write(
+            image.join("disc.cue"),
+            "FILE \"disc.wav\" WAVE\n  TRACK 01 AUDIO\n    INDEX 01 00:00:00\n  TRACK 02 AUDIO\n    INDEX 01 00:01:00\n",
+        )
+        .unwrap();
+        let mut flac = flac_codec::encode::FlacSampleWriter::new(
+            std::fs::File::create(loose.join("a.flac")).unwrap(),
+            flac_codec::encode::Options::default(),
+            44_100,
+            16,
+            2,
+            None,
+        )
+        .unwrap();
+        flac.write(&[0i32; 2 * 1_024]).unwrap();
+        flac.finalize().unwrap();
+        let mut config = crate::config::MountConfig {
+            sources: vec![SourceConfig {
+                path: dir.path().to_path_buf(),
+                recursive: true,
+                watch: false,
+                follow_symlinks: false,
+            }],
+            ..Default::default()
+        };
+        config.policies.write_through_tags = true;
+        let ctx = MountContext::new(config);
+        let writable = AdapterCapabilities {
+            writes: true,
+            ..AdapterCapabilities::READ_ONLY
+        };
+
+        let router = open_router(&ctx, writable).await.unwrap();
+        let first_track = |folder: &str| {
+            router
+                .list_dir(folder)
+                .unwrap()
+                .into_iter()
+                .find_map(|entry| match entry {
+                    crate::filesystem::VirtualEntry::TrackFile(id, _) => Some(id),
+                    _ => None,
+                })
+                .unwrap()
+        };
+        let delta = crate::metadata::TagDelta::set_one(
+            "TITLE",
+            crate::metadata::TagValue::Text("Edited".into()),
+        );
+
+        let err = router
+            .write_tags(&first_track("/Image"), &delta)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, MusFuseError::Unsupported(_)));
+
+        let id = first_track("/Loose");
+        router.write_tags(&id, &delta).await.unwrap();
+        let on_disk = LoftyTagReader::new()
+            .read_from_file(&id, &loose.join("a.flac"))
+            .await
+            .unwrap();
+        assert_eq!(on_disk.title, "Edited");
+    }
+
+    #[tokio::test]
+    async fn mounts_with_a_cache_dir_keep_converted_tracks_there() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = tempfile::tempdir().unwrap();
+        write_wav(&dir.path().join("a.wav"), 1);
+        let mut config = crate::config::MountConfig {
+            sources: vec![SourceConfig {
+                path: dir.path().to_path_buf(),
+                recursive: true,
+                watch: false,
+                follow_symlinks: false,
+            }],
+            cache_dir: Some(cache.path().to_path_buf()),
+            ..Default::default()
+        };
+        config.policies.lossless_strategy = crate::config::LosslessStrategy::ConvertToFlac;
+        let ctx = MountContext::new(config);
+
+        let router = open_router(&ctx, AdapterCapabilities::READ_ONLY)
+            .await
+            .unwrap();
+        let album = router.list_dir_names("/").unwrap().remove(0);
+        let id = router
+            .list_dir(&format!("/{album}"))
+            .unwrap()
+            .into_iter()
+            .find_map(|entry| match entry {
+                crate::filesystem::VirtualEntry::TrackFile(id, _) => Some(id),
+                _ => None,
+            })
+            .unwrap();
+        router.read_track(&id).await.unwrap();
+        assert_eq!(
+            std::fs::read_dir(cache.path().join("blobs"))
+                .unwrap()
+                .count(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn watched_mounts_signal_library_changes_while_the_router_lives() {
+        let dir = tempfile::tempdir().unwrap();
+        write_wav(&dir.path().join("a.wav"), 1);
+        let ctx = MountContext::new(crate::config::MountConfig {
+            sources: vec![SourceConfig {
+                path: dir.path().to_path_buf(),
+                recursive: true,
+                watch: true,
+                follow_symlinks: false,
+            }],
+            ..Default::default()
+        });
+        let mut events = ctx.signal.subscribe();
+
+        let router = open_router(&ctx, AdapterCapabilities::READ_ONLY)
+            .await
+            .unwrap();
+        let added = dir.path().join("b.wav");
+        write_wav(&added, 1);
+        let event = tokio::time::timeout(std::time::Duration::from_secs(5), events.recv())
+            .await
+            .expect("library change")
+            .unwrap();
+        assert_eq!(
+            event,
+            crate::mount::MountEvent::LibraryChanged(crate::scanner::ScanEvent::FileAdded(added))
+        );
+
+        drop(router);
+        write_wav(&dir.path().join("c.wav"), 1);
+        tokio::time::sleep(crate::scanner::DEFAULT_WATCH_DEBOUNCE * 2).await;
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn cue_tracks_of_a_missing_image_follow_the_policy() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("gone.cue"),
+            "FILE \"gone.wav\" WAVE\n  TRACK 01 AUDIO\n    TITLE \"Lost\"\n    INDEX 01 00:00:00\n",
+        )
+        .unwrap();
+        let records = scan(dir.path()).await;
+
+        let hidden = IndexBuilder::new(PolicyConfig::default())
+            .build(&records)
+            .await
+            .unwrap();
+        assert!(hidden.entries.is_empty());
+
+        let stubbed = IndexBuilder::new(PolicyConfig {
+            missing_files: MissingFilePolicy::ShowStub,
+            ..PolicyConfig::default()
+        })
+        .build(&records)
+        .await
+        .unwrap();
+        assert_eq!(
+            stubbed.entries[0].metadata.title,
+            format!("Lost {}", crate::track::UNAVAILABLE_MARKER)
+        );
+
+        let strict = IndexBuilder::new(PolicyConfig {
+            missing_files: MissingFilePolicy::Error,
+            ..PolicyConfig::default()
+        });
+        assert!(strict.build(&records).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn per_track_files_and_a_split_image_keep_one_source_per_track() {
+        let dir = tempfile::tempdir().unwrap();
+        write_wav(&dir.path().join("01.wav"), 1);
+        write_wav(&dir.path().join("02.wav"), 1);
+        write_wav(&dir.path().join("disc.wav"), 2);
+        std::fs::write(
+            dir.path().join("disc.cue"),
+            "FILE \"disc.wav\" WAVE\n  TRACK 01 AUDIO\n    INDEX 01 00:00:00\n  TRACK 02 AUDIO\n    INDEX 01 00:01:00\n",
+        )
+        .unwrap();
+        let records = scan(dir.path()).await;
+
+        for (preference, split) in [
+            (SourcePreference::PreferPerTrack, false),
+            (SourcePreference::PreferCueSplit, true),
+        ] {
+            let index = IndexBuilder::new(PolicyConfig {
+                source_preference: preference,
+                ..PolicyConfig::default()
+            })
+            .build(&records)
+            .await
+            .unwrap();
+            assert_eq!(index.entries.len(), 2, "{preference:?}");
+            assert!(
+                index
+                    .entries
+                    .iter()
+                    .all(|entry| entry.source.cue_path.is_some() == split),
+                "{preference:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LyricLine {
+    pub time_ms: u64,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Lyrics {
+    Synced(Vec<LyricLine>),
+    Plain(String),
+}
+
+impl Lyrics {
+    pub fn parse(text: &str) -> Option<Self> {
+        let text = text.trim();
+        if text.is_empty() {
+            return None;
+        }
+
+        let mut lines = Vec::new();
+        for raw in text.lines() {
+            let (stamps, lyric) = split_timestamps(raw.trim());
+            for time_ms in stamps {
+                lines.push(LyricLine {
+                    time_ms,
+                    text: lyric.to_string(),
+                });
+            }
+        }
+
+        if lines.is_empty() {
+            return Some(Lyrics::Plain(text.to_string()));
+        }
+
+        lines.sort_by_key(|line| line.time_ms);
+        Some(Lyrics::Synced(lines))
+    }
+
+    pub fn is_synced(&self) -> bool {
+        matches!(self, Lyrics::Synced(_))
+    }
+
+    pub fn to_lrc(&self) -> String {
+        match self {
+            Lyrics::Synced(lines) => lines
+                .iter()
+                .map(|line| format!("{}{}\n", format_timestamp(line.time_ms), line.text))
+                .collect(),
+            Lyrics::Plain(text) => format!("{}\n", text),
+        }
+    }
+}
+
+// A line may carry several leading `[mm:ss.xx]` stamps; ID tags like `[ar:...]` yield none.
+fn split_timestamps(line: &str) -> (Vec<u64>, &str) {
+    let mut stamps = Vec::new();
+    let mut rest = line;
+    while let Some(inner) = rest.strip_prefix('[')
+        && let Some(end) = inner.find(']')
+        && let Some(time_ms) = parse_timestamp(&inner[..end])
+    {
+        stamps.push(time_ms);
+        rest = &inner[end + 1..];
+    }
+    (stamps, rest.trim())
+}
+
+fn parse_timestamp(value: &str) -> Option<u64> {
+    let (minutes, seconds) = value.split_once(':')?;
+    let (seconds, fraction) = seconds.split_once('.').unwrap_or((seconds, ""));
+    let minutes: u64 = minutes.parse().ok()?;
+    let seconds: u64 = seconds.parse().ok()?;
+    if seconds >= 60 || fraction.len() > 3 || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let millis = format!("{:0<3}", fraction).parse::<u64>().ok()?;
+    Some((minutes * 60 + seconds) * 1_000 + millis)
+}
+
+fn format_timestamp(time_ms: u64) -> String {
+    let centis = time_ms / 10;
+    format!(
+        "[{:02}:{:02}.{:02}]",
+        centis / 6_000,
+        (centis / 100) % 60,
+        centis % 100
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn synced_lyrics_are_detected_and_sorted() {
+        let lyrics = Lyrics::parse("[ar:Someone]\n[00:12.50]second\n[00:01.00][01:02.3]first\n")
+            .expect("lyrics");
+
+        assert!(lyrics.is_synced());
+        assert_eq!(
+            lyrics.to_lrc(),
+            "[00:01.00]first\n[00:12.50]second\n[01:02.30]first\n"
+        );
+    }
+
+    #[test]
+    fn untimed_text_stays_plain() {
+        let lyrics = Lyrics::parse("  just words\nmore words  ").expect("lyrics");
+
+        assert_eq!(lyrics, Lyrics::Plain("just words\nmore words".into()));
+        assert!(Lyrics::parse(" \n ").is_none());
+    }
+}
@@ -1,28 +1,50 @@
 use async_trait::async_trait;
 use bytes::Bytes;
-use flac_codec::encode::{FlacSampleWriter, Options};
+use flac_codec::encode::{FlacStreamWriter, Options};
 use lofty::{Picture, PictureType, TaggedFileExt, read_from_path};
+use mp3lame_encoder::{Builder as Mp3Builder, FlushNoGap, InterleavedPcm, MonoPcm};
+use ogg::{PacketWriteEndInfo, PacketWriter};
+use opus_rs::{Application as OpusApplication, OpusEncoder};
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fs::{self, File};
-use std::io::{Cursor, ErrorKind, Read};
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use tokio::sync::{Semaphore, mpsc};
 use tokio::task;
+use tracing::warn;
 
 use symphonia::core::audio::SampleBuffer;
-use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::codecs::{Decoder, DecoderOptions};
 use symphonia::core::errors::Error as SymphoniaError;
-use symphonia::core::formats::FormatOptions;
-use symphonia::core::io::MediaSourceStream;
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::{MediaSource, MediaSourceStream};
 use symphonia::core::meta::MetadataOptions;
 use symphonia::core::probe::Hint;
 
-use crate::error::{MusFuseError, Result};
+use crate::artwork::{NormalizedCover, normalize_cover};
+use crate::error::{MalformedStream, MusFuseError, Result, TranscodeStage};
+use crate::format::AudioFormat;
 use crate::metadata::TrackId;
-use crate::policy::AudioFormatPolicy;
-use crate::track::SourceTrack;
+use crate::policy::{AudioFormatPolicy, LossyCodec, TargetFormat};
+use crate::probe::{WavFormat, wav_format};
+use crate::track::{SourceTrack, TrackCollection};
+
+pub mod replaygain;
 
 const DEFAULT_CHUNK_SIZE: usize = 256 * 1024; // 256 KiB
 const FALLBACK_CHUNK_DURATION_MS: u64 = 200;
+const DEFAULT_MAX_DECODE_BYTES: usize = 2 * 1024 * 1024 * 1024; // 2 GiB
+const DEFAULT_MAX_CONCURRENT_TRANSCODES: usize = 4;
+const STREAM_CHANNEL_CAPACITY: usize = 4;
+// Opus always runs at 48 kHz; 20 ms frames at that rate.
+const OPUS_SAMPLE_RATE: u32 = 48_000;
+const OPUS_FRAME_SAMPLES: usize = 960;
+const OPUS_PRE_SKIP: u16 = 312;
+const OPUS_BITRATE_BPS: i32 = 128_000;
+const OPUS_MAX_PACKET: usize = 4_000;
+const OPUS_STREAM_SERIAL: u32 = 1;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct AudioChunk {
@@ -41,6 +63,20 @@ pub enum MediaContent {
 pub struct TranscodeRequest {
     pub track: SourceTrack,
     pub policy: AudioFormatPolicy,
+    pub target_format: Option<TargetFormat>,
+    // Sample rate decoded audio is converted to before a lossless or MP3 encode; `None`
+    // keeps the source rate.
+    pub resample_to: Option<u32>,
+    // Bit depth lossless output is dithered down to; deeper targets than the source are
+    // ignored.
+    pub target_bits: Option<u16>,
+}
+
+impl TranscodeRequest {
+    // Whether the default transcoder serves this request as the source file's own bytes.
+    pub fn is_passthrough(&self) -> bool {
+        matches!(DefaultFormatTranscoder::plan(self), Ok(Output::Passthrough))
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -49,6 +85,8 @@ pub struct TranscodeResult {
     pub format: &'static str,
     pub chunks: Vec<AudioChunk>,
     pub artwork: Option<Vec<u8>>,
+    // Length of the audio actually decoded, when the transcoder had to decode it.
+    pub duration_ms: Option<u64>,
 }
 
 #[async_trait]
@@ -56,98 +94,437 @@ pub trait AudioReader: Send + Sync {
     async fn read(&self, track: &SourceTrack) -> Result<Vec<AudioChunk>>;
 }
 
+pub type ChunkReceiver = mpsc::Receiver<Result<AudioChunk>>;
+
 #[async_trait]
 pub trait FormatTranscoder: Send + Sync {
     async fn transcode(&self, request: &TranscodeRequest) -> Result<TranscodeResult>;
+
+    // Delivers chunks as they are produced. The default forwards a buffered `transcode`,
+    // so only transcoders that can do better need to override it.
+    async fn transcode_stream(&self, request: &TranscodeRequest) -> Result<ChunkReceiver> {
+        let result = self.transcode(request).await?;
+        let (sender, receiver) = mpsc::channel(result.chunks.len().max(1));
+        for chunk in result.chunks {
+            let _ = sender.try_send(Ok(chunk));
+        }
+        Ok(receiver)
+    }
+
+    // Encodes the cue-split tracks of one image back into a single stream. Joining tracks
+    // transcoded one by one is not sample-exact, so only transcoders that can decode the
+    // image in one pass support it.
+    async fn transcode_album(&self, _album: &TrackCollection) -> Result<Vec<AudioChunk>> {
+        Err(MusFuseError::Unsupported("gapless album streams"))
+    }
 }
 
 #[async_trait]
 pub trait CoverExtractor: Send + Sync {
     async fn extract(&self, track: &SourceTrack) -> Result<Option<Vec<u8>>>;
+
+    // The cover `extract` finds, fitted within `max_dim` and re-encoded as JPEG; see
+    // `normalize_cover`.
+    async fn extract_normalized(
+        &self,
+        track: &SourceTrack,
+        max_dim: u32,
+    ) -> Result<Option<NormalizedCover>> {
+        let Some(data) = self.extract(track).await? else {
+            return Ok(None);
+        };
+        task::spawn_blocking(move || normalize_cover(data, max_dim))
+            .await
+            .map(Some)
+            .map_err(|err| MusFuseError::Media(err.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeLimits {
+    pub max_decode_samples: Option<usize>,
+    pub max_decode_bytes: Option<usize>,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_decode_samples: None,
+            max_decode_bytes: Some(DEFAULT_MAX_DECODE_BYTES),
+        }
+    }
+}
+
+impl DecodeLimits {
+    pub fn unbounded() -> Self {
+        Self {
+            max_decode_samples: None,
+            max_decode_bytes: None,
+        }
+    }
+
+    fn max_samples(&self) -> Option<usize> {
+        let by_bytes = self
+            .max_decode_bytes
+            .map(|bytes| bytes / std::mem::size_of::<i32>());
+        match (self.max_decode_samples, by_bytes) {
+            (Some(samples), Some(bytes)) => Some(samples.min(bytes)),
+            (samples, bytes) => samples.or(bytes),
+        }
+    }
+
+    fn check(&self, samples: u64) -> Result<()> {
+        match self.max_samples() {
+            Some(limit) if samples > limit as u64 => Err(MusFuseError::Transcode {
+                stage: TranscodeStage::Decode,
+                message: format!(
+                    "source needs {} decoded samples, above the decode limit of {}; raise \
+                     max_decode_samples or max_decode_bytes in the transcoder's DecodeLimits to \
+                     convert it",
+                    samples, limit
+                ),
+                reason: None,
+            }),
+            _ => Ok(()),
+        }
+    }
 }
 
-pub struct DefaultFormatTranscoder;
+// How output is cut into chunks. Smaller chunks reach the reader sooner over slow
+// transports, at the cost of more messages per track.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MediaOptions {
+    pub chunk_size: usize,
+    // Spacing of chunk timestamps when the output has no fixed frame size to derive them from.
+    pub fallback_chunk_ms: u64,
+}
+
+impl Default for MediaOptions {
+    fn default() -> Self {
+        Self {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            fallback_chunk_ms: FALLBACK_CHUNK_DURATION_MS,
+        }
+    }
+}
+
+// Opt-in stand-ins for damaged files whose headers omit the stream format. A wrong guess
+// distorts timestamps, so without these the track is rejected instead.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProbeFallback {
+    pub assumed_sample_rate: Option<u32>,
+    pub assumed_channels: Option<u16>,
+}
+
+pub struct DefaultFormatTranscoder {
+    limits: DecodeLimits,
+    fallback: ProbeFallback,
+    options: MediaOptions,
+    permits: Arc<Semaphore>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CoverFilter {
+    pub min_edge: Option<u32>,
+    pub max_edge: Option<u32>,
+}
+
+impl CoverFilter {
+    fn is_active(&self) -> bool {
+        self.min_edge.is_some() || self.max_edge.is_some()
+    }
+
+    // Only the image header is parsed; undecodable images are rejected once a bound is set.
+    fn accepts(&self, bytes: &[u8]) -> Option<u64> {
+        if !self.is_active() {
+            return Some(0);
+        }
+        let size = imagesize::blob_size(bytes).ok()?;
+        let (width, height) = (size.width as u64, size.height as u64);
+        let shortest = width.min(height);
+        let longest = width.max(height);
+        if self.min_edge.is_some_and(|min| shortest < min as u64)
+            || self.max_edge.is_some_and(|max| longest > max as u64)
+        {
+            return None;
+        }
+        Some(width * height)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultCoverExtractor {
+    filter: CoverFilter,
+}
+
+// Transcoders keyed by the format they produce; formats without an entry fall back to the
+// engine's default transcoder.
+#[derive(Clone, Default)]
+pub struct TranscoderRegistry {
+    transcoders: HashMap<TargetFormat, Arc<dyn FormatTranscoder>>,
+}
+
+impl TranscoderRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(
+        &mut self,
+        format: TargetFormat,
+        transcoder: Arc<dyn FormatTranscoder>,
+    ) -> Option<Arc<dyn FormatTranscoder>> {
+        self.transcoders.insert(format, transcoder)
+    }
+
+    pub fn with(mut self, format: TargetFormat, transcoder: Arc<dyn FormatTranscoder>) -> Self {
+        self.register(format, transcoder);
+        self
+    }
+
+    pub fn get(&self, format: TargetFormat) -> Option<&Arc<dyn FormatTranscoder>> {
+        self.transcoders.get(&format)
+    }
 
-pub struct DefaultCoverExtractor;
+    pub fn formats(&self) -> impl Iterator<Item = TargetFormat> + '_ {
+        self.transcoders.keys().copied()
+    }
+}
 
-pub struct MediaEngine {
-    transcoder: Arc<dyn FormatTranscoder>,
-    cover: Arc<dyn CoverExtractor>,
+impl Default for DefaultFormatTranscoder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl DefaultFormatTranscoder {
     pub fn new() -> Self {
-        Self
-    }
-
-    fn extension_of(track: &SourceTrack) -> &'static str {
-        track
-            .path
-            .extension()
-            .and_then(|ext| ext.to_str())
-            .map(|ext| ext.to_ascii_lowercase())
-            .as_deref()
-            .map(|ext| match ext {
-                "wav" => "wav",
-                "flac" => "flac",
-                "ogg" => "ogg",
-                "mp3" => "mp3",
-                "aac" => "aac",
-                "m4a" => "m4a",
-                _ => "bin",
-            })
-            .unwrap_or("bin")
+        Self::with_limits(DecodeLimits::default())
     }
 
-    async fn passthrough(&self, track: &SourceTrack) -> Result<TranscodeResult> {
-        let format = Self::extension_of(track);
-        let track_clone = track.clone();
-        let sample_rate = track.sample_rate;
-        let channels = track.channels;
-        let chunks = task::spawn_blocking(move || {
-            Self::passthrough_chunks(track_clone.path, sample_rate, channels)
-        })
-        .await
-        .map_err(|err| MusFuseError::Media(err.to_string()))??;
+    pub fn with_limits(limits: DecodeLimits) -> Self {
+        Self {
+            limits,
+            fallback: ProbeFallback::default(),
+            options: MediaOptions::default(),
+            permits: Arc::new(Semaphore::new(DEFAULT_MAX_CONCURRENT_TRANSCODES)),
+        }
+    }
 
-        Ok(TranscodeResult {
-            track_id: track.id.clone(),
-            format,
-            chunks,
-            artwork: None,
-        })
+    pub fn with_probe_fallback(mut self, fallback: ProbeFallback) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    pub fn with_media_options(mut self, options: MediaOptions) -> Self {
+        self.options = MediaOptions {
+            chunk_size: options.chunk_size.max(1),
+            ..options
+        };
+        self
     }
 
-    async fn convert_lossless(&self, track: &SourceTrack) -> Result<TranscodeResult> {
-        let track_clone = track.clone();
-        let encoded = task::spawn_blocking(move || Self::encode_track_to_flac(&track_clone))
+    pub fn with_max_concurrency(mut self, permits: usize) -> Self {
+        self.permits = Arc::new(Semaphore::new(permits.max(1)));
+        self
+    }
+
+    // Decode/encode work shares tokio's blocking pool with the KV backend, so it is gated
+    // by its own semaphore; callers wait for a permit instead of piling up blocking tasks.
+    async fn run_blocking<T, F>(&self, work: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T> + Send + 'static,
+    {
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
             .await
-            .map_err(|err| MusFuseError::Media(err.to_string()))?
             .map_err(|err| MusFuseError::Media(err.to_string()))?;
+        task::spawn_blocking(move || {
+            let _permit = permit;
+            work()
+        })
+        .await
+        .map_err(|err| MusFuseError::Media(err.to_string()))?
+    }
 
-        let chunks = Self::chunk_bytes(
-            encoded.data,
-            DEFAULT_CHUNK_SIZE,
-            Some(encoded.sample_rate),
-            Some(encoded.channels),
-            Some(encoded.bits_per_sample),
-        );
+    fn extension_of(track: &SourceTrack) -> Result<&'static str> {
+        AudioFormat::from_path(&track.path)
+            .map(|format| format.extension())
+            .ok_or_else(|| {
+                let ext = track.path.extension().unwrap_or_default();
+                MusFuseError::UnsupportedFormat(ext.to_string_lossy().into_owned())
+            })
+    }
 
-        Ok(TranscodeResult {
-            track_id: track.id.clone(),
-            format: "flac",
-            chunks,
-            artwork: None,
+    fn sniff_dsd(path: &Path) -> Option<&'static str> {
+        let mut magic = [0u8; 4];
+        File::open(path).ok()?.read_exact(&mut magic).ok()?;
+        match &magic {
+            b"DSD " => Some("dsf"),
+            b"FRM8" => Some("dff"),
+            _ => None,
+        }
+    }
+
+    fn dsd_format(track: &SourceTrack) -> Option<&'static str> {
+        match Self::extension_of(track) {
+            Ok(format @ ("dsf" | "dff")) => Some(format),
+            Ok(_) => None,
+            Err(_) => Self::sniff_dsd(&track.path),
+        }
+    }
+
+    // Cue tracks address a range of a larger file, which cannot be served by copying bytes.
+    fn is_slice(track: &SourceTrack) -> bool {
+        track.offset_frames != 0 || track.length_frames != 0
+    }
+
+    // One track covering every track of `album`, which must be back-to-back slices of the
+    // same file. Only the last track may run to the end of the file.
+    fn album_span(album: &TrackCollection) -> Result<SourceTrack> {
+        let mut tracks: Vec<&SourceTrack> = album.tracks.iter().collect();
+        tracks.sort_by_key(|track| track.offset_frames);
+        let (first, last) = match (tracks.first(), tracks.last()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return Err(MusFuseError::Media("album has no tracks".into())),
+        };
+        for pair in tracks.windows(2) {
+            if pair[1].path != first.path {
+                return Err(MusFuseError::Media(format!(
+                    "album {} spans more than one file",
+                    album.album.0
+                )));
+            }
+            if pair[0].length_frames == 0
+                || pair[0].offset_frames + pair[0].length_frames != pair[1].offset_frames
+            {
+                return Err(MusFuseError::Media(format!(
+                    "track {} does not end where track {} starts",
+                    pair[0].id, pair[1].id
+                )));
+            }
+        }
+        let length_frames = match last.length_frames {
+            0 => 0,
+            length => last.offset_frames + length - first.offset_frames,
+        };
+        Ok(SourceTrack {
+            offset_frames: first.offset_frames,
+            length_frames,
+            ..first.clone()
+        })
+    }
+
+    // Decides once what a request produces, so the buffered and streamed paths agree.
+    fn plan(request: &TranscodeRequest) -> Result<Output> {
+        let track = &request.track;
+        let is_dsd = Self::dsd_format(track).is_some();
+        if let Some(target) = request.target_format
+            && !is_dsd
+        {
+            if !Self::is_slice(track)
+                && Self::extension_of(track).is_ok_and(|ext| ext == target.extension())
+                && !Conversion::of(request).alters(track)
+            {
+                return Ok(Output::Passthrough);
+            }
+            return match target {
+                TargetFormat::Flac => Ok(Output::Flac),
+                TargetFormat::Mp3 => Ok(Output::Mp3),
+                TargetFormat::Custom(_) => Err(MusFuseError::Unsupported(
+                    "custom target formats need a registered transcoder",
+                )),
+            };
+        }
+
+        Ok(match request.policy {
+            AudioFormatPolicy::PassthroughLossless if Self::is_slice(track) && !is_dsd => {
+                Output::Flac
+            }
+            AudioFormatPolicy::PassthroughLossy | AudioFormatPolicy::PassthroughLossless => {
+                Output::Passthrough
+            }
+            AudioFormatPolicy::ConvertLossless | AudioFormatPolicy::ConvertLossy { .. }
+                if is_dsd =>
+            {
+                Output::Passthrough
+            }
+            AudioFormatPolicy::ConvertLossless => Output::Flac,
+            AudioFormatPolicy::ConvertLossy {
+                codec: LossyCodec::Mp3,
+            } => Output::Mp3,
+            AudioFormatPolicy::ConvertLossy {
+                codec: LossyCodec::Opus,
+            } => Output::Opus,
         })
     }
 
+    fn output_format(track: &SourceTrack, output: Output) -> Result<&'static str> {
+        match output {
+            Output::Passthrough => match Self::dsd_format(track) {
+                Some(format) => Ok(format),
+                None => Self::extension_of(track),
+            },
+            Output::Flac => Ok("flac"),
+            Output::Mp3 => Ok("mp3"),
+            Output::Opus => Ok("opus"),
+        }
+    }
+
+    // Hands every chunk of `output` to `sink` as soon as it exists and returns the decoded
+    // duration. Passthrough reads the file chunk by chunk; conversions decode and encode a
+    // packet at a time, so memory stays bounded by the encoder's working set.
+    fn produce(
+        track: &SourceTrack,
+        output: Output,
+        conversion: Conversion,
+        options: MediaOptions,
+        limits: DecodeLimits,
+        fallback: ProbeFallback,
+        sink: &mut dyn FnMut(AudioChunk) -> Result<()>,
+    ) -> Result<Option<u64>> {
+        if output == Output::Passthrough {
+            Self::passthrough_chunks(
+                &track.path,
+                track.sample_rate,
+                track.channels,
+                options,
+                sink,
+            )?;
+            return Ok(None);
+        }
+
+        let decoder = PacketDecoder::open(track, limits, fallback)?;
+        let mut chunker = Chunker::new(options, sink);
+        let (frames, sample_rate) = match output {
+            Output::Flac => Self::stream_flac(
+                SampleStream::new(decoder, conversion.resample_to),
+                conversion.target_bits,
+                &mut chunker,
+            )?,
+            Output::Mp3 => Self::stream_mp3(
+                SampleStream::new(decoder, conversion.resample_to),
+                &mut chunker,
+            )?,
+            Output::Opus => Self::stream_opus(decoder, &mut chunker)?,
+            Output::Passthrough => unreachable!("passthrough is served above"),
+        };
+        chunker.finish()?;
+        Ok((sample_rate > 0).then(|| frames * 1000 / sample_rate as u64))
+    }
+
     fn passthrough_chunks(
-        path: PathBuf,
+        path: &Path,
         sample_rate: u32,
         channels: u16,
-    ) -> Result<Vec<AudioChunk>> {
-        let mut file = File::open(&path)?;
-        let mut buffer = vec![0u8; DEFAULT_CHUNK_SIZE];
+        options: MediaOptions,
+        sink: &mut dyn FnMut(AudioChunk) -> Result<()>,
+    ) -> Result<()> {
+        let mut file = File::open(path)?;
+        let mut buffer = vec![0u8; options.chunk_size];
         let mut total_bytes: usize = 0;
         let mut index: usize = 0;
         let frame_bytes = Self::bytes_per_frame(Some(channels), None);
@@ -156,19 +533,27 @@ impl DefaultFormatTranscoder {
         } else {
             None
         };
-        let mut chunks = Vec::new();
+        // A chunk is held back until the next read shows whether it is the last one.
+        let mut pending: Option<AudioChunk> = None;
 
         loop {
             let read = file.read(&mut buffer)?;
             if read == 0 {
                 break;
             }
+            if let Some(chunk) = pending.take() {
+                sink(chunk)?;
+            }
 
-            let timestamp_ms =
-                Self::offset_to_timestamp(total_bytes, frame_bytes, sample_rate_opt, index);
-            let bytes = Bytes::copy_from_slice(&buffer[..read]);
-            chunks.push(AudioChunk {
-                data: bytes,
+            let timestamp_ms = Self::offset_to_timestamp(
+                total_bytes,
+                frame_bytes,
+                sample_rate_opt,
+                index,
+                options.fallback_chunk_ms,
+            );
+            pending = Some(AudioChunk {
+                data: Bytes::copy_from_slice(&buffer[..read]),
                 timestamp_ms,
                 is_end: false,
             });
@@ -177,49 +562,11 @@ impl DefaultFormatTranscoder {
             index += 1;
         }
 
-        if let Some(last) = chunks.last_mut() {
+        if let Some(mut last) = pending {
             last.is_end = true;
+            sink(last)?;
         }
-
-        Ok(chunks)
-    }
-
-    fn chunk_bytes(
-        data: Vec<u8>,
-        chunk_size: usize,
-        sample_rate: Option<u32>,
-        channels: Option<u16>,
-        bits_per_sample: Option<u16>,
-    ) -> Vec<AudioChunk> {
-        if data.is_empty() {
-            return vec![];
-        }
-
-        let frame_bytes = Self::bytes_per_frame(channels, bits_per_sample);
-        let mut offset_bytes = 0usize;
-        let mut index = 0usize;
-
-        data.chunks(chunk_size)
-            .map(|chunk| {
-                let timestamp_ms =
-                    Self::offset_to_timestamp(offset_bytes, frame_bytes, sample_rate, index);
-                offset_bytes += chunk.len();
-                index += 1;
-                AudioChunk {
-                    data: Bytes::copy_from_slice(chunk),
-                    timestamp_ms,
-                    is_end: false,
-                }
-            })
-            .enumerate()
-            .map(|(idx, mut chunk)| {
-                let is_last = idx == (data.len() + chunk_size - 1) / chunk_size - 1;
-                if is_last {
-                    chunk.is_end = true;
-                }
-                chunk
-            })
-            .collect()
+        Ok(())
     }
 
     fn bytes_per_frame(channels: Option<u16>, bits_per_sample: Option<u16>) -> Option<usize> {
@@ -234,196 +581,312 @@ impl DefaultFormatTranscoder {
         frame_bytes: Option<usize>,
         sample_rate: Option<u32>,
         chunk_index: usize,
+        fallback_chunk_ms: u64,
     ) -> u64 {
-        if let (Some(frame_bytes), Some(sample_rate)) = (frame_bytes, sample_rate) {
-            if frame_bytes > 0 && sample_rate > 0 {
-                let frames = offset_bytes / frame_bytes;
-                return (frames as u64 * 1_000) / sample_rate as u64;
-            }
+        if let (Some(frame_bytes), Some(sample_rate)) = (frame_bytes, sample_rate)
+            && frame_bytes > 0
+            && sample_rate > 0
+        {
+            let frames = offset_bytes / frame_bytes;
+            return (frames as u64 * 1_000) / sample_rate as u64;
         }
 
-        chunk_index as u64 * FALLBACK_CHUNK_DURATION_MS
+        chunk_index as u64 * fallback_chunk_ms
     }
 
-    fn encode_track_to_flac(track: &SourceTrack) -> Result<EncodedAudio> {
-        let decoded = Self::decode_track(track)?;
-        Self::encode_flac(decoded)
+    // Encodes fixed-size FLAC blocks as samples arrive. The encoder writes frames only, so
+    // the stream header is written up front; see `flac_header`.
+    fn stream_flac(
+        mut samples: SampleStream,
+        target_bits: Option<u16>,
+        chunker: &mut Chunker,
+    ) -> Result<(u64, u32)> {
+        let media_err = |err: flac_codec::Error| MusFuseError::Media(err.to_string());
+        let sample_rate = samples.sample_rate();
+        let channels = samples.channels();
+        let mut requantizer = Requantizer::new(samples.bits_per_sample(), target_bits);
+        let depth = requantizer
+            .as_ref()
+            .map_or(samples.bits_per_sample(), |requantizer| requantizer.bits);
+        // Frame headers only name a few depths; others are widened to the next one, which
+        // loses nothing.
+        let bits = FLAC_DEPTHS
+            .into_iter()
+            .find(|&bits| bits >= depth)
+            .unwrap_or(32);
+        let widen = bits - depth;
+        let declared = samples.total_frames();
+        chunker.push(&flac_header(sample_rate, channels, bits, declared), Some(0))?;
+
+        let staged = RefCell::new(Vec::new());
+        let mut writer = FlacStreamWriter::new(Staged(&staged), Options::default());
+        let block_len = FLAC_BLOCK_FRAMES * channels as usize;
+        let mut pending: Vec<i32> = Vec::new();
+        let mut frames = 0u64;
+        let mut finished = false;
+        while !finished {
+            match samples.next()? {
+                Some(mut block) => {
+                    if let Some(requantizer) = &mut requantizer {
+                        requantizer.apply(&mut block);
+                    }
+                    if widen > 0 {
+                        block.iter_mut().for_each(|sample| *sample <<= widen);
+                    }
+                    pending.extend_from_slice(&block);
+                }
+                None => finished = true,
+            }
+            let mut start = 0;
+            while pending.len() - start >= block_len || (finished && start < pending.len()) {
+                let end = (start + block_len).min(pending.len());
+                writer
+                    .write(sample_rate, channels, bits, &pending[start..end])
+                    .map_err(media_err)?;
+                let timestamp_ms = frames * 1000 / sample_rate as u64;
+                chunker.push(&staged.take(), Some(timestamp_ms))?;
+                frames += ((end - start) / channels as usize) as u64;
+                start = end;
+            }
+            pending.drain(..start);
+        }
+        if let Some(declared) = declared
+            && declared != frames
+        {
+            warn!(
+                declared,
+                encoded = frames,
+                "decoded length differs from the one the source declared; the FLAC header total is wrong"
+            );
+        }
+        Ok((frames, sample_rate))
     }
 
-    fn decode_track(track: &SourceTrack) -> Result<DecodedAudio> {
-        let file = File::open(&track.path)?;
-        let mss = MediaSourceStream::new(Box::new(file), Default::default());
-
-        let mut hint = Hint::new();
-        if let Some(ext) = track.path.extension().and_then(|e| e.to_str()) {
-            hint.with_extension(ext);
+    fn stream_mp3(mut samples: SampleStream, chunker: &mut Chunker) -> Result<(u64, u32)> {
+        let encode_err = |message: String| MusFuseError::Transcode {
+            stage: TranscodeStage::Encode,
+            message,
+            reason: None,
+        };
+        let channels = samples.channels();
+        if channels > 2 {
+            return Err(encode_err(format!(
+                "mp3 supports at most two channels, source has {channels}"
+            )));
         }
-
-        let probed = symphonia::default::get_probe()
-            .format(
-                &hint,
-                mss,
-                &FormatOptions::default(),
-                &MetadataOptions::default(),
-            )
-            .map_err(|err| MusFuseError::Media(err.to_string()))?;
-
-        let mut format = probed.format;
-        let track_info = format
-            .default_track()
-            .ok_or_else(|| MusFuseError::Media("no default audio track".into()))?;
-
-        let codec_params = &track_info.codec_params;
-        let sample_rate = codec_params
-            .sample_rate
-            .ok_or_else(|| MusFuseError::Media("missing sample rate".into()))?;
-        let channels = codec_params
-            .channels
-            .ok_or_else(|| MusFuseError::Media("missing channel layout".into()))?;
-
-        let channel_count = channels.count() as u8;
-        if channel_count == 0 {
-            return Err(MusFuseError::Media("zero channel count".into()));
+        let sample_rate = samples.sample_rate();
+
+        let mut encoder = Mp3Builder::new()
+            .ok_or_else(|| encode_err("failed to allocate LAME encoder".into()))?
+            .with_num_channels(channels)
+            .and_then(|builder| builder.with_sample_rate(sample_rate))
+            .and_then(Mp3Builder::build)
+            .map_err(|err| encode_err(err.to_string()))?;
+
+        let shift = samples.bits_per_sample() as i32 - 16;
+        let mut frames = 0u64;
+        let mut data = Vec::new();
+        while let Some(block) = samples.next()? {
+            let pcm: Vec<i16> = block
+                .iter()
+                .map(|&sample| {
+                    let scaled = if shift >= 0 {
+                        sample >> shift
+                    } else {
+                        sample << -shift
+                    };
+                    scaled.clamp(i16::MIN as i32, i16::MAX as i32) as i16
+                })
+                .collect();
+            let count = pcm.len() / channels as usize;
+            frames += count as u64;
+            data.clear();
+            data.reserve(mp3lame_encoder::max_required_buffer_size(count));
+            let encoded = if channels == 1 {
+                encoder.encode_to_vec(MonoPcm(pcm.as_slice()), &mut data)
+            } else {
+                encoder.encode_to_vec(InterleavedPcm(pcm.as_slice()), &mut data)
+            };
+            encoded.map_err(|err| encode_err(err.to_string()))?;
+            chunker.push(&data, None)?;
         }
+        data.clear();
+        data.reserve(7_200);
+        encoder
+            .flush_to_vec::<FlushNoGap>(&mut data)
+            .map_err(|err| encode_err(err.to_string()))?;
+        chunker.push(&data, None)?;
+        Ok((frames, sample_rate))
+    }
 
-        let bits_per_sample = codec_params.bits_per_sample.unwrap_or(16) as u32;
-
-        let mut decoder = symphonia::default::get_codecs()
-            .make(codec_params, &DecoderOptions::default())
-            .map_err(|err| MusFuseError::Media(err.to_string()))?;
-
-        let start_frame = track.offset_frames;
-        let end_frame = if track.length_frames > 0 {
-            start_frame + track.length_frames
-        } else {
-            u64::MAX
+    // Writes an Ogg Opus stream (RFC 7845): OpusHead and OpusTags on their own pages, then
+    // 20 ms audio packets; the final granule position trims the padding off the last one.
+    // One frame is held back until the input ends, so the last packet is known as such.
+    fn stream_opus(mut decoder: PacketDecoder, chunker: &mut Chunker) -> Result<(u64, u32)> {
+        let encode_err = |message: String| MusFuseError::Transcode {
+            stage: TranscodeStage::Encode,
+            message,
+            reason: None,
         };
-
-        let mut current_frame: u64 = 0;
-        let mut samples: Vec<i32> = Vec::new();
-
-        loop {
-            let packet = match format.next_packet() {
-                Ok(packet) => packet,
-                Err(SymphoniaError::IoError(err))
-                    if err.kind() == std::io::ErrorKind::UnexpectedEof =>
-                {
-                    break;
+        if decoder.channels == 0 || decoder.channels > 2 {
+            return Err(encode_err(format!(
+                "opus output supports one or two channels, source has {}",
+                decoder.channels
+            )));
+        }
+        let channels = decoder.channels as usize;
+        let mut encoder =
+            OpusEncoder::new(OPUS_SAMPLE_RATE as i32, channels, OpusApplication::Audio)
+                .map_err(|err| encode_err(err.to_string()))?;
+        encoder.bitrate_bps = OPUS_BITRATE_BPS;
+
+        let scale = (1u64 << (decoder.bits_per_sample - 1)) as f32;
+        let mut resampler = LinearResampler::new(channels, decoder.sample_rate, OPUS_SAMPLE_RATE);
+        let mut writer = PacketWriter::new(Vec::new());
+        let write_err = |err: std::io::Error| encode_err(err.to_string());
+        writer
+            .write_packet(
+                opus_head(decoder.channels, decoder.sample_rate),
+                OPUS_STREAM_SERIAL,
+                PacketWriteEndInfo::EndPage,
+                0,
+            )
+            .map_err(write_err)?;
+        writer
+            .write_packet(
+                opus_tags(),
+                OPUS_STREAM_SERIAL,
+                PacketWriteEndInfo::EndPage,
+                0,
+            )
+            .map_err(write_err)?;
+        chunker.push(&std::mem::take(writer.inner_mut()), None)?;
+
+        let frame_len = OPUS_FRAME_SAMPLES * channels;
+        let mut packet = vec![0u8; OPUS_MAX_PACKET];
+        let mut pcm: Vec<f32> = Vec::new();
+        let mut written = 0u64;
+        let mut finished = false;
+        while !finished {
+            match decoder.next_packet()? {
+                Some(block) => {
+                    let block: Vec<f32> =
+                        block.iter().map(|&sample| sample as f32 / scale).collect();
+                    resampler.push(&block, &mut pcm);
                 }
-                Err(SymphoniaError::ResetRequired) => {
-                    decoder.reset();
-                    continue;
+                None => {
+                    resampler.finish(&mut pcm);
+                    finished = true;
                 }
-                Err(err) => return Err(MusFuseError::Media(err.to_string())),
-            };
-
-            let decoded = decoder
-                .decode(&packet)
-                .map_err(|err| MusFuseError::Media(err.to_string()))?;
-
-            let spec = *decoded.spec();
-            let mut sample_buf = SampleBuffer::<i32>::new(decoded.capacity() as u64, spec);
-            sample_buf.copy_interleaved_ref(decoded);
-            let buffer_samples = sample_buf.samples();
-            if buffer_samples.is_empty() {
-                current_frame += 0;
-                continue;
-            }
-
-            let frame_count = (buffer_samples.len() / channel_count as usize) as u64;
-            let buffer_start = current_frame;
-            let buffer_end = current_frame + frame_count;
-
-            let select_start = start_frame.max(buffer_start);
-            let select_end = end_frame.min(buffer_end);
-
-            if select_end > select_start {
-                let start_idx = (select_start - buffer_start) as usize * channel_count as usize;
-                let end_idx = (select_end - buffer_start) as usize * channel_count as usize;
-                samples.extend_from_slice(&buffer_samples[start_idx..end_idx]);
             }
-
-            current_frame = buffer_end;
-
-            if current_frame >= end_frame {
-                break;
+            let mut start = 0;
+            while pcm.len() - start > frame_len || (finished && start < pcm.len()) {
+                let left = pcm.len() - start;
+                let last = left <= frame_len;
+                let len = if last {
+                    let mut frame = pcm[start..].to_vec();
+                    frame.resize(frame_len, 0.0);
+                    encoder.encode(&frame, OPUS_FRAME_SAMPLES, &mut packet)
+                } else {
+                    encoder.encode(
+                        &pcm[start..start + frame_len],
+                        OPUS_FRAME_SAMPLES,
+                        &mut packet,
+                    )
+                }
+                .map_err(|err| encode_err(err.to_string()))?;
+                let (granule, info) = if last {
+                    let end = written + (left / channels) as u64;
+                    (end, PacketWriteEndInfo::EndStream)
+                } else {
+                    let end = written + OPUS_FRAME_SAMPLES as u64;
+                    (end, PacketWriteEndInfo::NormalPacket)
+                };
+                writer
+                    .write_packet(
+                        packet[..len].to_vec(),
+                        OPUS_STREAM_SERIAL,
+                        info,
+                        OPUS_PRE_SKIP as u64 + granule,
+                    )
+                    .map_err(write_err)?;
+                chunker.push(&std::mem::take(writer.inner_mut()), None)?;
+                written += OPUS_FRAME_SAMPLES as u64;
+                start += left.min(frame_len);
             }
+            pcm.drain(..start);
         }
-
-        if samples.is_empty() {
-            return Err(MusFuseError::Media("no audio samples decoded".into()));
-        }
-
-        Ok(DecodedAudio {
-            samples,
-            sample_rate,
-            channels: channel_count,
-            bits_per_sample,
-        })
-    }
-
-    fn encode_flac(decoded: DecodedAudio) -> Result<EncodedAudio> {
-        let mut cursor = Cursor::new(Vec::new());
-        {
-            let mut writer = FlacSampleWriter::new(
-                &mut cursor,
-                Options::default(),
-                decoded.sample_rate,
-                decoded.bits_per_sample,
-                decoded.channels,
-                None,
-            )
-            .map_err(|err| MusFuseError::Media(err.to_string()))?;
-
-            writer
-                .write(&decoded.samples)
-                .map_err(|err| MusFuseError::Media(err.to_string()))?;
-            writer
-                .finalize()
-                .map_err(|err| MusFuseError::Media(err.to_string()))?;
-        }
-
-        Ok(EncodedAudio {
-            data: cursor.into_inner(),
-            sample_rate: decoded.sample_rate,
-            channels: decoded.channels as u16,
-            bits_per_sample: decoded.bits_per_sample as u16,
-        })
+        Ok((decoder.frames(), decoder.sample_rate))
     }
 }
 
 impl DefaultCoverExtractor {
     pub fn new() -> Self {
-        Self
+        Self::with_filter(CoverFilter::default())
+    }
+
+    pub fn with_filter(filter: CoverFilter) -> Self {
+        Self { filter }
     }
 
-    fn extract_sync(path: PathBuf) -> Result<Option<Vec<u8>>> {
-        if let Some(bytes) = Self::extract_embedded(&path)? {
+    fn extract_sync(&self, track: SourceTrack) -> Result<Option<Vec<u8>>> {
+        if let Some(bytes) = self.extract_embedded(&track.path)? {
             return Ok(Some(bytes));
         }
-        Self::extract_external(&path)
+        self.extract_external(&Self::art_locations(&track))
+    }
+
+    // Cue-split tracks share one audio file, so their art belongs to the album: look next
+    // to the cue sheet (and the shared image) using the cue and image stems.
+    fn art_locations(track: &SourceTrack) -> Vec<(PathBuf, Vec<String>)> {
+        let stem_of = |path: &Path| {
+            path.file_stem()
+                .and_then(|stem| stem.to_str())
+                .map(str::to_string)
+        };
+        let audio_dir = track.path.parent().map(Path::to_path_buf);
+        let audio_stem = stem_of(&track.path);
+
+        let Some(cue_path) = &track.cue_path else {
+            return audio_dir
+                .map(|dir| (dir, audio_stem.into_iter().collect()))
+                .into_iter()
+                .collect();
+        };
+
+        let stems: Vec<String> = stem_of(cue_path).into_iter().chain(audio_stem).collect();
+        let mut locations = Vec::new();
+        for dir in [cue_path.parent().map(Path::to_path_buf), audio_dir]
+            .into_iter()
+            .flatten()
+        {
+            if !locations.iter().any(|(existing, _)| existing == &dir) {
+                locations.push((dir, stems.clone()));
+            }
+        }
+        locations
     }
 
-    fn extract_embedded(path: &Path) -> Result<Option<Vec<u8>>> {
+    fn extract_embedded(&self, path: &Path) -> Result<Option<Vec<u8>>> {
         let tagged = match read_from_path(path) {
             Ok(tagged) => tagged,
             Err(_) => return Ok(None),
         };
 
-        if let Some(primary) = tagged.primary_tag() {
-            if let Some(bytes) = Self::select_picture(primary.pictures()) {
-                return Ok(Some(bytes));
-            }
+        if let Some(primary) = tagged.primary_tag()
+            && let Some(bytes) = self.select_picture(primary.pictures())
+        {
+            return Ok(Some(bytes));
         }
 
-        if let Some(first) = tagged.first_tag() {
-            if let Some(bytes) = Self::select_picture(first.pictures()) {
-                return Ok(Some(bytes));
-            }
+        if let Some(first) = tagged.first_tag()
+            && let Some(bytes) = self.select_picture(first.pictures())
+        {
+            return Ok(Some(bytes));
         }
 
         for tag in tagged.tags() {
-            if let Some(bytes) = Self::select_picture(tag.pictures()) {
+            if let Some(bytes) = self.select_picture(tag.pictures()) {
                 return Ok(Some(bytes));
             }
         }
@@ -431,25 +894,34 @@ impl DefaultCoverExtractor {
         Ok(None)
     }
 
-    fn extract_external(path: &Path) -> Result<Option<Vec<u8>>> {
-        let dir = match path.parent() {
-            Some(dir) => dir,
-            None => return Ok(None),
-        };
+    fn extract_external(&self, locations: &[(PathBuf, Vec<String>)]) -> Result<Option<Vec<u8>>> {
+        let candidates = locations
+            .iter()
+            .flat_map(|(dir, stems)| Self::candidate_paths(dir, stems));
 
-        for candidate in Self::candidate_paths(dir, path.file_stem()) {
-            match fs::read(&candidate) {
-                Ok(bytes) if !bytes.is_empty() => return Ok(Some(bytes)),
+        let mut best: Option<(u64, Vec<u8>)> = None;
+        for candidate in candidates {
+            let bytes = match fs::read(&candidate) {
+                Ok(bytes) if !bytes.is_empty() => bytes,
                 Ok(_) => continue,
                 Err(err) if err.kind() == ErrorKind::NotFound => continue,
                 Err(err) => return Err(MusFuseError::Io(err)),
+            };
+
+            if !self.filter.is_active() {
+                return Ok(Some(bytes));
+            }
+            if let Some(area) = self.filter.accepts(&bytes)
+                && best.as_ref().is_none_or(|(best_area, _)| area > *best_area)
+            {
+                best = Some((area, bytes));
             }
         }
 
-        Ok(None)
+        Ok(best.map(|(_, bytes)| bytes))
     }
 
-    fn candidate_paths(dir: &Path, stem: Option<&std::ffi::OsStr>) -> Vec<PathBuf> {
+    fn candidate_paths(dir: &Path, stems: &[String]) -> Vec<PathBuf> {
         const CANDIDATES: &[&str] = &[
             "cover.jpg",
             "cover.jpeg",
@@ -463,7 +935,7 @@ impl DefaultCoverExtractor {
 
         let mut paths: Vec<PathBuf> = CANDIDATES.iter().map(|name| dir.join(name)).collect();
 
-        if let Some(stem) = stem.and_then(|s| s.to_str()) {
+        for stem in stems {
             for ext in &["jpg", "jpeg", "png", "webp"] {
                 paths.push(dir.join(format!("{}.{ext}", stem)));
             }
@@ -472,12 +944,12 @@ impl DefaultCoverExtractor {
         paths
     }
 
-    fn select_picture(pictures: &[Picture]) -> Option<Vec<u8>> {
+    fn select_picture(&self, pictures: &[Picture]) -> Option<Vec<u8>> {
         let mut front = None;
         let mut fallback = None;
 
         for picture in pictures {
-            if picture.data().is_empty() {
+            if picture.data().is_empty() || self.filter.accepts(picture.data()).is_none() {
                 continue;
             }
 
@@ -495,159 +967,1485 @@ impl DefaultCoverExtractor {
     }
 }
 
-impl MediaEngine {
-    pub fn new(transcoder: Arc<dyn FormatTranscoder>, cover: Arc<dyn CoverExtractor>) -> Self {
-        Self { transcoder, cover }
-    }
-
-    pub async fn open_stream(
-        &self,
+#[async_trait]
+impl CoverExtractor for DefaultCoverExtractor {
+    async fn extract(&self, track: &SourceTrack) -> Result<Option<Vec<u8>>> {
+        let track = track.clone();
+        let extractor = *self;
+        task::spawn_blocking(move || extractor.extract_sync(track))
+            .await
+            .map_err(|err| MusFuseError::Media(err.to_string()))?
+    }
+}
+
+#[async_trait]
+impl FormatTranscoder for DefaultFormatTranscoder {
+    async fn transcode(&self, request: &TranscodeRequest) -> Result<TranscodeResult> {
+        let output = Self::plan(request)?;
+        let format = Self::output_format(&request.track, output)?;
+        let track = request.track.clone();
+        let conversion = Conversion::of(request);
+        let options = self.options;
+        let limits = self.limits;
+        let fallback = self.fallback;
+        let (chunks, duration_ms) = self
+            .run_blocking(move || {
+                let mut chunks = Vec::new();
+                let duration_ms = Self::produce(
+                    &track,
+                    output,
+                    conversion,
+                    options,
+                    limits,
+                    fallback,
+                    &mut |chunk| {
+                        chunks.push(chunk);
+                        Ok(())
+                    },
+                )?;
+                Ok((chunks, duration_ms))
+            })
+            .await?;
+
+        Ok(TranscodeResult {
+            track_id: request.track.id.clone(),
+            format,
+            chunks,
+            artwork: None,
+            duration_ms,
+        })
+    }
+
+    // The whole span is decoded in one pass, so samples at track boundaries come out exactly
+    // as they would from decoding the image itself.
+    async fn transcode_album(&self, album: &TrackCollection) -> Result<Vec<AudioChunk>> {
+        let span = Self::album_span(album)?;
+        let options = self.options;
+        let limits = self.limits;
+        let fallback = self.fallback;
+        self.run_blocking(move || {
+            let mut chunks = Vec::new();
+            Self::produce(
+                &span,
+                Output::Flac,
+                Conversion::default(),
+                options,
+                limits,
+                fallback,
+                &mut |chunk| {
+                    chunks.push(chunk);
+                    Ok(())
+                },
+            )?;
+            Ok(chunks)
+        })
+        .await
+    }
+
+    // The worker holds a transcode permit until every chunk has been handed over, and stops
+    // early once the receiver is dropped.
+    async fn transcode_stream(&self, request: &TranscodeRequest) -> Result<ChunkReceiver> {
+        let output = Self::plan(request)?;
+        // Unknown formats are refused here too, before any bytes are passed through.
+        Self::output_format(&request.track, output)?;
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|err| MusFuseError::Media(err.to_string()))?;
+        let (sender, receiver) = mpsc::channel(STREAM_CHANNEL_CAPACITY);
+        let track = request.track.clone();
+        let conversion = Conversion::of(request);
+        let options = self.options;
+        let limits = self.limits;
+        let fallback = self.fallback;
+        task::spawn_blocking(move || {
+            let _permit = permit;
+            let produced = Self::produce(
+                &track,
+                output,
+                conversion,
+                options,
+                limits,
+                fallback,
+                &mut |chunk| {
+                    sender
+                        .blocking_send(Ok(chunk))
+                        .map_err(|_| MusFuseError::Media("chunk receiver was dropped".into()))
+                },
+            );
+            if let Err(err) = produced {
+                let _ = sender.blocking_send(Err(err));
+            }
+        });
+        Ok(receiver)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Output {
+    Passthrough,
+    Flac,
+    Mp3,
+    Opus,
+}
+
+// The sample-format changes a request asks for on top of its output format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+struct Conversion {
+    resample_to: Option<u32>,
+    target_bits: Option<u16>,
+}
+
+impl Conversion {
+    fn of(request: &TranscodeRequest) -> Self {
+        Self {
+            resample_to: request.resample_to,
+            target_bits: request.target_bits,
+        }
+    }
+
+    // The source bit depth is only known after decoding, so any depth target rules out
+    // passing the file through.
+    fn alters(&self, track: &SourceTrack) -> bool {
+        self.resample_to
+            .is_some_and(|rate| rate != track.sample_rate)
+            || self.target_bits.is_some()
+    }
+}
+
+// Serves a file with a few header bytes replaced, so a damaged header can be repaired in
+// flight without copying the audio.
+struct PatchedSource {
+    file: File,
+    len: u64,
+    pos: u64,
+    patch_offset: u64,
+    patch: Vec<u8>,
+}
+
+impl PatchedSource {
+    // symphonia panics on a WAV whose fmt chunk declares a zero sample rate, so the rate
+    // and the byte rate derived from it are overlaid before probing.
+    fn with_wav_rate(file: File, format: WavFormat, rate: u32) -> Result<Self> {
+        let mut patch = rate.to_le_bytes().to_vec();
+        patch.extend_from_slice(&rate.saturating_mul(format.block_align as u32).to_le_bytes());
+        let offset = format.rate_offset;
+        Ok(Self {
+            len: file.metadata()?.len(),
+            file,
+            pos: 0,
+            patch_offset: offset,
+            patch,
+        })
+    }
+}
+
+impl Read for PatchedSource {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.file.read(buf)?;
+        let patch_end = self.patch_offset + self.patch.len() as u64;
+        let start = self.pos.max(self.patch_offset);
+        let end = (self.pos + read as u64).min(patch_end);
+        if start < end {
+            let dst = (start - self.pos) as usize..(end - self.pos) as usize;
+            let src = (start - self.patch_offset) as usize..(end - self.patch_offset) as usize;
+            buf[dst].copy_from_slice(&self.patch[src]);
+        }
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for PatchedSource {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.pos = self.file.seek(pos)?;
+        Ok(self.pos)
+    }
+}
+
+impl MediaSource for PatchedSource {
+    fn is_seekable(&self) -> bool {
+        true
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        Some(self.len)
+    }
+}
+
+// A track's audio, decoded one packet at a time and trimmed to the track's span. Samples
+// come out at the source's own bit depth.
+struct PacketDecoder {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn Decoder>,
+    sample_rate: u32,
+    channels: u8,
+    bits_per_sample: u32,
+    // Frames the span holds, when the container declares its length up front.
+    total_frames: Option<u64>,
+    limits: DecodeLimits,
+    start_frame: u64,
+    end_frame: u64,
+    current_frame: u64,
+    decoded_samples: u64,
+    finished: bool,
+}
+
+impl PacketDecoder {
+    fn open(track: &SourceTrack, limits: DecodeLimits, fallback: ProbeFallback) -> Result<Self> {
+        let mut file = File::open(&track.path)?;
+        let source: Box<dyn MediaSource> = match wav_format(&mut file)? {
+            Some(format) if format.channels == 0 => {
+                return Err(MusFuseError::malformed(MalformedStream::ZeroChannels));
+            }
+            Some(format) if format.sample_rate == 0 => {
+                let rate = fallback
+                    .assumed_sample_rate
+                    .ok_or(MusFuseError::malformed(MalformedStream::MissingSampleRate))?;
+                warn!(path = %track.path.display(), rate, "WAV header has no sample rate, assuming fallback");
+                Box::new(PatchedSource::with_wav_rate(file, format, rate)?)
+            }
+            _ => Box::new(file),
+        };
+        let mss = MediaSourceStream::new(source, Default::default());
+
+        let mut hint = Hint::new();
+        if let Some(ext) = track.path.extension().and_then(|e| e.to_str()) {
+            hint.with_extension(ext);
+        }
+
+        let probed = symphonia::default::get_probe()
+            .format(
+                &hint,
+                mss,
+                &FormatOptions::default(),
+                &MetadataOptions::default(),
+            )
+            .map_err(|err| MusFuseError::Media(err.to_string()))?;
+
+        let format = probed.format;
+        let track_info = format
+            .default_track()
+            .ok_or(MusFuseError::malformed(MalformedStream::NoAudioTrack))?;
+
+        let codec_params = &track_info.codec_params;
+        let sample_rate = match codec_params.sample_rate.filter(|rate| *rate > 0) {
+            Some(rate) => rate,
+            None => {
+                let rate = fallback
+                    .assumed_sample_rate
+                    .ok_or(MusFuseError::malformed(MalformedStream::MissingSampleRate))?;
+                warn!(path = %track.path.display(), rate, "sample rate unknown, assuming fallback");
+                rate
+            }
+        };
+        let channels = match codec_params.channels.map(|channels| channels.count()) {
+            Some(count) if count > 0 => count as u8,
+            _ => {
+                let count = fallback
+                    .assumed_channels
+                    .filter(|count| *count > 0)
+                    .ok_or(MusFuseError::malformed(MalformedStream::ZeroChannels))?;
+                warn!(path = %track.path.display(), count, "channel count unknown, assuming fallback");
+                count as u8
+            }
+        };
+        let bits_per_sample = codec_params.bits_per_sample.unwrap_or(16).clamp(1, 32);
+
+        let decoder = symphonia::default::get_codecs()
+            .make(codec_params, &DecoderOptions::default())
+            .map_err(|err| MusFuseError::Media(err.to_string()))?;
+
+        let (start_frame, end_frame) = track.sample_span(sample_rate);
+        let end_frame = end_frame.unwrap_or(u64::MAX);
+        let total_frames = codec_params
+            .n_frames
+            .map(|total| end_frame.min(total).saturating_sub(start_frame));
+        if let Some(frames) = total_frames {
+            limits.check(frames * channels as u64)?;
+        }
+
+        Ok(Self {
+            format,
+            decoder,
+            sample_rate,
+            channels,
+            bits_per_sample,
+            total_frames,
+            limits,
+            start_frame,
+            end_frame,
+            current_frame: 0,
+            decoded_samples: 0,
+            finished: false,
+        })
+    }
+
+    fn frames(&self) -> u64 {
+        self.decoded_samples / self.channels as u64
+    }
+
+    // The samples of the next packet that fall within the span; `None` once it is exhausted.
+    fn next_packet(&mut self) -> Result<Option<Vec<i32>>> {
+        let channels = self.channels as usize;
+        while !self.finished && self.current_frame < self.end_frame {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(SymphoniaError::IoError(err)) if err.kind() == ErrorKind::UnexpectedEof => {
+                    self.finished = true;
+                    break;
+                }
+                Err(SymphoniaError::ResetRequired) => {
+                    self.decoder.reset();
+                    continue;
+                }
+                Err(err) => return Err(MusFuseError::Media(err.to_string())),
+            };
+
+            let decoded = self
+                .decoder
+                .decode(&packet)
+                .map_err(|err| MusFuseError::Media(err.to_string()))?;
+            let spec = *decoded.spec();
+            let mut sample_buf = SampleBuffer::<i32>::new(decoded.capacity() as u64, spec);
+            sample_buf.copy_interleaved_ref(decoded);
+            let buffer_samples = sample_buf.samples();
+            if buffer_samples.is_empty() {
+                continue;
+            }
+
+            let buffer_start = self.current_frame;
+            self.current_frame += (buffer_samples.len() / channels) as u64;
+            let select_start = self.start_frame.max(buffer_start);
+            let select_end = self.end_frame.min(self.current_frame);
+            if select_end > select_start {
+                let start_idx = (select_start - buffer_start) as usize * channels;
+                let end_idx = (select_end - buffer_start) as usize * channels;
+                self.decoded_samples += (end_idx - start_idx) as u64;
+                self.limits.check(self.decoded_samples)?;
+                // SampleBuffer<i32> scales every source to the full i32 range, while the
+                // encoders take samples at the source's own bit depth.
+                let scale = 32 - self.bits_per_sample;
+                return Ok(Some(
+                    buffer_samples[start_idx..end_idx]
+                        .iter()
+                        .map(|sample| sample >> scale)
+                        .collect(),
+                ));
+            }
+        }
+
+        if self.decoded_samples == 0 {
+            return Err(MusFuseError::Media("no audio samples decoded".into()));
+        }
+        Ok(None)
+    }
+}
+
+// Decoded packets, resampled on the way through when a request asks for another rate.
+struct SampleStream {
+    decoder: PacketDecoder,
+    resampler: Option<SincResampler>,
+}
+
+impl SampleStream {
+    fn new(decoder: PacketDecoder, rate: Option<u32>) -> Self {
+        let resampler = rate
+            .filter(|&rate| rate > 0 && rate != decoder.sample_rate)
+            .map(|rate| {
+                SincResampler::new(
+                    decoder.channels as usize,
+                    decoder.sample_rate,
+                    rate,
+                    decoder.bits_per_sample,
+                )
+            });
+        Self { decoder, resampler }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.resampler
+            .as_ref()
+            .map_or(self.decoder.sample_rate, |resampler| resampler.to)
+    }
+
+    fn channels(&self) -> u8 {
+        self.decoder.channels
+    }
+
+    fn bits_per_sample(&self) -> u32 {
+        self.decoder.bits_per_sample
+    }
+
+    fn total_frames(&self) -> Option<u64> {
+        let total = self.decoder.total_frames?;
+        Some(match &self.resampler {
+            Some(resampler) => total * resampler.to as u64 / resampler.from as u64,
+            None => total,
+        })
+    }
+
+    fn next(&mut self) -> Result<Option<Vec<i32>>> {
+        let Some(resampler) = &mut self.resampler else {
+            return self.decoder.next_packet();
+        };
+        loop {
+            match self.decoder.next_packet()? {
+                Some(samples) => {
+                    let out = resampler.push(&samples);
+                    if !out.is_empty() {
+                        return Ok(Some(out));
+                    }
+                }
+                None => {
+                    let out = resampler.finish();
+                    return Ok((!out.is_empty()).then_some(out));
+                }
+            }
+        }
+    }
+}
+
+const DITHER_SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+// Zero crossings of the windowed sinc on each side of the output position, measured at
+// the lower of the two rates.
+const SINC_HALF_WIDTH: f64 = 16.0;
+
+// Band-limited resampling with a Blackman-windowed sinc. The cutoff sits at the lower
+// Nyquist frequency, so downsampling does not alias; weights are normalised per output
+// frame to keep DC gain at unity near the edges. Input arrives a packet at a time and each
+// output frame is computed once every input in its reach is there, so the result does not
+// depend on how the input was split.
+struct SincResampler {
+    channels: usize,
+    from: u32,
+    to: u32,
+    step: f64,
+    cutoff: f64,
+    radius: f64,
+    min: f64,
+    max: f64,
+    // Input frames still in reach of upcoming output, the first being frame `offset`.
+    input: Vec<i32>,
+    offset: usize,
+    received: usize,
+    next_out: usize,
+    weights: Vec<f64>,
+}
+
+impl SincResampler {
+    fn new(channels: usize, from: u32, to: u32, bits: u32) -> Self {
+        let cutoff = (to as f64 / from as f64).min(1.0);
+        let max = ((1i64 << (bits.clamp(1, 32) - 1)) - 1) as f64;
+        Self {
+            channels,
+            from,
+            to,
+            step: from as f64 / to as f64,
+            cutoff,
+            radius: SINC_HALF_WIDTH / cutoff,
+            min: -max - 1.0,
+            max,
+            input: Vec::new(),
+            offset: 0,
+            received: 0,
+            next_out: 0,
+            weights: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, samples: &[i32]) -> Vec<i32> {
+        self.input.extend_from_slice(samples);
+        self.received += samples.len() / self.channels;
+        let mut out = Vec::new();
+        loop {
+            let position = self.next_out as f64 * self.step;
+            let last = (position + self.radius).floor() as usize;
+            // The second bound keeps output within the length of a whole-input conversion.
+            if last >= self.received
+                || (self.next_out as u64 + 1) * self.from as u64
+                    > self.received as u64 * self.to as u64
+            {
+                break;
+            }
+            self.emit(position, last, &mut out);
+            self.next_out += 1;
+        }
+        let first = (self.next_out as f64 * self.step - self.radius)
+            .ceil()
+            .max(0.0) as usize;
+        let first = first.min(self.received.saturating_sub(1));
+        if first > self.offset {
+            self.input.drain(..(first - self.offset) * self.channels);
+            self.offset = first;
+        }
+        out
+    }
+
+    // The rest of the output, up to the length a whole-input conversion has.
+    fn finish(&mut self) -> Vec<i32> {
+        let out_frames = (self.received as u64 * self.to as u64 / self.from as u64) as usize;
+        let mut out = Vec::new();
+        while self.next_out < out_frames {
+            let position = self.next_out as f64 * self.step;
+            let last = ((position + self.radius).floor() as usize).min(self.received - 1);
+            self.emit(position, last, &mut out);
+            self.next_out += 1;
+        }
+        out
+    }
+
+    fn emit(&mut self, position: f64, last: usize, out: &mut Vec<i32>) {
+        let (radius, cutoff) = (self.radius, self.cutoff);
+        let first = (position - radius).ceil().max(0.0) as usize;
+        self.weights.clear();
+        self.weights.extend((first..=last).map(|input| {
+            let x = position - input as f64;
+            let window = 0.42
+                + 0.5 * (std::f64::consts::PI * x / radius).cos()
+                + 0.08 * (2.0 * std::f64::consts::PI * x / radius).cos();
+            sinc(x * cutoff) * window
+        }));
+        let total: f64 = self.weights.iter().sum();
+        for channel in 0..self.channels {
+            let acc: f64 = self
+                .weights
+                .iter()
+                .zip(first..=last)
+                .map(|(weight, input)| {
+                    weight * self.input[(input - self.offset) * self.channels + channel] as f64
+                })
+                .sum();
+            let value = if total.abs() > f64::EPSILON {
+                acc / total
+            } else {
+                acc
+            };
+            out.push(value.round().clamp(self.min, self.max) as i32);
+        }
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-9 {
+        1.0
+    } else {
+        let x = std::f64::consts::PI * x;
+        x.sin() / x
+    }
+}
+
+// Linear interpolation between neighbouring frames; cheap, and adequate ahead of a lossy
+// encoder. Like `SincResampler`, it takes input a packet at a time.
+struct LinearResampler {
+    channels: usize,
+    from: u32,
+    to: u32,
+    step: f64,
+    input: Vec<f32>,
+    offset: usize,
+    received: usize,
+    next_out: usize,
+}
+
+impl LinearResampler {
+    fn new(channels: usize, from: u32, to: u32) -> Self {
+        Self {
+            channels,
+            from,
+            to,
+            step: from as f64 / to as f64,
+            input: Vec::new(),
+            offset: 0,
+            received: 0,
+            next_out: 0,
+        }
+    }
+
+    fn push(&mut self, samples: &[f32], out: &mut Vec<f32>) {
+        if self.from == self.to {
+            out.extend_from_slice(samples);
+            return;
+        }
+        self.input.extend_from_slice(samples);
+        self.received += samples.len() / self.channels;
+        loop {
+            let position = self.next_out as f64 * self.step;
+            let base = position as usize;
+            if base + 1 >= self.received
+                || (self.next_out as u64 + 1) * self.from as u64
+                    > self.received as u64 * self.to as u64
+            {
+                break;
+            }
+            self.emit(position, base, base + 1, out);
+            self.next_out += 1;
+        }
+        let first =
+            ((self.next_out as f64 * self.step) as usize).min(self.received.saturating_sub(1));
+        if first > self.offset {
+            self.input.drain(..(first - self.offset) * self.channels);
+            self.offset = first;
+        }
+    }
+
+    fn finish(&mut self, out: &mut Vec<f32>) {
+        if self.from == self.to {
+            return;
+        }
+        let out_frames = (self.received as u64 * self.to as u64 / self.from as u64) as usize;
+        while self.next_out < out_frames {
+            let position = self.next_out as f64 * self.step;
+            let base = (position as usize).min(self.received - 1);
+            let next = (base + 1).min(self.received - 1);
+            self.emit(position, base, next, out);
+            self.next_out += 1;
+        }
+    }
+
+    fn emit(&self, position: f64, base: usize, next: usize, out: &mut Vec<f32>) {
+        let weight = (position - base as f64) as f32;
+        for channel in 0..self.channels {
+            let a = self.input[(base - self.offset) * self.channels + channel];
+            let b = self.input[(next - self.offset) * self.channels + channel];
+            out.push(a + (b - a) * weight);
+        }
+    }
+}
+
+// Rounds to a lower bit depth with triangular dither of one target LSB. The noise comes
+// from a fixed-seed generator, so the same source always encodes to the same bytes.
+struct Requantizer {
+    bits: u32,
+    shift: u32,
+    min: i64,
+    max: i64,
+    mask: u64,
+    state: u64,
+}
+
+impl Requantizer {
+    // `None` unless `bits` is shallower than the source.
+    fn new(source_bits: u32, bits: Option<u16>) -> Option<Self> {
+        let bits = bits
+            .map(u32::from)
+            .filter(|&bits| bits > 0 && bits < source_bits)?;
+        let shift = source_bits - bits;
+        let max = (1i64 << (bits - 1)) - 1;
+        Some(Self {
+            bits,
+            shift,
+            min: -max - 1,
+            max,
+            mask: (1u64 << shift) - 1,
+            state: DITHER_SEED,
+        })
+    }
+
+    fn noise(&mut self) -> i64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        (self.state & self.mask) as i64
+    }
+
+    fn apply(&mut self, samples: &mut [i32]) {
+        for sample in samples {
+            let dither = self.noise() - self.noise();
+            let rounded = (*sample as i64 + dither + (1 << (self.shift - 1))) >> self.shift;
+            *sample = rounded.clamp(self.min, self.max) as i32;
+        }
+    }
+}
+
+// Cuts encoder output into chunks of `chunk_size` as it is written. A full chunk is held
+// back until more output, or the end, shows whether it is the last one.
+struct Chunker<'a> {
+    options: MediaOptions,
+    sink: &'a mut dyn FnMut(AudioChunk) -> Result<()>,
+    filling: Vec<u8>,
+    filling_ms: u64,
+    sealed: Option<AudioChunk>,
+    index: usize,
+}
+
+impl<'a> Chunker<'a> {
+    fn new(options: MediaOptions, sink: &'a mut dyn FnMut(AudioChunk) -> Result<()>) -> Self {
+        Self {
+            options,
+            sink,
+            filling: Vec::new(),
+            filling_ms: 0,
+            sealed: None,
+            index: 0,
+        }
+    }
+
+    // `timestamp_ms` is where the written audio starts; a chunk takes the timestamp of the
+    // write its first byte came from. Without one, chunks are spaced by the fallback
+    // duration.
+    fn push(&mut self, mut data: &[u8], timestamp_ms: Option<u64>) -> Result<()> {
+        while !data.is_empty() {
+            if self.filling.is_empty() {
+                self.filling_ms =
+                    timestamp_ms.unwrap_or(self.index as u64 * self.options.fallback_chunk_ms);
+            }
+            let take = (self.options.chunk_size - self.filling.len()).min(data.len());
+            self.filling.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.filling.len() == self.options.chunk_size {
+                self.seal()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn seal(&mut self) -> Result<()> {
+        let data = std::mem::replace(
+            &mut self.filling,
+            Vec::with_capacity(self.options.chunk_size),
+        );
+        self.index += 1;
+        let chunk = AudioChunk {
+            data: Bytes::from(data),
+            timestamp_ms: self.filling_ms,
+            is_end: false,
+        };
+        match self.sealed.replace(chunk) {
+            Some(previous) => (self.sink)(previous),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(mut self) -> Result<()> {
+        if !self.filling.is_empty() {
+            self.seal()?;
+        }
+        match self.sealed.take() {
+            Some(mut last) => {
+                last.is_end = true;
+                (self.sink)(last)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+// Every FLAC block but the last holds this many frames.
+const FLAC_BLOCK_FRAMES: usize = 4_096;
+// Depths a FLAC frame header can name.
+const FLAC_DEPTHS: [u32; 6] = [8, 12, 16, 20, 24, 32];
+
+// "fLaC" and a lone STREAMINFO block. The block size is fixed; frame sizes and the MD5 are
+// left unknown, and so is the total when the source did not declare its length.
+fn flac_header(sample_rate: u32, channels: u8, bits: u32, frames: Option<u64>) -> Vec<u8> {
+    let mut header = b"fLaC".to_vec();
+    // Last metadata block, type 0, 34 bytes long.
+    header.extend_from_slice(&[0x80, 0, 0, 34]);
+    let block = (FLAC_BLOCK_FRAMES as u16).to_be_bytes();
+    header.extend_from_slice(&block);
+    header.extend_from_slice(&block);
+    header.extend_from_slice(&[0; 6]);
+    let total = frames.filter(|&frames| frames < 1 << 36).unwrap_or(0);
+    let packed =
+        (sample_rate as u64) << 44 | (channels as u64 - 1) << 41 | (bits as u64 - 1) << 36 | total;
+    header.extend_from_slice(&packed.to_be_bytes());
+    header.extend_from_slice(&[0; 16]);
+    header
+}
+
+// Lets the FLAC encoder write into a buffer that is drained after every block.
+struct Staged<'a>(&'a RefCell<Vec<u8>>);
+
+impl Write for Staged<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn opus_head(channels: u8, input_rate: u32) -> Vec<u8> {
+    let mut head = b"OpusHead".to_vec();
+    head.push(1);
+    head.push(channels);
+    head.extend_from_slice(&OPUS_PRE_SKIP.to_le_bytes());
+    head.extend_from_slice(&input_rate.to_le_bytes());
+    head.extend_from_slice(&0i16.to_le_bytes());
+    head.push(0);
+    head
+}
+
+fn opus_tags() -> Vec<u8> {
+    let vendor = concat!("MusFuse ", env!("CARGO_PKG_VERSION"));
+    let mut tags = b"OpusTags".to_vec();
+    tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    tags.extend_from_slice(vendor.as_bytes());
+    tags.extend_from_slice(&0u32.to_le_bytes());
+    tags
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::{AlbumId, TrackId};
+    use flac_codec::encode::FlacSampleWriter;
+    use std::fs;
+    use std::io::Cursor;
+    use std::path::Path;
+    use std::sync::Arc;
+    use tempfile::tempdir;
+
+    fn write_test_wav(path: &Path, frames: usize) {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 44_100,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).expect("create wav");
+        for _ in 0..frames {
+            writer.write_sample(0i16).expect("write left");
+            writer.write_sample(0i16).expect("write right");
+        }
+        writer.finalize().expect("finalize wav");
+    }
+
+    struct DecodedAudio {
+        samples: Vec<i32>,
+        sample_rate: u32,
+        channels: u8,
+        bits_per_sample: u32,
+    }
+
+    fn decode_all(
         track: &SourceTrack,
-        policy: AudioFormatPolicy,
-    ) -> Result<TranscodeResult> {
+        limits: DecodeLimits,
+        fallback: ProbeFallback,
+    ) -> Result<DecodedAudio> {
+        let mut decoder = PacketDecoder::open(track, limits, fallback)?;
+        let mut samples = Vec::new();
+        while let Some(packet) = decoder.next_packet()? {
+            samples.extend(packet);
+        }
+        Ok(DecodedAudio {
+            samples,
+            sample_rate: decoder.sample_rate,
+            channels: decoder.channels,
+            bits_per_sample: decoder.bits_per_sample,
+        })
+    }
+
+    // Test images are written in one go by the seeking encoder, as a ripper would.
+    fn encode_flac(decoded: DecodedAudio) -> Result<Vec<u8>> {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut writer = FlacSampleWriter::new(
+            &mut cursor,
+            Options::default(),
+            decoded.sample_rate,
+            decoded.bits_per_sample,
+            decoded.channels,
+            None,
+        )
+        .map_err(|err| MusFuseError::Media(err.to_string()))?;
+        writer
+            .write(&decoded.samples)
+            .map_err(|err| MusFuseError::Media(err.to_string()))?;
+        writer
+            .finalize()
+            .map_err(|err| MusFuseError::Media(err.to_string()))?;
+        Ok(cursor.into_inner())
+    }
+
+    fn chunked(
+        pieces: &[&[u8]],
+        options: MediaOptions,
+        timestamp_ms: Option<u64>,
+    ) -> Vec<AudioChunk> {
+        let mut chunks = Vec::new();
+        let mut sink = |chunk| {
+            chunks.push(chunk);
+            Ok(())
+        };
+        let mut chunker = Chunker::new(options, &mut sink);
+        for piece in pieces {
+            chunker.push(piece, timestamp_ms).expect("push");
+        }
+        chunker.finish().expect("finish");
+        chunks
+    }
+
+    fn make_track(path: &Path) -> SourceTrack {
+        SourceTrack {
+            id: TrackId {
+                album: AlbumId("album".into()),
+                disc: 1,
+                index: 1,
+            },
+            path: path.to_path_buf(),
+            cue_path: None,
+            offset_frames: 0,
+            length_frames: 0,
+            sample_rate: 44_100,
+            channels: 2,
+        }
+    }
+
+    #[tokio::test]
+    async fn passthrough_lossless_returns_original_wav() {
+        let dir = tempdir().expect("tempdir");
+        let wav_path = dir.path().join("sample.wav");
+        write_test_wav(&wav_path, 1_000);
+
+        let transcoder = DefaultFormatTranscoder::new();
         let request = TranscodeRequest {
-            track: track.clone(),
-            policy,
+            track: make_track(&wav_path),
+            policy: AudioFormatPolicy::PassthroughLossless,
+            target_format: None,
+            resample_to: None,
+            target_bits: None,
+        };
+
+        let result = transcoder.transcode(&request).await.expect("transcode");
+        assert_eq!(result.track_id, request.track.id);
+        assert_eq!(result.format, "wav");
+        assert_eq!(result.chunks.len(), 1);
+        let data = &result.chunks[0].data;
+        assert!(data.starts_with(b"RIFF"));
+        assert!(result.chunks[0].is_end);
+    }
+
+    #[tokio::test]
+    async fn passthrough_keeps_lossless_extensions_and_rejects_unknown_ones() {
+        let dir = tempdir().expect("tempdir");
+        let transcoder = DefaultFormatTranscoder::new();
+        let request = |path: &Path| TranscodeRequest {
+            track: make_track(path),
+            policy: AudioFormatPolicy::PassthroughLossless,
+            target_format: None,
+            resample_to: None,
+            target_bits: None,
+        };
+
+        let ape = dir.path().join("album.ape");
+        std::fs::write(&ape, b"MAC \x96\x0f").unwrap();
+        let result = transcoder.transcode(&request(&ape)).await.expect("ape");
+        assert_eq!(result.format, "ape");
+
+        let unknown = dir.path().join("album.xyz");
+        std::fs::write(&unknown, b"????").unwrap();
+        let err = transcoder
+            .transcode(&request(&unknown))
+            .await
+            .expect_err("unknown extension");
+        assert!(matches!(err, MusFuseError::UnsupportedFormat(ext) if ext == "xyz"));
+        let err = transcoder
+            .transcode_stream(&request(&unknown))
+            .await
+            .expect_err("unknown extension");
+        assert!(matches!(err, MusFuseError::UnsupportedFormat(_)));
+    }
+
+    #[tokio::test]
+    async fn cue_split_passthrough_returns_only_the_track_range() {
+        let dir = tempdir().expect("tempdir");
+        let wav_path = dir.path().join("disc.wav");
+        write_test_wav(&wav_path, 44_100);
+
+        // 0.2 s in, 0.4 s long.
+        let mut track = make_track(&wav_path);
+        track.cue_path = Some(dir.path().join("disc.cue"));
+        track.offset_frames = 15;
+        track.length_frames = 30;
+
+        let transcoder = DefaultFormatTranscoder::new();
+        let request = TranscodeRequest {
+            track,
+            policy: AudioFormatPolicy::PassthroughLossless,
+            target_format: None,
+            resample_to: None,
+            target_bits: None,
+        };
+        let result = transcoder.transcode(&request).await.expect("transcode");
+        assert_eq!(result.format, "flac");
+
+        let out_path = dir.path().join("track.flac");
+        let data: Vec<u8> = result
+            .chunks
+            .iter()
+            .flat_map(|chunk| chunk.data.iter().copied())
+            .collect();
+        fs::write(&out_path, data).expect("write output");
+        let length = crate::probe::decoded_frames(&out_path).expect("decode output");
+        assert_eq!(length.frames, 17_640);
+    }
+
+    #[tokio::test]
+    async fn mapped_cue_tracks_decode_to_their_cue_duration() {
+        let dir = tempdir().expect("tempdir");
+        write_test_wav(&dir.path().join("disc.wav"), 3 * 44_100);
+        let cue = "FILE \"disc.wav\" WAVE\n  TRACK 01 AUDIO\n    INDEX 01 00:00:00\n  TRACK 02 AUDIO\n    INDEX 01 00:02:00\n";
+        let sheet = crate::cue::CueParser
+            .parse_str(cue, dir.path())
+            .expect("parse cue");
+        let index = crate::track::TrackMapper::from_cue(
+            &sheet,
+            &AlbumId("album".into()),
+            Some(&dir.path().join("disc.cue")),
+        )
+        .expect("map cue");
+
+        let transcoder = DefaultFormatTranscoder::new();
+        let mut decoded = Vec::new();
+        for entry in &index.entries {
+            let request = TranscodeRequest {
+                track: entry.source.clone(),
+                policy: AudioFormatPolicy::PassthroughLossless,
+                target_format: None,
+                resample_to: None,
+                target_bits: None,
+            };
+            let result = transcoder.transcode(&request).await.expect("transcode");
+            let out_path = dir.path().join(format!("{}.flac", entry.id.index));
+            let data: Vec<u8> = result
+                .chunks
+                .iter()
+                .flat_map(|chunk| chunk.data.iter().copied())
+                .collect();
+            fs::write(&out_path, data).expect("write output");
+            decoded.push(
+                crate::probe::decoded_frames(&out_path)
+                    .expect("decode output")
+                    .duration_ms(),
+            );
+        }
+        // The open-ended last track runs to the end of the file.
+        assert_eq!(index.entries[0].metadata.duration_ms, 2_000);
+        assert_eq!(decoded, vec![2_000, 1_000]);
+    }
+
+    #[test]
+    fn probed_last_track_decodes_to_the_end_of_the_file() {
+        let dir = tempdir().expect("tempdir");
+        // Not a whole number of CD frames long.
+        write_test_wav(&dir.path().join("disc.wav"), 2 * 44_100 + 100);
+        let cue = "FILE \"disc.wav\" WAVE\n  TRACK 01 AUDIO\n    INDEX 01 00:00:00\n  TRACK 02 AUDIO\n    INDEX 01 00:01:00\n";
+        let sheet = crate::cue::CueParser
+            .parse_str(cue, dir.path())
+            .expect("parse cue");
+        let index = crate::track::TrackMapper::from_cue_with_durations(
+            &sheet,
+            &AlbumId("album".into()),
+            None,
+        )
+        .expect("map cue");
+
+        let frames: Vec<usize> = index
+            .entries
+            .iter()
+            .map(|entry| {
+                let decoded = decode_all(
+                    &entry.source,
+                    DecodeLimits::default(),
+                    ProbeFallback::default(),
+                )
+                .expect("decode track");
+                decoded.samples.len() / decoded.channels as usize
+            })
+            .collect();
+        assert_eq!(frames, vec![44_100, 44_200]);
+        assert_eq!(index.entries[1].metadata.duration_ms, 1_013);
+    }
+
+    #[test]
+    fn known_file_length_gives_the_last_track_its_decoded_length() {
+        let dir = tempdir().expect("tempdir");
+        let wav_path = dir.path().join("disc.wav");
+        write_test_wav(&wav_path, 3 * 44_100);
+        let cue = "FILE \"disc.wav\" WAVE\n  TRACK 01 AUDIO\n    INDEX 01 00:00:00\n  TRACK 02 AUDIO\n    INDEX 01 00:02:00\n";
+        let sheet = crate::cue::CueParser
+            .parse_str(cue, dir.path())
+            .expect("parse cue");
+        let lengths = HashMap::from([(
+            wav_path.clone(),
+            crate::probe::total_frames(&wav_path)
+                .expect("probe")
+                .cue_frames(),
+        )]);
+        let index = crate::track::TrackMapper::from_cue_with_file_frames(
+            &sheet,
+            &AlbumId("album".into()),
+            None,
+            &lengths,
+        )
+        .expect("map cue");
+
+        let last = &index.entries[1];
+        assert_eq!(last.metadata.duration_ms, 1_000);
+        let decoded = decode_all(
+            &last.source,
+            DecodeLimits::default(),
+            ProbeFallback::default(),
+        )
+        .expect("decode track");
+        assert_eq!(decoded.samples.len() / decoded.channels as usize, 44_100);
+    }
+
+    #[tokio::test]
+    async fn probe_fallback_fills_in_missing_sample_rate() {
+        let dir = tempdir().expect("tempdir");
+        let wav_path = dir.path().join("damaged.wav");
+        write_test_wav(&wav_path, 1_000);
+        // Zero the fmt chunk's sample rate and byte rate.
+        let mut wav = fs::read(&wav_path).expect("read wav");
+        wav[24..32].fill(0);
+        fs::write(&wav_path, wav).expect("write wav");
+
+        let request = TranscodeRequest {
+            track: make_track(&wav_path),
+            policy: AudioFormatPolicy::ConvertLossless,
+            target_format: None,
+            resample_to: None,
+            target_bits: None,
         };
 
-        let mut result = self.transcoder.transcode(&request).await?;
-        if result.artwork.is_none() {
-            result.artwork = self.cover.extract(track).await?;
+        let strict = DefaultFormatTranscoder::new();
+        assert!(strict.transcode(&request).await.is_err());
+
+        let lenient = DefaultFormatTranscoder::new().with_probe_fallback(ProbeFallback {
+            assumed_sample_rate: Some(44_100),
+            assumed_channels: None,
+        });
+        let result = lenient.transcode(&request).await.expect("transcode");
+        assert_eq!(result.format, "flac");
+
+        let out_path = dir.path().join("recovered.flac");
+        let data: Vec<u8> = result
+            .chunks
+            .iter()
+            .flat_map(|chunk| chunk.data.iter().copied())
+            .collect();
+        fs::write(&out_path, data).expect("write output");
+        let length = crate::probe::header_frames(&out_path)
+            .expect("read header")
+            .expect("length");
+        assert_eq!(length.sample_rate, 44_100);
+        assert_eq!(length.frames, 1_000);
+    }
+
+    #[tokio::test]
+    async fn zero_channel_wav_is_reported_as_a_malformed_probe() {
+        let dir = tempdir().expect("tempdir");
+        let wav_path = dir.path().join("broken.wav");
+        write_test_wav(&wav_path, 1_000);
+        let mut wav = fs::read(&wav_path).expect("read wav");
+        wav[22..24].fill(0);
+        fs::write(&wav_path, wav).expect("write wav");
+
+        let request = TranscodeRequest {
+            track: make_track(&wav_path),
+            policy: AudioFormatPolicy::ConvertLossless,
+            target_format: None,
+            resample_to: None,
+            target_bits: None,
+        };
+        let err = DefaultFormatTranscoder::new()
+            .transcode(&request)
+            .await
+            .expect_err("zero channels cannot be decoded");
+
+        assert!(matches!(
+            err,
+            MusFuseError::Transcode {
+                stage: TranscodeStage::Probe,
+                ..
+            }
+        ));
+        assert_eq!(err.malformed_reason(), Some(MalformedStream::ZeroChannels));
+        assert!(matches!(
+            crate::probe::check_stream(&wav_path)
+                .unwrap_err()
+                .malformed_reason(),
+            Some(MalformedStream::ZeroChannels)
+        ));
+    }
+
+    #[tokio::test]
+    async fn convert_lossless_outputs_flac() {
+        let dir = tempdir().expect("tempdir");
+        let wav_path = dir.path().join("sample.wav");
+        write_test_wav(&wav_path, 1_000);
+
+        let transcoder = DefaultFormatTranscoder::new();
+        let request = TranscodeRequest {
+            track: make_track(&wav_path),
+            policy: AudioFormatPolicy::ConvertLossless,
+            target_format: None,
+            resample_to: None,
+            target_bits: None,
+        };
+
+        let result = transcoder.transcode(&request).await.expect("transcode");
+        assert_eq!(result.track_id, request.track.id);
+        assert_eq!(result.format, "flac");
+        assert_eq!(result.chunks.len(), 1);
+        let data = &result.chunks[0].data;
+        assert!(data.starts_with(b"fLaC"));
+        assert!(result.chunks[0].is_end);
+    }
+
+    #[tokio::test]
+    async fn convert_lossy_opus_outputs_a_decodable_ogg_stream() {
+        let dir = tempdir().expect("tempdir");
+        let wav_path = dir.path().join("sample.wav");
+        write_test_wav(&wav_path, 44_100);
+
+        let request = TranscodeRequest {
+            track: make_track(&wav_path),
+            policy: AudioFormatPolicy::ConvertLossy {
+                codec: LossyCodec::Opus,
+            },
+            target_format: None,
+            resample_to: None,
+            target_bits: None,
+        };
+        let result = DefaultFormatTranscoder::new()
+            .transcode(&request)
+            .await
+            .expect("transcode");
+        assert_eq!(result.format, "opus");
+        assert_eq!(result.duration_ms, Some(1_000));
+
+        let data: Vec<u8> = result
+            .chunks
+            .iter()
+            .flat_map(|chunk| chunk.data.iter().copied())
+            .collect();
+        let mut reader = ogg::PacketReader::new(Cursor::new(data));
+        let head = reader.read_packet().expect("read").expect("OpusHead");
+        assert!(head.data.starts_with(b"OpusHead"));
+        assert_eq!(head.data[9], 2);
+        let tags = reader.read_packet().expect("read").expect("OpusTags");
+        assert!(tags.data.starts_with(b"OpusTags"));
+
+        let mut decoder = opus_rs::OpusDecoder::new(48_000, 2).expect("decoder");
+        let mut pcm = vec![0f32; OPUS_FRAME_SAMPLES * 2];
+        let mut packets = 0;
+        let mut last_granule = 0;
+        while let Some(packet) = reader.read_packet().expect("read") {
+            let decoded = decoder
+                .decode(&packet.data, OPUS_FRAME_SAMPLES, &mut pcm)
+                .expect("decode");
+            assert_eq!(decoded, OPUS_FRAME_SAMPLES);
+            packets += 1;
+            last_granule = packet.absgp_page();
         }
-
-        Ok(result)
+        assert_eq!(packets, 50);
+        assert_eq!(last_granule, OPUS_PRE_SKIP as u64 + 48_000);
     }
-}
 
-#[async_trait]
-impl CoverExtractor for DefaultCoverExtractor {
-    async fn extract(&self, track: &SourceTrack) -> Result<Option<Vec<u8>>> {
-        let path = track.path.clone();
-        task::spawn_blocking(move || Self::extract_sync(path))
-            .await
-            .map_err(|err| MusFuseError::Media(err.to_string()))?
-    }
-}
+    #[tokio::test]
+    async fn streamed_chunks_match_the_buffered_transcode() {
+        let dir = tempdir().expect("tempdir");
+        let wav_path = dir.path().join("sample.wav");
+        // Large enough for the passthrough to span several chunks.
+        write_test_wav(&wav_path, 100_000);
 
-#[async_trait]
-impl FormatTranscoder for DefaultFormatTranscoder {
-    async fn transcode(&self, request: &TranscodeRequest) -> Result<TranscodeResult> {
-        match request.policy {
-            AudioFormatPolicy::PassthroughLossy | AudioFormatPolicy::PassthroughLossless => {
-                self.passthrough(&request.track).await
+        let transcoder = DefaultFormatTranscoder::new();
+        for policy in [
+            AudioFormatPolicy::PassthroughLossless,
+            AudioFormatPolicy::ConvertLossless,
+        ] {
+            let request = TranscodeRequest {
+                track: make_track(&wav_path),
+                policy,
+                target_format: None,
+                resample_to: None,
+                target_bits: None,
+            };
+            let buffered = transcoder.transcode(&request).await.expect("transcode");
+
+            let mut receiver = transcoder.transcode_stream(&request).await.expect("stream");
+            let mut streamed = Vec::new();
+            while let Some(chunk) = receiver.recv().await {
+                streamed.push(chunk.expect("chunk"));
             }
-            AudioFormatPolicy::ConvertLossless => self.convert_lossless(&request.track).await,
+            assert_eq!(streamed, buffered.chunks, "{:?}", request.policy);
+            assert!(streamed.last().is_some_and(|chunk| chunk.is_end));
         }
     }
-}
-
-struct DecodedAudio {
-    samples: Vec<i32>,
-    sample_rate: u32,
-    channels: u8,
-    bits_per_sample: u32,
-}
-
-struct EncodedAudio {
-    data: Vec<u8>,
-    sample_rate: u32,
-    channels: u16,
-    bits_per_sample: u16,
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::metadata::{AlbumId, TrackId};
-    use std::fs;
-    use std::io::Write;
-    use std::path::Path;
-    use std::sync::Arc;
-    use tempfile::tempdir;
+    #[tokio::test]
+    async fn decode_guard_rejects_sources_above_the_sample_cap() {
+        let dir = tempdir().expect("tempdir");
+        let wav_path = dir.path().join("huge.wav");
+        write_test_wav(&wav_path, 1_000);
 
-    fn write_test_wav(path: &Path, frames: usize) {
-        let spec = hound::WavSpec {
-            channels: 2,
-            sample_rate: 44_100,
-            bits_per_sample: 16,
-            sample_format: hound::SampleFormat::Int,
+        let transcoder = DefaultFormatTranscoder::with_limits(DecodeLimits {
+            max_decode_samples: Some(100),
+            max_decode_bytes: None,
+        });
+        let request = TranscodeRequest {
+            track: make_track(&wav_path),
+            policy: AudioFormatPolicy::ConvertLossless,
+            target_format: None,
+            resample_to: None,
+            target_bits: None,
         };
-        let mut writer = hound::WavWriter::create(path, spec).expect("create wav");
-        for _ in 0..frames {
-            writer.write_sample(0i16).expect("write left");
-            writer.write_sample(0i16).expect("write right");
-        }
-        writer.finalize().expect("finalize wav");
-    }
 
-    fn make_track(path: &Path) -> SourceTrack {
-        SourceTrack {
-            id: TrackId {
-                album: AlbumId("album".into()),
-                disc: 1,
-                index: 1,
-            },
-            path: path.to_path_buf(),
-            cue_path: None,
-            offset_frames: 0,
-            length_frames: 0,
-            sample_rate: 44_100,
-            channels: 2,
-        }
+        let err = transcoder.transcode(&request).await.unwrap_err();
+        assert!(matches!(
+            err,
+            MusFuseError::Transcode {
+                stage: TranscodeStage::Decode,
+                ..
+            }
+        ));
+        assert!(err.to_string().contains("above the decode limit"));
+        assert!(err.to_string().contains("max_decode_bytes"));
     }
 
     #[tokio::test]
-    async fn passthrough_lossless_returns_original_wav() {
+    async fn target_format_overrides_policy_per_request() {
         let dir = tempdir().expect("tempdir");
         let wav_path = dir.path().join("sample.wav");
-        write_test_wav(&wav_path, 1_000);
+        write_test_wav(&wav_path, 4_096);
 
         let transcoder = DefaultFormatTranscoder::new();
-        let request = TranscodeRequest {
+        let mut request = TranscodeRequest {
             track: make_track(&wav_path),
             policy: AudioFormatPolicy::PassthroughLossless,
+            target_format: Some(TargetFormat::Flac),
+            resample_to: None,
+            target_bits: None,
         };
 
-        let result = transcoder.transcode(&request).await.expect("transcode");
-        assert_eq!(result.track_id, request.track.id);
-        assert_eq!(result.format, "wav");
-        assert_eq!(result.chunks.len(), 1);
-        let data = &result.chunks[0].data;
-        assert!(data.starts_with(b"RIFF"));
-        assert!(result.chunks[0].is_end);
+        let flac = transcoder.transcode(&request).await.expect("flac");
+        assert_eq!(flac.format, "flac");
+        assert!(flac.chunks[0].data.starts_with(b"fLaC"));
+
+        request.target_format = Some(TargetFormat::Mp3);
+        let mp3 = transcoder.transcode(&request).await.expect("mp3");
+        assert_eq!(mp3.format, "mp3");
+        let data = &mp3.chunks[0].data;
+        assert!(data.len() > 2 && data[0] == 0xFF && data[1] & 0xE0 == 0xE0);
     }
 
     #[tokio::test]
-    async fn convert_lossless_outputs_flac() {
+    async fn resample_to_sets_the_encoded_sample_rate() {
         let dir = tempdir().expect("tempdir");
         let wav_path = dir.path().join("sample.wav");
-        write_test_wav(&wav_path, 1_000);
+        write_test_wav(&wav_path, 44_100);
 
         let transcoder = DefaultFormatTranscoder::new();
+        let mut request = TranscodeRequest {
+            track: make_track(&wav_path),
+            policy: AudioFormatPolicy::ConvertLossless,
+            target_format: None,
+            resample_to: Some(22_050),
+            target_bits: None,
+        };
+        // STREAMINFO: 20-bit sample rate, then channels, depth and a 36-bit frame count.
+        let stream_info = |bytes: &[u8]| {
+            let info = &bytes[8..];
+            let rate = (info[10] as u32) << 12 | (info[11] as u32) << 4 | (info[12] as u32) >> 4;
+            let frames = ((info[13] & 0x0F) as u64) << 32
+                | u32::from_be_bytes([info[14], info[15], info[16], info[17]]) as u64;
+            (rate, frames)
+        };
+
+        let resampled = transcoder.transcode(&request).await.expect("resampled");
+        let bytes: Vec<u8> = resampled
+            .chunks
+            .iter()
+            .flat_map(|chunk| chunk.data.iter().copied())
+            .collect();
+        assert_eq!(stream_info(&bytes), (22_050, 22_050));
+        assert_eq!(resampled.duration_ms, Some(1_000));
+
+        request.resample_to = Some(44_100);
+        let unchanged = transcoder.transcode(&request).await.expect("same rate");
+        assert_eq!(stream_info(&unchanged.chunks[0].data), (44_100, 44_100));
+    }
+
+    #[tokio::test]
+    async fn target_bits_dithers_24_bit_sources_down_to_16() {
+        let dir = tempdir().expect("tempdir");
+        let wav_path = dir.path().join("deep.wav");
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 44_100,
+            bits_per_sample: 24,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&wav_path, spec).expect("create wav");
+        for frame in 0..4_096i32 {
+            writer
+                .write_sample((frame % 512 - 256) * 30_000)
+                .expect("write left");
+            writer.write_sample(-frame * 1_000).expect("write right");
+        }
+        writer.finalize().expect("finalize wav");
+
         let request = TranscodeRequest {
             track: make_track(&wav_path),
             policy: AudioFormatPolicy::ConvertLossless,
+            target_format: None,
+            resample_to: None,
+            target_bits: Some(16),
+        };
+        let result = DefaultFormatTranscoder::new()
+            .transcode(&request)
+            .await
+            .expect("transcode");
+        let info = &result.chunks[0].data[8..];
+        let bits = (((info[12] & 0x01) << 4) | (info[13] >> 4)) + 1;
+        assert_eq!(bits, 16);
+
+        // Full-scale samples saturate instead of wrapping.
+        let mut requantizer = Requantizer::new(24, Some(16)).expect("shallower target");
+        let mut extremes = vec![(1 << 23) - 1, -(1 << 23), 0];
+        requantizer.apply(&mut extremes);
+        assert_eq!(requantizer.bits, 16);
+        assert_eq!(extremes[0], i16::MAX as i32);
+        assert_eq!(extremes[1], i16::MIN as i32);
+        assert!(extremes[2].abs() <= 1);
+        assert!(Requantizer::new(16, Some(24)).is_none());
+    }
+
+    #[tokio::test]
+    async fn dsd_sources_pass_through_with_their_own_label() {
+        let dir = tempdir().expect("tempdir");
+        let dsf_path = dir.path().join("sacd.dsf");
+        let mut payload = b"DSD ".to_vec();
+        payload.extend_from_slice(&[0x69; 64]);
+        fs::write(&dsf_path, &payload).expect("write dsf");
+
+        let transcoder = DefaultFormatTranscoder::new();
+        let request = TranscodeRequest {
+            track: make_track(&dsf_path),
+            policy: AudioFormatPolicy::ConvertLossless,
+            target_format: None,
+            resample_to: None,
+            target_bits: None,
         };
 
         let result = transcoder.transcode(&request).await.expect("transcode");
-        assert_eq!(result.track_id, request.track.id);
-        assert_eq!(result.format, "flac");
+        assert_eq!(result.format, "dsf");
         assert_eq!(result.chunks.len(), 1);
-        let data = &result.chunks[0].data;
-        assert!(data.starts_with(b"fLaC"));
-        assert!(result.chunks[0].is_end);
+        assert_eq!(result.chunks[0].data.as_ref(), payload.as_slice());
     }
 
     #[test]
-    fn chunk_bytes_splits_data_into_multiple_chunks() {
+    fn chunker_splits_output_into_multiple_chunks() {
         let data = vec![1u8; DEFAULT_CHUNK_SIZE * 2 + 10];
-        let chunks = DefaultFormatTranscoder::chunk_bytes(
-            data,
-            DEFAULT_CHUNK_SIZE,
-            Some(44_100),
-            Some(2),
-            Some(16),
-        );
+        let chunks = chunked(&[&data], MediaOptions::default(), None);
 
         assert_eq!(chunks.len(), 3);
         assert_eq!(chunks.iter().filter(|chunk| chunk.is_end).count(), 1);
@@ -657,6 +2455,175 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn media_options_control_chunk_size_and_fallback_spacing() {
+        let dir = tempdir().expect("tempdir");
+        let wav_path = dir.path().join("sample.wav");
+        write_test_wav(&wav_path, 4_096);
+        let file_len = fs::metadata(&wav_path).expect("metadata").len() as usize;
+
+        let transcoder = DefaultFormatTranscoder::new().with_media_options(MediaOptions {
+            chunk_size: 1_024,
+            fallback_chunk_ms: 50,
+        });
+        let mut request = TranscodeRequest {
+            track: make_track(&wav_path),
+            policy: AudioFormatPolicy::PassthroughLossless,
+            target_format: None,
+            resample_to: None,
+            target_bits: None,
+        };
+        let passthrough = transcoder.transcode(&request).await.expect("passthrough");
+        assert_eq!(passthrough.chunks.len(), file_len.div_ceil(1_024));
+        assert!(
+            passthrough
+                .chunks
+                .iter()
+                .all(|chunk| chunk.data.len() <= 1_024)
+        );
+
+        // MP3 chunks carry no frame size, so their timestamps use the fallback spacing.
+        request.target_format = Some(TargetFormat::Mp3);
+        let mp3 = transcoder.transcode(&request).await.expect("mp3");
+        assert!(mp3.chunks.len() > 1);
+        for (index, chunk) in mp3.chunks.iter().enumerate() {
+            assert_eq!(chunk.timestamp_ms, index as u64 * 50);
+        }
+    }
+
+    #[test]
+    fn chunker_marks_only_the_final_chunk_at_exact_multiples() {
+        let options = MediaOptions {
+            chunk_size: 64,
+            ..MediaOptions::default()
+        };
+        for (len, expected) in [(64, 1), (128, 2), (129, 3)] {
+            let chunks = chunked(&[&vec![0u8; len]], options, None);
+            assert_eq!(chunks.len(), expected, "{len} bytes");
+            assert_eq!(
+                chunks.iter().filter(|chunk| chunk.is_end).count(),
+                1,
+                "{len} bytes"
+            );
+            assert!(
+                chunks.last().is_some_and(|chunk| chunk.is_end),
+                "{len} bytes"
+            );
+        }
+    }
+
+    #[test]
+    fn chunker_output_does_not_depend_on_how_writes_are_split() {
+        let options = MediaOptions {
+            chunk_size: 64,
+            ..MediaOptions::default()
+        };
+        let data: Vec<u8> = (0..1_000u32).map(|n| n as u8).collect();
+        let pieces: Vec<&[u8]> = data.chunks(7).collect();
+        assert_eq!(
+            chunked(&pieces, options, None),
+            chunked(&[&data], options, None)
+        );
+    }
+
+    #[test]
+    fn resamplers_do_not_depend_on_how_input_is_split() {
+        let input: Vec<i32> = (0..2 * 10_000).map(|n| (n * 37 % 4_096) - 2_048).collect();
+        for (from, to) in [(44_100, 22_050), (44_100, 48_000), (96_000, 44_100)] {
+            let mut whole = SincResampler::new(2, from, to, 16);
+            let mut expected = whole.push(&input);
+            expected.extend(whole.finish());
+            assert_eq!(expected.len() as u64, 10_000 * to as u64 / from as u64 * 2);
+
+            let mut split = SincResampler::new(2, from, to, 16);
+            let mut out = Vec::new();
+            for packet in input.chunks(2 * 333) {
+                out.extend(split.push(packet));
+            }
+            out.extend(split.finish());
+            assert_eq!(out, expected, "{from} -> {to}");
+
+            let floats: Vec<f32> = input.iter().map(|&sample| sample as f32).collect();
+            let mut whole = LinearResampler::new(2, from, to);
+            let mut expected = Vec::new();
+            whole.push(&floats, &mut expected);
+            whole.finish(&mut expected);
+            let mut split = LinearResampler::new(2, from, to);
+            let mut out = Vec::new();
+            for packet in floats.chunks(2 * 333) {
+                split.push(packet, &mut out);
+            }
+            split.finish(&mut out);
+            assert_eq!(out, expected, "{from} -> {to}");
+        }
+    }
+
+    #[tokio::test]
+    async fn converted_chunks_carry_the_timestamp_of_their_first_block() {
+        let dir = tempdir().expect("tempdir");
+        let wav_path = dir.path().join("noise.wav");
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 44_100,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&wav_path, spec).expect("create wav");
+        // Noise, so every block encodes to much more than a chunk.
+        let mut state = 1u32;
+        for _ in 0..2 * 44_100 {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            writer
+                .write_sample((state >> 16) as i16)
+                .expect("write sample");
+        }
+        writer.finalize().expect("finalize wav");
+
+        let transcoder = DefaultFormatTranscoder::new().with_media_options(MediaOptions {
+            chunk_size: 1_024,
+            fallback_chunk_ms: 50,
+        });
+        let request = TranscodeRequest {
+            track: make_track(&wav_path),
+            policy: AudioFormatPolicy::ConvertLossless,
+            target_format: None,
+            resample_to: None,
+            target_bits: None,
+        };
+        let mut receiver = transcoder.transcode_stream(&request).await.expect("stream");
+        let mut chunks = Vec::new();
+        while let Some(chunk) = receiver.recv().await {
+            chunks.push(chunk.expect("chunk"));
+        }
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|chunk| chunk.data.len() <= 1_024));
+        assert_eq!(chunks[0].timestamp_ms, 0);
+        assert!(
+            chunks
+                .windows(2)
+                .all(|pair| pair[0].timestamp_ms <= pair[1].timestamp_ms)
+        );
+        // Blocks hold 4096 frames, so the last one starts at 10 * 4096 frames.
+        assert_eq!(chunks.last().unwrap().timestamp_ms, 40_960 * 1000 / 44_100);
+
+        let flac_path = dir.path().join("sample.flac");
+        let bytes: Vec<u8> = chunks
+            .iter()
+            .flat_map(|chunk| chunk.data.iter().copied())
+            .collect();
+        fs::write(&flac_path, bytes).expect("write flac");
+        let limits = DecodeLimits::default();
+        let fallback = ProbeFallback::default();
+        assert_eq!(
+            decode_all(&make_track(&flac_path), limits, fallback)
+                .expect("decode flac")
+                .samples,
+            decode_all(&make_track(&wav_path), limits, fallback)
+                .expect("decode wav")
+                .samples
+        );
+    }
+
     #[tokio::test]
     async fn cover_extractor_reads_external_cover() {
         let dir = tempdir().expect("tempdir");
@@ -676,27 +2643,237 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn media_engine_streams_chunks_with_artwork() {
+    async fn normalized_cover_is_scaled_while_extract_stays_raw() {
+        let dir = tempdir().expect("tempdir");
+        let wav_path = dir.path().join("track.wav");
+        write_test_wav(&wav_path, 1_000);
+        let mut png = Vec::new();
+        image::DynamicImage::ImageRgb8(image::RgbImage::new(1000, 500))
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .expect("encode png");
+        fs::write(dir.path().join("cover.png"), &png).expect("write cover");
+
+        let extractor = DefaultCoverExtractor::new();
+        let track = make_track(&wav_path);
+        assert_eq!(extractor.extract(&track).await.unwrap(), Some(png));
+        let cover = extractor
+            .extract_normalized(&track, 200)
+            .await
+            .unwrap()
+            .expect("cover");
+        let scaled = image::load_from_memory(&cover.data).unwrap();
+        assert_eq!((scaled.width(), scaled.height()), (200, 100));
+        assert_eq!(cover.artwork.mime, "image/jpeg");
+
+        fs::remove_file(dir.path().join("cover.png")).unwrap();
+        assert_eq!(
+            extractor.extract_normalized(&track, 200).await.unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn cue_split_tracks_share_album_cover() {
+        let dir = tempdir().expect("tempdir");
+        let audio_dir = dir.path().join("image");
+        fs::create_dir(&audio_dir).expect("audio dir");
+        let wav_path = audio_dir.join("CDImage.wav");
+        write_test_wav(&wav_path, 1_000);
+        let cue_path = dir.path().join("Album.cue");
+        fs::write(&cue_path, b"FILE \"image/CDImage.wav\" WAVE\n").expect("write cue");
+        fs::write(dir.path().join("cover.jpg"), [5u8, 6, 7, 8]).expect("write cover");
+
+        let extractor = DefaultCoverExtractor::new();
+        for index in 1..=3 {
+            let mut track = make_track(&wav_path);
+            track.id.index = index;
+            track.cue_path = Some(cue_path.clone());
+            track.offset_frames = (index as u64 - 1) * 300;
+            track.length_frames = 300;
+            let cover = extractor.extract(&track).await.expect("extract");
+            assert_eq!(cover, Some(vec![5u8, 6, 7, 8]), "track {index}");
+        }
+
+        let plain = make_track(&wav_path);
+        assert_eq!(extractor.extract(&plain).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn gapless_album_stream_matches_the_decoded_image() {
+        let dir = tempdir().expect("tempdir");
+        let image_path = dir.path().join("image.flac");
+        let samples: Vec<i32> = (0..88_200).map(|n| (n * 37 % 65_536) - 32_768).collect();
+        let image = encode_flac(DecodedAudio {
+            samples,
+            sample_rate: 44_100,
+            channels: 2,
+            bits_per_sample: 16,
+        })
+        .expect("encode image");
+        fs::write(&image_path, &image).expect("write image");
+
+        let limits = DecodeLimits::default();
+        let fallback = ProbeFallback::default();
+        let whole = decode_all(&make_track(&image_path), limits, fallback)
+            .expect("decode image")
+            .samples;
+
+        // Boundaries fall inside FLAC blocks; the last track runs to the end of the file.
+        let mut tracks = Vec::new();
+        for (index, (offset, length)) in [(0, 7), (7, 11), (18, 0)].into_iter().enumerate() {
+            let mut track = make_track(&image_path);
+            track.id.index = index as u32 + 1;
+            track.offset_frames = offset;
+            track.length_frames = length;
+            tracks.push(track);
+        }
+        let joined: Vec<i32> = tracks
+            .iter()
+            .flat_map(|track| {
+                decode_all(track, limits, fallback)
+                    .expect("decode track")
+                    .samples
+            })
+            .collect();
+        assert_eq!(joined, whole);
+
+        tracks.reverse();
+        let album = TrackCollection {
+            album: AlbumId("album".into()),
+            tracks,
+        };
+        let transcoder = DefaultFormatTranscoder::new();
+        let chunks = transcoder
+            .transcode_album(&album)
+            .await
+            .expect("album stream");
+        assert!(chunks.last().is_some_and(|chunk| chunk.is_end));
+        let rejoined_path = dir.path().join("rejoined.flac");
+        let bytes: Vec<u8> = chunks
+            .iter()
+            .flat_map(|chunk| chunk.data.iter().copied())
+            .collect();
+        fs::write(&rejoined_path, bytes).expect("write album");
+        let rejoined =
+            decode_all(&make_track(&rejoined_path), limits, fallback).expect("decode album");
+        assert_eq!(rejoined.samples, whole);
+
+        let mut gapped = album.clone();
+        gapped.tracks[1].length_frames -= 1;
+        assert!(transcoder.transcode_album(&gapped).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn mapped_cue_album_streams_back_to_the_image() {
+        let dir = tempdir().expect("tempdir");
+        let image_path = dir.path().join("image.flac");
+        let samples: Vec<i32> = (0..3 * 88_200)
+            .map(|n| (n * 53 % 65_536) - 32_768)
+            .collect();
+        let image = encode_flac(DecodedAudio {
+            samples,
+            sample_rate: 44_100,
+            channels: 2,
+            bits_per_sample: 16,
+        })
+        .expect("encode image");
+        fs::write(&image_path, &image).expect("write image");
+
+        let cue = "FILE \"image.flac\" WAVE\n  TRACK 01 AUDIO\n    INDEX 01 00:00:00\n  TRACK 02 AUDIO\n    INDEX 01 00:01:37\n  TRACK 03 AUDIO\n    INDEX 01 00:02:05\n";
+        let sheet = crate::cue::CueParser
+            .parse_str(cue, dir.path())
+            .expect("parse cue");
+        let index = crate::track::TrackMapper::from_cue_with_durations(
+            &sheet,
+            &AlbumId("album".into()),
+            Some(&dir.path().join("image.cue")),
+        )
+        .expect("map cue");
+        let album = TrackCollection {
+            album: AlbumId("album".into()),
+            tracks: index
+                .entries
+                .iter()
+                .map(|entry| entry.source.clone())
+                .collect(),
+        };
+
+        let chunks = DefaultFormatTranscoder::new()
+            .transcode_album(&album)
+            .await
+            .expect("album stream");
+        let rejoined_path = dir.path().join("rejoined.flac");
+        let bytes: Vec<u8> = chunks
+            .iter()
+            .flat_map(|chunk| chunk.data.iter().copied())
+            .collect();
+        fs::write(&rejoined_path, bytes).expect("write album");
+
+        let limits = DecodeLimits::default();
+        let fallback = ProbeFallback::default();
+        let whole = decode_all(&make_track(&image_path), limits, fallback).expect("decode image");
+        let rejoined =
+            decode_all(&make_track(&rejoined_path), limits, fallback).expect("decode album");
+        assert_eq!(rejoined.samples, whole.samples);
+    }
+
+    fn png_header(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        bytes.extend_from_slice(&width.to_be_bytes());
+        bytes.extend_from_slice(&height.to_be_bytes());
+        bytes.extend_from_slice(&[8, 2, 0, 0, 0, 0, 0, 0, 0]);
+        bytes
+    }
+
+    #[tokio::test]
+    async fn cover_filter_skips_thumbnails_below_min_edge() {
+        let dir = tempdir().expect("tempdir");
+        let wav_path = dir.path().join("track.wav");
+        write_test_wav(&wav_path, 1_000);
+
+        let small = png_header(75, 75);
+        let large = png_header(1_200, 1_200);
+        fs::write(dir.path().join("cover.png"), &small).expect("write small cover");
+        fs::write(dir.path().join("AlbumArtSmall.jpg"), &small).expect("write thumbnail");
+        fs::write(dir.path().join("folder.png"), &large).expect("write large cover");
+
+        let track = make_track(&wav_path);
+        let unfiltered = DefaultCoverExtractor::new().extract(&track).await.unwrap();
+        assert_eq!(unfiltered, Some(small));
+
+        let extractor = DefaultCoverExtractor::with_filter(CoverFilter {
+            min_edge: Some(300),
+            max_edge: None,
+        });
+        let filtered = extractor.extract(&track).await.unwrap();
+        assert_eq!(filtered, Some(large));
+
+        let capped = DefaultCoverExtractor::with_filter(CoverFilter {
+            min_edge: Some(300),
+            max_edge: Some(1_000),
+        });
+        assert_eq!(capped.extract(&track).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn transcodes_stream_in_ordered_bounded_chunks() {
         let dir = tempdir().expect("tempdir");
         let wav_path = dir.path().join("stream.wav");
         let frames = DEFAULT_CHUNK_SIZE / 4 + 2_048;
         write_test_wav(&wav_path, frames);
 
-        let cover_path = dir.path().join("cover.png");
-        let mut cover_file = fs::File::create(&cover_path).expect("cover file");
-        cover_file.write_all(&[9u8, 8, 7, 6]).expect("write cover");
-        cover_file.flush().expect("flush cover");
-
-        let engine = MediaEngine::new(
-            Arc::new(DefaultFormatTranscoder::new()),
-            Arc::new(DefaultCoverExtractor::new()),
-        );
-
         let track = make_track(&wav_path);
-        let result = engine
-            .open_stream(&track, AudioFormatPolicy::PassthroughLossless)
+        let request = TranscodeRequest {
+            track: track.clone(),
+            policy: AudioFormatPolicy::PassthroughLossless,
+            target_format: None,
+            resample_to: None,
+            target_bits: None,
+        };
+        let result = DefaultFormatTranscoder::new()
+            .transcode(&request)
             .await
-            .expect("open stream");
+            .expect("transcode");
 
         assert_eq!(result.track_id, track.id);
         assert_eq!(result.format, "wav");
@@ -714,6 +2891,64 @@ mod tests {
                 .all(|pair| pair[0].timestamp_ms <= pair[1].timestamp_ms)
         );
         assert!(result.chunks.last().map(|c| c.is_end).unwrap_or(false));
-        assert_eq!(result.artwork, Some(vec![9u8, 8, 7, 6]));
+    }
+
+    #[tokio::test]
+    async fn cue_slice_transcodes_report_the_decoded_duration() {
+        let dir = tempdir().expect("tempdir");
+        let wav_path = dir.path().join("disc.wav");
+        write_test_wav(&wav_path, 4_410);
+
+        // The cue estimate runs past the end of the file, as a guessed last track does.
+        let mut track = make_track(&wav_path);
+        track.cue_path = Some(dir.path().join("disc.cue"));
+        track.offset_frames = 3;
+        track.length_frames = 75;
+
+        let request = TranscodeRequest {
+            track,
+            policy: AudioFormatPolicy::ConvertLossless,
+            target_format: None,
+            resample_to: None,
+            target_bits: None,
+        };
+        let result = DefaultFormatTranscoder::new()
+            .transcode(&request)
+            .await
+            .expect("transcode");
+        assert_eq!(result.duration_ms, Some((4_410 - 3 * 588) * 1000 / 44_100));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn blocking_transcode_work_respects_permit_count() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::time::Duration;
+
+        let transcoder = Arc::new(DefaultFormatTranscoder::new().with_max_concurrency(2));
+        let active = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let mut jobs = Vec::new();
+        for _ in 0..8 {
+            let transcoder = transcoder.clone();
+            let active = active.clone();
+            let peak = peak.clone();
+            jobs.push(tokio::spawn(async move {
+                transcoder
+                    .run_blocking(move || {
+                        let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(now, Ordering::SeqCst);
+                        std::thread::sleep(Duration::from_millis(20));
+                        active.fetch_sub(1, Ordering::SeqCst);
+                        Ok(())
+                    })
+                    .await
+            }));
+        }
+        for job in jobs {
+            job.await.unwrap().unwrap();
+        }
+
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
     }
 }
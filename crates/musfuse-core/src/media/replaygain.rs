@@ -0,0 +1,349 @@
+use std::f64::consts::PI;
+
+use tokio::task;
+
+use crate::error::{MusFuseError, Result};
+use crate::metadata::{TagMap, TagValue, TrackId};
+use crate::track::{SourceTrack, TrackCollection};
+
+use super::{DecodeLimits, PacketDecoder, ProbeFallback};
+
+// ReplayGain 2.0 measures EBU R128 / BS.1770 loudness and targets -18 LUFS.
+const REFERENCE_LUFS: f64 = -18.0;
+const ABSOLUTE_GATE_LUFS: f64 = -70.0;
+const RELATIVE_GATE_LU: f64 = -10.0;
+const BLOCK_MS: u64 = 400;
+// Blocks overlap by 75%, so a new one starts every 100 ms.
+const BLOCK_STEP_MS: u64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReplayGain {
+    pub gain_db: f64,
+    // Largest absolute sample, where 1.0 is full scale.
+    pub peak: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlbumReplayGain {
+    pub album: ReplayGain,
+    pub tracks: Vec<(TrackId, ReplayGain)>,
+}
+
+impl ReplayGain {
+    // Writes the track values, and the album values when given, in the usual text form
+    // ("-6.50 dB", "0.988553").
+    pub fn write_tags(&self, tags: &mut TagMap, album: Option<&ReplayGain>) {
+        let mut write = |prefix: &str, gain: &ReplayGain| {
+            tags.insert_ignore_case(
+                &format!("REPLAYGAIN_{prefix}_GAIN"),
+                TagValue::Text(format!("{:.2} dB", gain.gain_db)),
+            );
+            tags.insert_ignore_case(
+                &format!("REPLAYGAIN_{prefix}_PEAK"),
+                TagValue::Text(format!("{:.6}", gain.peak)),
+            );
+        };
+        write("TRACK", self);
+        if let Some(album) = album {
+            write("ALBUM", album);
+        }
+    }
+}
+
+pub async fn analyze_track(track: &SourceTrack) -> Result<ReplayGain> {
+    let track = track.clone();
+    let loudness = task::spawn_blocking(move || measure(&track))
+        .await
+        .map_err(|err| MusFuseError::Media(err.to_string()))??;
+    Ok(loudness.gain())
+}
+
+// Album gain gates the blocks of every track together, so a quiet interlude does not pull
+// the album value down the way averaging track gains would.
+pub async fn analyze_album(album: &TrackCollection) -> Result<AlbumReplayGain> {
+    let tracks = album.tracks.clone();
+    let measured = task::spawn_blocking(move || {
+        tracks
+            .into_iter()
+            .map(|track| measure(&track).map(|loudness| (track.id, loudness)))
+            .collect::<Result<Vec<_>>>()
+    })
+    .await
+    .map_err(|err| MusFuseError::Media(err.to_string()))??;
+
+    let mut combined = Loudness::default();
+    for (_, loudness) in &measured {
+        combined.blocks.extend_from_slice(&loudness.blocks);
+        combined.peak = combined.peak.max(loudness.peak);
+    }
+    Ok(AlbumReplayGain {
+        album: combined.gain(),
+        tracks: measured
+            .into_iter()
+            .map(|(id, loudness)| (id, loudness.gain()))
+            .collect(),
+    })
+}
+
+fn measure(track: &SourceTrack) -> Result<Loudness> {
+    let mut decoder =
+        PacketDecoder::open(track, DecodeLimits::default(), ProbeFallback::default())?;
+    Loudness::of(&mut decoder)
+}
+
+#[derive(Debug, Default)]
+struct Loudness {
+    // Channel-weighted mean square of each gating block.
+    blocks: Vec<f64>,
+    peak: f64,
+}
+
+impl Loudness {
+    // Measures the track packet by packet as it decodes.
+    fn of(decoder: &mut PacketDecoder) -> Result<Self> {
+        let channels = decoder.channels.max(1) as usize;
+        let rate = decoder.sample_rate as u64;
+        let full_scale = (1u64 << (decoder.bits_per_sample - 1)) as f64;
+        let weights = channel_weights(channels);
+
+        let mut filters: Vec<KWeighting> = (0..channels)
+            .map(|_| KWeighting::new(decoder.sample_rate))
+            .collect();
+        let mut peak = 0f64;
+        // Squared K-weighted samples summed per channel, one entry per frame.
+        let mut energy: Vec<f64> = Vec::new();
+        while let Some(samples) = decoder.next_packet()? {
+            for frame in samples.chunks_exact(channels) {
+                let mut sum = 0.0;
+                for (channel, sample) in frame.iter().enumerate() {
+                    let value = *sample as f64 / full_scale;
+                    peak = peak.max(value.abs());
+                    let filtered = filters[channel].process(value);
+                    sum += weights[channel] * filtered * filtered;
+                }
+                energy.push(sum);
+            }
+        }
+
+        let block = (rate * BLOCK_MS / 1_000).max(1) as usize;
+        let step = (rate * BLOCK_STEP_MS / 1_000).max(1) as usize;
+        let mut blocks = Vec::new();
+        if energy.len() < block {
+            // Shorter than one block: measure what there is.
+            if !energy.is_empty() {
+                blocks.push(energy.iter().sum::<f64>() / energy.len() as f64);
+            }
+        } else {
+            let mut prefix = Vec::with_capacity(energy.len() + 1);
+            prefix.push(0.0);
+            for value in &energy {
+                prefix.push(prefix.last().copied().unwrap_or(0.0) + value);
+            }
+            let mut start = 0;
+            while start + block <= energy.len() {
+                blocks.push((prefix[start + block] - prefix[start]) / block as f64);
+                start += step;
+            }
+        }
+        Ok(Self { blocks, peak })
+    }
+
+    // Integrated loudness after the absolute and relative gates. Silence is held at the
+    // absolute gate so its gain stays finite.
+    fn integrated_lufs(&self) -> f64 {
+        let above_absolute: Vec<f64> = self
+            .blocks
+            .iter()
+            .copied()
+            .filter(|power| lufs(*power) > ABSOLUTE_GATE_LUFS)
+            .collect();
+        if above_absolute.is_empty() {
+            return ABSOLUTE_GATE_LUFS;
+        }
+        let relative_gate = lufs(mean(&above_absolute)) + RELATIVE_GATE_LU;
+        let gated: Vec<f64> = above_absolute
+            .into_iter()
+            .filter(|power| lufs(*power) > relative_gate)
+            .collect();
+        if gated.is_empty() {
+            return ABSOLUTE_GATE_LUFS;
+        }
+        lufs(mean(&gated))
+    }
+
+    fn gain(&self) -> ReplayGain {
+        ReplayGain {
+            gain_db: REFERENCE_LUFS - self.integrated_lufs(),
+            peak: self.peak,
+        }
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+fn lufs(power: f64) -> f64 {
+    -0.691 + 10.0 * power.log10()
+}
+
+// BS.1770 weights: surround channels of a 5.1 layout count 1.41, the LFE not at all.
+fn channel_weights(channels: usize) -> Vec<f64> {
+    (0..channels)
+        .map(|channel| match (channels, channel) {
+            (6, 3) => 0.0,
+            (5, 3..=4) | (6, 4..=5) => 1.41,
+            _ => 1.0,
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 3],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    fn new(b: [f64; 3], a: [f64; 3]) -> Self {
+        Self {
+            b,
+            a,
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    fn process(&mut self, input: f64) -> f64 {
+        let output = self.b[0] * input + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[1] * self.y[0]
+            - self.a[2] * self.y[1];
+        self.x = [input, self.x[0]];
+        self.y = [output, self.y[0]];
+        output
+    }
+}
+
+// The BS.1770 K-weighting curve (a high-shelf pre-filter then the RLB high-pass), with
+// coefficients derived for the stream's rate instead of the 48 kHz table in the spec.
+#[derive(Debug, Clone, Copy)]
+struct KWeighting {
+    shelf: Biquad,
+    high_pass: Biquad,
+}
+
+impl KWeighting {
+    fn new(sample_rate: u32) -> Self {
+        let rate = sample_rate as f64;
+
+        let (f0, gain_db, q) = (
+            1_681.974_450_955_533,
+            3.999_843_853_973_347,
+            0.707_175_236_955_419_6,
+        );
+        let k = (PI * f0 / rate).tan();
+        let vh = 10f64.powf(gain_db / 20.0);
+        let vb = vh.powf(0.499_666_774_154_541_6);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Biquad::new(
+            [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        );
+
+        let (f0, q) = (38.135_470_876_024_44, 0.500_327_037_323_877_3);
+        let k = (PI * f0 / rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let high_pass = Biquad::new(
+            [1.0, -2.0, 1.0],
+            [1.0, 2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        );
+
+        Self { shelf, high_pass }
+    }
+
+    fn process(&mut self, sample: f64) -> f64 {
+        self.high_pass.process(self.shelf.process(sample))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::AlbumId;
+
+    fn sine_wav(path: &std::path::Path, amplitude: f64) {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 44_100,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).unwrap();
+        for frame in 0..44_100 * 2 {
+            let phase = 2.0 * PI * 1_000.0 * frame as f64 / 44_100.0;
+            let sample = (phase.sin() * amplitude * i16::MAX as f64) as i16;
+            writer.write_sample(sample).unwrap();
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    fn source(path: std::path::PathBuf, index: u32) -> SourceTrack {
+        SourceTrack {
+            id: TrackId {
+                album: AlbumId("album".into()),
+                disc: 1,
+                index,
+            },
+            path,
+            cue_path: None,
+            offset_frames: 0,
+            length_frames: 0,
+            sample_rate: 44_100,
+            channels: 2,
+        }
+    }
+
+    #[tokio::test]
+    async fn sine_gain_is_finite_deterministic_and_tagged() {
+        let dir = tempfile::tempdir().unwrap();
+        let loud = source(dir.path().join("loud.wav"), 1);
+        let quiet = source(dir.path().join("quiet.wav"), 2);
+        sine_wav(&loud.path, 0.5);
+        sine_wav(&quiet.path, 0.25);
+
+        let first = analyze_track(&loud).await.unwrap();
+        assert!(first.gain_db.is_finite() && first.peak.is_finite());
+        assert_eq!(analyze_track(&loud).await.unwrap(), first);
+        // A 1 kHz sine at half scale in both channels measures about -6 LUFS.
+        assert!((-12.5..-11.5).contains(&first.gain_db), "{}", first.gain_db);
+        assert!((first.peak - 0.5).abs() < 0.001, "{}", first.peak);
+
+        // Halving the amplitude is 6 dB quieter; the album sits between its tracks.
+        let quiet_gain = analyze_track(&quiet).await.unwrap();
+        assert!((quiet_gain.gain_db - first.gain_db - 6.02).abs() < 0.05);
+        let album = analyze_album(&TrackCollection {
+            album: AlbumId("album".into()),
+            tracks: vec![loud.clone(), quiet.clone()],
+        })
+        .await
+        .unwrap();
+        assert_eq!(album.tracks, vec![(loud.id, first), (quiet.id, quiet_gain)]);
+        assert!(album.album.gain_db > first.gain_db && album.album.gain_db < quiet_gain.gain_db);
+        assert_eq!(album.album.peak, first.peak);
+
+        let mut tags = TagMap::default();
+        first.write_tags(&mut tags, Some(&album.album));
+        assert_eq!(
+            tags.get("REPLAYGAIN_TRACK_GAIN"),
+            Some(&TagValue::Text(format!("{:.2} dB", first.gain_db)))
+        );
+        assert!(tags.get("REPLAYGAIN_ALBUM_PEAK").is_some());
+    }
+}
@@ -1,8 +1,11 @@
 use std::collections::{BTreeMap, HashMap};
-use std::fmt;
+use std::fmt::{self, Write};
 
 use serde::{Deserialize, Serialize};
 
+use crate::kv::{KvKey, KvNamespace};
+use crate::lyrics::Lyrics;
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Ord, PartialOrd)]
 pub struct AlbumId(pub String);
 
@@ -25,6 +28,35 @@ impl fmt::Display for TrackId {
     }
 }
 
+// Stored records are keyed by the `Display` form; these build the keys in one allocation so
+// every store spells them the same way.
+impl TrackId {
+    // The `<id>:<suffix>` record of this track in the track namespace.
+    pub fn kv_key(&self, suffix: &str) -> KvKey {
+        let mut key = self.key_text("", suffix.len() + 1);
+        key.push(':');
+        key.push_str(suffix);
+        KvKey::new(KvNamespace::Track, key)
+    }
+
+    // The `<prefix><id>` record of this track, for stores that scan by prefix.
+    pub fn prefixed_kv_key(&self, namespace: KvNamespace, prefix: &str) -> KvKey {
+        KvKey::new(namespace, self.key_text(prefix, 0))
+    }
+
+    pub fn as_key_bytes(&self) -> Vec<u8> {
+        self.key_text("", 0).into_bytes()
+    }
+
+    fn key_text(&self, prefix: &str, reserve: usize) -> String {
+        // Room for the album, two separators and the usual two-digit disc and index.
+        let mut key = String::with_capacity(prefix.len() + self.album.0.len() + 6 + reserve);
+        key.push_str(prefix);
+        write!(key, "{self}").expect("writing to a String cannot fail");
+        key
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
 pub struct TagMap(pub BTreeMap<String, TagValue>);
 
@@ -36,6 +68,119 @@ impl TagMap {
     pub fn insert(&mut self, key: impl Into<String>, value: TagValue) {
         self.0.insert(key.into(), value);
     }
+
+    pub fn get_ignore_case(&self, key: &str) -> Option<&TagValue> {
+        self.0
+            .iter()
+            .find(|(existing, _)| existing.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value)
+    }
+
+    // Replaces an entry whose key matches regardless of case, keeping the stored spelling.
+    pub fn insert_ignore_case(&mut self, key: &str, value: TagValue) {
+        let existing = self
+            .0
+            .keys()
+            .find(|existing| existing.eq_ignore_ascii_case(key))
+            .cloned();
+        self.0
+            .insert(existing.unwrap_or_else(|| key.to_string()), value);
+    }
+
+    pub fn remove_ignore_case(&mut self, key: &str) {
+        self.0
+            .retain(|existing, _| !existing.eq_ignore_ascii_case(key));
+    }
+
+    // Merges into the entry matching `key` regardless of case; a new key holds just the
+    // appended values, as a scalar when there is only one.
+    pub fn append_ignore_case(&mut self, key: &str, values: Vec<TagValue>) {
+        let merged = match self.get_ignore_case(key) {
+            Some(existing) => existing.clone().appended(values),
+            None => match TagValue::List(Vec::new()).appended(values) {
+                TagValue::List(mut items) if items.len() == 1 => items.remove(0),
+                list => list,
+            },
+        };
+        self.insert_ignore_case(key, merged);
+    }
+}
+
+// Vorbis comment and APE keys are case-insensitive, so by default keys read from files are
+// folded to the upper-case Vorbis convention.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TagKeyCase {
+    Preserve,
+    #[default]
+    Upper,
+}
+
+impl TagKeyCase {
+    pub fn normalize(&self, key: &str) -> String {
+        match self {
+            Self::Preserve => key.to_string(),
+            Self::Upper => key.to_uppercase(),
+        }
+    }
+}
+
+// Cleanup applied to titles before they become metadata and virtual names. Applying it
+// twice gives the same result as applying it once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TitleNormalization {
+    pub trim: bool,
+    pub collapse_whitespace: bool,
+    // Tabs and line breaks become spaces; other control characters are dropped.
+    pub strip_control: bool,
+    pub straighten_quotes: bool,
+}
+
+impl Default for TitleNormalization {
+    fn default() -> Self {
+        Self {
+            trim: true,
+            collapse_whitespace: true,
+            strip_control: true,
+            straighten_quotes: false,
+        }
+    }
+}
+
+impl TitleNormalization {
+    pub const NONE: TitleNormalization = TitleNormalization {
+        trim: false,
+        collapse_whitespace: false,
+        strip_control: false,
+        straighten_quotes: false,
+    };
+
+    pub fn apply(&self, title: &str) -> String {
+        let mut normalized = String::with_capacity(title.len());
+        for c in title.chars() {
+            let c = match c {
+                c if c.is_control() && self.strip_control => {
+                    if !c.is_whitespace() {
+                        continue;
+                    }
+                    ' '
+                }
+                c if c.is_whitespace() && self.collapse_whitespace => ' ',
+                '\u{2018}' | '\u{2019}' if self.straighten_quotes => '\'',
+                '\u{201C}' | '\u{201D}' if self.straighten_quotes => '"',
+                c => c,
+            };
+            if c == ' ' && self.collapse_whitespace && normalized.ends_with(' ') {
+                continue;
+            }
+            normalized.push(c);
+        }
+        if self.trim {
+            normalized.trim().to_string()
+        } else {
+            normalized
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -47,6 +192,110 @@ pub enum TagValue {
     List(Vec<TagValue>),
 }
 
+pub const DEFAULT_MULTI_VALUE_SEPARATOR: &str = "; ";
+
+const MULTI_VALUE_KEYS: &[&str] = &[
+    "ARTIST",
+    "ALBUMARTIST",
+    "GENRE",
+    "COMPOSER",
+    "TPE1",
+    "TPE2",
+    "TCON",
+    "TCOM",
+];
+
+pub fn is_multi_value_key(key: &str) -> bool {
+    MULTI_VALUE_KEYS
+        .iter()
+        .any(|candidate| candidate.eq_ignore_ascii_case(key))
+}
+
+// Frames whose text is a plain integer; read back as numbers so queries can compare them.
+const NUMERIC_KEYS: &[&str] = &[
+    "TRACKNUMBER",
+    "TRACKTOTAL",
+    "DISCNUMBER",
+    "DISCTOTAL",
+    "BPM",
+    "RATING",
+];
+
+pub fn is_numeric_key(key: &str) -> bool {
+    NUMERIC_KEYS
+        .iter()
+        .any(|candidate| candidate.eq_ignore_ascii_case(key))
+}
+
+impl TagValue {
+    // Adds `values` after the existing ones, promoting a scalar to a list; values already
+    // present are not added twice.
+    pub fn appended(self, values: impl IntoIterator<Item = TagValue>) -> Self {
+        let mut items = match self {
+            TagValue::List(items) => items,
+            scalar => vec![scalar],
+        };
+        for value in values {
+            if !items.contains(&value) {
+                items.push(value);
+            }
+        }
+        TagValue::List(items)
+    }
+
+    // Texts that parse as integers become numbers; anything else (such as "3/12") is kept.
+    pub fn parse_numbers(self) -> Self {
+        match self {
+            TagValue::Text(text) => match text.trim().parse() {
+                Ok(number) => TagValue::Number(number),
+                Err(_) => TagValue::Text(text),
+            },
+            TagValue::List(values) => {
+                TagValue::List(values.into_iter().map(TagValue::parse_numbers).collect())
+            }
+            other => other,
+        }
+    }
+
+    pub fn from_texts(mut values: Vec<String>) -> Self {
+        if values.len() == 1 {
+            TagValue::Text(values.remove(0))
+        } else {
+            TagValue::List(values.into_iter().map(TagValue::Text).collect())
+        }
+    }
+
+    // Splits a joined display string (or a null-separated raw value) back into a list.
+    pub fn split_text(value: &str, separator: &str) -> Self {
+        let parts: Vec<String> = value
+            .split('\0')
+            .flat_map(|part| part.split(separator))
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .map(str::to_string)
+            .collect();
+        if parts.is_empty() {
+            TagValue::Text(value.to_string())
+        } else {
+            Self::from_texts(parts)
+        }
+    }
+
+    pub fn join_text(&self, separator: &str) -> String {
+        match self {
+            TagValue::Text(value) => value.clone(),
+            TagValue::Number(value) => value.to_string(),
+            TagValue::Float(value) => value.to_string(),
+            TagValue::Bool(value) => value.to_string(),
+            TagValue::List(values) => values
+                .iter()
+                .map(|value| value.join_text(separator))
+                .collect::<Vec<_>>()
+                .join(separator),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct TrackMetadata {
     pub id: TrackId,
@@ -56,6 +305,8 @@ pub struct TrackMetadata {
     pub duration_ms: u64,
     pub tags: TagMap,
     pub artwork: Option<ArtworkRef>,
+    #[serde(default)]
+    pub lyrics: Option<Lyrics>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -77,13 +328,175 @@ pub struct ArtworkRef {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+// Applied in order: `remove`, then `set`, then `append`.
 pub struct TagDelta {
     pub set: HashMap<String, TagValue>,
     pub remove: Vec<String>,
+    // Values added to a key without replacing what it already holds.
+    #[serde(default)]
+    pub append: HashMap<String, Vec<TagValue>>,
 }
 
 impl TagDelta {
+    pub fn builder() -> TagDeltaBuilder {
+        TagDeltaBuilder::default()
+    }
+
+    pub fn set_one(key: impl Into<String>, value: TagValue) -> Self {
+        Self::builder().set(key, value).build()
+    }
+
     pub fn is_empty(&self) -> bool {
-        self.set.is_empty() && self.remove.is_empty()
+        self.set.is_empty() && self.remove.is_empty() && self.append.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TagDeltaBuilder {
+    set: HashMap<String, TagValue>,
+    remove: Vec<String>,
+    append: HashMap<String, Vec<TagValue>>,
+}
+
+impl TagDeltaBuilder {
+    pub fn set(mut self, key: impl Into<String>, value: TagValue) -> Self {
+        let key = key.into();
+        self.remove.retain(|existing| existing != &key);
+        self.append.remove(&key);
+        self.set.insert(key, value);
+        self
+    }
+
+    pub fn append(mut self, key: impl Into<String>, value: TagValue) -> Self {
+        self.append.entry(key.into()).or_default().push(value);
+        self
+    }
+
+    pub fn set_text(self, key: impl Into<String>, value: &str) -> Self {
+        self.set(key, TagValue::Text(value.to_string()))
+    }
+
+    pub fn remove(mut self, key: impl Into<String>) -> Self {
+        let key = key.into();
+        self.set.remove(&key);
+        self.append.remove(&key);
+        if !self.remove.contains(&key) {
+            self.remove.push(key);
+        }
+        self
+    }
+
+    pub fn build(self) -> TagDelta {
+        TagDelta {
+            set: self.set,
+            remove: self.remove,
+            append: self.append,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn track_keys_keep_the_stored_spelling() {
+        let id = TrackId {
+            album: AlbumId("Abbey Road".into()),
+            disc: 1,
+            index: 7,
+        };
+
+        assert_eq!(
+            id.kv_key("tag"),
+            KvKey::new(KvNamespace::Track, format!("{}:tag", id))
+        );
+        assert_eq!(id.kv_key("tag").to_string(), "track:Abbey Road-01-07:tag");
+        assert_eq!(
+            id.prefixed_kv_key(KvNamespace::Policy, "pin:"),
+            KvKey::new(KvNamespace::Policy, "pin:Abbey Road-01-07")
+        );
+        assert_eq!(id.as_key_bytes(), id.to_string().into_bytes());
+        assert_eq!(id.as_key_bytes(), b"Abbey Road-01-07");
+
+        let wide = TrackId {
+            album: AlbumId("Box".into()),
+            disc: 12,
+            index: 123,
+        };
+        assert_eq!(wide.as_key_bytes(), b"Box-12-123");
+    }
+
+    #[test]
+    fn titles_are_trimmed_and_collapsed_idempotently() {
+        let messy = "  Dark   Side\tof the\u{7} Moon  ";
+        let normalized = TitleNormalization::default().apply(messy);
+        assert_eq!(normalized, "Dark Side of the Moon");
+        assert_eq!(TitleNormalization::default().apply(&normalized), normalized);
+        assert_eq!(TitleNormalization::NONE.apply(messy), messy);
+
+        let quotes = TitleNormalization {
+            straighten_quotes: true,
+            ..TitleNormalization::default()
+        };
+        assert_eq!(
+            quotes.apply("\u{201C}Don\u{2019}t Stop\u{201D} "),
+            "\"Don't Stop\""
+        );
+    }
+
+    #[test]
+    fn list_values_join_with_the_configured_separator() {
+        let artists = TagValue::List(vec![
+            TagValue::Text("Alice".into()),
+            TagValue::Text("Bob".into()),
+        ]);
+
+        assert_eq!(artists.join_text(" / "), "Alice / Bob");
+        assert_eq!(
+            artists.join_text(DEFAULT_MULTI_VALUE_SEPARATOR),
+            "Alice; Bob"
+        );
+        assert_eq!(TagValue::split_text("Alice / Bob", " / "), artists);
+    }
+
+    #[test]
+    fn builder_matches_manual_delta() {
+        let built = TagDelta::builder()
+            .set("RATING", TagValue::Number(5))
+            .set_text("GENRE", "Jazz")
+            .remove("COMMENT")
+            .build();
+
+        let manual = TagDelta {
+            set: HashMap::from([
+                (String::from("RATING"), TagValue::Number(5)),
+                (String::from("GENRE"), TagValue::Text("Jazz".into())),
+            ]),
+            remove: vec![String::from("COMMENT")],
+            append: HashMap::new(),
+        };
+
+        assert_eq!(built, manual);
+    }
+
+    #[test]
+    fn set_one_and_later_calls_win() {
+        assert_eq!(
+            TagDelta::set_one("TITLE", TagValue::Text("Song".into())),
+            TagDelta {
+                set: HashMap::from([(String::from("TITLE"), TagValue::Text("Song".into()))]),
+                remove: Vec::new(),
+                append: HashMap::new(),
+            }
+        );
+
+        let delta = TagDelta::builder()
+            .set_text("TITLE", "Song")
+            .remove("TITLE")
+            .remove("TITLE")
+            .build();
+        assert!(delta.set.is_empty());
+        assert_eq!(delta.remove, vec![String::from("TITLE")]);
     }
 }
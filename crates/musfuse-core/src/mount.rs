@@ -1,17 +1,24 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
 
 use async_trait::async_trait;
-use tokio::sync::broadcast;
+use tokio::sync::{Notify, broadcast};
 
 use crate::config::MountConfig;
-use crate::error::Result;
+use crate::error::{MusFuseError, Result};
+use crate::kv::KvBackend;
+use crate::scanner::ScanEvent;
+
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MountStatus {
     Unmounted,
     Mounting,
     Mounted,
+    Remounting,
     Unmounting,
     Faulted(String),
 }
@@ -20,13 +27,41 @@ pub enum MountStatus {
 pub trait MountProvider: Send + Sync {
     async fn mount(&self, ctx: Arc<MountContext>) -> Result<()>;
     async fn unmount(&self) -> Result<()>;
+
+    // Swaps the running mount over to `ctx`. The default unmounts and mounts again;
+    // providers that can keep the mount point attached meanwhile override it.
+    async fn remount(&self, ctx: Arc<MountContext>) -> Result<()> {
+        self.unmount().await?;
+        self.mount(ctx).await
+    }
+
     fn status(&self) -> MountStatus;
+
+    // Counters of the current mount; providers without one report zeroes.
+    fn stats(&self) -> MountStats {
+        MountStats::default()
+    }
 }
 
-#[derive(Debug)]
 pub struct MountContext {
     pub config: Arc<MountConfig>,
     pub signal: broadcast::Sender<MountEvent>,
+    pub operations: Arc<OperationTracker>,
+    pub drain_timeout: Duration,
+    pub kv: Option<Arc<dyn KvBackend>>,
+    pub stats: MountStats,
+}
+
+impl std::fmt::Debug for MountContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MountContext")
+            .field("config", &self.config)
+            .field("operations", &self.operations)
+            .field("drain_timeout", &self.drain_timeout)
+            .field("kv", &self.kv.is_some())
+            .field("stats", &self.stats)
+            .finish()
+    }
 }
 
 impl MountContext {
@@ -35,24 +70,302 @@ impl MountContext {
         Self {
             config: Arc::new(config),
             signal,
+            operations: Arc::new(OperationTracker::new()),
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+            kv: None,
+            stats: MountStats::default(),
         }
     }
 
+    pub fn with_drain_timeout(mut self, timeout: Duration) -> Self {
+        self.drain_timeout = timeout;
+        self
+    }
+
+    pub fn with_kv(mut self, kv: Arc<dyn KvBackend>) -> Self {
+        self.kv = Some(kv);
+        self
+    }
+
+    // Shares counters the media engine of this mount already reports into.
+    pub fn with_stats(mut self, stats: MountStats) -> Self {
+        self.stats = stats;
+        self
+    }
+
     pub fn mount_point(&self) -> &Path {
         &self.config.mount_point
     }
+
+    // The mount under a reloaded config. The old context's operations are drained by the
+    // remount, so this one starts a fresh tracker; store, counters and events stay shared.
+    pub fn reconfigured(&self, config: Arc<MountConfig>) -> Self {
+        Self {
+            config,
+            signal: self.signal.clone(),
+            operations: Arc::new(OperationTracker::new()),
+            drain_timeout: self.drain_timeout,
+            kv: self.kv.clone(),
+            stats: self.stats.clone(),
+        }
+    }
+
+    // The same mount served somewhere else, e.g. on an auto-selected free drive; operations,
+    // store and counters stay shared.
+    pub fn relocated(&self, mount_point: PathBuf) -> Self {
+        let mut config = (*self.config).clone();
+        config.mount_point = mount_point;
+        Self {
+            config: Arc::new(config),
+            signal: self.signal.clone(),
+            operations: self.operations.clone(),
+            drain_timeout: self.drain_timeout,
+            kv: self.kv.clone(),
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct OperationTracker {
+    in_flight: AtomicUsize,
+    closed: AtomicBool,
+    idle: Notify,
+}
+
+impl OperationTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn begin(self: &Arc<Self>) -> Option<OperationGuard> {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        if self.closed.load(Ordering::SeqCst) {
+            self.finish();
+            return None;
+        }
+        Some(OperationGuard {
+            tracker: self.clone(),
+        })
+    }
+
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    // Returns false if operations were still running when the timeout elapsed.
+    pub async fn drain(&self, timeout: Duration) -> bool {
+        let wait = async {
+            loop {
+                let idle = self.idle.notified();
+                if self.in_flight() == 0 {
+                    return;
+                }
+                idle.await;
+            }
+        };
+        tokio::time::timeout(timeout, wait).await.is_ok()
+    }
+
+    fn finish(&self) {
+        if self.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.idle.notify_waiters();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct OperationGuard {
+    tracker: Arc<OperationTracker>,
+}
+
+impl Drop for OperationGuard {
+    fn drop(&mut self) {
+        self.tracker.finish();
+    }
+}
+
+#[derive(Debug, Default)]
+struct StatCounters {
+    bytes_read: AtomicU64,
+    track_reads: AtomicU64,
+    transcodes: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    open_handles: AtomicU64,
+}
+
+// Running totals of a mount. Clones share the same counters, so the media engine, the
+// filesystem adapter and the provider can each hold one.
+#[derive(Debug, Clone, Default)]
+pub struct MountStats {
+    counters: Arc<StatCounters>,
+}
+
+impl MountStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // A whole track served in one read.
+    pub fn record_read(&self, bytes: usize) {
+        self.record_track_open();
+        self.record_bytes(bytes);
+    }
+
+    // A track file opened through the mount; it counts as one track read however many
+    // ranges the player then reads from it.
+    pub fn record_track_open(&self) {
+        self.counters.track_reads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_bytes(&self, bytes: usize) {
+        self.counters
+            .bytes_read
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_transcode(&self) {
+        self.counters.transcodes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_cache_lookup(&self, hit: bool) {
+        let counter = if hit {
+            &self.counters.cache_hits
+        } else {
+            &self.counters.cache_misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn handle_opened(&self) {
+        self.counters.open_handles.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn handle_closed(&self) {
+        let _ =
+            self.counters
+                .open_handles
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |open| {
+                    open.checked_sub(1)
+                });
+    }
+
+    pub fn bytes_read(&self) -> u64 {
+        self.counters.bytes_read.load(Ordering::Relaxed)
+    }
+
+    pub fn track_reads(&self) -> u64 {
+        self.counters.track_reads.load(Ordering::Relaxed)
+    }
+
+    pub fn transcodes(&self) -> u64 {
+        self.counters.transcodes.load(Ordering::Relaxed)
+    }
+
+    pub fn cache_hits(&self) -> u64 {
+        self.counters.cache_hits.load(Ordering::Relaxed)
+    }
+
+    pub fn cache_misses(&self) -> u64 {
+        self.counters.cache_misses.load(Ordering::Relaxed)
+    }
+
+    pub fn open_handles(&self) -> u64 {
+        self.counters.open_handles.load(Ordering::Relaxed)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MountEvent {
     Mounted,
+    Remounted,
     Unmounted,
     Fault(String),
+    MountPointSelected(PathBuf),
+    // A watched source changed on disk; the served index is only rebuilt by a remount.
+    LibraryChanged(ScanEvent),
+}
+
+// Operations an adapter carries through to the filesystem it exposes, so core code and UIs
+// can hide what the platform cannot do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AdapterCapabilities {
+    pub writes: bool,
+    pub renames: bool,
+    pub reparse_points: bool,
+    pub named_streams: bool,
+}
+
+impl AdapterCapabilities {
+    pub const READ_ONLY: AdapterCapabilities = AdapterCapabilities {
+        writes: false,
+        renames: false,
+        reparse_points: false,
+        named_streams: false,
+    };
+
+    pub const ALL: AdapterCapabilities = AdapterCapabilities {
+        writes: true,
+        renames: true,
+        reparse_points: true,
+        named_streams: true,
+    };
+
+    pub fn is_read_only(&self) -> bool {
+        !self.writes && !self.renames
+    }
 }
 
 #[async_trait]
 pub trait PlatformAdapter: Send + Sync {
     async fn prepare_environment(&self, config: &MountConfig) -> Result<()>;
-    async fn mount(&self, config: &MountConfig) -> Result<()>;
+
+    // Adapters that do not say otherwise are assumed to be read-only.
+    fn capabilities(&self) -> AdapterCapabilities {
+        AdapterCapabilities::READ_ONLY
+    }
+
+    async fn resolve_mount_point(&self, config: &MountConfig) -> Result<PathBuf> {
+        Ok(config.mount_point.clone())
+    }
+
+    async fn mount(&self, ctx: &MountContext) -> Result<()>;
     async fn unmount(&self, mount_point: &Path) -> Result<()>;
+
+    // Re-scans under `ctx` while `mount_point` stays attached. Adapters that cannot do that
+    // leave this unsupported, and the provider unmounts and mounts again instead.
+    async fn remount(&self, _mount_point: &Path, _ctx: &MountContext) -> Result<()> {
+        Err(MusFuseError::Unsupported("in-place remount"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn drain_waits_for_guards_and_rejects_new_work() {
+        let tracker = Arc::new(OperationTracker::new());
+        let guard = tracker.begin().expect("open tracker");
+        tracker.close();
+        assert!(tracker.begin().is_none());
+        assert!(!tracker.drain(Duration::from_millis(20)).await);
+
+        let release = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            drop(guard);
+        });
+        assert!(tracker.drain(Duration::from_secs(5)).await);
+        assert_eq!(tracker.in_flight(), 0);
+        release.await.unwrap();
+    }
 }
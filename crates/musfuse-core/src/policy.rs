@@ -1,23 +1,349 @@
+use std::path::Path;
+
 use serde::{Deserialize, Serialize};
 
 use crate::config::{LosslessStrategy, PolicyConfig};
+use crate::error::{MusFuseError, Result};
+use crate::format::AudioFormat;
+use crate::probe;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum AudioFormatPolicy {
     PassthroughLossy,
     PassthroughLossless,
     ConvertLossless,
+    // Re-encode every source, lossless or not, to a lossy codec (e.g. a mobile mount).
+    ConvertLossy { codec: LossyCodec },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum LossyCodec {
+    Mp3,
+    Opus,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum TargetFormat {
+    Flac,
+    Mp3,
+    // Formats served by transcoders an embedder registers at runtime; never persisted.
+    #[serde(skip)]
+    Custom(&'static str),
+}
+
+impl TargetFormat {
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_ascii_lowercase().as_str() {
+            "flac" => Some(TargetFormat::Flac),
+            "mp3" => Some(TargetFormat::Mp3),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            TargetFormat::Flac => "flac",
+            TargetFormat::Mp3 => "mp3",
+            TargetFormat::Custom(extension) => extension,
+        }
+    }
 }
 
 impl AudioFormatPolicy {
-    pub fn from_extension(ext: &str, config: &PolicyConfig) -> Self {
-        let lowered = ext.to_ascii_lowercase();
-        match lowered.as_str() {
-            "mp3" | "aac" | "ogg" | "opus" | "m4a" => AudioFormatPolicy::PassthroughLossy,
-            _ => match config.lossless_strategy {
-                LosslessStrategy::Passthrough => AudioFormatPolicy::PassthroughLossless,
-                LosslessStrategy::ConvertToFlac => AudioFormatPolicy::ConvertLossless,
-            },
+    // Extensions without a configured policy must name a format MusFuse knows; guessing
+    // lossless for anything else would serve unreadable passthrough files.
+    pub fn from_extension(ext: &str, config: &PolicyConfig) -> Result<Self> {
+        if let Some(policy) = Self::configured(ext, config) {
+            return Ok(policy);
+        }
+        AudioFormat::from_extension(ext)
+            .map(|format| Self::for_format(format, config))
+            .ok_or_else(|| MusFuseError::UnsupportedFormat(ext.to_string()))
+    }
+
+    pub fn for_format(format: AudioFormat, config: &PolicyConfig) -> Self {
+        if let Some(policy) = Self::configured(format.extension(), config) {
+            return policy;
         }
+        match format {
+            // No DSD-to-PCM converter yet, so DSD is always served byte-exact.
+            format if format.is_dsd() => AudioFormatPolicy::PassthroughLossless,
+            format => Self::by_losslessness(format.is_lossless(), config),
+        }
+    }
+
+    fn by_losslessness(lossless: bool, config: &PolicyConfig) -> Self {
+        if !lossless {
+            return AudioFormatPolicy::PassthroughLossy;
+        }
+        match config.lossless_strategy {
+            LosslessStrategy::Passthrough => AudioFormatPolicy::PassthroughLossless,
+            LosslessStrategy::ConvertToFlac => AudioFormatPolicy::ConvertLossless,
+        }
+    }
+
+    fn configured(ext: &str, config: &PolicyConfig) -> Option<Self> {
+        config
+            .format_policies
+            .iter()
+            .find(|(key, _)| key.trim_start_matches('.').eq_ignore_ascii_case(ext))
+            .map(|(_, policy)| policy.clone())
+    }
+}
+
+// Classifies a source by the codec its container actually holds. Configured extensions
+// still win, and files symphonia cannot probe (DSD, APE, a missing file) are judged by
+// their extension.
+pub fn classify_source(path: &Path, config: &PolicyConfig) -> Result<AudioFormatPolicy> {
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default();
+    if let Some(policy) = AudioFormatPolicy::configured(ext, config) {
+        return Ok(policy);
+    }
+    if is_dsd_extension(ext) {
+        return AudioFormatPolicy::from_extension(ext, config);
+    }
+    match probe::is_lossless_source(path) {
+        Ok(lossless) => Ok(AudioFormatPolicy::by_losslessness(lossless, config)),
+        Err(_) => AudioFormatPolicy::from_extension(ext, config),
+    }
+}
+
+pub fn is_dsd_extension(ext: &str) -> bool {
+    AudioFormat::from_extension(ext).is_some_and(|format| format.is_dsd())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn format_policies_override_the_lossless_strategy() {
+        let config = PolicyConfig {
+            lossless_strategy: LosslessStrategy::Passthrough,
+            format_policies: HashMap::from([
+                ("wav".to_string(), AudioFormatPolicy::ConvertLossless),
+                ("FLAC".to_string(), AudioFormatPolicy::PassthroughLossless),
+            ]),
+            ..PolicyConfig::default()
+        };
+
+        assert_eq!(
+            AudioFormatPolicy::from_extension("WAV", &config).unwrap(),
+            AudioFormatPolicy::ConvertLossless
+        );
+        assert_eq!(
+            AudioFormatPolicy::from_extension("flac", &config).unwrap(),
+            AudioFormatPolicy::PassthroughLossless
+        );
+        // Unlisted formats keep the defaults.
+        assert_eq!(
+            AudioFormatPolicy::from_extension("aiff", &config).unwrap(),
+            AudioFormatPolicy::PassthroughLossless
+        );
+        assert_eq!(
+            AudioFormatPolicy::from_extension("mp3", &config).unwrap(),
+            AudioFormatPolicy::PassthroughLossy
+        );
+    }
+
+    #[test]
+    fn lossless_extensions_are_recognized_and_unknown_ones_rejected() {
+        let mut config = PolicyConfig {
+            lossless_strategy: LosslessStrategy::ConvertToFlac,
+            ..PolicyConfig::default()
+        };
+
+        for ext in ["wav", "flac", "alac", "ape", "wv", "tak", "tta"] {
+            let format = AudioFormat::from_extension(ext).expect(ext);
+            assert!(format.is_lossless(), "{ext}");
+            assert_eq!(format.extension(), ext);
+            assert_eq!(
+                AudioFormatPolicy::from_extension(&ext.to_uppercase(), &config).unwrap(),
+                AudioFormatPolicy::ConvertLossless,
+                "{ext}"
+            );
+        }
+
+        let err = AudioFormatPolicy::from_extension("bin", &config).unwrap_err();
+        assert!(matches!(err, MusFuseError::UnsupportedFormat(ext) if ext == "bin"));
+
+        // A configured policy makes an otherwise unknown extension usable.
+        config
+            .format_policies
+            .insert(".bin".into(), AudioFormatPolicy::PassthroughLossless);
+        assert_eq!(
+            AudioFormatPolicy::from_extension("bin", &config).unwrap(),
+            AudioFormatPolicy::PassthroughLossless
+        );
+    }
+
+    fn atom(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut out = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        out.extend_from_slice(kind);
+        out.extend_from_slice(body);
+        out
+    }
+
+    // ISO BMFF "full" atoms lead with a version byte and 24 bits of flags, all zero here.
+    fn full_atom(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        atom(kind, &[&[0u8; 4][..], body].concat())
+    }
+
+    // Just enough of an iTunes-style .m4a for symphonia to find one ALAC track holding a
+    // single (undecodable) packet.
+    fn alac_m4a() -> Vec<u8> {
+        let ftyp = atom(b"ftyp", b"M4A \0\0\0\0M4A mp42isom");
+        let mut cookie = Vec::new();
+        cookie.extend_from_slice(&4096u32.to_be_bytes());
+        cookie.extend_from_slice(&[0, 16, 40, 10, 14, 2]);
+        cookie.extend_from_slice(&255u16.to_be_bytes());
+        cookie.extend_from_slice(&[0; 8]);
+        cookie.extend_from_slice(&44_100u32.to_be_bytes());
+        let mut entry = vec![0u8; 6];
+        entry.extend_from_slice(&1u16.to_be_bytes());
+        entry.extend_from_slice(&[0; 8]);
+        entry.extend_from_slice(&2u16.to_be_bytes());
+        entry.extend_from_slice(&16u16.to_be_bytes());
+        entry.extend_from_slice(&[0; 4]);
+        entry.extend_from_slice(&(44_100u32 << 16).to_be_bytes());
+        entry.extend(full_atom(b"alac", &cookie));
+        let stsd = full_atom(
+            b"stsd",
+            &[&1u32.to_be_bytes()[..], &atom(b"alac", &entry)].concat(),
+        );
+
+        let build = |chunk_offset: u32| {
+            let table = |kind: &[u8; 4], values: &[u32]| {
+                let body: Vec<u8> = values.iter().flat_map(|v| v.to_be_bytes()).collect();
+                full_atom(kind, &body)
+            };
+            let stbl = atom(
+                b"stbl",
+                &[
+                    stsd.clone(),
+                    table(b"stts", &[1, 1, 4096]),
+                    table(b"stsc", &[1, 1, 1, 1]),
+                    table(b"stsz", &[4, 1]),
+                    table(b"stco", &[1, chunk_offset]),
+                ]
+                .concat(),
+            );
+            let dref = full_atom(
+                b"dref",
+                &[&1u32.to_be_bytes()[..], &atom(b"url ", &[0, 0, 0, 1])].concat(),
+            );
+            let minf = atom(
+                b"minf",
+                &[full_atom(b"smhd", &[0; 4]), atom(b"dinf", &dref), stbl].concat(),
+            );
+            let mut mdhd = vec![0u8; 8];
+            mdhd.extend_from_slice(&44_100u32.to_be_bytes());
+            mdhd.extend_from_slice(&4096u32.to_be_bytes());
+            mdhd.extend_from_slice(&[0; 4]);
+            let hdlr = full_atom(b"hdlr", &[&[0u8; 4][..], b"soun", &[0; 13]].concat());
+            let mdia = atom(b"mdia", &[full_atom(b"mdhd", &mdhd), hdlr, minf].concat());
+            let mut tkhd = vec![0u8; 8];
+            tkhd.extend_from_slice(&1u32.to_be_bytes());
+            tkhd.extend_from_slice(&[0; 67]);
+            let mut mvhd = vec![0u8; 8];
+            mvhd.extend_from_slice(&44_100u32.to_be_bytes());
+            mvhd.extend_from_slice(&4096u32.to_be_bytes());
+            mvhd.extend_from_slice(&[0; 76]);
+            let trak = atom(b"trak", &[full_atom(b"tkhd", &tkhd), mdia].concat());
+            atom(b"moov", &[full_atom(b"mvhd", &mvhd), trak].concat())
+        };
+        let moov_len = build(0).len();
+        let moov = build((ftyp.len() + moov_len + 8) as u32);
+        [ftyp, moov, atom(b"mdat", &[0; 4])].concat()
+    }
+
+    fn ogg_crc(page: &[u8]) -> u32 {
+        page.iter().fold(0u32, |crc, byte| {
+            (0..8).fold(crc ^ (u32::from(*byte) << 24), |crc, _| {
+                if crc & 0x8000_0000 != 0 {
+                    (crc << 1) ^ 0x04c1_1db7
+                } else {
+                    crc << 1
+                }
+            })
+        })
+    }
+
+    fn ogg_page(flags: u8, sequence: u32, granule: u64, packet: &[u8]) -> Vec<u8> {
+        let mut page = b"OggS\0".to_vec();
+        page.push(flags);
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&0x4d46u32.to_le_bytes());
+        page.extend_from_slice(&sequence.to_le_bytes());
+        page.extend_from_slice(&[0; 4]);
+        page.push(1);
+        page.push(packet.len() as u8);
+        page.extend_from_slice(packet);
+        let crc = ogg_crc(&page);
+        page[22..26].copy_from_slice(&crc.to_le_bytes());
+        page
+    }
+
+    // The FLAC-in-Ogg mapping: an identification packet carrying STREAMINFO, the remaining
+    // metadata blocks (here a single empty Vorbis comment), then audio packets. symphonia
+    // only reports the track once it has seen the first of those.
+    fn flac_ogg() -> Vec<u8> {
+        let mut ident = b"\x7fFLAC\x01\x00\x00\x01fLaC\x00\x00\x00\x22".to_vec();
+        ident.extend_from_slice(&4096u16.to_be_bytes());
+        ident.extend_from_slice(&4096u16.to_be_bytes());
+        ident.extend_from_slice(&[0; 6]);
+        let packed = (44_100u64 << 44) | (1 << 41) | (15 << 36);
+        ident.extend_from_slice(&packed.to_be_bytes());
+        ident.extend_from_slice(&[0; 16]);
+        let comment = [&[0x84, 0, 0, 8][..], &[0; 8]].concat();
+        [
+            ogg_page(0x02, 0, 0, &ident),
+            ogg_page(0x00, 1, 0, &comment),
+            ogg_page(0x04, 2, 4096, &[0xff, 0xf8, 0, 0]),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn sources_are_classified_by_their_probed_codec() {
+        let config = PolicyConfig {
+            lossless_strategy: LosslessStrategy::ConvertToFlac,
+            ..PolicyConfig::default()
+        };
+        let dir = tempfile::tempdir().unwrap();
+
+        // Both extensions usually mean a lossy codec.
+        let m4a = dir.path().join("alac.m4a");
+        std::fs::write(&m4a, alac_m4a()).unwrap();
+        assert_eq!(
+            AudioFormatPolicy::from_extension("m4a", &config).unwrap(),
+            AudioFormatPolicy::PassthroughLossy
+        );
+        assert_eq!(
+            classify_source(&m4a, &config).unwrap(),
+            AudioFormatPolicy::ConvertLossless
+        );
+        let ogg = dir.path().join("flac.ogg");
+        std::fs::write(&ogg, flac_ogg()).unwrap();
+        assert_eq!(
+            classify_source(&ogg, &config).unwrap(),
+            AudioFormatPolicy::ConvertLossless
+        );
+
+        // Unprobeable files fall back to their extension.
+        let broken = dir.path().join("broken.mp3");
+        std::fs::write(&broken, b"not audio").unwrap();
+        assert_eq!(
+            classify_source(&broken, &config).unwrap(),
+            AudioFormatPolicy::PassthroughLossy
+        );
+        assert!(matches!(
+            classify_source(&dir.path().join("missing.bin"), &config),
+            Err(MusFuseError::UnsupportedFormat(_))
+        ));
     }
 }
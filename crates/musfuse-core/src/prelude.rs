@@ -1,9 +1,15 @@
 pub use crate::config::{
-    KvBackendKind, LosslessStrategy, MountConfig, PolicyConfig, ScanMode, SourceConfig,
+    CueTrackOrder, KvBackendKind, LosslessStrategy, MissingFilePolicy, MountConfig, PolicyConfig,
+    ScanMode, SmartFolderConfig, SourceConfig, SourcePreference,
 };
 pub use crate::error::{MusFuseError, Result};
+pub use crate::filesystem::MediaEngine;
 pub use crate::media::{
-    DefaultCoverExtractor, DefaultFormatTranscoder, MediaEngine, TranscodeRequest, TranscodeResult,
+    DefaultCoverExtractor, DefaultFormatTranscoder, TranscodeRequest, TranscodeResult,
 };
-pub use crate::mount::{MountContext, MountEvent, MountProvider, MountStatus, PlatformAdapter};
-pub use crate::policy::AudioFormatPolicy;
+pub use crate::mount::{
+    AdapterCapabilities, MountContext, MountEvent, MountProvider, MountStats, MountStatus,
+    PlatformAdapter,
+};
+pub use crate::policy::{AudioFormatPolicy, LossyCodec, TargetFormat};
+pub use crate::provider::AdapterMountProvider;
@@ -0,0 +1,554 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use symphonia::core::codecs::{
+    CODEC_TYPE_AAC, CODEC_TYPE_AC4, CODEC_TYPE_ADPCM_G722, CODEC_TYPE_ADPCM_G726,
+    CODEC_TYPE_ADPCM_G726LE, CODEC_TYPE_ADPCM_IMA_QT, CODEC_TYPE_ADPCM_IMA_WAV,
+    CODEC_TYPE_ADPCM_MS, CODEC_TYPE_ATRAC1, CODEC_TYPE_ATRAC3, CODEC_TYPE_ATRAC3PLUS,
+    CODEC_TYPE_ATRAC9, CODEC_TYPE_DCA, CODEC_TYPE_EAC3, CODEC_TYPE_MP1, CODEC_TYPE_MP2,
+    CODEC_TYPE_MP3, CODEC_TYPE_MUSEPACK, CODEC_TYPE_NULL, CODEC_TYPE_OPUS, CODEC_TYPE_PCM_ALAW,
+    CODEC_TYPE_PCM_MULAW, CODEC_TYPE_SPEEX, CODEC_TYPE_VORBIS, CODEC_TYPE_WMA, CodecParameters,
+    CodecType, DecoderOptions,
+};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+use crate::error::{MalformedStream, MusFuseError, Result, TranscodeStage};
+
+// Enough to hold the final Ogg page (max 65 307 bytes) plus its capture pattern.
+const OGG_TAIL_BYTES: u64 = 66 * 1024;
+// Covers every header `stream_header_frames` parses.
+const TRAIL_HEAD_BYTES: usize = 4 * 1024;
+const OPUS_SAMPLE_RATE: u32 = 48_000;
+// symphonia names PCM, a handful of lossless codecs and these; anything else it recognizes
+// reproduces the samples exactly.
+const LOSSY_CODECS: [CodecType; 24] = [
+    CODEC_TYPE_VORBIS,
+    CODEC_TYPE_MP1,
+    CODEC_TYPE_MP2,
+    CODEC_TYPE_MP3,
+    CODEC_TYPE_AAC,
+    CODEC_TYPE_OPUS,
+    CODEC_TYPE_SPEEX,
+    CODEC_TYPE_MUSEPACK,
+    CODEC_TYPE_ATRAC1,
+    CODEC_TYPE_ATRAC3,
+    CODEC_TYPE_ATRAC3PLUS,
+    CODEC_TYPE_ATRAC9,
+    CODEC_TYPE_EAC3,
+    CODEC_TYPE_AC4,
+    CODEC_TYPE_DCA,
+    CODEC_TYPE_WMA,
+    CODEC_TYPE_PCM_ALAW,
+    CODEC_TYPE_PCM_MULAW,
+    CODEC_TYPE_ADPCM_G722,
+    CODEC_TYPE_ADPCM_G726,
+    CODEC_TYPE_ADPCM_G726LE,
+    CODEC_TYPE_ADPCM_MS,
+    CODEC_TYPE_ADPCM_IMA_WAV,
+    CODEC_TYPE_ADPCM_IMA_QT,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamLength {
+    pub frames: u64,
+    pub sample_rate: u32,
+}
+
+impl StreamLength {
+    pub fn duration_ms(&self) -> u64 {
+        if self.sample_rate == 0 {
+            return 0;
+        }
+        self.frames * 1000 / self.sample_rate as u64
+    }
+
+    // Cue sheets address audio in CD frames (1/75 s). Rounded up, so a last track measured
+    // from it keeps the file's final partial frame; decoding stops at the end of the file.
+    pub fn cue_frames(&self) -> u64 {
+        if self.sample_rate == 0 {
+            return 0;
+        }
+        (self.frames * 75).div_ceil(self.sample_rate as u64)
+    }
+}
+
+// Prefer the container's own length field; decoding the whole stream is the last resort.
+pub fn total_frames(path: &Path) -> Result<StreamLength> {
+    match header_frames(path)? {
+        Some(length) => Ok(length),
+        None => decoded_frames(path),
+    }
+}
+
+pub fn header_frames(path: &Path) -> Result<Option<StreamLength>> {
+    stream_header_frames(&mut BufReader::new(File::open(path)?))
+}
+
+// The same header lookup over output already held in memory, such as a finished encode.
+pub fn encoded_frames(data: &[u8]) -> Result<Option<StreamLength>> {
+    stream_header_frames(&mut std::io::Cursor::new(data))
+}
+
+// Keeps only what `encoded_frames` reads of a stream passing through: the header at the
+// front and the last pages, whose granule gives an Ogg stream's length.
+#[derive(Debug, Default)]
+pub struct LengthTrail {
+    head: Vec<u8>,
+    tail: Vec<u8>,
+}
+
+impl LengthTrail {
+    pub fn push(&mut self, mut data: &[u8]) {
+        if self.head.len() < TRAIL_HEAD_BYTES {
+            let take = (TRAIL_HEAD_BYTES - self.head.len()).min(data.len());
+            self.head.extend_from_slice(&data[..take]);
+            data = &data[take..];
+        }
+        self.tail.extend_from_slice(data);
+        // Trimmed in bulk, so the tail never shrinks below what is read from it.
+        let keep = OGG_TAIL_BYTES as usize;
+        if self.tail.len() > 2 * keep {
+            self.tail.drain(..self.tail.len() - keep);
+        }
+    }
+
+    pub fn frames(&self) -> Result<Option<StreamLength>> {
+        let mut data = Vec::with_capacity(self.head.len() + self.tail.len());
+        data.extend_from_slice(&self.head);
+        data.extend_from_slice(&self.tail);
+        encoded_frames(&data)
+    }
+}
+
+fn stream_header_frames(file: &mut (impl Read + Seek)) -> Result<Option<StreamLength>> {
+    let mut magic = [0u8; 4];
+    if file.read_exact(&mut magic).is_err() {
+        return Ok(None);
+    }
+    file.seek(SeekFrom::Start(0))?;
+    match &magic {
+        b"fLaC" => flac_frames(file),
+        b"RIFF" => wav_frames(file),
+        b"OggS" => ogg_frames(file),
+        _ => Ok(None),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub block_align: u16,
+    // Byte offset of the sample-rate field, for callers that patch it.
+    pub rate_offset: u64,
+}
+
+// Reads the `fmt ` chunk of a RIFF/WAVE file and rewinds; None for anything else.
+pub fn wav_format(file: &mut (impl Read + Seek)) -> Result<Option<WavFormat>> {
+    let mut riff = [0u8; 12];
+    let is_wave =
+        file.read_exact(&mut riff).is_ok() && &riff[..4] == b"RIFF" && &riff[8..] == b"WAVE";
+    let mut format = None;
+    let mut header = [0u8; 8];
+    while is_wave && file.read_exact(&mut header).is_ok() {
+        let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as u64;
+        if &header[..4] == b"fmt " {
+            let mut body = [0u8; 16];
+            if size >= 16 && file.read_exact(&mut body).is_ok() {
+                format = Some(WavFormat {
+                    sample_rate: u32::from_le_bytes([body[4], body[5], body[6], body[7]]),
+                    channels: u16::from_le_bytes([body[2], body[3]]),
+                    block_align: u16::from_le_bytes([body[12], body[13]]),
+                    rate_offset: file.stream_position()? - 12,
+                });
+            }
+            break;
+        }
+        file.seek(SeekFrom::Current((size + (size & 1)) as i64))?;
+    }
+    file.rewind()?;
+    Ok(format)
+}
+
+// Sample rate and channel count as the container headers declare them; a field the headers
+// leave out, or give as zero, is None.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StreamFormat {
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u16>,
+}
+
+// Reads only the headers, so indexing a library does not decode it.
+pub fn stream_format(path: &Path) -> Result<StreamFormat> {
+    let mut file = File::open(path)?;
+    // symphonia panics on a zero-rate WAV, so it never sees one.
+    if let Some(format) = wav_format(&mut file)? {
+        return Ok(StreamFormat {
+            sample_rate: Some(format.sample_rate).filter(|&rate| rate > 0),
+            channels: Some(format.channels).filter(|&channels| channels > 0),
+        });
+    }
+    let params = default_track_params(file, path)?;
+    Ok(StreamFormat {
+        sample_rate: params.sample_rate.filter(|&rate| rate > 0),
+        channels: params
+            .channels
+            .map(|channels| channels.count() as u16)
+            .filter(|&channels| channels > 0),
+    })
+}
+
+// Cheap structural check used by eager scans: probes the container without decoding and
+// reports files that can never be played as `MalformedStream` errors.
+pub fn check_stream(path: &Path) -> Result<()> {
+    let mut file = File::open(path)?;
+    if let Some(format) = wav_format(&mut file)? {
+        // symphonia panics on a zero-rate WAV, so it never sees one.
+        return if format.channels == 0 {
+            Err(MusFuseError::malformed(MalformedStream::ZeroChannels))
+        } else if format.sample_rate == 0 {
+            Err(MusFuseError::malformed(MalformedStream::MissingSampleRate))
+        } else {
+            Ok(())
+        };
+    }
+
+    let params = default_track_params(file, path)?;
+    if params.sample_rate.is_none_or(|rate| rate == 0) {
+        return Err(MusFuseError::malformed(MalformedStream::MissingSampleRate));
+    }
+    if params
+        .channels
+        .is_some_and(|channels| channels.count() == 0)
+    {
+        return Err(MusFuseError::malformed(MalformedStream::ZeroChannels));
+    }
+    Ok(())
+}
+
+fn default_track_params(file: File, path: &Path) -> Result<CodecParameters> {
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+    let probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map_err(|err| MusFuseError::Transcode {
+            stage: TranscodeStage::Probe,
+            message: err.to_string(),
+            reason: None,
+        })?;
+    let track = probed
+        .format
+        .default_track()
+        .ok_or(MusFuseError::malformed(MalformedStream::NoAudioTrack))?;
+    Ok(track.codec_params.clone())
+}
+
+// Whether the codec inside the container is lossless, whatever the extension suggests: an
+// `.m4a` may hold ALAC and an `.ogg` FLAC. Only the headers are read.
+pub fn is_lossless_source(path: &Path) -> Result<bool> {
+    let mut file = File::open(path)?;
+    // symphonia panics on a zero-rate WAV, and RIFF/WAVE sources are PCM anyway.
+    if wav_format(&mut file)?.is_some() {
+        return Ok(true);
+    }
+
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+    let probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map_err(|err| MusFuseError::Transcode {
+            stage: TranscodeStage::Probe,
+            message: err.to_string(),
+            reason: None,
+        })?;
+    let codec = probed
+        .format
+        .default_track()
+        .ok_or(MusFuseError::malformed(MalformedStream::NoAudioTrack))?
+        .codec_params
+        .codec;
+    if codec == CODEC_TYPE_NULL {
+        return Err(MusFuseError::Transcode {
+            stage: TranscodeStage::Probe,
+            message: "unknown codec".into(),
+            reason: None,
+        });
+    }
+    Ok(!LOSSY_CODECS.contains(&codec))
+}
+
+pub fn decoded_frames(path: &Path) -> Result<StreamLength> {
+    let probe_err = |err: SymphoniaError| MusFuseError::Transcode {
+        stage: TranscodeStage::Probe,
+        message: err.to_string(),
+        reason: None,
+    };
+    let decode_err = |err: SymphoniaError| MusFuseError::Transcode {
+        stage: TranscodeStage::Decode,
+        message: err.to_string(),
+        reason: None,
+    };
+
+    let mss = MediaSourceStream::new(Box::new(File::open(path)?), Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+    let probed = symphonia::default::get_probe()
+        .format(
+            &hint,
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .map_err(probe_err)?;
+
+    let mut format = probed.format;
+    let track = format
+        .default_track()
+        .ok_or_else(|| MusFuseError::Media("no default audio track".into()))?;
+    let track_id = track.id;
+    let sample_rate = track
+        .codec_params
+        .sample_rate
+        .ok_or_else(|| MusFuseError::Media("missing sample rate".into()))?;
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(decode_err)?;
+
+    let mut frames = 0u64;
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(err))
+                if err.kind() == std::io::ErrorKind::UnexpectedEof =>
+            {
+                break;
+            }
+            Err(SymphoniaError::ResetRequired) => {
+                decoder.reset();
+                continue;
+            }
+            Err(err) => return Err(decode_err(err)),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        frames += decoder.decode(&packet).map_err(decode_err)?.frames() as u64;
+    }
+
+    Ok(StreamLength {
+        frames,
+        sample_rate,
+    })
+}
+
+fn flac_frames(file: &mut (impl Read + Seek)) -> Result<Option<StreamLength>> {
+    // "fLaC", then the mandatory STREAMINFO block header and its 34-byte body.
+    let mut header = [0u8; 4 + 4 + 34];
+    if file.read_exact(&mut header).is_err() || header[4] & 0x7F != 0 {
+        return Ok(None);
+    }
+    let info = &header[8..];
+    let sample_rate =
+        ((info[10] as u32) << 12) | ((info[11] as u32) << 4) | ((info[12] as u32) >> 4);
+    let frames = ((info[13] as u64 & 0x0F) << 32)
+        | u32::from_be_bytes([info[14], info[15], info[16], info[17]]) as u64;
+    // A total of zero means the encoder did not know the length up front.
+    if frames == 0 || sample_rate == 0 {
+        return Ok(None);
+    }
+    Ok(Some(StreamLength {
+        frames,
+        sample_rate,
+    }))
+}
+
+fn wav_frames(file: &mut (impl Read + Seek)) -> Result<Option<StreamLength>> {
+    let mut riff = [0u8; 12];
+    if file.read_exact(&mut riff).is_err() || &riff[8..12] != b"WAVE" {
+        return Ok(None);
+    }
+
+    let mut format: Option<(u32, u16)> = None;
+    let mut chunk = [0u8; 8];
+    while file.read_exact(&mut chunk).is_ok() {
+        let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+        match &chunk[..4] {
+            b"fmt " => {
+                let mut fmt = [0u8; 16];
+                if size < 16 || file.read_exact(&mut fmt).is_err() {
+                    return Ok(None);
+                }
+                let sample_rate = u32::from_le_bytes([fmt[4], fmt[5], fmt[6], fmt[7]]);
+                let block_align = u16::from_le_bytes([fmt[12], fmt[13]]);
+                format = Some((sample_rate, block_align));
+                file.seek(SeekFrom::Current(size as i64 - 16 + (size & 1) as i64))?;
+            }
+            b"data" => {
+                // Streaming writers leave the size as 0 or 0xFFFFFFFF.
+                return Ok(match format {
+                    Some((sample_rate, block_align))
+                        if sample_rate > 0 && block_align > 0 && size != 0 && size != u32::MAX =>
+                    {
+                        Some(StreamLength {
+                            frames: size as u64 / block_align as u64,
+                            sample_rate,
+                        })
+                    }
+                    _ => None,
+                });
+            }
+            _ => {
+                file.seek(SeekFrom::Current(size as i64 + (size & 1) as i64))?;
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn ogg_frames(file: &mut (impl Read + Seek)) -> Result<Option<StreamLength>> {
+    // The first page carries the codec identification header.
+    let mut page = [0u8; 27];
+    if file.read_exact(&mut page).is_err() {
+        return Ok(None);
+    }
+    let mut lacing = vec![0u8; page[26] as usize];
+    file.read_exact(&mut lacing)?;
+    let mut packet = [0u8; 19];
+    if file.read_exact(&mut packet).is_err() {
+        return Ok(None);
+    }
+    let (sample_rate, pre_skip) = if packet.starts_with(b"\x01vorbis") {
+        (
+            u32::from_le_bytes([packet[12], packet[13], packet[14], packet[15]]),
+            0,
+        )
+    } else if packet.starts_with(b"OpusHead") {
+        (
+            OPUS_SAMPLE_RATE,
+            u16::from_le_bytes([packet[10], packet[11]]) as u64,
+        )
+    } else {
+        return Ok(None);
+    };
+
+    let end = file.seek(SeekFrom::End(0))?;
+    let start = end.saturating_sub(OGG_TAIL_BYTES);
+    file.seek(SeekFrom::Start(start))?;
+    let mut tail = Vec::with_capacity((end - start) as usize);
+    file.read_to_end(&mut tail)?;
+
+    // The granule position of the last page is the stream's final sample index.
+    let granule = tail
+        .windows(4)
+        .enumerate()
+        .rev()
+        .filter(|(_, window)| *window == b"OggS")
+        .find_map(|(pos, _)| {
+            let bytes = tail.get(pos + 6..pos + 14)?;
+            let granule = i64::from_le_bytes(bytes.try_into().ok()?);
+            (granule >= 0).then_some(granule as u64)
+        });
+
+    Ok(match granule {
+        Some(granule) if sample_rate > 0 && granule > pre_skip => Some(StreamLength {
+            frames: granule - pre_skip,
+            sample_rate,
+        }),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flac_codec::encode::{FlacSampleWriter, Options};
+
+    fn write_flac(path: &Path, frames: usize) {
+        let samples: Vec<i32> = (0..frames * 2)
+            .map(|i| ((i as i32 * 37) % 2000) - 1000)
+            .collect();
+        let mut file = File::create(path).expect("create flac");
+        let mut writer = FlacSampleWriter::new(&mut file, Options::default(), 44_100, 16, 2, None)
+            .expect("writer");
+        writer.write(&samples).expect("write samples");
+        writer.finalize().expect("finalize");
+    }
+
+    #[test]
+    fn flac_streaminfo_matches_decoded_length() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("album.flac");
+        write_flac(&path, 10_000);
+
+        let fast = header_frames(&path)
+            .expect("read header")
+            .expect("streaminfo has a length");
+        let decoded = decoded_frames(&path).expect("decode");
+
+        assert_eq!(fast, decoded);
+        assert_eq!(fast.frames, 10_000);
+        assert_eq!(total_frames(&path).unwrap(), fast);
+    }
+
+    #[test]
+    fn length_trail_reads_the_same_length_as_the_whole_stream() {
+        let mut writer = ogg::PacketWriter::new(Vec::new());
+        let mut head = b"OpusHead\x01\x02".to_vec();
+        head.extend_from_slice(&312u16.to_le_bytes());
+        head.extend_from_slice(&[0; 7]);
+        writer
+            .write_packet(head, 1, ogg::PacketWriteEndInfo::EndPage, 0)
+            .unwrap();
+        let packets = 2_000u64;
+        for packet in 1..=packets {
+            let info = if packet == packets {
+                ogg::PacketWriteEndInfo::EndStream
+            } else {
+                ogg::PacketWriteEndInfo::NormalPacket
+            };
+            writer
+                .write_packet(vec![packet as u8; 200], 1, info, 312 + packet * 960)
+                .unwrap();
+        }
+        let ogg = writer.into_inner();
+        assert!(ogg.len() as u64 > 4 * OGG_TAIL_BYTES);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("album.flac");
+        write_flac(&path, 10_000);
+        let flac = std::fs::read(&path).unwrap();
+
+        for stream in [ogg, flac] {
+            let mut trail = LengthTrail::default();
+            for piece in stream.chunks(1_000) {
+                trail.push(piece);
+            }
+            let whole = encoded_frames(&stream).unwrap();
+            assert!(whole.is_some());
+            assert_eq!(trail.frames().unwrap(), whole);
+        }
+    }
+}
@@ -0,0 +1,607 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use parking_lot::RwLock;
+use tracing::warn;
+
+use crate::error::{MusFuseError, Result};
+use crate::mount::{
+    AdapterCapabilities, MountContext, MountEvent, MountProvider, MountStats, MountStatus,
+    PlatformAdapter,
+};
+
+// Drives any `PlatformAdapter` through the mount lifecycle: status transitions, draining
+// in-flight operations and falling back to unmount-and-mount when remounting in place fails.
+pub struct AdapterMountProvider<A: PlatformAdapter> {
+    adapter: Arc<A>,
+    status: RwLock<MountStatus>,
+    context: RwLock<Option<Arc<MountContext>>>,
+    mounted_at: RwLock<Option<PathBuf>>,
+}
+
+impl<A: PlatformAdapter> AdapterMountProvider<A> {
+    pub fn new(adapter: Arc<A>) -> Self {
+        Self {
+            adapter,
+            status: RwLock::new(MountStatus::Unmounted),
+            context: RwLock::new(None),
+            mounted_at: RwLock::new(None),
+        }
+    }
+
+    pub fn with_adapter(adapter: A) -> Self {
+        Self::new(Arc::new(adapter))
+    }
+
+    pub fn capabilities(&self) -> AdapterCapabilities {
+        self.adapter.capabilities()
+    }
+
+    fn transition_to_mounting(&self) -> Result<()> {
+        let mut status = self.status.write();
+        match &*status {
+            MountStatus::Unmounted | MountStatus::Faulted(_) => {
+                *status = MountStatus::Mounting;
+                Ok(())
+            }
+            MountStatus::Mounting => Err(MusFuseError::Mount("mount already in progress".into())),
+            MountStatus::Mounted => Err(MusFuseError::Mount("already mounted".into())),
+            MountStatus::Remounting => {
+                Err(MusFuseError::Mount("remount currently in progress".into()))
+            }
+            MountStatus::Unmounting => {
+                Err(MusFuseError::Mount("unmount currently in progress".into()))
+            }
+        }
+    }
+
+    fn transition_to_unmounting(&self) -> Result<()> {
+        let mut status = self.status.write();
+        match &*status {
+            MountStatus::Mounted => {
+                *status = MountStatus::Unmounting;
+                Ok(())
+            }
+            MountStatus::Unmounted => Ok(()),
+            MountStatus::Mounting => {
+                Err(MusFuseError::Mount("cannot unmount while mounting".into()))
+            }
+            MountStatus::Remounting => Err(MusFuseError::Mount(
+                "cannot unmount while remounting".into(),
+            )),
+            MountStatus::Unmounting => {
+                Err(MusFuseError::Mount("unmount already in progress".into()))
+            }
+            MountStatus::Faulted(_) => {
+                *status = MountStatus::Unmounting;
+                Ok(())
+            }
+        }
+    }
+
+    fn transition_to_remounting(&self) -> Result<()> {
+        let mut status = self.status.write();
+        match &*status {
+            MountStatus::Mounted | MountStatus::Faulted(_) => {
+                *status = MountStatus::Remounting;
+                Ok(())
+            }
+            MountStatus::Unmounted => Err(MusFuseError::Mount("not mounted".into())),
+            MountStatus::Mounting => {
+                Err(MusFuseError::Mount("cannot remount while mounting".into()))
+            }
+            MountStatus::Remounting => {
+                Err(MusFuseError::Mount("remount already in progress".into()))
+            }
+            MountStatus::Unmounting => Err(MusFuseError::Mount(
+                "cannot remount while unmounting".into(),
+            )),
+        }
+    }
+
+    fn set_status(&self, status: MountStatus) {
+        *self.status.write() = status;
+    }
+
+    fn update_context(&self, ctx: Option<Arc<MountContext>>) {
+        *self.context.write() = ctx;
+    }
+
+    fn current_context(&self) -> Option<Arc<MountContext>> {
+        self.context.read().clone()
+    }
+
+    fn emit_event(ctx: &MountContext, event: MountEvent) {
+        let _ = ctx.signal.send(event);
+    }
+
+    // Prepares the environment and mounts `ctx`, returning where the mount ended up.
+    async fn attach(&self, ctx: &MountContext) -> Result<PathBuf> {
+        self.adapter.prepare_environment(&ctx.config).await?;
+        let mount_point = self.adapter.resolve_mount_point(&ctx.config).await?;
+
+        if mount_point == ctx.config.mount_point {
+            self.adapter.mount(ctx).await?;
+        } else {
+            Self::emit_event(ctx, MountEvent::MountPointSelected(mount_point.clone()));
+            self.adapter
+                .mount(&ctx.relocated(mount_point.clone()))
+                .await?;
+        }
+        Ok(mount_point)
+    }
+
+    // Fallback for adapters that cannot remount in place: detach, then mount `ctx` afresh.
+    async fn reattach(&self, mount_point: &Path, ctx: &MountContext) -> Result<PathBuf> {
+        self.adapter.unmount(mount_point).await?;
+        *self.mounted_at.write() = None;
+        self.update_context(None);
+        self.attach(ctx).await
+    }
+
+    // Stops new operations on `ctx`, waits for running ones and flushes its store.
+    async fn quiesce(&self, ctx: &MountContext) -> Result<()> {
+        ctx.operations.close();
+        if !ctx.operations.drain(ctx.drain_timeout).await {
+            warn!(
+                in_flight = ctx.operations.in_flight(),
+                "unmounting with operations still in flight"
+            );
+        }
+
+        if let Some(kv) = &ctx.kv {
+            kv.flush().await?;
+        }
+        Ok(())
+    }
+
+    fn handle_fault(&self, ctx: &Arc<MountContext>, err: MusFuseError) -> MusFuseError {
+        let reason = err.to_string();
+        self.set_status(MountStatus::Faulted(reason.clone()));
+        Self::emit_event(ctx, MountEvent::Fault(reason));
+        err
+    }
+}
+
+#[async_trait]
+impl<A: PlatformAdapter> MountProvider for AdapterMountProvider<A> {
+    async fn mount(&self, ctx: Arc<MountContext>) -> Result<()> {
+        self.transition_to_mounting()?;
+
+        let mount_point = match self.attach(&ctx).await {
+            Ok(mount_point) => mount_point,
+            Err(err) => return Err(self.handle_fault(&ctx, err)),
+        };
+
+        *self.mounted_at.write() = Some(mount_point);
+        self.update_context(Some(ctx.clone()));
+        self.set_status(MountStatus::Mounted);
+        Self::emit_event(&ctx, MountEvent::Mounted);
+        Ok(())
+    }
+
+    async fn unmount(&self) -> Result<()> {
+        let ctx = match self.current_context() {
+            Some(ctx) => ctx,
+            None => {
+                self.set_status(MountStatus::Unmounted);
+                return Ok(());
+            }
+        };
+
+        self.transition_to_unmounting()?;
+
+        if let Err(err) = self.quiesce(&ctx).await {
+            return Err(self.handle_fault(&ctx, err));
+        }
+
+        let mount_point = self
+            .mounted_at
+            .read()
+            .clone()
+            .unwrap_or_else(|| ctx.mount_point().to_path_buf());
+        if let Err(err) = self.adapter.unmount(&mount_point).await {
+            return Err(self.handle_fault(&ctx, err));
+        }
+
+        *self.mounted_at.write() = None;
+        self.update_context(None);
+        self.set_status(MountStatus::Unmounted);
+        Self::emit_event(&ctx, MountEvent::Unmounted);
+        Ok(())
+    }
+
+    async fn remount(&self, ctx: Arc<MountContext>) -> Result<()> {
+        let Some(previous) = self.current_context() else {
+            return self.mount(ctx).await;
+        };
+
+        self.transition_to_remounting()?;
+
+        if let Err(err) = self.quiesce(&previous).await {
+            return Err(self.handle_fault(&ctx, err));
+        }
+
+        let mount_point = self
+            .mounted_at
+            .read()
+            .clone()
+            .unwrap_or_else(|| previous.mount_point().to_path_buf());
+        // A new mount point cannot be reached in place, so only the same one is tried.
+        let result = if ctx.config.mount_point == previous.config.mount_point {
+            match self.adapter.remount(&mount_point, &ctx).await {
+                Ok(()) => Ok(mount_point),
+                Err(MusFuseError::Unsupported(_)) => self.reattach(&mount_point, &ctx).await,
+                Err(err) => Err(err),
+            }
+        } else {
+            self.reattach(&mount_point, &ctx).await
+        };
+        let mount_point = match result {
+            Ok(mount_point) => mount_point,
+            Err(err) => return Err(self.handle_fault(&ctx, err)),
+        };
+
+        *self.mounted_at.write() = Some(mount_point);
+        self.update_context(Some(ctx.clone()));
+        self.set_status(MountStatus::Mounted);
+        Self::emit_event(&ctx, MountEvent::Remounted);
+        Ok(())
+    }
+
+    fn status(&self) -> MountStatus {
+        self.status.read().clone()
+    }
+
+    fn stats(&self) -> MountStats {
+        self.current_context()
+            .map(|ctx| ctx.stats.clone())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    use mockall::{mock, predicate::always};
+
+    use crate::config::{LosslessStrategy, MountConfig, PolicyConfig, SourceConfig};
+
+    mock! {
+        pub Adapter {}
+
+        #[async_trait]
+        impl PlatformAdapter for Adapter {
+            async fn prepare_environment(&self, config: &MountConfig) -> Result<()>;
+            async fn resolve_mount_point(&self, config: &MountConfig) -> Result<PathBuf>;
+            async fn mount(&self, ctx: &MountContext) -> Result<()>;
+            async fn unmount(&self, mount_point: &Path) -> Result<()>;
+            async fn remount(&self, mount_point: &Path, ctx: &MountContext) -> Result<()>;
+        }
+    }
+
+    fn sample_config() -> MountConfig {
+        MountConfig {
+            sources: vec![SourceConfig {
+                path: "/srv/music".into(),
+                recursive: true,
+                watch: true,
+                follow_symlinks: true,
+            }],
+            mount_point: "/mnt/music".into(),
+            cache_dir: Some("/var/cache/musfuse".into()),
+            policies: PolicyConfig {
+                lossless_strategy: LosslessStrategy::ConvertToFlac,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn mount_invokes_adapter_and_updates_status() {
+        let mut mock_adapter = MockAdapter::new();
+        mock_adapter
+            .expect_prepare_environment()
+            .with(always())
+            .returning(|_| Ok(()));
+        mock_adapter
+            .expect_resolve_mount_point()
+            .returning(|config| Ok(config.mount_point.clone()));
+        mock_adapter
+            .expect_mount()
+            .with(always())
+            .returning(|_| Ok(()));
+
+        let provider = AdapterMountProvider::new(Arc::new(mock_adapter));
+        let ctx = Arc::new(MountContext::new(sample_config()));
+        let mut rx = ctx.signal.subscribe();
+
+        provider
+            .mount(ctx.clone())
+            .await
+            .expect("mount should succeed");
+        assert_eq!(provider.status(), MountStatus::Mounted);
+
+        let event = rx.recv().await.expect("event expected");
+        assert_eq!(event, MountEvent::Mounted);
+    }
+
+    #[tokio::test]
+    async fn unmount_invokes_adapter_and_resets_status() {
+        let mut mock_adapter = MockAdapter::new();
+        mock_adapter
+            .expect_prepare_environment()
+            .returning(|_| Ok(()));
+        mock_adapter
+            .expect_resolve_mount_point()
+            .returning(|config| Ok(config.mount_point.clone()));
+        mock_adapter.expect_mount().returning(|_| Ok(()));
+        mock_adapter
+            .expect_unmount()
+            .withf(|path| path.to_string_lossy() == "/mnt/music")
+            .returning(|_| Ok(()));
+
+        let provider = AdapterMountProvider::new(Arc::new(mock_adapter));
+        let ctx = Arc::new(MountContext::new(sample_config()));
+        let mut rx = ctx.signal.subscribe();
+
+        provider.mount(ctx.clone()).await.unwrap();
+        let _ = rx.recv().await.unwrap(); // Mounted
+
+        provider.unmount().await.expect("unmount should succeed");
+        assert_eq!(provider.status(), MountStatus::Unmounted);
+        let event = rx.recv().await.expect("unmount event expected");
+        assert_eq!(event, MountEvent::Unmounted);
+    }
+
+    #[tokio::test]
+    async fn unmount_waits_for_in_flight_operations() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::time::{Duration, Instant};
+
+        let finished = Arc::new(AtomicBool::new(false));
+        let observed = finished.clone();
+
+        let mut mock_adapter = MockAdapter::new();
+        mock_adapter
+            .expect_prepare_environment()
+            .returning(|_| Ok(()));
+        mock_adapter
+            .expect_resolve_mount_point()
+            .returning(|config| Ok(config.mount_point.clone()));
+        mock_adapter.expect_mount().returning(|_| Ok(()));
+        mock_adapter.expect_unmount().returning(move |_| {
+            assert!(observed.load(Ordering::SeqCst), "unmounted before drain");
+            Ok(())
+        });
+
+        let provider = AdapterMountProvider::new(Arc::new(mock_adapter));
+        let ctx =
+            Arc::new(MountContext::new(sample_config()).with_drain_timeout(Duration::from_secs(5)));
+        provider.mount(ctx.clone()).await.unwrap();
+
+        let guard = ctx.operations.begin().expect("accepting operations");
+        let flag = finished.clone();
+        let worker = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            flag.store(true, Ordering::SeqCst);
+            drop(guard);
+        });
+
+        let started = Instant::now();
+        provider.unmount().await.expect("unmount should succeed");
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert!(ctx.operations.begin().is_none());
+        assert_eq!(provider.status(), MountStatus::Unmounted);
+        worker.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn unmount_gives_up_on_stuck_operations_after_timeout() {
+        use std::time::Duration;
+
+        let mut mock_adapter = MockAdapter::new();
+        mock_adapter
+            .expect_prepare_environment()
+            .returning(|_| Ok(()));
+        mock_adapter
+            .expect_resolve_mount_point()
+            .returning(|config| Ok(config.mount_point.clone()));
+        mock_adapter.expect_mount().returning(|_| Ok(()));
+        mock_adapter.expect_unmount().returning(|_| Ok(()));
+
+        let provider = AdapterMountProvider::new(Arc::new(mock_adapter));
+        let ctx = Arc::new(
+            MountContext::new(sample_config()).with_drain_timeout(Duration::from_millis(20)),
+        );
+        provider.mount(ctx.clone()).await.unwrap();
+
+        let _stuck = ctx.operations.begin().expect("accepting operations");
+        provider.unmount().await.expect("unmount should succeed");
+        assert_eq!(provider.status(), MountStatus::Unmounted);
+        assert_eq!(ctx.operations.in_flight(), 1);
+    }
+
+    #[tokio::test]
+    async fn mount_reports_auto_selected_mount_point_and_unmounts_it() {
+        let mut mock_adapter = MockAdapter::new();
+        mock_adapter
+            .expect_prepare_environment()
+            .returning(|_| Ok(()));
+        mock_adapter
+            .expect_resolve_mount_point()
+            .returning(|_| Ok(PathBuf::from("/mnt/music-1")));
+        mock_adapter
+            .expect_mount()
+            .withf(|ctx| ctx.config.mount_point == Path::new("/mnt/music-1"))
+            .returning(|_| Ok(()));
+        mock_adapter
+            .expect_unmount()
+            .withf(|path| path.to_string_lossy() == "/mnt/music-1")
+            .returning(|_| Ok(()));
+
+        let provider = AdapterMountProvider::new(Arc::new(mock_adapter));
+        let ctx = Arc::new(MountContext::new(sample_config()));
+        let mut rx = ctx.signal.subscribe();
+
+        provider
+            .mount(ctx.clone())
+            .await
+            .expect("mount should succeed");
+        assert_eq!(
+            rx.recv().await.unwrap(),
+            MountEvent::MountPointSelected(PathBuf::from("/mnt/music-1"))
+        );
+        assert_eq!(rx.recv().await.unwrap(), MountEvent::Mounted);
+
+        provider.unmount().await.expect("unmount should succeed");
+    }
+
+    #[tokio::test]
+    async fn mount_failure_moves_to_fault_state() {
+        let mut mock_adapter = MockAdapter::new();
+        mock_adapter
+            .expect_prepare_environment()
+            .returning(|_| Ok(()));
+        mock_adapter
+            .expect_resolve_mount_point()
+            .returning(|config| Ok(config.mount_point.clone()));
+        mock_adapter
+            .expect_mount()
+            .returning(|_| Err(MusFuseError::Mount("mount failed".into())));
+
+        let provider = AdapterMountProvider::new(Arc::new(mock_adapter));
+        let ctx = Arc::new(MountContext::new(sample_config()));
+        let mut rx = ctx.signal.subscribe();
+
+        let err = provider.mount(ctx.clone()).await.expect_err("should fail");
+        assert!(matches!(err, MusFuseError::Mount(_)));
+
+        match provider.status() {
+            MountStatus::Faulted(reason) => assert!(reason.contains("mount failed")),
+            other => panic!("unexpected status {other:?}", other = other),
+        }
+
+        let event = rx.recv().await.expect("fault event expected");
+        match event {
+            MountEvent::Fault(reason) => assert!(reason.contains("mount failed")),
+            other => panic!("unexpected event {other:?}", other = other),
+        }
+    }
+
+    #[tokio::test]
+    async fn remount_swaps_config_in_place_through_remounting() {
+        use std::sync::{OnceLock, Weak};
+
+        let slot: Arc<OnceLock<Weak<AdapterMountProvider<MockAdapter>>>> =
+            Arc::new(OnceLock::new());
+        let during = Arc::new(parking_lot::Mutex::new(None));
+
+        let mut mock_adapter = MockAdapter::new();
+        mock_adapter
+            .expect_prepare_environment()
+            .times(1)
+            .returning(|_| Ok(()));
+        mock_adapter
+            .expect_resolve_mount_point()
+            .times(1)
+            .returning(|config| Ok(config.mount_point.clone()));
+        mock_adapter.expect_mount().times(1).returning(|_| Ok(()));
+        mock_adapter.expect_unmount().never();
+        let provider_ref = slot.clone();
+        let observed = during.clone();
+        mock_adapter
+            .expect_remount()
+            .withf(|path, ctx| {
+                path.to_string_lossy() == "/mnt/music"
+                    && ctx.config.policies.lossless_strategy == LosslessStrategy::Passthrough
+            })
+            .times(1)
+            .returning(move |_, _| {
+                let provider = provider_ref
+                    .get()
+                    .and_then(Weak::upgrade)
+                    .expect("provider");
+                *observed.lock() = Some(provider.status());
+                Ok(())
+            });
+
+        let provider = Arc::new(AdapterMountProvider::new(Arc::new(mock_adapter)));
+        slot.set(Arc::downgrade(&provider)).expect("slot is empty");
+
+        let first = Arc::new(MountContext::new(sample_config()));
+        provider.mount(first.clone()).await.unwrap();
+
+        let mut config = sample_config();
+        config.policies.lossless_strategy = LosslessStrategy::Passthrough;
+        let second = Arc::new(MountContext::new(config));
+        let mut rx = second.signal.subscribe();
+
+        provider
+            .remount(second.clone())
+            .await
+            .expect("remount should succeed");
+        assert_eq!(*during.lock(), Some(MountStatus::Remounting));
+        assert_eq!(provider.status(), MountStatus::Mounted);
+        assert_eq!(rx.recv().await.unwrap(), MountEvent::Remounted);
+        assert!(first.operations.begin().is_none());
+        assert!(second.operations.begin().is_some());
+    }
+
+    #[tokio::test]
+    async fn remount_falls_back_to_unmount_and_mount() {
+        let mounted = Arc::new(parking_lot::Mutex::new(Vec::new()));
+        let recorded = mounted.clone();
+
+        let mut mock_adapter = MockAdapter::new();
+        mock_adapter
+            .expect_prepare_environment()
+            .times(2)
+            .returning(|_| Ok(()));
+        mock_adapter
+            .expect_resolve_mount_point()
+            .times(2)
+            .returning(|config| Ok(config.mount_point.clone()));
+        mock_adapter.expect_mount().times(2).returning(move |ctx| {
+            recorded
+                .lock()
+                .push(ctx.config.policies.lossless_strategy.clone());
+            Ok(())
+        });
+        mock_adapter
+            .expect_remount()
+            .times(1)
+            .returning(|_, _| Err(MusFuseError::Unsupported("in-place remount")));
+        mock_adapter
+            .expect_unmount()
+            .withf(|path| path.to_string_lossy() == "/mnt/music")
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let provider = AdapterMountProvider::new(Arc::new(mock_adapter));
+        provider
+            .mount(Arc::new(MountContext::new(sample_config())))
+            .await
+            .unwrap();
+
+        let mut config = sample_config();
+        config.policies.lossless_strategy = LosslessStrategy::Passthrough;
+        let ctx = Arc::new(MountContext::new(config));
+        let mut rx = ctx.signal.subscribe();
+
+        provider.remount(ctx).await.expect("remount should succeed");
+        assert_eq!(provider.status(), MountStatus::Mounted);
+        assert_eq!(rx.recv().await.unwrap(), MountEvent::Remounted);
+        assert_eq!(
+            *mounted.lock(),
+            vec![
+                LosslessStrategy::ConvertToFlac,
+                LosslessStrategy::Passthrough
+            ]
+        );
+    }
+}
@@ -0,0 +1,223 @@
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+use crate::metadata::TagValue;
+use crate::track::TrackIndexEntry;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum QueryField {
+    Tag(String),
+    Title,
+    Artist,
+    AlbumArtist,
+    Album,
+    Disc,
+    Index,
+    DurationMs,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Comparison {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn accepts(self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Lt => ordering == Ordering::Less,
+            Comparison::Le => ordering != Ordering::Greater,
+            Comparison::Gt => ordering == Ordering::Greater,
+            Comparison::Ge => ordering != Ordering::Less,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum TagQuery {
+    Eq(QueryField, TagValue),
+    Compare(QueryField, Comparison, TagValue),
+    And(Vec<TagQuery>),
+    Or(Vec<TagQuery>),
+}
+
+impl TagQuery {
+    pub fn tag_eq(key: impl Into<String>, value: TagValue) -> Self {
+        TagQuery::Eq(QueryField::Tag(key.into()), value)
+    }
+
+    pub fn tag_cmp(key: impl Into<String>, op: Comparison, value: TagValue) -> Self {
+        TagQuery::Compare(QueryField::Tag(key.into()), op, value)
+    }
+
+    pub fn and(self, other: TagQuery) -> Self {
+        match self {
+            TagQuery::And(mut queries) => {
+                queries.push(other);
+                TagQuery::And(queries)
+            }
+            query => TagQuery::And(vec![query, other]),
+        }
+    }
+
+    pub fn or(self, other: TagQuery) -> Self {
+        match self {
+            TagQuery::Or(mut queries) => {
+                queries.push(other);
+                TagQuery::Or(queries)
+            }
+            query => TagQuery::Or(vec![query, other]),
+        }
+    }
+
+    pub fn matches(&self, entry: &TrackIndexEntry) -> bool {
+        match self {
+            TagQuery::Eq(field, expected) => field_value(entry, field)
+                .is_some_and(|value| any_value(&value, |v| values_equal(v, expected))),
+            TagQuery::Compare(field, op, expected) => {
+                field_value(entry, field).is_some_and(|value| {
+                    any_value(&value, |v| {
+                        compare_values(v, expected).is_some_and(|ordering| op.accepts(ordering))
+                    })
+                })
+            }
+            TagQuery::And(queries) => queries.iter().all(|query| query.matches(entry)),
+            TagQuery::Or(queries) => queries.iter().any(|query| query.matches(entry)),
+        }
+    }
+}
+
+fn field_value(entry: &TrackIndexEntry, field: &QueryField) -> Option<TagValue> {
+    let metadata = &entry.metadata;
+    match field {
+        QueryField::Tag(key) => metadata
+            .tags
+            .get(key)
+            .or_else(|| {
+                metadata
+                    .tags
+                    .0
+                    .iter()
+                    .find(|(candidate, _)| candidate.eq_ignore_ascii_case(key))
+                    .map(|(_, value)| value)
+            })
+            .cloned(),
+        QueryField::Title => Some(TagValue::Text(metadata.title.clone())),
+        QueryField::Artist => Some(TagValue::Text(metadata.artist.clone())),
+        QueryField::AlbumArtist => metadata.album_artist.clone().map(TagValue::Text),
+        QueryField::Album => Some(TagValue::Text(entry.id.album.0.clone())),
+        QueryField::Disc => Some(TagValue::Number(entry.id.disc.into())),
+        QueryField::Index => Some(TagValue::Number(entry.id.index.into())),
+        QueryField::DurationMs => Some(TagValue::Number(metadata.duration_ms as i64)),
+    }
+}
+
+// A multi-valued tag matches when any of its values does.
+fn any_value(value: &TagValue, predicate: impl Fn(&TagValue) -> bool + Copy) -> bool {
+    match value {
+        TagValue::List(values) => values.iter().any(|value| any_value(value, predicate)),
+        value => predicate(value),
+    }
+}
+
+// Tags read from files are text, so "2003" or "2003-05-01" should compare as a number.
+fn as_number(value: &TagValue) -> Option<f64> {
+    match value {
+        TagValue::Number(value) => Some(*value as f64),
+        TagValue::Float(value) => Some(*value),
+        TagValue::Text(text) => {
+            let text = text.trim();
+            text.parse().ok().or_else(|| {
+                let digits: String = text.chars().take_while(|c| c.is_ascii_digit()).collect();
+                digits.parse().ok()
+            })
+        }
+        TagValue::Bool(_) | TagValue::List(_) => None,
+    }
+}
+
+fn values_equal(actual: &TagValue, expected: &TagValue) -> bool {
+    match (actual, expected) {
+        (TagValue::Text(a), TagValue::Text(b)) => a.trim().eq_ignore_ascii_case(b.trim()),
+        (TagValue::Bool(a), TagValue::Bool(b)) => a == b,
+        (TagValue::Bool(_), _) | (_, TagValue::Bool(_)) => false,
+        _ => compare_values(actual, expected) == Some(Ordering::Equal),
+    }
+}
+
+fn compare_values(actual: &TagValue, expected: &TagValue) -> Option<Ordering> {
+    if let (Some(a), Some(b)) = (as_number(actual), as_number(expected)) {
+        return a.partial_cmp(&b);
+    }
+    match (actual, expected) {
+        (TagValue::Text(a), TagValue::Text(b)) => Some(a.to_lowercase().cmp(&b.to_lowercase())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::track::TrackIndex;
+
+    fn entry(index: u32, genre: TagValue, date: &str, duration_ms: u64) -> TrackIndexEntry {
+        let mut entry = fixtures::entry("mixed", index, format!("{index:02}.flac"));
+        entry.metadata.duration_ms = duration_ms;
+        entry.metadata.tags.insert("GENRE", genre);
+        entry
+            .metadata
+            .tags
+            .insert("DATE", TagValue::Text(date.into()));
+        entry
+    }
+
+    fn mixed_index() -> TrackIndex {
+        TrackIndex {
+            entries: vec![
+                entry(1, TagValue::Text("Jazz".into()), "1998", 200_000),
+                entry(2, TagValue::Text("jazz".into()), "2003-05-01", 400_000),
+                entry(
+                    3,
+                    TagValue::List(vec![
+                        TagValue::Text("Rock".into()),
+                        TagValue::Text("Jazz".into()),
+                    ]),
+                    "2010",
+                    150_000,
+                ),
+                entry(4, TagValue::Text("Rock".into()), "2015", 500_000),
+            ],
+        }
+    }
+
+    fn indices(results: Vec<&TrackIndexEntry>) -> Vec<u32> {
+        results.iter().map(|entry| entry.id.index).collect()
+    }
+
+    #[test]
+    fn compound_query_selects_matching_subset() {
+        let index = mixed_index();
+        let query = TagQuery::tag_eq("genre", TagValue::Text("Jazz".into())).and(
+            TagQuery::tag_cmp("DATE", Comparison::Ge, TagValue::Number(2000)),
+        );
+
+        assert_eq!(indices(index.query(&query)), vec![2, 3]);
+    }
+
+    #[test]
+    fn or_combines_structured_fields_and_tags() {
+        let index = mixed_index();
+        let query = TagQuery::Compare(
+            QueryField::DurationMs,
+            Comparison::Gt,
+            TagValue::Number(450_000),
+        )
+        .or(TagQuery::Eq(QueryField::Index, TagValue::Number(1)));
+
+        assert_eq!(indices(index.query(&query)), vec![1, 4]);
+    }
+}
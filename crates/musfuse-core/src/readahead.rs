@@ -0,0 +1,220 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use parking_lot::Mutex;
+use tokio::task::{self, JoinHandle};
+
+use crate::error::{MusFuseError, Result};
+
+#[async_trait]
+pub trait ChunkSource: Send + Sync + 'static {
+    async fn fetch(&self, index: usize) -> Result<Option<Bytes>>;
+}
+
+pub struct FileChunkSource {
+    path: PathBuf,
+    chunk_size: usize,
+}
+
+impl FileChunkSource {
+    pub fn new(path: impl Into<PathBuf>, chunk_size: usize) -> Self {
+        Self {
+            path: path.into(),
+            chunk_size: chunk_size.max(1),
+        }
+    }
+}
+
+#[async_trait]
+impl ChunkSource for FileChunkSource {
+    async fn fetch(&self, index: usize) -> Result<Option<Bytes>> {
+        let path = self.path.clone();
+        let chunk_size = self.chunk_size;
+        task::spawn_blocking(move || {
+            let mut file = std::fs::File::open(&path)?;
+            file.seek(SeekFrom::Start((index * chunk_size) as u64))?;
+            let mut buffer = Vec::with_capacity(chunk_size);
+            file.take(chunk_size as u64).read_to_end(&mut buffer)?;
+            Ok(if buffer.is_empty() {
+                None
+            } else {
+                Some(Bytes::from(buffer))
+            })
+        })
+        .await
+        .map_err(|err| MusFuseError::Media(err.to_string()))?
+    }
+}
+
+#[derive(Default)]
+struct ReadAheadState {
+    last: Option<usize>,
+    buffer: BTreeMap<usize, Bytes>,
+    task: Option<JoinHandle<()>>,
+}
+
+impl ReadAheadState {
+    fn cancel(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+        self.buffer.clear();
+    }
+}
+
+pub struct ReadAhead<S: ChunkSource> {
+    source: Arc<S>,
+    depth: usize,
+    state: Arc<Mutex<ReadAheadState>>,
+    hits: AtomicUsize,
+}
+
+impl<S: ChunkSource> ReadAhead<S> {
+    pub fn new(source: Arc<S>, depth: usize) -> Self {
+        Self {
+            source,
+            depth,
+            state: Arc::new(Mutex::new(ReadAheadState::default())),
+            hits: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn prefetch_hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn buffered(&self) -> usize {
+        self.state.lock().buffer.len()
+    }
+
+    pub async fn read(&self, index: usize) -> Result<Option<Bytes>> {
+        let (cached, sequential) = {
+            let mut state = self.state.lock();
+            let sequential = state.last.is_some_and(|last| index == last + 1);
+            if !sequential && state.last != Some(index) {
+                state.cancel();
+            }
+            state.last = Some(index);
+            // Drop anything behind the reader so the buffer stays bounded.
+            state.buffer = state.buffer.split_off(&index);
+            (state.buffer.remove(&index), sequential)
+        };
+
+        let chunk = match cached {
+            Some(chunk) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(chunk)
+            }
+            None => self.source.fetch(index).await?,
+        };
+
+        if sequential && self.depth > 0 && chunk.is_some() {
+            self.schedule(index + 1);
+        }
+        Ok(chunk)
+    }
+
+    fn schedule(&self, next: usize) {
+        let mut state = self.state.lock();
+        if state.task.as_ref().is_some_and(|task| !task.is_finished()) {
+            return;
+        }
+
+        let source = self.source.clone();
+        let shared = self.state.clone();
+        let end = next + self.depth;
+        state.task = Some(tokio::spawn(async move {
+            for index in next..end {
+                if shared.lock().buffer.contains_key(&index) {
+                    continue;
+                }
+                match source.fetch(index).await {
+                    Ok(Some(chunk)) => {
+                        let mut state = shared.lock();
+                        if state.last.is_some_and(|last| index > last) {
+                            state.buffer.insert(index, chunk);
+                        }
+                    }
+                    _ => break,
+                }
+            }
+        }));
+    }
+}
+
+impl<S: ChunkSource> Drop for ReadAhead<S> {
+    fn drop(&mut self) {
+        self.state.lock().cancel();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    struct CountingSource {
+        fetches: AtomicUsize,
+        chunks: usize,
+    }
+
+    #[async_trait]
+    impl ChunkSource for CountingSource {
+        async fn fetch(&self, index: usize) -> Result<Option<Bytes>> {
+            self.fetches.fetch_add(1, Ordering::SeqCst);
+            Ok((index < self.chunks).then(|| Bytes::from(vec![index as u8; 4])))
+        }
+    }
+
+    async fn wait_for_buffer<S: ChunkSource>(reader: &ReadAhead<S>, expected: usize) {
+        for _ in 0..200 {
+            if reader.buffered() >= expected {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        panic!("prefetch never filled {expected} chunks");
+    }
+
+    #[tokio::test]
+    async fn sequential_reads_are_served_from_prefetch() {
+        let source = Arc::new(CountingSource {
+            fetches: AtomicUsize::new(0),
+            chunks: 16,
+        });
+        let reader = ReadAhead::new(source.clone(), 2);
+
+        assert_eq!(reader.read(0).await.unwrap().unwrap()[0], 0);
+        assert_eq!(reader.read(1).await.unwrap().unwrap()[0], 1);
+        wait_for_buffer(&reader, 2).await;
+
+        assert_eq!(reader.read(2).await.unwrap().unwrap()[0], 2);
+        assert_eq!(reader.prefetch_hits(), 1);
+        wait_for_buffer(&reader, 2).await;
+        assert_eq!(reader.read(3).await.unwrap().unwrap()[0], 3);
+        assert_eq!(reader.prefetch_hits(), 2);
+        assert!(reader.buffered() <= 2);
+    }
+
+    #[tokio::test]
+    async fn seeking_discards_the_prefetch_buffer() {
+        let source = Arc::new(CountingSource {
+            fetches: AtomicUsize::new(0),
+            chunks: 16,
+        });
+        let reader = ReadAhead::new(source.clone(), 3);
+
+        reader.read(0).await.unwrap();
+        reader.read(1).await.unwrap();
+        wait_for_buffer(&reader, 3).await;
+
+        assert_eq!(reader.read(10).await.unwrap().unwrap()[0], 10);
+        assert_eq!(reader.buffered(), 0);
+        assert_eq!(reader.prefetch_hits(), 0);
+    }
+}
@@ -0,0 +1,284 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+use tracing::info;
+
+use crate::config::{ConfigValidationError, MountConfig};
+use crate::error::Result;
+use crate::mount::{MountContext, MountProvider};
+
+// Holds the running config and swaps it atomically; readers keep whichever snapshot they
+// took, so a reload never tears a config mid-operation.
+#[derive(Debug)]
+pub struct LiveConfig {
+    current: RwLock<Arc<MountConfig>>,
+}
+
+impl LiveConfig {
+    pub fn new(config: MountConfig) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(config)),
+        }
+    }
+
+    pub fn current(&self) -> Arc<MountConfig> {
+        self.current.read().clone()
+    }
+
+    pub async fn reload_config(&self, path: &Path) -> Result<Vec<String>> {
+        self.apply(read_config(path).await?)
+    }
+
+    // Reloads `path` and, when anything changed, swaps `provider` over from `ctx` to a
+    // context carrying the new config so the mounted router picks it up. The running config
+    // only moves to the new one once the remount succeeded, so a rejected reload or a failed
+    // remount both leave it alone. Returns the context now mounted.
+    pub async fn reload_mount(
+        &self,
+        path: &Path,
+        provider: &dyn MountProvider,
+        ctx: &Arc<MountContext>,
+    ) -> Result<Arc<MountContext>> {
+        let staged = self.stage(read_config(path).await?)?;
+        if staged.changed.is_empty() {
+            return Ok(ctx.clone());
+        }
+        info!(changed = ?staged.changed, "config reloaded, remounting");
+        let next = Arc::new(ctx.reconfigured(staged.config.clone()));
+        provider.remount(next.clone()).await?;
+        self.commit(staged);
+        Ok(next)
+    }
+
+    // Returns the names of the fields that changed. Anything that requires a remount is
+    // rejected and the running config is left untouched.
+    pub fn apply(&self, next: MountConfig) -> Result<Vec<String>> {
+        let staged = self.stage(next)?;
+        let changed = staged.changed.clone();
+        self.commit(staged);
+        Ok(changed)
+    }
+
+    // Validates `next` and diffs it against the running config without swapping it in.
+    pub fn stage(&self, next: MountConfig) -> Result<StagedConfig> {
+        next.validate()?;
+
+        let changed = changed_fields(&self.current(), &next)?;
+        if let Some(field) = UNSAFE_FIELDS
+            .iter()
+            .find(|field| changed.iter().any(|changed| changed == *field))
+        {
+            return Err(ConfigValidationError::UnsafeChange(field).into());
+        }
+        Ok(StagedConfig {
+            config: Arc::new(next),
+            changed,
+        })
+    }
+
+    // Makes a staged config the running one; nothing happens when it changed nothing.
+    pub fn commit(&self, staged: StagedConfig) {
+        if !staged.changed.is_empty() {
+            *self.current.write() = staged.config;
+        }
+    }
+}
+
+// A validated config waiting to replace the running one, with the fields it changes.
+#[derive(Debug)]
+pub struct StagedConfig {
+    pub config: Arc<MountConfig>,
+    pub changed: Vec<String>,
+}
+
+// Fields that can only change through a fresh mount.
+const UNSAFE_FIELDS: [&str; 5] = [
+    "sources",
+    "mount_point",
+    "cache_dir",
+    "kv_backend",
+    "passthrough",
+];
+
+async fn read_config(path: &Path) -> Result<MountConfig> {
+    let content = tokio::fs::read_to_string(path).await?;
+    Ok(MountConfig::from_toml_str(&content)?)
+}
+
+// Compares every serialized field, so a newly added option is diffed without being listed.
+fn changed_fields(current: &MountConfig, next: &MountConfig) -> Result<Vec<String>> {
+    let (current, next) = (fields(current)?, fields(next)?);
+    Ok(current
+        .iter()
+        .filter(|(field, value)| next.get(*field) != Some(*value))
+        .map(|(field, _)| field.clone())
+        .collect())
+}
+
+fn fields(config: &MountConfig) -> Result<serde_json::Map<String, serde_json::Value>> {
+    match serde_json::to_value(config) {
+        Ok(serde_json::Value::Object(fields)) => Ok(fields),
+        Ok(_) => Err(ConfigValidationError::Parse("config is not a table".into()).into()),
+        Err(err) => Err(ConfigValidationError::Parse(err.to_string()).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LosslessStrategy;
+    use crate::error::MusFuseError;
+    use crate::mount::MountStatus;
+    use parking_lot::Mutex;
+
+    // Records the config of every context it is remounted onto, or fails every remount.
+    #[derive(Default)]
+    struct RecordingProvider {
+        remounts: Mutex<Vec<Arc<MountConfig>>>,
+        fail: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl MountProvider for RecordingProvider {
+        async fn mount(&self, _ctx: Arc<MountContext>) -> Result<()> {
+            Ok(())
+        }
+
+        async fn unmount(&self) -> Result<()> {
+            Ok(())
+        }
+
+        async fn remount(&self, ctx: Arc<MountContext>) -> Result<()> {
+            if self.fail {
+                return Err(MusFuseError::Mount("remount failed".into()));
+            }
+            self.remounts.lock().push(ctx.config.clone());
+            Ok(())
+        }
+
+        fn status(&self) -> MountStatus {
+            MountStatus::Mounted
+        }
+    }
+
+    fn config_toml(mount_point: &str, lossless_strategy: &str) -> String {
+        format!(
+            r#"
+mount_point = "{mount_point}"
+kv_backend = "Sled"
+scan_mode = "Lazy"
+
+[[sources]]
+path = "/music"
+recursive = true
+watch = false
+
+[policies]
+lossless_strategy = "{lossless_strategy}"
+lossy_passthrough = true
+"#
+        )
+    }
+
+    #[tokio::test]
+    async fn policy_changes_apply_and_mount_point_changes_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("musfuse.toml");
+        std::fs::write(&path, config_toml("M:", "Passthrough")).unwrap();
+        let initial: MountConfig = toml::from_str(&config_toml("M:", "Passthrough")).unwrap();
+        let live = LiveConfig::new(initial);
+
+        std::fs::write(&path, config_toml("M:", "ConvertToFlac")).unwrap();
+        let changed = live.reload_config(&path).await.expect("policy reload");
+        assert_eq!(changed, vec!["policies"]);
+        assert_eq!(
+            live.current().policies.lossless_strategy,
+            LosslessStrategy::ConvertToFlac
+        );
+
+        std::fs::write(&path, config_toml("N:", "Passthrough")).unwrap();
+        let err = live.reload_config(&path).await.expect_err("unsafe change");
+        assert!(matches!(
+            err,
+            MusFuseError::Config(ConfigValidationError::UnsafeChange("mount_point"))
+        ));
+        let current = live.current();
+        assert_eq!(current.mount_point, Path::new("M:"));
+        assert_eq!(
+            current.policies.lossless_strategy,
+            LosslessStrategy::ConvertToFlac
+        );
+
+        std::fs::write(&path, "mount_point = ").unwrap();
+        let err = live.reload_config(&path).await.expect_err("parse error");
+        assert!(matches!(
+            err,
+            MusFuseError::Config(ConfigValidationError::Parse(_))
+        ));
+        assert_eq!(live.current(), current);
+    }
+
+    #[tokio::test]
+    async fn reloads_remount_only_when_the_config_changed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("musfuse.toml");
+        std::fs::write(&path, config_toml("M:", "Passthrough")).unwrap();
+        let initial: MountConfig = toml::from_str(&config_toml("M:", "Passthrough")).unwrap();
+        let live = LiveConfig::new(initial.clone());
+        let provider = RecordingProvider::default();
+        let ctx = Arc::new(MountContext::new(initial));
+
+        let same = live.reload_mount(&path, &provider, &ctx).await.unwrap();
+        assert!(Arc::ptr_eq(&same, &ctx));
+        assert!(provider.remounts.lock().is_empty());
+
+        std::fs::write(&path, config_toml("M:", "ConvertToFlac")).unwrap();
+        let next = live.reload_mount(&path, &provider, &ctx).await.unwrap();
+        assert_eq!(
+            next.config.policies.lossless_strategy,
+            LosslessStrategy::ConvertToFlac
+        );
+        assert_eq!(*provider.remounts.lock(), vec![next.config.clone()]);
+
+        std::fs::write(&path, config_toml("N:", "ConvertToFlac")).unwrap();
+        assert!(live.reload_mount(&path, &provider, &next).await.is_err());
+        assert_eq!(provider.remounts.lock().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn failed_remounts_keep_the_running_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("musfuse.toml");
+        let initial: MountConfig = toml::from_str(&config_toml("M:", "Passthrough")).unwrap();
+        let live = LiveConfig::new(initial.clone());
+        let provider = RecordingProvider {
+            fail: true,
+            ..Default::default()
+        };
+        let ctx = Arc::new(MountContext::new(initial.clone()));
+
+        std::fs::write(&path, config_toml("M:", "ConvertToFlac")).unwrap();
+        assert!(live.reload_mount(&path, &provider, &ctx).await.is_err());
+        assert_eq!(*live.current(), initial);
+    }
+
+    #[test]
+    fn every_changed_field_is_reported() {
+        let initial: MountConfig = toml::from_str(&config_toml("M:", "Passthrough")).unwrap();
+        let live = LiveConfig::new(initial.clone());
+
+        let mut next = initial;
+        next.directories_first = true;
+        next.prewarm_tracks = 4;
+        next.max_concurrent_transcodes = Some(2);
+        assert_eq!(
+            live.apply(next).unwrap(),
+            vec![
+                "directories_first",
+                "max_concurrent_transcodes",
+                "prewarm_tracks"
+            ]
+        );
+    }
+}
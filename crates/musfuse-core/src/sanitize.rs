@@ -0,0 +1,108 @@
+use std::sync::Arc;
+
+const WINDOWS_ILLEGAL: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+const WINDOWS_RESERVED: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+pub trait PathSanitizer: Send + Sync {
+    fn sanitize_component(&self, name: &str) -> String;
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WindowsSanitizer;
+
+impl PathSanitizer for WindowsSanitizer {
+    fn sanitize_component(&self, name: &str) -> String {
+        let replaced: String = name
+            .chars()
+            .map(|c| {
+                if c.is_control() || WINDOWS_ILLEGAL.contains(&c) {
+                    '_'
+                } else {
+                    c
+                }
+            })
+            .collect();
+        // NTFS silently drops trailing dots and spaces.
+        let mut cleaned = replaced.trim_end_matches(['.', ' ']).to_string();
+        if cleaned.is_empty() {
+            return "_".into();
+        }
+
+        let stem = cleaned.split('.').next().unwrap_or_default().trim_end();
+        if WINDOWS_RESERVED
+            .iter()
+            .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+        {
+            cleaned.insert(0, '_');
+        }
+        cleaned
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PosixSanitizer;
+
+impl PathSanitizer for PosixSanitizer {
+    fn sanitize_component(&self, name: &str) -> String {
+        let cleaned: String = name
+            .chars()
+            .map(|c| if c == '/' || c == '\0' { '_' } else { c })
+            .collect();
+        match cleaned.as_str() {
+            "" | "." | ".." => "_".into(),
+            _ => cleaned,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MountPlatform {
+    Windows,
+    Posix,
+}
+
+impl MountPlatform {
+    pub fn current() -> Self {
+        if cfg!(windows) {
+            MountPlatform::Windows
+        } else {
+            MountPlatform::Posix
+        }
+    }
+
+    pub fn sanitizer(&self) -> Arc<dyn PathSanitizer> {
+        match self {
+            MountPlatform::Windows => Arc::new(WindowsSanitizer),
+            MountPlatform::Posix => Arc::new(PosixSanitizer),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_sanitizer_rewrites_reserved_and_illegal_names() {
+        let sanitizer = WindowsSanitizer;
+
+        assert_eq!(sanitizer.sanitize_component("CON.flac"), "_CON.flac");
+        assert_eq!(sanitizer.sanitize_component("nul"), "_nul");
+        assert_eq!(sanitizer.sanitize_component("AC/DC: Live?"), "AC_DC_ Live_");
+        assert_eq!(sanitizer.sanitize_component("Encore..."), "Encore");
+        assert_eq!(sanitizer.sanitize_component("Console.flac"), "Console.flac");
+    }
+
+    #[test]
+    fn posix_sanitizer_only_touches_separators_and_nul() {
+        let sanitizer = PosixSanitizer;
+
+        assert_eq!(sanitizer.sanitize_component("CON.flac"), "CON.flac");
+        assert_eq!(sanitizer.sanitize_component("AC/DC: Live?"), "AC_DC: Live?");
+        assert_eq!(sanitizer.sanitize_component(".."), "_");
+    }
+}
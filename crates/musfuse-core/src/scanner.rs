@@ -1,10 +1,24 @@
-use std::path::PathBuf;
-use std::time::SystemTime;
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 use async_trait::async_trait;
+use lofty::{Accessor, TaggedFileExt};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use parking_lot::Mutex;
+use tokio::fs;
+use tokio::sync::{Semaphore, broadcast, mpsc};
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use tracing::warn;
 
-use crate::config::ScanMode;
-use crate::error::Result;
+use crate::config::{ScanMode, SourceConfig};
+use crate::cue::CueParser;
+use crate::error::{MalformedStream, MusFuseError, Result};
+use crate::format::{AudioFormat, is_audio_file};
 use crate::metadata::{AlbumId, TrackId};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -23,9 +37,944 @@ pub enum ScanEvent {
     AlbumUpdated(AlbumId),
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanProgress {
+    Directory(PathBuf),
+    File(PathBuf),
+}
+
+pub type ScanProgressFn = dyn Fn(&ScanProgress) + Send + Sync;
+
+// A file an eager scan found but could not index because it is structurally broken.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanFailure {
+    pub path: PathBuf,
+    pub reason: MalformedStream,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ScanOutcome {
+    pub records: Vec<ScanRecord>,
+    pub failures: Vec<ScanFailure>,
+    pub cancelled: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DirSize {
+    pub bytes: u64,
+    pub files: u64,
+    // Set when `max_entries` was reached before the walk finished.
+    pub partial: bool,
+}
+
+// Total file size below `path`, visiting at most `max_entries` entries. Directories are
+// tracked by canonical path so symlink loops are entered once.
+pub async fn dir_size(path: &Path, max_entries: usize) -> Result<DirSize> {
+    let root = fs::canonicalize(path).await?;
+    let mut size = DirSize::default();
+    let mut visited = HashSet::from([root.clone()]);
+    let mut pending = vec![root];
+    let mut seen = 0usize;
+    while let Some(dir) = pending.pop() {
+        let mut reader = fs::read_dir(&dir).await?;
+        while let Some(entry) = reader.next_entry().await? {
+            if seen >= max_entries {
+                size.partial = true;
+                return Ok(size);
+            }
+            seen += 1;
+
+            let path = entry.path();
+            let metadata = match fs::metadata(&path).await {
+                Ok(metadata) => metadata,
+                // Dangling links and entries removed mid-walk contribute nothing.
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err.into()),
+            };
+            if metadata.is_dir() {
+                let target = fs::canonicalize(&path).await?;
+                if visited.insert(target.clone()) {
+                    pending.push(target);
+                }
+            } else {
+                size.bytes += metadata.len();
+                size.files += 1;
+            }
+        }
+    }
+    Ok(size)
+}
+
 #[async_trait]
 pub trait LibraryScanner: Send + Sync {
-    async fn full_scan(&self, mode: ScanMode) -> Result<Vec<ScanRecord>>;
+    async fn full_scan(
+        &self,
+        mode: ScanMode,
+        cancel: &CancellationToken,
+        progress: Option<&ScanProgressFn>,
+    ) -> Result<ScanOutcome>;
     async fn refresh_paths(&self, paths: &[PathBuf]) -> Result<Vec<ScanEvent>>;
     async fn watch(&self) -> Result<()>;
 }
+
+// Directories listed at once during a full scan; kept low so a slow NAS is not flooded.
+pub const DEFAULT_SCAN_CONCURRENCY: usize = 4;
+
+// Quiet period a watched path must see before its event is published; editors saving
+// through temp files and renames produce a burst of raw events for one logical change.
+pub const DEFAULT_WATCH_DEBOUNCE: Duration = Duration::from_millis(250);
+
+const WATCH_CHANNEL_CAPACITY: usize = 64;
+
+pub struct FsLibraryScanner {
+    sources: Vec<SourceConfig>,
+    concurrency: usize,
+    debounce: Duration,
+    signal: broadcast::Sender<ScanEvent>,
+    watchers: Mutex<Vec<RecommendedWatcher>>,
+}
+
+// One directory's scannable files and subdirectories (with their canonical targets),
+// both in name order.
+#[derive(Debug, Default)]
+struct DirListing {
+    files: Vec<ListedFile>,
+    subdirs: Vec<(PathBuf, PathBuf)>,
+}
+
+#[derive(Debug)]
+struct ListedFile {
+    path: PathBuf,
+    modified: SystemTime,
+    malformed: Option<MalformedStream>,
+    tracks: Vec<TrackId>,
+}
+
+impl FsLibraryScanner {
+    pub fn new(sources: Vec<SourceConfig>) -> Self {
+        let (signal, _) = broadcast::channel(WATCH_CHANNEL_CAPACITY);
+        Self {
+            sources,
+            concurrency: DEFAULT_SCAN_CONCURRENCY,
+            debounce: DEFAULT_WATCH_DEBOUNCE,
+            signal,
+            watchers: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    // Events from `watch`, published once each changed path has been quiet for the
+    // debounce window.
+    pub fn subscribe(&self) -> broadcast::Receiver<ScanEvent> {
+        self.signal.subscribe()
+    }
+
+    fn is_scannable(path: &Path) -> bool {
+        is_audio_file(path) || Self::is_cue(path)
+    }
+
+    fn is_cue(path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("cue"))
+    }
+
+    fn album_for(root: &Path, path: &Path) -> AlbumId {
+        let dir = path.parent().unwrap_or(root);
+        let relative = dir.strip_prefix(root).unwrap_or(dir);
+        let name = relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        if name.is_empty() {
+            AlbumId(
+                root.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default(),
+            )
+        } else {
+            AlbumId(name)
+        }
+    }
+
+    // Returns the canonical target of a directory or file, or None when a link leaves the
+    // root and the source does not follow symlinks.
+    async fn contained(source: &SourceConfig, root: &Path, path: &Path) -> Result<Option<PathBuf>> {
+        let target = fs::canonicalize(path).await?;
+        let is_link = fs::symlink_metadata(path).await?.file_type().is_symlink();
+        if is_link && !source.follow_symlinks && !target.starts_with(root) {
+            return Ok(None);
+        }
+        Ok(Some(target))
+    }
+
+    // Only files the probe positively identifies as broken are failures; formats it cannot
+    // open at all (DSD, unsupported codecs) are still listed.
+    async fn probe_failure(path: &Path) -> Result<Option<MalformedStream>> {
+        if Self::is_probe_skipped(path) {
+            return Ok(None);
+        }
+        let owned = path.to_path_buf();
+        let checked = tokio::task::spawn_blocking(move || crate::probe::check_stream(&owned))
+            .await
+            .map_err(|err| MusFuseError::Media(err.to_string()))?;
+        match checked {
+            Ok(()) => Ok(None),
+            Err(MusFuseError::Io(err)) => Err(MusFuseError::Io(err)),
+            Err(err) => Ok(err.malformed_reason()),
+        }
+    }
+
+    // Eager scans name the tracks a file holds: one per cue TRACK, or the track and disc
+    // numbers of a tagged audio file. Files that cannot be read this way list none.
+    async fn track_ids(path: &Path, album: AlbumId) -> Vec<TrackId> {
+        if Self::is_cue(path) {
+            return match CueParser.parse_file(path).await {
+                Ok(sheet) => sheet
+                    .files
+                    .iter()
+                    .flat_map(|file| &file.tracks)
+                    .map(|track| TrackId {
+                        album: album.clone(),
+                        disc: 1,
+                        index: track.number,
+                    })
+                    .collect(),
+                Err(err) => {
+                    warn!(path = %path.display(), error = %err, "could not read cue tracks");
+                    Vec::new()
+                }
+            };
+        }
+        let owned = path.to_path_buf();
+        let numbers = tokio::task::spawn_blocking(move || {
+            let tagged = lofty::read_from_path(&owned).ok()?;
+            let tag = tagged.primary_tag().or_else(|| tagged.first_tag())?;
+            Some((tag.track()?, tag.disk().unwrap_or(1)))
+        })
+        .await
+        .ok()
+        .flatten();
+        numbers
+            .map(|(index, disc)| TrackId {
+                album,
+                disc: u8::try_from(disc).unwrap_or(u8::MAX),
+                index,
+            })
+            .into_iter()
+            .collect()
+    }
+
+    // Raw notify events are reduced to one flag per path: whether the path was new when
+    // the burst started. The event itself is settled by what is on disk once it is quiet,
+    // so every raw event pushes that path's deadline back.
+    fn record_change(
+        pending: &mut BTreeMap<PathBuf, (bool, Instant)>,
+        event: notify::Event,
+        deadline: Instant,
+    ) {
+        let created = |index: usize| match event.kind {
+            EventKind::Create(_) => true,
+            EventKind::Modify(ModifyKind::Name(RenameMode::To)) => true,
+            EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => index == 1,
+            _ => false,
+        };
+        if !matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+        ) {
+            return;
+        }
+        for (index, path) in event.paths.iter().enumerate() {
+            if Self::is_scannable(path) {
+                pending
+                    .entry(path.clone())
+                    .and_modify(|(_, quiet)| *quiet = deadline)
+                    .or_insert((created(index), deadline));
+            }
+        }
+    }
+
+    fn settle(path: PathBuf, created: bool) -> Option<ScanEvent> {
+        match (path.exists(), created) {
+            (true, true) => Some(ScanEvent::FileAdded(path)),
+            (true, false) => Some(ScanEvent::FileModified(path)),
+            (false, false) => Some(ScanEvent::FileRemoved(path)),
+            // Created and gone again within one window, like an editor's scratch copy.
+            (false, true) => None,
+        }
+    }
+
+    async fn debounce_changes(
+        mut raw: mpsc::UnboundedReceiver<notify::Event>,
+        debounce: Duration,
+        signal: broadcast::Sender<ScanEvent>,
+    ) {
+        let mut pending: BTreeMap<PathBuf, (bool, Instant)> = BTreeMap::new();
+        loop {
+            let next = match pending.values().map(|(_, quiet)| *quiet).min() {
+                None => raw.recv().await,
+                Some(quiet) => match tokio::time::timeout_at(quiet, raw.recv()).await {
+                    Ok(next) => next,
+                    Err(_) => {
+                        let now = Instant::now();
+                        let settled: Vec<_> = pending
+                            .iter()
+                            .filter(|(_, (_, quiet))| *quiet <= now)
+                            .map(|(path, _)| path.clone())
+                            .collect();
+                        for path in settled {
+                            let (created, _) = pending.remove(&path).expect("pending path");
+                            if let Some(event) = Self::settle(path, created) {
+                                // No subscribers is not an error; events are advisory.
+                                let _ = signal.send(event);
+                            }
+                        }
+                        continue;
+                    }
+                },
+            };
+            match next {
+                Some(event) => Self::record_change(&mut pending, event, Instant::now() + debounce),
+                // The watchers were dropped, so nothing more will arrive.
+                None => break,
+            }
+        }
+    }
+
+    async fn report(events: Option<&mpsc::Sender<ScanProgress>>, event: ScanProgress) {
+        if let Some(events) = events {
+            // The scan only stops listening once the level is done, so nothing is lost.
+            let _ = events.send(event).await;
+        }
+    }
+
+    fn is_probe_skipped(path: &Path) -> bool {
+        Self::is_cue(path) || AudioFormat::from_path(path).is_some_and(|format| format.is_dsd())
+    }
+
+    async fn scan_source(
+        &self,
+        source: &SourceConfig,
+        mode: &ScanMode,
+        cancel: &CancellationToken,
+        progress: Option<&ScanProgressFn>,
+        outcome: &mut ScanOutcome,
+    ) -> Result<bool> {
+        // Walk from the canonical root so symlinked sources and junctions cannot escape
+        // it unnoticed, and remember canonical directories to break link cycles.
+        let root = fs::canonicalize(&source.path).await?;
+        let probe = *mode == ScanMode::Eager;
+        let mut visited = HashSet::from([root.clone()]);
+        let mut frontier = vec![root.clone()];
+        while !frontier.is_empty() {
+            // Workers report each directory and file as they reach it; the bounded channel
+            // keeps them from running ahead of a callback that cancels the scan.
+            let (events, mut received) = mpsc::channel(1);
+            let listing = self.list_level(
+                source,
+                &root,
+                &frontier,
+                probe,
+                cancel,
+                progress.is_some().then_some(events),
+            );
+            let report = async {
+                if let Some(progress) = progress {
+                    while let Some(event) = received.recv().await {
+                        progress(&event);
+                    }
+                }
+            };
+            let (listings, ()) = tokio::join!(listing, report);
+
+            // Listings come back in frontier order, so records and the visited set are built
+            // in the same order whatever the worker count. A cancelled level still keeps
+            // what its workers finished.
+            let mut next = Vec::new();
+            for listing in listings? {
+                for file in listing.files {
+                    if let Some(reason) = file.malformed {
+                        warn!(path = %file.path.display(), %reason, "skipping unreadable file");
+                        outcome.failures.push(ScanFailure {
+                            path: file.path,
+                            reason,
+                        });
+                        continue;
+                    }
+                    outcome.records.push(ScanRecord {
+                        albums: vec![Self::album_for(&root, &file.path)],
+                        source: file.path,
+                        modified: file.modified,
+                        tracks: file.tracks,
+                    });
+                }
+                for (path, target) in listing.subdirs {
+                    if visited.insert(target) {
+                        next.push(path);
+                    }
+                }
+            }
+            if cancel.is_cancelled() {
+                return Ok(false);
+            }
+            frontier = next;
+        }
+        Ok(true)
+    }
+
+    // Lists every directory of one walk level, at most `concurrency` at a time.
+    async fn list_level(
+        &self,
+        source: &SourceConfig,
+        root: &Path,
+        dirs: &[PathBuf],
+        probe: bool,
+        cancel: &CancellationToken,
+        events: Option<mpsc::Sender<ScanProgress>>,
+    ) -> Result<Vec<DirListing>> {
+        let limit = Arc::new(Semaphore::new(self.concurrency));
+        let mut tasks = JoinSet::new();
+        for (position, dir) in dirs.iter().cloned().enumerate() {
+            let source = source.clone();
+            let root = root.to_path_buf();
+            let limit = limit.clone();
+            let cancel = cancel.clone();
+            let events = events.clone();
+            tasks.spawn(async move {
+                let _permit = limit.acquire_owned().await;
+                let listing =
+                    Self::list_dir(&source, &root, &dir, probe, &cancel, events.as_ref()).await;
+                (position, listing)
+            });
+        }
+
+        let mut listings = Vec::new();
+        listings.resize_with(dirs.len(), DirListing::default);
+        while let Some(joined) = tasks.join_next().await {
+            let (position, listing) = joined.map_err(|err| MusFuseError::Media(err.to_string()))?;
+            listings[position] = listing?;
+        }
+        Ok(listings)
+    }
+
+    async fn list_dir(
+        source: &SourceConfig,
+        root: &Path,
+        dir: &Path,
+        probe: bool,
+        cancel: &CancellationToken,
+        events: Option<&mpsc::Sender<ScanProgress>>,
+    ) -> Result<DirListing> {
+        let mut listing = DirListing::default();
+        if cancel.is_cancelled() {
+            return Ok(listing);
+        }
+        Self::report(events, ScanProgress::Directory(dir.to_path_buf())).await;
+        let mut entries = Vec::new();
+        let mut reader = fs::read_dir(dir).await?;
+        while let Some(entry) = reader.next_entry().await? {
+            entries.push(entry.path());
+        }
+        entries.sort();
+
+        for path in entries {
+            if cancel.is_cancelled() {
+                break;
+            }
+            // Dangling links and entries removed mid-listing are skipped, not fatal.
+            let metadata = match fs::metadata(&path).await {
+                Ok(metadata) => metadata,
+                Err(err) => {
+                    warn!(path = %path.display(), error = %err, "skipping unreadable entry");
+                    continue;
+                }
+            };
+            if metadata.is_dir() {
+                if source.recursive
+                    && let Some(target) = Self::contained(source, root, &path).await?
+                {
+                    listing.subdirs.push((path, target));
+                }
+                continue;
+            }
+            if !Self::is_scannable(&path) || Self::contained(source, root, &path).await?.is_none() {
+                continue;
+            }
+            Self::report(events, ScanProgress::File(path.clone())).await;
+            // A cancel that arrives while this file was reported skips its probe.
+            if probe && cancel.is_cancelled() {
+                break;
+            }
+            let malformed = if probe {
+                Self::probe_failure(&path).await?
+            } else {
+                None
+            };
+            let tracks = if probe && malformed.is_none() {
+                Self::track_ids(&path, Self::album_for(root, &path)).await
+            } else {
+                Vec::new()
+            };
+            listing.files.push(ListedFile {
+                path,
+                modified: metadata.modified()?,
+                malformed,
+                tracks,
+            });
+        }
+        Ok(listing)
+    }
+}
+
+#[async_trait]
+impl LibraryScanner for FsLibraryScanner {
+    async fn full_scan(
+        &self,
+        mode: ScanMode,
+        cancel: &CancellationToken,
+        progress: Option<&ScanProgressFn>,
+    ) -> Result<ScanOutcome> {
+        let mut outcome = ScanOutcome::default();
+        for source in &self.sources {
+            if !self
+                .scan_source(source, &mode, cancel, progress, &mut outcome)
+                .await?
+            {
+                outcome.cancelled = true;
+                break;
+            }
+        }
+        Ok(outcome)
+    }
+
+    async fn refresh_paths(&self, paths: &[PathBuf]) -> Result<Vec<ScanEvent>> {
+        let mut events = Vec::with_capacity(paths.len());
+        for path in paths {
+            match fs::metadata(path).await {
+                Ok(_) => events.push(ScanEvent::FileModified(path.clone())),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                    events.push(ScanEvent::FileRemoved(path.clone()))
+                }
+                Err(err) => return Err(MusFuseError::Io(err)),
+            }
+        }
+        Ok(events)
+    }
+
+    // Watches every source with `watch` set. Calling it again replaces the running
+    // watchers, so each source is only reported once.
+    async fn watch(&self) -> Result<()> {
+        let (sender, raw) = mpsc::unbounded_channel();
+        let mut watchers = Vec::new();
+        for source in self.sources.iter().filter(|source| source.watch) {
+            let sender = sender.clone();
+            let mut watcher = notify::recommended_watcher(
+                move |event: notify::Result<notify::Event>| match event {
+                    Ok(event) => {
+                        let _ = sender.send(event);
+                    }
+                    Err(err) => warn!(error = %err, "filesystem watch error"),
+                },
+            )
+            .map_err(|err| MusFuseError::Mount(format!("unable to start watcher: {err}")))?;
+            let mode = if source.recursive {
+                RecursiveMode::Recursive
+            } else {
+                RecursiveMode::NonRecursive
+            };
+            watcher.watch(&source.path, mode).map_err(|err| {
+                MusFuseError::Mount(format!("unable to watch {}: {err}", source.path.display()))
+            })?;
+            watchers.push(watcher);
+        }
+        drop(sender);
+        if watchers.is_empty() {
+            return Ok(());
+        }
+        *self.watchers.lock() = watchers;
+        tokio::spawn(Self::debounce_changes(
+            raw,
+            self.debounce,
+            self.signal.clone(),
+        ));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tempfile::tempdir;
+
+    fn build_tree(root: &Path, dirs: usize, files: usize) {
+        for dir in 0..dirs {
+            let album = root.join(format!("album-{dir:03}"));
+            std::fs::create_dir_all(&album).unwrap();
+            for file in 0..files {
+                std::fs::write(album.join(format!("{file:02}.flac")), b"").unwrap();
+            }
+            std::fs::write(album.join("notes.txt"), b"").unwrap();
+        }
+    }
+
+    fn scanner_for(root: &Path) -> FsLibraryScanner {
+        FsLibraryScanner::new(vec![SourceConfig {
+            path: root.to_path_buf(),
+            recursive: true,
+            watch: false,
+            follow_symlinks: true,
+        }])
+    }
+
+    #[tokio::test]
+    async fn full_scan_reports_progress_and_groups_by_folder() {
+        let dir = tempdir().unwrap();
+        build_tree(dir.path(), 3, 4);
+
+        let files = Arc::new(AtomicUsize::new(0));
+        let counter = files.clone();
+        let progress = move |event: &ScanProgress| {
+            if let ScanProgress::File(_) = event {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }
+        };
+
+        let outcome = scanner_for(dir.path())
+            .full_scan(ScanMode::Lazy, &CancellationToken::new(), Some(&progress))
+            .await
+            .unwrap();
+
+        assert!(!outcome.cancelled);
+        assert_eq!(outcome.records.len(), 12);
+        assert_eq!(files.load(Ordering::SeqCst), 12);
+        assert_eq!(outcome.records[0].albums, vec![AlbumId("album-000".into())]);
+    }
+
+    #[tokio::test]
+    async fn worker_count_does_not_change_records_or_grouping() {
+        let dir = tempdir().unwrap();
+        for artist in ["Alpha", "Beta", "Gamma"] {
+            build_tree(&dir.path().join(artist), 4, 3);
+        }
+        std::fs::write(dir.path().join("Beta").join("single.mp3"), b"").unwrap();
+
+        let mut scans = Vec::new();
+        for concurrency in [1, 3, 16] {
+            let outcome = scanner_for(dir.path())
+                .with_concurrency(concurrency)
+                .full_scan(ScanMode::Lazy, &CancellationToken::new(), None)
+                .await
+                .unwrap();
+            let grouped: Vec<_> = outcome
+                .records
+                .into_iter()
+                .map(|record| (record.source, record.albums))
+                .collect();
+            scans.push(grouped);
+        }
+
+        assert_eq!(scans[0].len(), 3 * 4 * 3 + 1);
+        assert_eq!(scans[0], scans[1]);
+        assert_eq!(scans[0], scans[2]);
+        let root = dir.path().canonicalize().unwrap();
+        for (source, albums) in &scans[0] {
+            let folder = source.parent().unwrap().strip_prefix(&root).unwrap();
+            let expected = folder.to_string_lossy().replace('\\', "/");
+            assert_eq!(albums, &vec![AlbumId(expected)]);
+        }
+    }
+
+    #[tokio::test]
+    async fn dir_size_sums_the_subtree_and_reports_truncation() {
+        let dir = tempdir().unwrap();
+        let album = dir.path().join("album");
+        std::fs::create_dir_all(album.join("scans")).unwrap();
+        std::fs::write(dir.path().join("folder.jpg"), vec![0u8; 100]).unwrap();
+        std::fs::write(album.join("01.flac"), vec![0u8; 1_000]).unwrap();
+        std::fs::write(album.join("02.flac"), vec![0u8; 2_500]).unwrap();
+        std::fs::write(album.join("scans").join("front.png"), vec![0u8; 40]).unwrap();
+
+        let total = dir_size(dir.path(), 100).await.unwrap();
+        assert_eq!(
+            total,
+            DirSize {
+                bytes: 3_640,
+                files: 4,
+                partial: false,
+            }
+        );
+
+        let bounded = dir_size(dir.path(), 2).await.unwrap();
+        assert!(bounded.partial);
+        assert!(bounded.bytes < total.bytes);
+    }
+
+    fn wav_bytes(channels: u16, sample_rate: u32) -> Vec<u8> {
+        let data = [0u8; 64];
+        let block_align = channels.max(1) * 2;
+        let mut wav = b"RIFF".to_vec();
+        wav.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&channels.to_le_bytes());
+        wav.extend_from_slice(&sample_rate.to_le_bytes());
+        wav.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+        wav.extend_from_slice(&block_align.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        wav.extend_from_slice(&data);
+        wav
+    }
+
+    #[tokio::test]
+    async fn eager_scan_records_malformed_files_as_failures() {
+        let dir = tempdir().unwrap();
+        let album = dir.path().join("album");
+        std::fs::create_dir_all(&album).unwrap();
+        std::fs::write(album.join("01.wav"), wav_bytes(2, 44_100)).unwrap();
+        std::fs::write(album.join("02.wav"), wav_bytes(0, 44_100)).unwrap();
+        std::fs::write(album.join("03.wav"), wav_bytes(2, 0)).unwrap();
+
+        let outcome = scanner_for(dir.path())
+            .full_scan(ScanMode::Eager, &CancellationToken::new(), None)
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.records.len(), 1);
+        assert!(outcome.records[0].source.ends_with("01.wav"));
+        let reasons: Vec<_> = outcome
+            .failures
+            .iter()
+            .map(|failure| (failure.path.file_name().unwrap().to_owned(), failure.reason))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                ("02.wav".into(), MalformedStream::ZeroChannels),
+                ("03.wav".into(), MalformedStream::MissingSampleRate),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn eager_scans_read_track_numbers_and_lazy_scans_do_not() {
+        use lofty::{Tag, TagExt, TagType};
+
+        let dir = tempdir().unwrap();
+        let album = dir.path().join("album");
+        std::fs::create_dir_all(&album).unwrap();
+        let tagged = album.join("tagged.wav");
+        std::fs::write(&tagged, wav_bytes(2, 44_100)).unwrap();
+        let mut tag = Tag::new(TagType::Id3v2);
+        tag.set_track(7);
+        tag.set_disk(2);
+        tag.save_to_path(&tagged).unwrap();
+        std::fs::write(album.join("untagged.wav"), wav_bytes(2, 44_100)).unwrap();
+        std::fs::write(
+            album.join("disc.cue"),
+            "FILE \"disc.wav\" WAVE\n  TRACK 01 AUDIO\n    INDEX 01 00:00:00\n  TRACK 02 AUDIO\n    INDEX 01 01:00:00\n",
+        )
+        .unwrap();
+
+        let scanner = scanner_for(dir.path());
+        let tracks = |outcome: &ScanOutcome| -> Vec<(String, Vec<(u8, u32)>)> {
+            outcome
+                .records
+                .iter()
+                .map(|record| {
+                    (
+                        record.source.file_name().unwrap().to_string_lossy().into(),
+                        record.tracks.iter().map(|id| (id.disc, id.index)).collect(),
+                    )
+                })
+                .collect()
+        };
+        let eager = scanner
+            .full_scan(ScanMode::Eager, &CancellationToken::new(), None)
+            .await
+            .unwrap();
+        assert_eq!(
+            tracks(&eager),
+            vec![
+                ("disc.cue".to_string(), vec![(1, 1), (1, 2)]),
+                ("tagged.wav".to_string(), vec![(2, 7)]),
+                ("untagged.wav".to_string(), vec![]),
+            ]
+        );
+        assert!(
+            eager.records[0]
+                .tracks
+                .iter()
+                .all(|id| id.album == AlbumId("album".into()))
+        );
+
+        let lazy = scanner
+            .full_scan(ScanMode::Lazy, &CancellationToken::new(), None)
+            .await
+            .unwrap();
+        assert!(lazy.records.iter().all(|record| record.tracks.is_empty()));
+        assert_eq!(lazy.records.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn watching_reports_debounced_file_changes() {
+        let dir = tempdir().unwrap();
+        let scanner = FsLibraryScanner::new(vec![SourceConfig {
+            path: dir.path().to_path_buf(),
+            recursive: true,
+            watch: true,
+            follow_symlinks: false,
+        }])
+        .with_debounce(Duration::from_millis(50));
+        let mut events = scanner.subscribe();
+        scanner.watch().await.unwrap();
+
+        let mut next = async || {
+            tokio::time::timeout(Duration::from_secs(5), events.recv())
+                .await
+                .expect("scan event")
+                .unwrap()
+        };
+        let track = dir.path().join("track.wav");
+
+        // Several writes in one window, plus a non-audio scratch file, settle to one event.
+        std::fs::write(dir.path().join("notes.txt"), b"ignored").unwrap();
+        std::fs::write(&track, wav_bytes(2, 44_100)).unwrap();
+        std::fs::write(&track, wav_bytes(1, 44_100)).unwrap();
+        assert_eq!(next().await, ScanEvent::FileAdded(track.clone()));
+
+        std::fs::write(&track, wav_bytes(2, 48_000)).unwrap();
+        assert_eq!(next().await, ScanEvent::FileModified(track.clone()));
+
+        std::fs::remove_file(&track).unwrap();
+        assert_eq!(next().await, ScanEvent::FileRemoved(track));
+    }
+
+    #[tokio::test]
+    async fn a_busy_path_does_not_hold_back_quiet_ones() {
+        let dir = tempdir().unwrap();
+        let scanner = FsLibraryScanner::new(vec![SourceConfig {
+            path: dir.path().to_path_buf(),
+            recursive: true,
+            watch: true,
+            follow_symlinks: false,
+        }])
+        .with_debounce(Duration::from_millis(100));
+        let mut events = scanner.subscribe();
+        scanner.watch().await.unwrap();
+
+        let quiet = dir.path().join("quiet.wav");
+        let busy = dir.path().join("busy.wav");
+        std::fs::write(&quiet, wav_bytes(1, 44_100)).unwrap();
+        for _ in 0..15 {
+            std::fs::write(&busy, wav_bytes(1, 44_100)).unwrap();
+            tokio::time::sleep(Duration::from_millis(40)).await;
+        }
+        // The busy file is still inside its window, the quiet one settled long ago.
+        assert_eq!(events.try_recv().unwrap(), ScanEvent::FileAdded(quiet));
+        assert!(events.try_recv().is_err());
+
+        let settled = tokio::time::timeout(Duration::from_secs(5), events.recv())
+            .await
+            .expect("scan event")
+            .unwrap();
+        assert_eq!(settled, ScanEvent::FileAdded(busy));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn symlink_loops_do_not_recurse_forever() {
+        let dir = tempdir().unwrap();
+        build_tree(dir.path(), 2, 2);
+        let album = dir.path().join("album-000");
+        std::os::unix::fs::symlink(dir.path(), album.join("loop")).unwrap();
+        std::os::unix::fs::symlink(&album, dir.path().join("album-link")).unwrap();
+
+        let outcome = scanner_for(dir.path())
+            .full_scan(ScanMode::Lazy, &CancellationToken::new(), None)
+            .await
+            .unwrap();
+
+        assert!(!outcome.cancelled);
+        assert_eq!(outcome.records.len(), 4);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn links_outside_the_root_are_skipped_unless_followed() {
+        let dir = tempdir().unwrap();
+        let outside = tempdir().unwrap();
+        build_tree(dir.path(), 1, 1);
+        build_tree(outside.path(), 1, 3);
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("external")).unwrap();
+        let album = dir.path().join("album-000");
+        std::os::unix::fs::symlink(
+            outside.path().join("album-000/00.flac"),
+            album.join("linked.flac"),
+        )
+        .unwrap();
+        std::os::unix::fs::symlink(album.join("gone.flac"), album.join("dangling.flac")).unwrap();
+
+        let mut scanner = scanner_for(dir.path());
+        let followed = scanner
+            .full_scan(ScanMode::Lazy, &CancellationToken::new(), None)
+            .await
+            .unwrap();
+        assert_eq!(followed.records.len(), 5);
+
+        scanner.sources[0].follow_symlinks = false;
+        let contained = scanner
+            .full_scan(ScanMode::Lazy, &CancellationToken::new(), None)
+            .await
+            .unwrap();
+        assert_eq!(contained.records.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn cancelling_mid_scan_returns_partial_records() {
+        let dir = tempdir().unwrap();
+        build_tree(dir.path(), 50, 20);
+
+        let cancel = CancellationToken::new();
+        let trigger = cancel.clone();
+        let seen = Arc::new(AtomicUsize::new(0));
+        let counter = seen.clone();
+        let progress = move |event: &ScanProgress| {
+            if let ScanProgress::File(_) = event
+                && counter.fetch_add(1, Ordering::SeqCst) + 1 == 100
+            {
+                trigger.cancel();
+            }
+        };
+
+        let outcome = scanner_for(dir.path())
+            .full_scan(ScanMode::Eager, &cancel, Some(&progress))
+            .await
+            .unwrap();
+
+        // All 1000 files sit in one walk level, so stopping short of it means the workers
+        // saw the cancel mid-directory: each may have reported one more file at most.
+        assert!(outcome.cancelled);
+        let seen = seen.load(Ordering::SeqCst);
+        assert!(
+            seen <= 100 + DEFAULT_SCAN_CONCURRENCY + 1,
+            "{seen} files reported"
+        );
+        let indexed = outcome.records.len() + outcome.failures.len();
+        assert!((100 - DEFAULT_SCAN_CONCURRENCY..=seen).contains(&indexed));
+    }
+}
@@ -0,0 +1,344 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tokio::fs;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::{LosslessStrategy, PolicyConfig, ScanMode, SourceConfig};
+use crate::cue::CueParser;
+use crate::error::{MusFuseError, Result};
+use crate::filesystem::{FileRouter, MediaEngine, VirtualEntry};
+use crate::kv::{KvBackend, KvStore};
+use crate::library::NoReader;
+use crate::media::{
+    AudioChunk, DefaultCoverExtractor, DefaultFormatTranscoder, FormatTranscoder, TranscodeRequest,
+};
+use crate::metadata::{TagDelta, TagValue};
+use crate::policy::{AudioFormatPolicy, TargetFormat};
+use crate::scanner::{FsLibraryScanner, LibraryScanner};
+use crate::tag::{KvTagPersistence, TagOverlay};
+use crate::track::{TrackIndexEntry, TrackMapper};
+
+const SAMPLE_RATE: u32 = 44_100;
+const CHANNELS: u16 = 2;
+const SECONDS: u32 = 2;
+const ALBUM_DIR: &str = "Selftest Album";
+const CUE_NAME: &str = "image.cue";
+const CUE_SHEET: &str = "PERFORMER \"MusFuse\"
+TITLE \"Selftest Album\"
+FILE \"image.wav\" WAVE
+  TRACK 01 AUDIO
+    TITLE \"Tone A\"
+    INDEX 01 00:00:00
+  TRACK 02 AUDIO
+    TITLE \"Tone B\"
+    INDEX 01 00:01:00
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfTestStage {
+    Generate,
+    Scan,
+    Map,
+    Transcode,
+    Read,
+}
+
+impl SelfTestStage {
+    pub const ALL: [SelfTestStage; 5] = [
+        SelfTestStage::Generate,
+        SelfTestStage::Scan,
+        SelfTestStage::Map,
+        SelfTestStage::Transcode,
+        SelfTestStage::Read,
+    ];
+}
+
+impl fmt::Display for SelfTestStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SelfTestStage::Generate => "generate",
+            SelfTestStage::Scan => "scan",
+            SelfTestStage::Map => "map",
+            SelfTestStage::Transcode => "transcode",
+            SelfTestStage::Read => "read",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StageReport {
+    pub stage: SelfTestStage,
+    pub error: Option<String>,
+}
+
+impl StageReport {
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SelfTestReport {
+    pub stages: Vec<StageReport>,
+}
+
+impl SelfTestReport {
+    // Stages after a failure never run, so a short report is a failed one.
+    pub fn passed(&self) -> bool {
+        self.stages.len() == SelfTestStage::ALL.len() && self.stages.iter().all(StageReport::passed)
+    }
+
+    fn record<T>(&mut self, stage: SelfTestStage, result: Result<T>) -> Option<T> {
+        let (value, error) = match result {
+            Ok(value) => (Some(value), None),
+            Err(err) => (None, Some(err.to_string())),
+        };
+        self.stages.push(StageReport { stage, error });
+        value
+    }
+}
+
+// Builds a two-track WAV image with a cue sheet in a scratch directory and pushes it
+// through scan, cue mapping, transcoding and router reads, stopping at the first stage
+// that fails. Tag edits made by the read stage go to `backend`.
+pub async fn run_selftest<B: KvBackend + ?Sized>(backend: Arc<B>) -> SelfTestReport {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let root =
+        std::env::temp_dir().join(format!("musfuse-selftest-{}-{nanos}", std::process::id()));
+    let mut report = SelfTestReport::default();
+    run_stages(&root, backend, &mut report).await;
+    let _ = fs::remove_dir_all(&root).await;
+    report
+}
+
+async fn run_stages<B: KvBackend + ?Sized>(
+    root: &Path,
+    backend: Arc<B>,
+    report: &mut SelfTestReport,
+) -> Option<()> {
+    report.record(SelfTestStage::Generate, generate(root).await)?;
+    let cue = report.record(SelfTestStage::Scan, scan(root).await)?;
+    let entries = report.record(SelfTestStage::Map, map(&cue).await)?;
+    report.record(SelfTestStage::Transcode, transcode(root, &entries).await)?;
+    report.record(SelfTestStage::Read, read(entries, backend).await)
+}
+
+fn check(ok: bool, message: impl Into<String>) -> Result<()> {
+    if ok {
+        Ok(())
+    } else {
+        Err(MusFuseError::Media(message.into()))
+    }
+}
+
+async fn generate(root: &Path) -> Result<()> {
+    let album = root.join(ALBUM_DIR);
+    fs::create_dir_all(&album).await?;
+    fs::write(album.join("image.wav"), tone_wav()).await?;
+    fs::write(album.join(CUE_NAME), CUE_SHEET).await?;
+    Ok(())
+}
+
+// Two seconds of 16-bit stereo ramps; real signal rather than silence, so the encoders
+// see full-range samples.
+fn tone_wav() -> Vec<u8> {
+    let frames = SAMPLE_RATE * SECONDS;
+    let block_align = CHANNELS * 2;
+    let data_len = frames * block_align as u32;
+    let mut wav = b"RIFF".to_vec();
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&CHANNELS.to_le_bytes());
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(SAMPLE_RATE * block_align as u32).to_le_bytes());
+    wav.extend_from_slice(&block_align.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for frame in 0..frames {
+        let sample = ((frame % 200) as i16 - 100) * 300;
+        for channel in 0..CHANNELS as i16 {
+            wav.extend_from_slice(&(sample / (channel + 1)).to_le_bytes());
+        }
+    }
+    wav
+}
+
+async fn scan(root: &Path) -> Result<PathBuf> {
+    let scanner = FsLibraryScanner::new(vec![SourceConfig {
+        path: root.to_path_buf(),
+        recursive: true,
+        watch: false,
+        follow_symlinks: true,
+    }]);
+    let outcome = scanner
+        .full_scan(ScanMode::Eager, &CancellationToken::new(), None)
+        .await?;
+    check(!outcome.cancelled, "scan was cancelled")?;
+    check(
+        outcome.failures.is_empty(),
+        format!("scan rejected {} file(s)", outcome.failures.len()),
+    )?;
+    check(
+        outcome.records.len() == 2,
+        format!("expected 2 scanned files, found {}", outcome.records.len()),
+    )?;
+    let cue = outcome
+        .records
+        .iter()
+        .find(|record| record.source.ends_with(CUE_NAME))
+        .ok_or_else(|| MusFuseError::Media("cue sheet was not scanned".into()))?;
+    check(
+        cue.albums.iter().any(|album| album.0 == ALBUM_DIR),
+        "cue sheet was not grouped under its folder",
+    )?;
+    Ok(cue.source.clone())
+}
+
+async fn map(cue: &Path) -> Result<Vec<TrackIndexEntry>> {
+    let sheet = CueParser.parse_file(cue).await?;
+    let album = crate::metadata::AlbumId(ALBUM_DIR.into());
+    let index = TrackMapper::from_cue_with_durations(&sheet, &album, Some(cue))?;
+    let titles: Vec<&str> = index
+        .entries
+        .iter()
+        .map(|entry| entry.metadata.title.as_str())
+        .collect();
+    check(
+        titles == ["Tone A", "Tone B"],
+        format!("unexpected track titles {titles:?}"),
+    )?;
+    // One second each, in CD frames.
+    let lengths: Vec<u64> = index
+        .entries
+        .iter()
+        .map(|entry| entry.source.length_frames)
+        .collect();
+    check(
+        lengths == [75, 75],
+        format!("unexpected track lengths {lengths:?}"),
+    )?;
+    Ok(index.entries)
+}
+
+// Each track is converted on its own and the output decoded again, so a slice that comes
+// out the wrong length fails here rather than in a player.
+async fn transcode(root: &Path, entries: &[TrackIndexEntry]) -> Result<()> {
+    let transcoder = DefaultFormatTranscoder::new();
+    for entry in entries {
+        let request = TranscodeRequest {
+            track: entry.source.clone(),
+            policy: AudioFormatPolicy::ConvertLossless,
+            target_format: Some(TargetFormat::Flac),
+            resample_to: None,
+            target_bits: None,
+        };
+        let result = transcoder.transcode(&request).await?;
+        check(
+            result.format == "flac",
+            format!("got {} output", result.format),
+        )?;
+        let bytes = concat(&result.chunks);
+        check(bytes.starts_with(b"fLaC"), "output is not a FLAC stream")?;
+        check(
+            result.chunks.last().is_some_and(|chunk| chunk.is_end),
+            "last chunk is not marked as the end",
+        )?;
+
+        let output = root.join(format!("track{:02}.flac", entry.id.index));
+        fs::write(&output, &bytes).await?;
+        let length = tokio::task::spawn_blocking(move || crate::probe::decoded_frames(&output))
+            .await
+            .map_err(|err| MusFuseError::Media(err.to_string()))??;
+        check(
+            length.frames == SAMPLE_RATE as u64,
+            format!(
+                "track {} decodes to {} ms instead of 1000 ms",
+                entry.id.index,
+                length.duration_ms()
+            ),
+        )?;
+    }
+    Ok(())
+}
+
+async fn read<B: KvBackend + ?Sized>(entries: Vec<TrackIndexEntry>, backend: Arc<B>) -> Result<()> {
+    let policy = PolicyConfig {
+        lossless_strategy: LosslessStrategy::ConvertToFlac,
+        ..PolicyConfig::default()
+    };
+    let tags = TagOverlay::for_policy(
+        &policy,
+        Arc::new(KvTagPersistence::new(KvStore::new(backend))),
+    );
+    let media = MediaEngine::new(
+        Arc::new(NoReader),
+        Arc::new(DefaultFormatTranscoder::new()),
+        Arc::new(DefaultCoverExtractor::new()),
+        policy,
+    );
+    let id = entries[0].id.clone();
+    let router = FileRouter::new(Arc::new(entries), Arc::new(media), Arc::new(tags));
+
+    let name = router.track_file_name(&id, TargetFormat::Flac);
+    let album = format!("/{}", router.album_dir_name(&id.album));
+    check(
+        router
+            .list_dir(&album)
+            .is_some_and(|entries| entries.contains(&VirtualEntry::TrackFile(id.clone(), None))),
+        "album listing does not show the first track",
+    )?;
+    check(
+        router.resolve(&name)
+            == Some(VirtualEntry::TrackFile(
+                id.clone(),
+                Some(TargetFormat::Flac),
+            )),
+        format!("{name} does not resolve to the first track"),
+    )?;
+    let bytes = router.read_track_as(&id, Some(TargetFormat::Flac)).await?;
+    check(bytes.starts_with(b"fLaC"), format!("{name} is not FLAC"))?;
+
+    let delta = TagDelta::builder()
+        .set("COMMENT", TagValue::Text("selftest".into()))
+        .build();
+    router.write_tags(&id, &delta).await?;
+    let metadata = router.read_tags(&id).await?;
+    check(
+        metadata.tags.get("COMMENT") == Some(&TagValue::Text("selftest".into())),
+        "tag edit was not read back from the overlay",
+    )
+}
+
+fn concat(chunks: &[AudioChunk]) -> Vec<u8> {
+    chunks
+        .iter()
+        .flat_map(|chunk| chunk.data.iter().copied())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv::MemoryBackend;
+
+    #[tokio::test]
+    async fn every_stage_passes_against_an_in_memory_backend() {
+        let report = run_selftest(Arc::new(MemoryBackend::new())).await;
+
+        let stages: Vec<_> = report.stages.iter().map(|stage| stage.stage).collect();
+        assert_eq!(stages, SelfTestStage::ALL);
+        for stage in &report.stages {
+            assert!(stage.passed(), "{}: {:?}", stage.stage, stage.error);
+        }
+        assert!(report.passed());
+    }
+}
@@ -1,17 +1,399 @@
-use std::path::Path;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::OpenOptions;
+use std::io::Seek;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use async_trait::async_trait;
+use lofty::flac::FlacFile;
+use lofty::ogg::VorbisComments;
+use lofty::{
+    Accessor, AudioFile, ItemKey, ItemValue, ParseOptions, Tag, TagExt, TagItem, TaggedFileExt,
+    read_from_path,
+};
+use tokio::task;
 
-use crate::error::Result;
-use crate::kv::{KvBackend, KvKey, KvNamespace, KvStore};
-use crate::metadata::{TagDelta, TrackId, TrackMetadata};
+use crate::config::PolicyConfig;
+use crate::duration::DurationCorrections;
+use crate::error::{MusFuseError, Result};
+use crate::format::AudioFormat;
+use crate::kv::{KvBackend, KvKey, KvStore};
+use crate::lyrics::Lyrics;
+use crate::metadata::{
+    DEFAULT_MULTI_VALUE_SEPARATOR, TagDelta, TagKeyCase, TagMap, TagValue, TitleNormalization,
+    TrackId, TrackMetadata, is_multi_value_key, is_numeric_key,
+};
 
 #[async_trait]
 pub trait TagReader: Send + Sync {
     async fn read_from_file(&self, track: &TrackId, path: &Path) -> Result<TrackMetadata>;
 }
 
+#[derive(Debug, Clone)]
+pub struct LoftyTagReader {
+    separator: String,
+    key_case: TagKeyCase,
+    titles: TitleNormalization,
+}
+
+impl Default for LoftyTagReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoftyTagReader {
+    pub fn new() -> Self {
+        Self::with_separator(DEFAULT_MULTI_VALUE_SEPARATOR)
+    }
+
+    pub fn with_separator(separator: impl Into<String>) -> Self {
+        Self {
+            separator: separator.into(),
+            key_case: TagKeyCase::default(),
+            titles: TitleNormalization::default(),
+        }
+    }
+
+    pub fn for_policy(policy: &PolicyConfig) -> Self {
+        Self::with_separator(policy.multi_value_separator.clone())
+    }
+
+    pub fn with_key_case(mut self, key_case: TagKeyCase) -> Self {
+        self.key_case = key_case;
+        self
+    }
+
+    pub fn with_title_normalization(mut self, titles: TitleNormalization) -> Self {
+        self.titles = titles;
+        self
+    }
+
+    fn is_dsd(path: &Path) -> bool {
+        AudioFormat::from_path(path).is_some_and(|format| format.is_dsd())
+    }
+
+    fn is_wav(path: &Path) -> bool {
+        AudioFormat::from_path(path) == Some(AudioFormat::Wav)
+    }
+
+    // DSD containers are not understood by lofty but should still be listed.
+    fn untagged(track: TrackId, path: &Path) -> TrackMetadata {
+        TrackMetadata {
+            title: path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_else(|| track.to_string()),
+            id: track,
+            artist: "Unknown Artist".into(),
+            album_artist: None,
+            duration_ms: 0,
+            tags: TagMap::default(),
+            artwork: None,
+            lyrics: None,
+        }
+    }
+
+    fn read_sync(
+        track: TrackId,
+        path: PathBuf,
+        separator: &str,
+        key_case: TagKeyCase,
+        titles: TitleNormalization,
+    ) -> Result<TrackMetadata> {
+        let tagged = match read_from_path(&path) {
+            Ok(tagged) => tagged,
+            Err(_) if Self::is_dsd(&path) => return Ok(Self::untagged(track, &path)),
+            Err(err) => return Err(MusFuseError::Media(err.to_string())),
+        };
+        let duration_ms = tagged.properties().duration().as_millis() as u64;
+        let tag = tagged.primary_tag().or_else(|| tagged.first_tag());
+
+        let title = tag
+            .and_then(|tag| tag.title().map(|title| titles.apply(&title)))
+            .filter(|title| !title.is_empty())
+            .or_else(|| {
+                path.file_stem()
+                    .map(|stem| stem.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| track.to_string());
+        let artists: Vec<&str> = tag
+            .map(|tag| {
+                tag.get_strings(&ItemKey::TrackArtist)
+                    .flat_map(|value| value.split('\0'))
+                    .filter(|value| !value.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let artist = if artists.is_empty() {
+            "Unknown Artist".to_string()
+        } else {
+            artists.join(separator)
+        };
+        let album_artist = tag
+            .and_then(|tag| tag.get_string(&ItemKey::AlbumArtist))
+            .map(str::to_string);
+        let lyrics = tag
+            .and_then(|tag| tag.get_string(&ItemKey::Lyrics))
+            .and_then(Lyrics::parse);
+
+        let mut values: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        if let Some(tag) = tag {
+            for item in tag.items() {
+                if item.key() == &ItemKey::Lyrics {
+                    continue;
+                }
+                if let (Some(key), ItemValue::Text(value)) =
+                    (item.key().map_key(tag.tag_type(), true), item.value())
+                {
+                    let key = key_case.normalize(key);
+                    let is_title = ["TITLE", "ALBUM"]
+                        .iter()
+                        .any(|title_key| key.eq_ignore_ascii_case(title_key));
+                    values
+                        .entry(key)
+                        .or_default()
+                        .extend(value.split('\0').map(|text| {
+                            if is_title {
+                                titles.apply(text)
+                            } else {
+                                text.to_string()
+                            }
+                        }));
+                }
+            }
+        }
+        let mut tags = TagMap::default();
+        for (key, texts) in values {
+            let value = TagValue::from_texts(texts);
+            let value = if is_numeric_key(&key) {
+                value.parse_numbers()
+            } else {
+                value
+            };
+            tags.insert(key, value);
+        }
+        // Broadcast WAV headers are invisible to lofty.
+        if Self::is_wav(&path)
+            && let Some(bext) = crate::bwf::read_bext(&path)?
+        {
+            bext.merge_into(&mut tags);
+        }
+
+        Ok(TrackMetadata {
+            id: track,
+            title,
+            artist,
+            album_artist,
+            duration_ms,
+            tags,
+            artwork: None,
+            lyrics,
+        })
+    }
+}
+
+#[async_trait]
+impl TagReader for LoftyTagReader {
+    async fn read_from_file(&self, track: &TrackId, path: &Path) -> Result<TrackMetadata> {
+        let track = track.clone();
+        let path = path.to_path_buf();
+        let separator = self.separator.clone();
+        let key_case = self.key_case;
+        let titles = self.titles;
+        task::spawn_blocking(move || Self::read_sync(track, path, &separator, key_case, titles))
+            .await
+            .map_err(|err| MusFuseError::Media(err.to_string()))?
+    }
+}
+
+#[async_trait]
+pub trait TagWriter: Send + Sync {
+    // Makes the tags stored in `path` match `metadata`, the merged view of `track`.
+    async fn write_to_file(
+        &self,
+        track: &TrackId,
+        path: &Path,
+        metadata: &TrackMetadata,
+    ) -> Result<()>;
+}
+
+// Rewrites only the keys whose values differ from what the file holds now, as read with
+// the same separator and key case the overlay reads with, so untouched tags keep their
+// original spelling and order.
+#[derive(Debug, Clone)]
+pub struct LoftyTagWriter {
+    separator: String,
+    reader: LoftyTagReader,
+}
+
+impl Default for LoftyTagWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LoftyTagWriter {
+    pub fn new() -> Self {
+        Self::with_separator(DEFAULT_MULTI_VALUE_SEPARATOR)
+    }
+
+    pub fn with_separator(separator: impl Into<String>) -> Self {
+        let separator = separator.into();
+        Self {
+            reader: LoftyTagReader::with_separator(separator.clone()),
+            separator,
+        }
+    }
+
+    pub fn for_policy(policy: &PolicyConfig) -> Self {
+        Self {
+            separator: policy.multi_value_separator.clone(),
+            reader: LoftyTagReader::for_policy(policy),
+        }
+    }
+
+    // The edits that turn the tags read from a file into `wanted`.
+    fn changes(current: &TagMap, wanted: &TagMap) -> TagDelta {
+        let mut delta = TagDelta::builder().build();
+        for (key, value) in &wanted.0 {
+            if current.get_ignore_case(key) != Some(value) {
+                delta.set.insert(key.clone(), value.clone());
+            }
+        }
+        for key in current.0.keys() {
+            if wanted.get_ignore_case(key).is_none() {
+                delta.remove.push(key.clone());
+            }
+        }
+        delta
+    }
+
+    fn texts(key: &str, value: &TagValue, separator: &str) -> Vec<String> {
+        let value = match value {
+            TagValue::Text(text) if is_multi_value_key(key) => {
+                TagValue::split_text(text, separator)
+            }
+            other => other.clone(),
+        };
+        match value {
+            TagValue::List(values) => values
+                .iter()
+                .map(|value| value.join_text(separator))
+                .collect(),
+            other => vec![other.join_text(separator)],
+        }
+    }
+
+    fn appended_texts(key: &str, values: &[TagValue], separator: &str) -> Vec<String> {
+        values
+            .iter()
+            .flat_map(|value| Self::texts(key, value, separator))
+            .collect()
+    }
+
+    // Only the VORBIS_COMMENT block is rebuilt; STREAMINFO, pictures and audio frames are kept as-is.
+    fn write_flac(path: &Path, delta: &TagDelta, separator: &str) -> Result<()> {
+        let media_err = |err: lofty::LoftyError| MusFuseError::Media(err.to_string());
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        let mut flac = FlacFile::read_from(&mut file, ParseOptions::new()).map_err(media_err)?;
+        if flac.vorbis_comments().is_none() {
+            flac.set_vorbis_comments(VorbisComments::new());
+        }
+        if let Some(comments) = flac.vorbis_comments_mut() {
+            for key in &delta.remove {
+                let _ = comments.remove(key).count();
+            }
+            for (key, value) in &delta.set {
+                let _ = comments.remove(key).count();
+                for text in Self::texts(key, value, separator) {
+                    comments.push(key.clone(), text);
+                }
+            }
+            for (key, values) in &delta.append {
+                for text in Self::appended_texts(key, values, separator) {
+                    if !comments.get_all(key).any(|existing| existing == text) {
+                        comments.push(key.clone(), text);
+                    }
+                }
+            }
+        }
+        file.rewind()?;
+        flac.save_to(&mut file).map_err(media_err)
+    }
+
+    fn write_generic(path: &Path, delta: &TagDelta, separator: &str) -> Result<()> {
+        let media_err = |err: lofty::LoftyError| MusFuseError::Media(err.to_string());
+        let mut tagged = read_from_path(path).map_err(media_err)?;
+        let tag_type = tagged.primary_tag_type();
+        if tagged.tag(tag_type).is_none() {
+            tagged.insert_tag(Tag::new(tag_type));
+        }
+        let Some(tag) = tagged.tag_mut(tag_type) else {
+            return Err(MusFuseError::Unsupported("tag type not writable"));
+        };
+        for key in &delta.remove {
+            tag.remove_key(&ItemKey::from_key(tag_type, key));
+        }
+        for (key, value) in &delta.set {
+            let item_key = ItemKey::from_key(tag_type, key);
+            tag.remove_key(&item_key);
+            for text in Self::texts(key, value, separator) {
+                tag.push(TagItem::new(item_key.clone(), ItemValue::Text(text)));
+            }
+        }
+        for (key, values) in &delta.append {
+            let item_key = ItemKey::from_key(tag_type, key);
+            for text in Self::appended_texts(key, values, separator) {
+                if !tag.get_strings(&item_key).any(|existing| existing == text) {
+                    tag.push(TagItem::new(item_key.clone(), ItemValue::Text(text)));
+                }
+            }
+        }
+        tag.save_to_path(path).map_err(media_err)
+    }
+
+    fn write_sync(path: PathBuf, delta: TagDelta, separator: &str) -> Result<()> {
+        if AudioFormat::from_path(&path) == Some(AudioFormat::Flac) {
+            Self::write_flac(&path, &delta, separator)
+        } else {
+            Self::write_generic(&path, &delta, separator)
+        }
+    }
+}
+
+#[async_trait]
+impl TagWriter for LoftyTagWriter {
+    async fn write_to_file(
+        &self,
+        track: &TrackId,
+        path: &Path,
+        metadata: &TrackMetadata,
+    ) -> Result<()> {
+        let track = track.clone();
+        let path = path.to_path_buf();
+        let wanted = metadata.tags.clone();
+        let writer = self.clone();
+        task::spawn_blocking(move || {
+            let reader = &writer.reader;
+            let current = LoftyTagReader::read_sync(
+                track,
+                path.clone(),
+                &reader.separator,
+                reader.key_case,
+                reader.titles,
+            )?;
+            let delta = Self::changes(&current.tags, &wanted);
+            if delta.is_empty() {
+                return Ok(());
+            }
+            Self::write_sync(path, delta, &writer.separator)
+        })
+        .await
+        .map_err(|err| MusFuseError::Media(err.to_string()))?
+    }
+}
+
 #[async_trait]
 pub trait TagPersistence: Send + Sync {
     async fn load_delta(&self, track: &TrackId) -> Result<Option<TagDelta>>;
@@ -19,22 +401,22 @@ pub trait TagPersistence: Send + Sync {
     async fn delete_delta(&self, track: &TrackId) -> Result<()>;
 }
 
-pub struct KvTagPersistence<B: KvBackend> {
+pub struct KvTagPersistence<B: KvBackend + ?Sized> {
     store: KvStore<B>,
 }
 
-impl<B: KvBackend> KvTagPersistence<B> {
+impl<B: KvBackend + ?Sized> KvTagPersistence<B> {
     pub fn new(store: KvStore<B>) -> Self {
         Self { store }
     }
 
     fn key(track: &TrackId) -> KvKey {
-        KvKey::new(KvNamespace::Track, format!("{}:tag", track))
+        track.kv_key("tag")
     }
 }
 
 #[async_trait]
-impl<B: KvBackend> TagPersistence for KvTagPersistence<B> {
+impl<B: KvBackend + ?Sized> TagPersistence for KvTagPersistence<B> {
     async fn load_delta(&self, track: &TrackId) -> Result<Option<TagDelta>> {
         self.store.load(&Self::key(track)).await
     }
@@ -63,6 +445,22 @@ pub trait TagOverlayService: Send + Sync {
 pub struct TagOverlay<R: TagReader, P: TagPersistence> {
     reader: Arc<R>,
     persistence: Arc<P>,
+    separator: String,
+    durations: Option<Arc<DurationCorrections>>,
+    writer: Arc<dyn TagWriter>,
+    write_through: bool,
+    shared_sources: HashSet<PathBuf>,
+}
+
+impl<P: TagPersistence> TagOverlay<LoftyTagReader, P> {
+    // Reads, splits and joins multi-value tags with the mount's configured separator, and
+    // writes edits through to the files when the policy asks for it.
+    pub fn for_policy(policy: &PolicyConfig, persistence: Arc<P>) -> Self {
+        Self::new(Arc::new(LoftyTagReader::for_policy(policy)), persistence)
+            .with_multi_value_separator(policy.multi_value_separator.clone())
+            .with_tag_writer(Arc::new(LoftyTagWriter::for_policy(policy)))
+            .with_write_through(policy.write_through_tags)
+    }
 }
 
 impl<R: TagReader, P: TagPersistence> TagOverlay<R, P> {
@@ -70,15 +468,58 @@ impl<R: TagReader, P: TagPersistence> TagOverlay<R, P> {
         Self {
             reader,
             persistence,
+            separator: DEFAULT_MULTI_VALUE_SEPARATOR.to_string(),
+            durations: None,
+            writer: Arc::new(LoftyTagWriter::new()),
+            write_through: false,
+            shared_sources: HashSet::new(),
         }
     }
 
-    fn apply_delta(meta: &mut TrackMetadata, delta: &TagDelta) {
+    // Also writes the merged tags of each applied delta into the source file, so players
+    // reading the library directly see the edit.
+    pub fn with_write_through(mut self, write_through: bool) -> Self {
+        self.write_through = write_through;
+        self
+    }
+
+    pub fn with_tag_writer(mut self, writer: Arc<dyn TagWriter>) -> Self {
+        self.writer = writer;
+        self
+    }
+
+    // Files backing more than one track, such as cue images; write-through edits to their
+    // tracks are refused, since the file's tags belong to all of them.
+    pub fn with_shared_sources(mut self, sources: impl IntoIterator<Item = PathBuf>) -> Self {
+        self.shared_sources = sources.into_iter().collect();
+        self
+    }
+
+    pub fn with_duration_corrections(mut self, durations: Arc<DurationCorrections>) -> Self {
+        self.durations = Some(durations);
+        self
+    }
+
+    pub fn with_multi_value_separator(mut self, separator: impl Into<String>) -> Self {
+        self.separator = separator.into();
+        self
+    }
+
+    fn apply_delta(meta: &mut TrackMetadata, delta: &TagDelta, separator: &str) {
         for key in &delta.remove {
-            meta.tags.0.remove(key);
+            meta.tags.remove_ignore_case(key);
         }
         for (key, value) in &delta.set {
-            meta.tags.insert(key.clone(), value.clone());
+            let value = match value {
+                TagValue::Text(text) if is_multi_value_key(key) => {
+                    TagValue::split_text(text, separator)
+                }
+                other => other.clone(),
+            };
+            meta.tags.insert_ignore_case(key, value);
+        }
+        for (key, values) in &delta.append {
+            meta.tags.append_ignore_case(key, values.clone());
         }
     }
 }
@@ -92,7 +533,10 @@ where
     async fn read(&self, track: &TrackId, source: &Path) -> Result<TrackMetadata> {
         let mut metadata = self.reader.read_from_file(track, source).await?;
         if let Some(delta) = self.persistence.load_delta(track).await? {
-            Self::apply_delta(&mut metadata, &delta);
+            Self::apply_delta(&mut metadata, &delta, &self.separator);
+        }
+        if let Some(durations) = &self.durations {
+            durations.apply(&mut metadata).await?;
         }
         Ok(metadata)
     }
@@ -103,9 +547,18 @@ where
         source: &Path,
         delta: &TagDelta,
     ) -> Result<TrackMetadata> {
+        if self.write_through && self.shared_sources.contains(source) {
+            return Err(MusFuseError::Unsupported(
+                "writing tags through to a file shared by several tracks",
+            ));
+        }
         let mut merged = self.reader.read_from_file(track, source).await?;
-        Self::apply_delta(&mut merged, delta);
+        Self::apply_delta(&mut merged, delta, &self.separator);
         self.persistence.save_delta(track, delta).await?;
+        // The delta stays in the store too; re-applying it over the rewritten file is a no-op.
+        if self.write_through {
+            self.writer.write_to_file(track, source, &merged).await?;
+        }
         Ok(merged)
     }
 
@@ -117,12 +570,12 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::kv::MemoryBackend;
     use mockall::{mock, predicate::always};
     use std::collections::HashMap;
     use tempfile::tempdir;
 
-    use crate::kv::SledBackend;
-    use crate::metadata::{AlbumId, TagMap, TagValue};
+    use crate::metadata::AlbumId;
 
     mock! {
         pub Reader {}
@@ -146,6 +599,7 @@ mod tests {
             duration_ms: 1000,
             tags: TagMap::default(),
             artwork: None,
+            lyrics: None,
         }
     }
 
@@ -157,8 +611,7 @@ mod tests {
             .with(always(), always())
             .returning(|_, _| Ok(sample_track()));
 
-        let dir = tempdir().unwrap();
-        let backend = SledBackend::open(dir.path()).unwrap();
+        let backend = MemoryBackend::new();
         let store = KvStore::new(Arc::new(backend));
         let persistence = Arc::new(KvTagPersistence::new(store));
         let overlay = TagOverlay::new(Arc::new(reader), persistence.clone());
@@ -172,6 +625,7 @@ mod tests {
         let delta = TagDelta {
             set: HashMap::from([(String::from("RATING"), TagValue::Number(5))]),
             remove: vec![String::from("COMMENT")],
+            append: HashMap::new(),
         };
 
         let merged = overlay
@@ -186,4 +640,545 @@ mod tests {
             .unwrap();
         assert_eq!(reloaded.tags.get("RATING"), Some(&TagValue::Number(5)));
     }
+
+    fn write_test_flac(path: &Path) {
+        use flac_codec::encode::{FlacSampleWriter, Options};
+
+        let file = std::fs::File::create(path).expect("create flac");
+        let mut writer =
+            FlacSampleWriter::new(file, Options::default(), 44_100, 16, 2, None).expect("writer");
+        writer.write(&[0i32; 2 * 1_024]).expect("write samples");
+        writer.finalize().expect("finalize flac");
+    }
+
+    #[tokio::test]
+    async fn lofty_reader_keeps_repeated_artists_as_a_list() {
+        use lofty::{Tag, TagExt, TagItem, TagType};
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("duet.flac");
+        write_test_flac(&path);
+
+        let mut tag = Tag::new(TagType::VorbisComments);
+        for artist in ["Alice", "Bob"] {
+            tag.push(TagItem::new(
+                ItemKey::TrackArtist,
+                ItemValue::Text(artist.into()),
+            ));
+        }
+        tag.save_to_path(&path).expect("save tag");
+
+        let metadata = LoftyTagReader::new()
+            .read_from_file(&sample_track().id, &path)
+            .await
+            .unwrap();
+
+        assert_eq!(metadata.artist, "Alice; Bob");
+        assert_eq!(
+            metadata.tags.get("ARTIST"),
+            Some(&TagValue::List(vec![
+                TagValue::Text("Alice".into()),
+                TagValue::Text("Bob".into()),
+            ]))
+        );
+    }
+
+    #[tokio::test]
+    async fn lofty_reader_populates_fields_and_types_numeric_frames() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("known.flac");
+        write_test_flac(&path);
+        let mut comments = VorbisComments::new();
+        for (key, value) in [
+            ("TITLE", "Known Title"),
+            ("ARTIST", "Known Artist"),
+            ("ALBUMARTIST", "Various"),
+            ("TRACKNUMBER", "3"),
+            ("DISCNUMBER", "1/2"),
+            ("GENRE", "Jazz"),
+            ("GENRE", "Blues"),
+            ("COMMENT", "42 takes"),
+        ] {
+            comments.push(key.into(), value.into());
+        }
+        comments.save_to_path(&path).expect("save comments");
+
+        let track = sample_track().id;
+        let metadata = LoftyTagReader::new()
+            .read_from_file(&track, &path)
+            .await
+            .unwrap();
+
+        assert_eq!(metadata.id, track);
+        assert_eq!(metadata.title, "Known Title");
+        assert_eq!(metadata.artist, "Known Artist");
+        assert_eq!(metadata.album_artist.as_deref(), Some("Various"));
+        // 1,024 frames at 44.1 kHz.
+        assert_eq!(metadata.duration_ms, 23);
+        assert_eq!(metadata.tags.get("TRACKNUMBER"), Some(&TagValue::Number(3)));
+        assert_eq!(
+            metadata.tags.get("DISCNUMBER"),
+            Some(&TagValue::Text("1/2".into()))
+        );
+        assert_eq!(
+            metadata.tags.get("GENRE"),
+            Some(&TagValue::List(vec![
+                TagValue::Text("Jazz".into()),
+                TagValue::Text("Blues".into()),
+            ]))
+        );
+        assert_eq!(
+            metadata.tags.get("COMMENT"),
+            Some(&TagValue::Text("42 takes".into()))
+        );
+    }
+
+    #[tokio::test]
+    async fn mixed_case_keys_collapse_and_deltas_match_any_case() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("mood.flac");
+        write_test_flac(&path);
+
+        let mut comments = VorbisComments::new();
+        comments.push("Mood".into(), "Calm".into());
+        comments.push("MOOD".into(), "Dark".into());
+        comments.push("mood".into(), "Warm".into());
+        comments.save_to_path(&path).expect("save comments");
+
+        let mut metadata = LoftyTagReader::new()
+            .read_from_file(&sample_track().id, &path)
+            .await
+            .unwrap();
+        let moods: Vec<_> = metadata
+            .tags
+            .0
+            .keys()
+            .filter(|key| key.eq_ignore_ascii_case("mood"))
+            .collect();
+        assert_eq!(moods, vec!["MOOD"]);
+        assert!(matches!(
+            metadata.tags.get("MOOD"),
+            Some(TagValue::List(values)) if values.len() == 3
+        ));
+
+        let delta = TagDelta::builder()
+            .set_text("mood", "Bright")
+            .remove("Comment")
+            .build();
+        metadata
+            .tags
+            .insert("COMMENT", TagValue::Text("rip log".into()));
+        TagOverlay::<MockReader, KvTagPersistence<MemoryBackend>>::apply_delta(
+            &mut metadata,
+            &delta,
+            DEFAULT_MULTI_VALUE_SEPARATOR,
+        );
+        assert_eq!(
+            metadata.tags.get("MOOD"),
+            Some(&TagValue::Text("Bright".into()))
+        );
+        assert_eq!(metadata.tags.get_ignore_case("comment"), None);
+        assert_eq!(
+            metadata
+                .tags
+                .0
+                .keys()
+                .filter(|key| key.eq_ignore_ascii_case("mood"))
+                .count(),
+            1
+        );
+    }
+
+    fn write_broadcast_wav(path: &Path, description: &str, date: &str) {
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 48_000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).expect("create wav");
+        for _ in 0..480 {
+            writer.write_sample(0i16).expect("write left");
+            writer.write_sample(0i16).expect("write right");
+        }
+        writer.finalize().expect("finalize wav");
+
+        let mut bext = vec![0u8; 602];
+        bext[..description.len()].copy_from_slice(description.as_bytes());
+        bext[256..256 + 6].copy_from_slice(b"Studio");
+        bext[320..330].copy_from_slice(date.as_bytes());
+        bext[338..346].copy_from_slice(&48_000u64.to_le_bytes());
+
+        let mut wav = std::fs::read(path).expect("read wav");
+        wav.extend_from_slice(b"bext");
+        wav.extend_from_slice(&(bext.len() as u32).to_le_bytes());
+        wav.extend_from_slice(&bext);
+        let riff_size = (wav.len() - 8) as u32;
+        wav[4..8].copy_from_slice(&riff_size.to_le_bytes());
+        std::fs::write(path, wav).expect("write bext");
+    }
+
+    #[tokio::test]
+    async fn broadcast_wav_description_is_merged_into_tags() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("take.wav");
+        write_broadcast_wav(&path, "Scene 4, take 2", "2024-03-18");
+
+        let metadata = LoftyTagReader::new()
+            .read_from_file(&sample_track().id, &path)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            metadata.tags.get("BWF_DESCRIPTION"),
+            Some(&TagValue::Text("Scene 4, take 2".into()))
+        );
+        assert_eq!(
+            metadata.tags.get("BWF_ORIGINATION_DATE"),
+            Some(&TagValue::Text("2024-03-18".into()))
+        );
+        assert_eq!(
+            metadata.tags.get("BWF_ORIGINATOR"),
+            Some(&TagValue::Text("Studio".into()))
+        );
+        assert_eq!(
+            metadata.tags.get("BWF_TIME_REFERENCE"),
+            Some(&TagValue::Number(48_000))
+        );
+    }
+
+    #[test]
+    fn multi_value_text_is_split_back_on_write() {
+        let mut metadata = sample_track();
+        let delta = TagDelta::builder()
+            .set_text("ARTIST", "Alice; Bob")
+            .set_text("TITLE", "Intro; Outro")
+            .build();
+
+        TagOverlay::<MockReader, KvTagPersistence<MemoryBackend>>::apply_delta(
+            &mut metadata,
+            &delta,
+            DEFAULT_MULTI_VALUE_SEPARATOR,
+        );
+
+        assert_eq!(
+            metadata.tags.get("ARTIST"),
+            Some(&TagValue::List(vec![
+                TagValue::Text("Alice".into()),
+                TagValue::Text("Bob".into()),
+            ]))
+        );
+        assert_eq!(
+            metadata.tags.get("TITLE"),
+            Some(&TagValue::Text("Intro; Outro".into()))
+        );
+    }
+
+    // Returns the STREAMINFO MD5 and everything after the last metadata block.
+    fn flac_audio_section(path: &Path) -> (Vec<u8>, Vec<u8>) {
+        let bytes = std::fs::read(path).expect("read flac");
+        assert_eq!(&bytes[..4], b"fLaC");
+        let mut offset = 4;
+        let mut md5 = Vec::new();
+        loop {
+            let header = bytes[offset];
+            let length =
+                u32::from_be_bytes([0, bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+                    as usize;
+            if header & 0x7f == 0 {
+                md5 = bytes[offset + 4 + 18..offset + 4 + 34].to_vec();
+            }
+            offset += 4 + length;
+            if header & 0x80 != 0 {
+                break;
+            }
+        }
+        (md5, bytes[offset..].to_vec())
+    }
+
+    // Writes `delta` merged over the file's current tags, as a write-through overlay does.
+    async fn write_merged(path: &Path, delta: &TagDelta) {
+        let track = sample_track().id;
+        let mut merged = LoftyTagReader::new()
+            .read_from_file(&track, path)
+            .await
+            .unwrap();
+        TagOverlay::<MockReader, KvTagPersistence<MemoryBackend>>::apply_delta(
+            &mut merged,
+            delta,
+            DEFAULT_MULTI_VALUE_SEPARATOR,
+        );
+        LoftyTagWriter::new()
+            .write_to_file(&track, path, &merged)
+            .await
+            .expect("write tags");
+    }
+
+    #[tokio::test]
+    async fn flac_retag_rewrites_comments_without_touching_frames() {
+        use lofty::{Tag, TagExt, TagType};
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("retag.flac");
+        write_test_flac(&path);
+        let mut tag = Tag::new(TagType::VorbisComments);
+        tag.set_title("Before".into());
+        tag.save_to_path(&path).expect("save tag");
+        let (md5_before, frames_before) = flac_audio_section(&path);
+
+        let delta = TagDelta::builder()
+            .set_text("TITLE", "After")
+            .set_text("ARTIST", "Alice; Bob")
+            .build();
+        write_merged(&path, &delta).await;
+
+        let (md5_after, frames_after) = flac_audio_section(&path);
+        assert_eq!(md5_before, md5_after);
+        assert_eq!(frames_before, frames_after);
+
+        let metadata = LoftyTagReader::new()
+            .read_from_file(&sample_track().id, &path)
+            .await
+            .unwrap();
+        assert_eq!(metadata.title, "After");
+        assert_eq!(metadata.artist, "Alice; Bob");
+    }
+
+    #[tokio::test]
+    async fn write_through_persists_deltas_into_the_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("edit.flac");
+        write_test_flac(&path);
+        let track = sample_track().id;
+        let persistence = Arc::new(KvTagPersistence::new(KvStore::new(Arc::new(
+            MemoryBackend::new(),
+        ))));
+        let overlay = TagOverlay::new(Arc::new(LoftyTagReader::new()), persistence.clone())
+            .with_write_through(true);
+
+        let delta = TagDelta::builder()
+            .set_text("TITLE", "Edited")
+            .set(
+                "ARTIST",
+                TagValue::List(vec![
+                    TagValue::Text("Alice".into()),
+                    TagValue::Text("Bob".into()),
+                ]),
+            )
+            .set("RATING", TagValue::Number(4))
+            .build();
+        let merged = overlay.apply(&track, &path, &delta).await.unwrap();
+        assert_eq!(merged.tags.get("RATING"), Some(&TagValue::Number(4)));
+        assert!(persistence.load_delta(&track).await.unwrap().is_some());
+
+        // A plain reader, without the overlay, sees the edit in the file itself.
+        let on_disk = LoftyTagReader::new()
+            .read_from_file(&track, &path)
+            .await
+            .unwrap();
+        assert_eq!(on_disk.title, "Edited");
+        assert_eq!(
+            on_disk.tags.get("ARTIST"),
+            Some(&TagValue::List(vec![
+                TagValue::Text("Alice".into()),
+                TagValue::Text("Bob".into()),
+            ]))
+        );
+        assert_eq!(on_disk.tags.get("RATING"), Some(&TagValue::Number(4)));
+        assert_eq!(
+            overlay.read(&track, &path).await.unwrap().tags,
+            on_disk.tags
+        );
+    }
+
+    #[tokio::test]
+    async fn write_through_drops_removed_keys_and_refuses_shared_files() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("edit.flac");
+        write_test_flac(&path);
+        let track = sample_track().id;
+        let persistence = Arc::new(KvTagPersistence::new(KvStore::new(Arc::new(
+            MemoryBackend::new(),
+        ))));
+        let overlay = TagOverlay::new(Arc::new(LoftyTagReader::new()), persistence.clone())
+            .with_write_through(true);
+
+        let tagged = TagDelta::builder()
+            .set_text("GENRE", "Jazz")
+            .set_text("MOOD", "Calm")
+            .build();
+        overlay.apply(&track, &path, &tagged).await.unwrap();
+        let untagged = TagDelta::builder().remove("MOOD").build();
+        overlay.apply(&track, &path, &untagged).await.unwrap();
+
+        let on_disk = LoftyTagReader::new()
+            .read_from_file(&track, &path)
+            .await
+            .unwrap();
+        assert_eq!(
+            on_disk.tags.get("GENRE"),
+            Some(&TagValue::Text("Jazz".into()))
+        );
+        assert_eq!(on_disk.tags.get("MOOD"), None);
+
+        let shared = TagOverlay::new(Arc::new(LoftyTagReader::new()), persistence.clone())
+            .with_write_through(true)
+            .with_shared_sources([path.clone()]);
+        let err = shared.apply(&track, &path, &tagged).await.unwrap_err();
+        assert!(matches!(err, MusFuseError::Unsupported(_)));
+    }
+
+    #[tokio::test]
+    async fn policy_separator_joins_reads_and_splits_edits() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("duet.flac");
+        write_test_flac(&path);
+        let policy = PolicyConfig {
+            multi_value_separator: " / ".into(),
+            ..PolicyConfig::default()
+        };
+        let track = sample_track().id;
+        let persistence = Arc::new(KvTagPersistence::new(KvStore::new(Arc::new(
+            MemoryBackend::new(),
+        ))));
+        let policy = PolicyConfig {
+            write_through_tags: true,
+            ..policy
+        };
+        let overlay = TagOverlay::for_policy(&policy, persistence);
+
+        let delta = TagDelta::builder()
+            .set_text("ARTIST", "Alice / Bob; Carol")
+            .build();
+        let merged = overlay.apply(&track, &path, &delta).await.unwrap();
+        let expected = Some(TagValue::List(vec![
+            TagValue::Text("Alice".into()),
+            TagValue::Text("Bob; Carol".into()),
+        ]));
+        assert_eq!(merged.tags.get("ARTIST"), expected.as_ref());
+
+        // The file holds two ARTIST values, joined back with the same separator on read.
+        let on_disk = LoftyTagReader::for_policy(&policy)
+            .read_from_file(&track, &path)
+            .await
+            .unwrap();
+        assert_eq!(on_disk.artist, "Alice / Bob; Carol");
+        assert_eq!(on_disk.tags.get("ARTIST"), expected.as_ref());
+    }
+
+    fn genre_track(genre: Option<TagValue>) -> TrackMetadata {
+        let mut metadata = sample_track();
+        if let Some(genre) = genre {
+            metadata.tags.insert("GENRE", genre);
+        }
+        metadata
+    }
+
+    fn append_genre(metadata: &mut TrackMetadata, genres: &[&str]) {
+        let delta = genres
+            .iter()
+            .fold(TagDelta::builder(), |builder, genre| {
+                builder.append("genre", TagValue::Text(genre.to_string()))
+            })
+            .build();
+        TagOverlay::<MockReader, KvTagPersistence<MemoryBackend>>::apply_delta(
+            metadata,
+            &delta,
+            DEFAULT_MULTI_VALUE_SEPARATOR,
+        );
+    }
+
+    fn texts(values: &[&str]) -> TagValue {
+        TagValue::List(
+            values
+                .iter()
+                .map(|value| TagValue::Text(value.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn append_extends_lists_promotes_scalars_and_creates_keys() {
+        let mut listed = genre_track(Some(texts(&["Jazz", "Blues"])));
+        append_genre(&mut listed, &["Soul", "Jazz"]);
+        assert_eq!(
+            listed.tags.get("GENRE"),
+            Some(&texts(&["Jazz", "Blues", "Soul"]))
+        );
+
+        let mut scalar = genre_track(Some(TagValue::Text("Jazz".into())));
+        append_genre(&mut scalar, &["Soul"]);
+        assert_eq!(scalar.tags.get("GENRE"), Some(&texts(&["Jazz", "Soul"])));
+
+        let mut fresh = genre_track(None);
+        append_genre(&mut fresh, &["Soul"]);
+        assert_eq!(
+            fresh.tags.get("genre"),
+            Some(&TagValue::Text("Soul".into()))
+        );
+        let mut two = genre_track(None);
+        append_genre(&mut two, &["Soul", "Funk"]);
+        assert_eq!(two.tags.get("genre"), Some(&texts(&["Soul", "Funk"])));
+
+        // A later `remove` drops any pending append for the key.
+        let delta = TagDelta::builder()
+            .append("GENRE", TagValue::Text("Soul".into()))
+            .remove("GENRE")
+            .build();
+        assert!(delta.append.is_empty());
+    }
+
+    #[tokio::test]
+    async fn appended_values_are_written_after_the_existing_ones() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("genres.flac");
+        write_test_flac(&path);
+        let mut comments = VorbisComments::new();
+        comments.push("GENRE".into(), "Jazz".into());
+        comments.save_to_path(&path).expect("save comments");
+
+        let delta = TagDelta::builder()
+            .append("GENRE", TagValue::Text("Jazz".into()))
+            .append("GENRE", TagValue::Text("Soul".into()))
+            .build();
+        write_merged(&path, &delta).await;
+
+        let metadata = LoftyTagReader::new()
+            .read_from_file(&sample_track().id, &path)
+            .await
+            .unwrap();
+        assert_eq!(metadata.tags.get("GENRE"), Some(&texts(&["Jazz", "Soul"])));
+    }
+
+    #[tokio::test]
+    async fn lofty_reader_exposes_synced_lyrics_as_lrc() {
+        use lofty::{Tag, TagExt, TagType};
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("song.flac");
+        write_test_flac(&path);
+
+        let mut tag = Tag::new(TagType::VorbisComments);
+        tag.set_title("Song".into());
+        tag.insert_text(
+            ItemKey::Lyrics,
+            "[00:00.50]first line\n[00:01.25]second line".into(),
+        );
+        tag.save_to_path(&path).expect("save tag");
+
+        let track_id = sample_track().id;
+        let metadata = LoftyTagReader::new()
+            .read_from_file(&track_id, &path)
+            .await
+            .unwrap();
+
+        assert_eq!(metadata.title, "Song");
+        let lyrics = metadata.lyrics.expect("lyrics");
+        assert!(lyrics.is_synced());
+        assert_eq!(
+            lyrics.to_lrc(),
+            "[00:00.50]first line\n[00:01.25]second line\n"
+        );
+        assert!(metadata.tags.get("LYRICS").is_none());
+    }
 }
@@ -0,0 +1,113 @@
+use std::fmt::Display;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+
+// Logs only when an operation breaches the threshold; a disabled threshold never logs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SlowOpThreshold(Option<Duration>);
+
+impl SlowOpThreshold {
+    pub fn from_ms(threshold_ms: Option<u64>) -> Self {
+        Self(threshold_ms.map(Duration::from_millis))
+    }
+
+    pub fn disabled() -> Self {
+        Self(None)
+    }
+
+    pub fn observe(&self, op: &'static str, context: impl Display, elapsed: Duration) -> bool {
+        match self.0 {
+            Some(threshold) if elapsed >= threshold => {
+                warn!(
+                    op,
+                    context = %context,
+                    elapsed_ms = elapsed.as_millis() as u64,
+                    threshold_ms = threshold.as_millis() as u64,
+                    "slow operation"
+                );
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn time<T>(&self, op: &'static str, context: impl Display, work: impl FnOnce() -> T) -> T {
+        if self.0.is_none() {
+            return work();
+        }
+        let started = Instant::now();
+        let value = work();
+        self.observe(op, context, started.elapsed());
+        value
+    }
+
+    pub async fn time_async<T>(
+        &self,
+        op: &'static str,
+        context: impl Display,
+        work: impl Future<Output = T>,
+    ) -> T {
+        if self.0.is_none() {
+            return work.await;
+        }
+        let started = Instant::now();
+        let value = work.await;
+        self.observe(op, context, started.elapsed());
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn slow_operations_emit_a_warning_with_their_duration() {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+
+        let threshold = SlowOpThreshold::from_ms(Some(10));
+        tracing::subscriber::with_default(subscriber, || {
+            threshold.time("kv_get", "metadata:album", || {
+                std::thread::sleep(Duration::from_millis(30))
+            });
+            threshold.time("kv_get", "metadata:fast", || ());
+        });
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("WARN"), "{output}");
+        assert!(output.contains("slow operation"), "{output}");
+        assert!(output.contains("op=\"kv_get\""), "{output}");
+        assert!(output.contains("context=metadata:album"), "{output}");
+        let elapsed: u64 = output
+            .split("elapsed_ms=")
+            .nth(1)
+            .and_then(|rest| rest.split_whitespace().next())
+            .and_then(|value| value.parse().ok())
+            .expect("elapsed_ms field");
+        assert!(elapsed >= 30, "{output}");
+        assert!(!output.contains("metadata:fast"), "{output}");
+    }
+}
@@ -1,23 +1,57 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io;
 use std::path::PathBuf;
 
 use std::path::Path;
 
 use serde::{Deserialize, Serialize};
+use tracing::warn;
 
-use crate::cue::CueSheet;
-use crate::metadata::{AlbumId, TagMap, TrackId, TrackMetadata};
+use crate::config::{CueTrackOrder, MissingFilePolicy, SourcePreference};
+use crate::cue::{CueFile, CueSheet, CueTrack, frames_to_samples};
+use crate::error::{MusFuseError, Result};
+use crate::metadata::{AlbumId, TagMap, TagValue, TitleNormalization, TrackId, TrackMetadata};
+use crate::query::TagQuery;
+
+const DURATION_TOLERANCE_MS: u64 = 2_000;
+pub const UNAVAILABLE_MARKER: &str = "[unavailable]";
+pub const HIDDEN_TRACK_TITLE: &str = "Hidden Track";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CueMapOptions {
+    // Probe each FILE for its length so the last track gets a duration.
+    pub probe_durations: bool,
+    // Expose audio before track 1's INDEX 01 (hidden track one audio) as track 0.
+    pub hidden_track_one: bool,
+    pub track_order: CueTrackOrder,
+    pub titles: TitleNormalization,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct SourceTrack {
     pub id: TrackId,
     pub path: PathBuf,
     pub cue_path: Option<PathBuf>,
+    // Where the track lies in `path`, in CD frames (1/75 s) as cue sheets address audio.
+    // Both zero means the whole file; a zero length runs to the end of the file.
     pub offset_frames: u64,
     pub length_frames: u64,
     pub sample_rate: u32,
     pub channels: u16,
 }
 
+impl SourceTrack {
+    // The track's first and end sample frame in audio decoded at `sample_rate`; no end
+    // when it runs to the end of the file. The end is converted from the CD-frame end so
+    // back-to-back tracks still meet exactly.
+    pub fn sample_span(&self, sample_rate: u32) -> (u64, Option<u64>) {
+        let start = frames_to_samples(self.offset_frames, sample_rate);
+        let end = (self.length_frames > 0)
+            .then(|| frames_to_samples(self.offset_frames + self.length_frames, sample_rate));
+        (start, end)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct TrackCollection {
     pub album: AlbumId,
@@ -40,25 +74,138 @@ impl TrackIndex {
     pub fn by_id(&self, id: &TrackId) -> Option<&TrackIndexEntry> {
         self.entries.iter().find(|entry| &entry.id == id)
     }
+
+    pub fn query(&self, predicate: &TagQuery) -> Vec<&TrackIndexEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| predicate.matches(entry))
+            .collect()
+    }
 }
 
 pub struct TrackMapper;
 
 impl TrackMapper {
-    pub fn from_cue(sheet: &CueSheet, album_id: &AlbumId, cue_path: Option<&Path>) -> TrackIndex {
+    pub fn from_cue(
+        sheet: &CueSheet,
+        album_id: &AlbumId,
+        cue_path: Option<&Path>,
+    ) -> Result<TrackIndex> {
+        Self::from_cue_with_options(sheet, album_id, cue_path, CueMapOptions::default())
+    }
+
+    // The cue sheet only marks where tracks start, so the last track of each file needs the
+    // file's own length; probe it from the container header where possible.
+    pub fn from_cue_with_durations(
+        sheet: &CueSheet,
+        album_id: &AlbumId,
+        cue_path: Option<&Path>,
+    ) -> Result<TrackIndex> {
+        let options = CueMapOptions {
+            probe_durations: true,
+            ..CueMapOptions::default()
+        };
+        Self::from_cue_with_options(sheet, album_id, cue_path, options)
+    }
+
+    // Like `from_cue_with_durations`, for callers that already know each FILE's length in
+    // cue frames; files missing from the map fall back to an open-ended last track.
+    pub fn from_cue_with_file_frames(
+        sheet: &CueSheet,
+        album_id: &AlbumId,
+        cue_path: Option<&Path>,
+        file_frames: &HashMap<PathBuf, u64>,
+    ) -> Result<TrackIndex> {
+        Self::map_cue(
+            sheet,
+            album_id,
+            cue_path,
+            CueMapOptions::default(),
+            Some(file_frames),
+        )
+    }
+
+    pub fn from_cue_with_options(
+        sheet: &CueSheet,
+        album_id: &AlbumId,
+        cue_path: Option<&Path>,
+        options: CueMapOptions,
+    ) -> Result<TrackIndex> {
+        Self::map_cue(sheet, album_id, cue_path, options, None)
+    }
+
+    fn map_cue(
+        sheet: &CueSheet,
+        album_id: &AlbumId,
+        cue_path: Option<&Path>,
+        options: CueMapOptions,
+        file_frames: Option<&HashMap<PathBuf, u64>>,
+    ) -> Result<TrackIndex> {
+        let file_length = |path: &Path| {
+            if let Some(&frames) = file_frames.and_then(|known| known.get(path)) {
+                return Some(frames);
+            }
+            if !options.probe_durations {
+                return None;
+            }
+            match crate::probe::total_frames(path) {
+                Ok(length) => Some(length.cue_frames()),
+                Err(err) => {
+                    warn!(path = %path.display(), error = %err, "could not determine file length");
+                    None
+                }
+            }
+        };
+
+        // REM keys are already tag names (GENRE, DATE, DISCID, COMMENT, REPLAYGAIN_*).
+        let mut album_tags = TagMap::default();
+        for (key, value) in &sheet.rem {
+            album_tags.insert(key.clone(), TagValue::Text(value.clone()));
+        }
+        if let Some(catalog) = &sheet.catalog {
+            album_tags.insert("CATALOG", TagValue::Text(catalog.clone()));
+        }
+        if let Some(title) = &sheet.album_title {
+            album_tags.insert("ALBUM", TagValue::Text(options.titles.apply(title)));
+        }
+
         let mut entries = Vec::new();
-        for file in &sheet.files {
-            let mut iter = file.tracks.iter().peekable();
+        for (position, file) in sheet.files.iter().enumerate() {
+            let tracks = Self::ordered_tracks(file, options.track_order)?;
+            let file_end = tracks.last().and_then(|_| file_length(&file.path));
+            let hidden = tracks
+                .first()
+                .filter(|first| {
+                    options.hidden_track_one && position == 0 && first.index_01_frames() > 0
+                })
+                .map(|first| CueTrack {
+                    number: 0,
+                    title: Some(HIDDEN_TRACK_TITLE.into()),
+                    performer: first.performer.clone(),
+                    indices: BTreeMap::from([(1, 0)]),
+                    rem: BTreeMap::new(),
+                    isrc: None,
+                    pregap_frames: None,
+                    postgap_frames: None,
+                });
+            let mut iter = hidden.iter().chain(tracks.iter()).peekable();
             while let Some(track) = iter.next() {
+                // The next track's pregap (INDEX 00 or PREGAP up to its INDEX 01) and this
+                // track's POSTGAP are not part of this track. A pregap starting at or
+                // before this track's start, as when the pregap itself was exposed as
+                // track 0, leaves INDEX 01 as the boundary.
                 let next_start = iter
                     .peek()
-                    .map(|next| next.index_01_frames)
-                    .unwrap_or(track.index_01_frames);
-                let length_frames = if next_start > track.index_01_frames {
-                    next_start - track.index_01_frames
-                } else {
-                    0
-                };
+                    .map(|next| {
+                        next.pregap_start_frames()
+                            .filter(|&pregap| pregap > track.index_01_frames())
+                            .unwrap_or(next.index_01_frames())
+                    })
+                    .or(file_end)
+                    .unwrap_or(track.index_01_frames());
+                let length_frames = next_start
+                    .saturating_sub(track.index_01_frames())
+                    .saturating_sub(track.postgap_frames.unwrap_or(0));
 
                 let track_id = TrackId {
                     album: album_id.clone(),
@@ -66,11 +213,20 @@ impl TrackMapper {
                     index: track.number,
                 };
 
+                let mut tags = album_tags.clone();
+                for (key, value) in &track.rem {
+                    tags.insert(key.clone(), TagValue::Text(value.clone()));
+                }
+                if let Some(isrc) = &track.isrc {
+                    tags.insert("ISRC", TagValue::Text(isrc.clone()));
+                }
                 let metadata = TrackMetadata {
                     id: track_id.clone(),
                     title: track
                         .title
-                        .clone()
+                        .as_deref()
+                        .map(|title| options.titles.apply(title))
+                        .filter(|title| !title.is_empty())
                         .unwrap_or_else(|| format!("Track {:02}", track.number)),
                     artist: track
                         .performer
@@ -79,15 +235,16 @@ impl TrackMapper {
                         .unwrap_or_else(|| "Unknown Artist".into()),
                     album_artist: sheet.album_performer.clone(),
                     duration_ms: crate::cue::frames_to_ms(length_frames),
-                    tags: TagMap::default(),
+                    tags,
                     artwork: None,
+                    lyrics: None,
                 };
 
                 let source = SourceTrack {
                     id: track_id.clone(),
                     path: file.path.clone(),
                     cue_path: cue_path.map(|p| p.to_path_buf()),
-                    offset_frames: track.index_01_frames,
+                    offset_frames: track.index_01_frames(),
                     length_frames,
                     sample_rate: 44_100,
                     channels: 2,
@@ -100,7 +257,109 @@ impl TrackMapper {
                 });
             }
         }
-        TrackIndex { entries }
+        Ok(TrackIndex { entries })
+    }
+
+    fn ordered_tracks(file: &CueFile, order: CueTrackOrder) -> Result<Vec<CueTrack>> {
+        let mut tracks = file.tracks.clone();
+        let in_order = tracks
+            .windows(2)
+            .all(|pair| pair[0].index_01_frames() < pair[1].index_01_frames());
+        if in_order {
+            return Ok(tracks);
+        }
+        match order {
+            CueTrackOrder::Reject => {
+                return Err(MusFuseError::Mount(format!(
+                    "cue tracks for {} are not in INDEX order",
+                    file.path.display()
+                )));
+            }
+            CueTrackOrder::Reorder => {
+                warn!(file = %file.path.display(), "cue tracks out of INDEX order, sorting them");
+                tracks.sort_by_key(CueTrack::index_01_frames);
+            }
+            CueTrackOrder::Clamp => {
+                warn!(file = %file.path.display(), "cue tracks out of INDEX order, some will be empty");
+            }
+        }
+        Ok(tracks)
+    }
+
+    // A folder may hold both per-track files and a whole-disc image with a cue describing
+    // the same tracks; keep one source per track id according to the preference.
+    pub fn merge_sources(
+        entries: impl IntoIterator<Item = TrackIndexEntry>,
+        preference: SourcePreference,
+    ) -> TrackIndex {
+        let mut merged: BTreeMap<TrackId, TrackIndexEntry> = BTreeMap::new();
+        for entry in entries {
+            match merged.get_mut(&entry.id) {
+                None => {
+                    merged.insert(entry.id.clone(), entry);
+                }
+                Some(existing) => {
+                    let (a, b) = (existing.metadata.duration_ms, entry.metadata.duration_ms);
+                    if a > 0 && b > 0 && a.abs_diff(b) > DURATION_TOLERANCE_MS {
+                        warn!(track = %entry.id, "duplicate sources disagree on duration");
+                    }
+                    if Self::prefers(&entry, existing, preference) {
+                        *existing = entry;
+                    }
+                }
+            }
+        }
+        TrackIndex {
+            entries: merged.into_values().collect(),
+        }
+    }
+
+    // A cue may outlive the audio file it references; check each backing file once.
+    pub fn apply_missing_files(index: TrackIndex, policy: MissingFilePolicy) -> Result<TrackIndex> {
+        let mut exists: HashMap<PathBuf, bool> = HashMap::new();
+        let mut entries = Vec::with_capacity(index.entries.len());
+        for mut entry in index.entries {
+            let present = *exists
+                .entry(entry.source.path.clone())
+                .or_insert_with(|| entry.source.path.is_file());
+            if present {
+                entries.push(entry);
+                continue;
+            }
+            match policy {
+                MissingFilePolicy::Hide => {
+                    warn!(track = %entry.id, path = %entry.source.path.display(), "hiding track with missing backing file");
+                }
+                MissingFilePolicy::ShowStub => {
+                    entry.metadata.title = format!("{} {UNAVAILABLE_MARKER}", entry.metadata.title);
+                    entries.push(entry);
+                }
+                MissingFilePolicy::Error => {
+                    return Err(MusFuseError::Io(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!(
+                            "backing file for track {} is missing: {}",
+                            entry.id,
+                            entry.source.path.display()
+                        ),
+                    )));
+                }
+            }
+        }
+        Ok(TrackIndex { entries })
+    }
+
+    fn prefers(
+        candidate: &TrackIndexEntry,
+        current: &TrackIndexEntry,
+        preference: SourcePreference,
+    ) -> bool {
+        let candidate_split = candidate.source.cue_path.is_some();
+        let current_split = current.source.cue_path.is_some();
+        match preference {
+            SourcePreference::PreferCueSplit => candidate_split && !current_split,
+            SourcePreference::PreferPerTrack => !candidate_split && current_split,
+        }
     }
 }
 
@@ -108,12 +367,14 @@ impl TrackMapper {
 mod tests {
     use super::*;
     use crate::cue::{CueFile, CueSheet, CueTrack};
+    use crate::fixtures;
 
-    #[test]
-    fn map_cue_to_track_index() {
-        let sheet = CueSheet {
+    fn sample_sheet() -> CueSheet {
+        CueSheet {
             album_title: Some("Album".into()),
             album_performer: Some("Artist".into()),
+            rem: BTreeMap::from([("GENRE".to_string(), "Ambient".to_string())]),
+            catalog: Some("0724384260927".into()),
             files: vec![CueFile {
                 path: Path::new("/music/disc.flac").to_path_buf(),
                 tracks: vec![
@@ -121,20 +382,33 @@ mod tests {
                         number: 1,
                         title: Some("Intro".into()),
                         performer: Some("Artist".into()),
-                        index_01_frames: 0,
+                        indices: BTreeMap::from([(1, 0)]),
+                        rem: BTreeMap::from([("DATE".to_string(), "1999".to_string())]),
+                        isrc: Some("GBAYE7300001".into()),
+                        pregap_frames: None,
+                        postgap_frames: None,
                     },
                     CueTrack {
                         number: 2,
                         title: Some("Song".into()),
                         performer: None,
-                        index_01_frames: 75 * 120,
+                        indices: BTreeMap::from([(1, 75 * 120)]),
+                        rem: BTreeMap::new(),
+                        isrc: None,
+                        pregap_frames: None,
+                        postgap_frames: None,
                     },
                 ],
             }],
-        };
+        }
+    }
 
+    #[test]
+    fn map_cue_to_track_index() {
+        let sheet = sample_sheet();
         let album = AlbumId("album".into());
-        let index = TrackMapper::from_cue(&sheet, &album, Some(Path::new("/music/disc.cue")));
+        let index =
+            TrackMapper::from_cue(&sheet, &album, Some(Path::new("/music/disc.cue"))).unwrap();
         assert_eq!(index.entries.len(), 2);
         let second = &index.entries[1];
         assert_eq!(second.metadata.title, "Song");
@@ -144,5 +418,205 @@ mod tests {
             second.source.cue_path.as_deref(),
             Some(Path::new("/music/disc.cue"))
         );
+        assert_eq!(
+            second.metadata.tags.get("GENRE"),
+            Some(&TagValue::Text("Ambient".into()))
+        );
+        assert_eq!(second.metadata.tags.get("DATE"), None);
+        assert_eq!(second.metadata.tags.get("ISRC"), None);
+        assert_eq!(
+            second.metadata.tags.get("CATALOG"),
+            Some(&TagValue::Text("0724384260927".into()))
+        );
+        let first = &index.entries[0].metadata.tags;
+        assert_eq!(first.get("DATE"), Some(&TagValue::Text("1999".into())));
+        assert_eq!(
+            first.get("ISRC"),
+            Some(&TagValue::Text("GBAYE7300001".into()))
+        );
+    }
+
+    #[test]
+    fn out_of_order_cue_tracks_follow_the_order_policy() {
+        let cue = "FILE \"disc.flac\" WAVE\n  TRACK 01 AUDIO\n    INDEX 01 03:00:00\n  TRACK 02 AUDIO\n    INDEX 01 00:00:00\n  TRACK 03 AUDIO\n    INDEX 01 05:00:00\n";
+        let sheet = crate::cue::CueParser
+            .parse_str(cue, Path::new("/music"))
+            .unwrap();
+        let album = AlbumId("album".into());
+        let with_order = |track_order| CueMapOptions {
+            track_order,
+            ..CueMapOptions::default()
+        };
+
+        let reordered = TrackMapper::from_cue(&sheet, &album, None).unwrap();
+        let layout: Vec<_> = reordered
+            .entries
+            .iter()
+            .map(|entry| (entry.id.index, entry.source.offset_frames))
+            .collect();
+        assert_eq!(layout, vec![(2, 0), (1, 180 * 75), (3, 300 * 75)]);
+        assert!(
+            reordered.entries[..2]
+                .iter()
+                .all(|entry| entry.source.length_frames > 0)
+        );
+
+        let clamped = TrackMapper::from_cue_with_options(
+            &sheet,
+            &album,
+            None,
+            with_order(CueTrackOrder::Clamp),
+        )
+        .unwrap();
+        assert_eq!(clamped.entries[0].id.index, 1);
+        assert_eq!(clamped.entries[0].source.length_frames, 0);
+
+        let rejected = TrackMapper::from_cue_with_options(
+            &sheet,
+            &album,
+            None,
+            with_order(CueTrackOrder::Reject),
+        );
+        assert!(matches!(rejected, Err(MusFuseError::Mount(_))));
+    }
+
+    #[test]
+    fn leading_audio_becomes_track_zero_when_enabled() {
+        let cue = "FILE \"disc.flac\" WAVE\n  TRACK 01 AUDIO\n    TITLE \"Opener\"\n    INDEX 00 00:00:00\n    INDEX 01 01:30:00\n  TRACK 02 AUDIO\n    INDEX 01 05:00:00\n";
+        let sheet = crate::cue::CueParser
+            .parse_str(cue, Path::new("/music"))
+            .unwrap();
+        assert_eq!(sheet.files[0].tracks[0].index_00_frames(), Some(0));
+        let album = AlbumId("album".into());
+
+        let plain = TrackMapper::from_cue(&sheet, &album, None).unwrap();
+        assert_eq!(plain.entries.len(), 2);
+        assert_eq!(plain.entries[0].id.index, 1);
+
+        let options = CueMapOptions {
+            hidden_track_one: true,
+            ..CueMapOptions::default()
+        };
+        let index = TrackMapper::from_cue_with_options(&sheet, &album, None, options).unwrap();
+        assert_eq!(index.entries.len(), 3);
+        let hidden = &index.entries[0];
+        assert_eq!(hidden.id.index, 0);
+        assert_eq!(hidden.metadata.title, HIDDEN_TRACK_TITLE);
+        assert_eq!(hidden.source.offset_frames, 0);
+        assert_eq!(hidden.source.length_frames, 90 * 75);
+        assert_eq!(hidden.metadata.duration_ms, 90_000);
+        assert_eq!(index.entries[1].source.offset_frames, 90 * 75);
+    }
+
+    #[test]
+    fn pregap_is_left_out_of_the_previous_track() {
+        let cue = "FILE \"disc.flac\" WAVE\n  TRACK 01 AUDIO\n    INDEX 01 00:00:00\n  TRACK 02 AUDIO\n    INDEX 00 02:58:00\n    INDEX 01 03:00:00\n  TRACK 03 AUDIO\n    INDEX 01 06:00:00\n";
+        let sheet = crate::cue::CueParser
+            .parse_str(cue, Path::new("/music"))
+            .unwrap();
+        let index = TrackMapper::from_cue(&sheet, &AlbumId("album".into()), None).unwrap();
+
+        let layout: Vec<_> = index
+            .entries
+            .iter()
+            .map(|entry| (entry.source.offset_frames, entry.source.length_frames))
+            .collect();
+        assert_eq!(
+            layout,
+            vec![(0, 178 * 75), (180 * 75, 180 * 75), (360 * 75, 0)]
+        );
+        assert_eq!(index.entries[0].metadata.duration_ms, 178_000);
+    }
+
+    #[test]
+    fn known_file_length_closes_the_last_track() {
+        let sheet = sample_sheet();
+        let album = AlbumId("album".into());
+        let open_ended = TrackMapper::from_cue(&sheet, &album, None).unwrap();
+        assert_eq!(open_ended.entries[1].metadata.duration_ms, 0);
+
+        let frames = HashMap::from([(PathBuf::from("/music/disc.flac"), 75 * 300)]);
+        let index = TrackMapper::from_cue_with_file_frames(&sheet, &album, None, &frames).unwrap();
+        let last = &index.entries[1];
+        assert_eq!(last.source.length_frames, 75 * 180);
+        assert_eq!(last.metadata.duration_ms, 180_000);
+    }
+
+    #[test]
+    fn pregap_and_postgap_directives_shorten_tracks() {
+        let cue = "FILE \"disc.flac\" WAVE\n  TRACK 01 AUDIO\n    INDEX 01 00:00:00\n  TRACK 02 AUDIO\n    PREGAP 00:02:00\n    INDEX 01 03:00:00\n    POSTGAP 00:01:00\n  TRACK 03 AUDIO\n    PREGAP 00:05:00\n    INDEX 00 05:59:00\n    INDEX 01 06:00:00\n  TRACK 04 AUDIO\n    INDEX 01 09:00:00\n";
+        let sheet = crate::cue::CueParser
+            .parse_str(cue, Path::new("/music"))
+            .unwrap();
+        let index = TrackMapper::from_cue(&sheet, &AlbumId("album".into()), None).unwrap();
+
+        let lengths: Vec<_> = index
+            .entries
+            .iter()
+            .map(|entry| entry.source.length_frames)
+            .collect();
+        // Track 3's INDEX 00 is used instead of its five-second PREGAP.
+        assert_eq!(lengths, vec![178 * 75, 178 * 75, 180 * 75, 0]);
+    }
+
+    #[test]
+    fn tracks_with_missing_backing_file_are_hidden_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let cue = "PERFORMER \"Artist\"\nFILE \"deleted.flac\" WAVE\n  TRACK 01 AUDIO\n    INDEX 01 00:00:00\n  TRACK 02 AUDIO\n    INDEX 01 02:00:00\n";
+        let sheet = crate::cue::CueParser.parse_str(cue, dir.path()).unwrap();
+        let album = AlbumId("album".into());
+        let index =
+            TrackMapper::from_cue(&sheet, &album, Some(&dir.path().join("disc.cue"))).unwrap();
+        assert_eq!(index.entries.len(), 2);
+
+        let visible =
+            TrackMapper::apply_missing_files(index.clone(), MissingFilePolicy::default()).unwrap();
+        assert!(visible.entries.is_empty());
+
+        let stubs =
+            TrackMapper::apply_missing_files(index.clone(), MissingFilePolicy::ShowStub).unwrap();
+        assert!(
+            stubs
+                .entries
+                .iter()
+                .all(|entry| entry.metadata.title.ends_with(UNAVAILABLE_MARKER))
+        );
+
+        let err = TrackMapper::apply_missing_files(index, MissingFilePolicy::Error)
+            .expect_err("scan should fail");
+        assert!(matches!(err, MusFuseError::Io(ref io) if io.kind() == io::ErrorKind::NotFound));
+    }
+
+    #[test]
+    fn merge_sources_keeps_one_entry_per_track() {
+        let album = AlbumId("album".into());
+        let split =
+            TrackMapper::from_cue(&sample_sheet(), &album, Some(Path::new("/music/disc.cue")))
+                .unwrap();
+        let per_track = vec![
+            fixtures::entry(&album.0, 1, "/music/01.flac"),
+            fixtures::entry(&album.0, 2, "/music/02.flac"),
+        ];
+        let all: Vec<_> = split.entries.iter().cloned().chain(per_track).collect();
+
+        let preferred = TrackMapper::merge_sources(all.clone(), SourcePreference::PreferPerTrack);
+        assert_eq!(preferred.entries.len(), 2);
+        assert_eq!(
+            preferred.entries[0].source.path,
+            Path::new("/music/01.flac")
+        );
+        assert_eq!(
+            preferred.entries[1].source.path,
+            Path::new("/music/02.flac")
+        );
+
+        let preferred = TrackMapper::merge_sources(all, SourcePreference::PreferCueSplit);
+        assert_eq!(preferred.entries.len(), 2);
+        assert!(
+            preferred
+                .entries
+                .iter()
+                .all(|entry| entry.source.path == Path::new("/music/disc.flac"))
+        );
     }
 }
@@ -0,0 +1,36 @@
+use libc::c_int;
+
+use musfuse_core::{ErrorClass, MusFuseError};
+
+/// Translate a platform-neutral error class into the errno reported to the kernel
+pub fn errno_for(class: ErrorClass) -> c_int {
+    match class {
+        ErrorClass::NotFound => libc::ENOENT,
+        ErrorClass::PermissionDenied => libc::EACCES,
+        ErrorClass::NotSupported => libc::ENOTSUP,
+        ErrorClass::IoError => libc::EIO,
+        ErrorClass::Corrupt => libc::EBADMSG,
+        ErrorClass::Transient => libc::EAGAIN,
+    }
+}
+
+/// Convert a core error into the errno returned from filesystem callbacks
+pub fn to_errno(err: &MusFuseError) -> c_int {
+    errno_for(err.status_hint())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_errors_map_through_their_class() {
+        let missing = MusFuseError::Io(std::io::Error::from(std::io::ErrorKind::NotFound));
+        assert_eq!(to_errno(&missing), libc::ENOENT);
+        assert_eq!(
+            to_errno(&MusFuseError::Media("bad frame".into())),
+            libc::EBADMSG
+        );
+        assert_eq!(errno_for(ErrorClass::Transient), libc::EAGAIN);
+    }
+}
@@ -0,0 +1,449 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use fuser::{
+    FileAttr, FileType, Filesystem, ReplyAttr, ReplyData, ReplyDirectory, ReplyEmpty, ReplyEntry,
+    ReplyOpen, Request,
+};
+use libc::c_int;
+use parking_lot::Mutex;
+use tokio::runtime::Handle;
+use tracing::{debug, warn};
+
+use musfuse_core::ErrorClass;
+use musfuse_core::filesystem::{FileRouter, VirtualEntry};
+use musfuse_core::mount::{OperationGuard, OperationTracker};
+
+use super::errno::{errno_for, to_errno};
+
+const ROOT_INO: u64 = fuser::FUSE_ROOT_ID;
+const ATTR_TTL: Duration = Duration::from_secs(1);
+const BLOCK_SIZE: u32 = 4096;
+
+/// Virtual paths handed out as inode numbers; an inode stays valid for the whole mount
+#[derive(Default)]
+struct Inodes {
+    paths: Vec<String>,
+    by_path: HashMap<String, u64>,
+}
+
+impl Inodes {
+    fn new() -> Self {
+        let mut inodes = Self::default();
+        let root = inodes.intern("/");
+        debug_assert_eq!(root, ROOT_INO);
+        inodes
+    }
+
+    fn intern(&mut self, path: &str) -> u64 {
+        if let Some(ino) = self.by_path.get(path) {
+            return *ino;
+        }
+        self.paths.push(path.to_string());
+        let ino = self.paths.len() as u64;
+        self.by_path.insert(path.to_string(), ino);
+        ino
+    }
+
+    fn path(&self, ino: u64) -> Option<String> {
+        let index = usize::try_from(ino.checked_sub(1)?).ok()?;
+        self.paths.get(index).cloned()
+    }
+}
+
+/// Read-only FUSE view of the virtual tree a `FileRouter` serves
+///
+/// FUSE callbacks run on the session thread and block on `runtime` for the async router.
+pub struct MusFuseFs {
+    router: Arc<FileRouter>,
+    runtime: Handle,
+    operations: Arc<OperationTracker>,
+    inodes: Mutex<Inodes>,
+    uid: u32,
+    gid: u32,
+    mounted_at: SystemTime,
+}
+
+impl MusFuseFs {
+    pub fn new(router: Arc<FileRouter>, runtime: Handle) -> Self {
+        // SAFETY: getuid and getgid cannot fail and touch no memory.
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        Self {
+            router,
+            runtime,
+            operations: Arc::new(OperationTracker::new()),
+            inodes: Mutex::new(Inodes::new()),
+            uid,
+            gid,
+            mounted_at: SystemTime::now(),
+        }
+    }
+
+    /// Count opens and reads against the mount's `operations`, so unmounting drains them
+    pub fn with_operations(mut self, operations: Arc<OperationTracker>) -> Self {
+        self.operations = operations;
+        self
+    }
+
+    /// Start an operation, or refuse it once the mount is shutting down
+    fn begin(&self) -> Result<OperationGuard, c_int> {
+        self.operations
+            .begin()
+            .ok_or(errno_for(ErrorClass::Transient))
+    }
+
+    fn path_of(&self, ino: u64) -> Result<String, c_int> {
+        self.inodes.lock().path(ino).ok_or(libc::ENOENT)
+    }
+
+    fn resolve(&self, path: &str) -> Result<VirtualEntry, c_int> {
+        self.router.resolve(path).ok_or(libc::ENOENT)
+    }
+
+    /// Resolve `name` under the directory `parent`, handing out an inode for it
+    pub fn lookup_node(&self, parent: u64, name: &str) -> Result<FileAttr, c_int> {
+        let path = child_path(&self.path_of(parent)?, name);
+        let entry = self.resolve(&path)?;
+        let ino = self.inodes.lock().intern(&path);
+        self.attributes(ino, &entry)
+    }
+
+    pub fn node_attributes(&self, ino: u64) -> Result<FileAttr, c_int> {
+        let entry = self.resolve(&self.path_of(ino)?)?;
+        self.attributes(ino, &entry)
+    }
+
+    /// Directory listing including `.` and `..`, in the order the router returns it
+    pub fn read_directory(&self, ino: u64) -> Result<Vec<(u64, FileType, String)>, c_int> {
+        let path = self.path_of(ino)?;
+        let entries = self.router.list_dir(&path).ok_or(libc::ENOTDIR)?;
+        let mut inodes = self.inodes.lock();
+        let parent = inodes.intern(&parent_path(&path));
+        let mut listing = vec![
+            (ino, FileType::Directory, ".".to_string()),
+            (parent, FileType::Directory, "..".to_string()),
+        ];
+        for entry in entries {
+            let name = self.router.virtual_name(&entry);
+            let child = inodes.intern(&child_path(&path, &name));
+            listing.push((child, file_type(&entry), name));
+        }
+        Ok(listing)
+    }
+
+    /// Up to `size` bytes of the file at `ino`, starting at `offset`
+    pub fn read_node(&self, ino: u64, offset: u64, size: usize) -> Result<Vec<u8>, c_int> {
+        let _operation = self.begin()?;
+        let entry = self.resolve(&self.path_of(ino)?)?;
+        if let VirtualEntry::TrackFile(id, format) = &entry {
+            return self
+                .runtime
+                .block_on(self.router.read_track_range(id, *format, offset, size))
+                .map_err(|err| to_errno(&err));
+        }
+        if let VirtualEntry::AlbumStream(album) = &entry {
+            return self
+                .runtime
+                .block_on(self.router.read_album_range(album, offset, size))
+                .map_err(|err| to_errno(&err));
+        }
+        if let VirtualEntry::OriginalFile(id) = &entry {
+            return self
+                .runtime
+                .block_on(self.router.read_original_range(id, offset, size))
+                .map_err(|err| to_errno(&err));
+        }
+        let data = self.whole_file(&entry)?;
+        let start = usize::try_from(offset)
+            .unwrap_or(usize::MAX)
+            .min(data.len());
+        let end = start.saturating_add(size).min(data.len());
+        Ok(data[start..end].to_vec())
+    }
+
+    /// Covers, lyrics, originals and album cue sheets are small enough, or cheap enough, to
+    /// load whole
+    fn whole_file(&self, entry: &VirtualEntry) -> Result<Vec<u8>, c_int> {
+        let loaded = match entry {
+            VirtualEntry::CoverImage(id) => self.runtime.block_on(self.router.read_cover(id)),
+            VirtualEntry::Lyrics(id) => self
+                .runtime
+                .block_on(self.router.read_lyrics(id))
+                .map(|lyrics| lyrics.map(String::into_bytes)),
+            VirtualEntry::AlbumCue(album) => {
+                Ok(self.router.album_cue(album).map(String::into_bytes))
+            }
+            VirtualEntry::Directory(_) => return Err(libc::EISDIR),
+            VirtualEntry::TrackFile(..)
+            | VirtualEntry::OriginalFile(_)
+            | VirtualEntry::AlbumStream(_) => return Err(libc::EINVAL),
+        };
+        loaded.map_err(|err| to_errno(&err))?.ok_or(libc::ENOENT)
+    }
+
+    /// Converted tracks and album streams report an estimate until they are first encoded,
+    /// so a lookup or `stat` never transcodes; they are opened with direct I/O so reads are
+    /// not cut off at the estimate
+    fn size_of(&self, entry: &VirtualEntry) -> Result<u64, c_int> {
+        match entry {
+            VirtualEntry::Directory(_) => Ok(0),
+            VirtualEntry::TrackFile(id, format) => self
+                .runtime
+                .block_on(self.router.listed_length(id, *format))
+                .map_err(|err| to_errno(&err)),
+            VirtualEntry::AlbumStream(album) => self
+                .runtime
+                .block_on(self.router.listed_album_stream_length(album))
+                .map_err(|err| to_errno(&err)),
+            VirtualEntry::OriginalFile(id) => self
+                .runtime
+                .block_on(self.router.original_length(id))
+                .map_err(|err| to_errno(&err)),
+            _ => self.whole_file(entry).map(|data| data.len() as u64),
+        }
+    }
+
+    fn attributes(&self, ino: u64, entry: &VirtualEntry) -> Result<FileAttr, c_int> {
+        let size = self.size_of(entry)?;
+        let kind = file_type(entry);
+        let (perm, nlink) = match kind {
+            FileType::Directory => (0o555, 2),
+            _ => (0o444, 1),
+        };
+        Ok(FileAttr {
+            ino,
+            size,
+            blocks: size.div_ceil(512),
+            atime: self.mounted_at,
+            mtime: self.mounted_at,
+            ctime: self.mounted_at,
+            crtime: self.mounted_at,
+            kind,
+            perm,
+            nlink,
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            blksize: BLOCK_SIZE,
+            flags: 0,
+        })
+    }
+}
+
+fn file_type(entry: &VirtualEntry) -> FileType {
+    match entry {
+        VirtualEntry::Directory(_) => FileType::Directory,
+        _ => FileType::RegularFile,
+    }
+}
+
+fn child_path(parent: &str, name: &str) -> String {
+    match parent.trim_end_matches('/') {
+        "" => format!("/{name}"),
+        parent => format!("{parent}/{name}"),
+    }
+}
+
+fn parent_path(path: &str) -> String {
+    match path.trim_end_matches('/').rsplit_once('/') {
+        Some(("", _)) | None => "/".to_string(),
+        Some((parent, _)) => parent.to_string(),
+    }
+}
+
+impl Filesystem for MusFuseFs {
+    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let Some(name) = name.to_str() else {
+            return reply.error(libc::ENOENT);
+        };
+        match self.lookup_node(parent, name) {
+            Ok(attr) => reply.entry(&ATTR_TTL, &attr, 0),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request<'_>, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+        match self.node_attributes(ino) {
+            Ok(attr) => reply.attr(&ATTR_TTL, &attr),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn open(&mut self, _req: &Request<'_>, ino: u64, flags: i32, reply: ReplyOpen) {
+        if flags & libc::O_ACCMODE != libc::O_RDONLY {
+            return reply.error(libc::EROFS);
+        }
+        let _operation = match self.begin() {
+            Ok(operation) => operation,
+            Err(errno) => return reply.error(errno),
+        };
+        match self.path_of(ino).and_then(|path| self.resolve(&path)) {
+            Ok(VirtualEntry::Directory(_)) => reply.error(libc::EISDIR),
+            Ok(entry) => {
+                if let VirtualEntry::TrackFile(..) | VirtualEntry::OriginalFile(_) = entry {
+                    self.router.stats().record_track_open();
+                }
+                self.router.stats().handle_opened();
+                let flags = match entry {
+                    VirtualEntry::TrackFile(..) | VirtualEntry::AlbumStream(_) => {
+                        fuser::consts::FOPEN_DIRECT_IO
+                    }
+                    _ => 0,
+                };
+                reply.opened(0, flags)
+            }
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn opendir(&mut self, _req: &Request<'_>, ino: u64, _flags: i32, reply: ReplyOpen) {
+        let path = match self.path_of(ino) {
+            Ok(path) => path,
+            Err(errno) => return reply.error(errno),
+        };
+        // Opening an album folder usually means playback is about to start.
+        let router = self.router.clone();
+        self.runtime.spawn(async move {
+            if let Err(err) = router.prewarm_dir(&path).await {
+                warn!(path, error = %err, "prewarm failed");
+            }
+        });
+        reply.opened(0, 0)
+    }
+
+    fn release(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        _fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: ReplyEmpty,
+    ) {
+        self.router.stats().handle_closed();
+        reply.ok();
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let Ok(offset) = u64::try_from(offset) else {
+            return reply.error(libc::EINVAL);
+        };
+        match self.read_node(ino, offset, size as usize) {
+            Ok(data) => reply.data(&data),
+            Err(errno) => {
+                debug!(ino, offset, errno, "read failed");
+                reply.error(errno)
+            }
+        }
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        let listing = match self.read_directory(ino) {
+            Ok(listing) => listing,
+            Err(errno) => return reply.error(errno),
+        };
+        let skip = usize::try_from(offset).unwrap_or(0);
+        for (index, (child, kind, name)) in listing.into_iter().enumerate().skip(skip) {
+            if reply.add(child, index as i64 + 1, kind, name) {
+                break;
+            }
+        }
+        reply.ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use musfuse_core::config::{MountConfig, SourceConfig};
+    use musfuse_core::library::open_router;
+    use musfuse_core::mount::{AdapterCapabilities, MountContext};
+
+    fn write_wav(path: &std::path::Path) {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: 8_000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(path, spec).unwrap();
+        for frame in 0..800i16 {
+            writer.write_sample(frame).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    #[test]
+    fn reads_are_refused_once_the_mount_drains() {
+        let library = tempfile::tempdir().unwrap();
+        std::fs::create_dir(library.path().join("Album")).unwrap();
+        write_wav(&library.path().join("Album").join("01.wav"));
+        let ctx = MountContext::new(MountConfig {
+            sources: vec![SourceConfig {
+                path: library.path().to_path_buf(),
+                recursive: true,
+                watch: false,
+                follow_symlinks: false,
+            }],
+            ..Default::default()
+        });
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let router = runtime
+            .block_on(open_router(&ctx, AdapterCapabilities::READ_ONLY))
+            .unwrap();
+        let album = router.list_dir_names("/").unwrap().remove(0);
+        let track = router
+            .list_dir(&format!("/{album}"))
+            .unwrap()
+            .into_iter()
+            .find(|entry| matches!(entry, VirtualEntry::TrackFile(..)))
+            .map(|entry| router.virtual_name(&entry))
+            .unwrap();
+        let fs = MusFuseFs::new(Arc::new(router), runtime.handle().clone())
+            .with_operations(ctx.operations.clone());
+
+        let album = fs.lookup_node(ROOT_INO, &album).unwrap();
+        let track = fs.lookup_node(album.ino, &track).unwrap();
+        assert_eq!(fs.read_node(track.ino, 0, 4).unwrap().len(), 4);
+        assert_eq!(ctx.operations.in_flight(), 0);
+
+        ctx.operations.close();
+        assert_eq!(fs.read_node(track.ino, 0, 4), Err(libc::EAGAIN));
+    }
+
+    #[test]
+    fn child_and_parent_paths_round_trip() {
+        assert_eq!(child_path("/", "Album"), "/Album");
+        assert_eq!(child_path("/Album", "01.flac"), "/Album/01.flac");
+        assert_eq!(parent_path("/Album/01.flac"), "/Album");
+        assert_eq!(parent_path("/Album"), "/");
+        assert_eq!(parent_path("/"), "/");
+
+        let mut inodes = Inodes::new();
+        assert_eq!(inodes.path(ROOT_INO).as_deref(), Some("/"));
+        let album = inodes.intern("/Album");
+        assert_eq!(inodes.intern("/Album"), album);
+        assert_eq!(inodes.path(album).as_deref(), Some("/Album"));
+        assert_eq!(inodes.path(0), None);
+    }
+}
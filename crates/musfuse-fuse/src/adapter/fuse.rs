@@ -0,0 +1,108 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use fuser::{BackgroundSession, MountOption};
+use parking_lot::Mutex;
+use tokio::runtime::Handle;
+use tracing::{debug, info};
+
+use musfuse_core::filesystem::FileRouter;
+use musfuse_core::library::open_router;
+use musfuse_core::prelude::*;
+
+use super::filesystem::MusFuseFs;
+
+const FUSE_DEVICE: &str = "/dev/fuse";
+
+/// Mounts a library's virtual tree through the kernel FUSE driver, read-only
+pub struct FuseAdapter {
+    /// Served instead of a router built from the mount context, when set
+    router: Option<Arc<FileRouter>>,
+    runtime: Handle,
+    session: Mutex<Option<BackgroundSession>>,
+}
+
+impl FuseAdapter {
+    /// `runtime` drives the router's async reads from the FUSE session thread; each mount
+    /// scans and indexes the sources of its context
+    pub fn new(runtime: Handle) -> Self {
+        Self {
+            router: None,
+            runtime,
+            session: Mutex::new(None),
+        }
+    }
+
+    /// Serve `router` rather than scanning the mount context's sources
+    pub fn with_router(mut self, router: Arc<FileRouter>) -> Self {
+        self.router = Some(router);
+        self
+    }
+}
+
+#[async_trait]
+impl PlatformAdapter for FuseAdapter {
+    async fn prepare_environment(&self, config: &MountConfig) -> Result<()> {
+        if config.mount_point.as_os_str().is_empty() {
+            return Err(MusFuseError::Mount("missing mount point".into()));
+        }
+        if !Path::new(FUSE_DEVICE).exists() {
+            return Err(MusFuseError::Mount(format!(
+                "FUSE is not available: {FUSE_DEVICE} does not exist"
+            )));
+        }
+        if !config.mount_point.is_dir() {
+            return Err(MusFuseError::Mount(format!(
+                "mount point {} is not a directory",
+                config.mount_point.display()
+            )));
+        }
+        Ok(())
+    }
+
+    async fn mount(&self, ctx: &MountContext) -> Result<()> {
+        if self.session.lock().is_some() {
+            return Err(MusFuseError::Mount("already mounted".into()));
+        }
+        let router = match &self.router {
+            Some(router) => router.clone(),
+            None => Arc::new(open_router(ctx, self.capabilities()).await?),
+        };
+        let config = &ctx.config;
+        let mut session = self.session.lock();
+        if session.is_some() {
+            return Err(MusFuseError::Mount("already mounted".into()));
+        }
+        debug!("mounting virtual tree at {:?}", config.mount_point);
+        let fs =
+            MusFuseFs::new(router, self.runtime.clone()).with_operations(ctx.operations.clone());
+        let options = [
+            MountOption::RO,
+            MountOption::FSName("musfuse".into()),
+            MountOption::Subtype("musfuse".into()),
+            MountOption::DefaultPermissions,
+        ];
+        let mounted = fuser::spawn_mount2(fs, &config.mount_point, &options).map_err(|e| {
+            MusFuseError::Mount(format!(
+                "failed to mount {}: {e}",
+                config.mount_point.display()
+            ))
+        })?;
+        *session = Some(mounted);
+        info!("mounted at {:?}", config.mount_point);
+        Ok(())
+    }
+
+    async fn unmount(&self, mount_point: &Path) -> Result<()> {
+        let Some(session) = self.session.lock().take() else {
+            return Ok(());
+        };
+        // Joining waits for the session thread to see the unmount and exit.
+        tokio::task::spawn_blocking(move || session.join())
+            .await
+            .map_err(|e| MusFuseError::Mount(format!("FUSE session ended abnormally: {e}")))?;
+        info!("unmounted {:?}", mount_point);
+        Ok(())
+    }
+}
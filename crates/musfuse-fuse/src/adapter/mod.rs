@@ -0,0 +1,7 @@
+mod errno;
+mod filesystem;
+mod fuse;
+
+pub use errno::{errno_for, to_errno};
+pub use filesystem::MusFuseFs;
+pub use fuse::FuseAdapter;
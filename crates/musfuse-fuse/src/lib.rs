@@ -0,0 +1,9 @@
+#[cfg(target_os = "linux")]
+pub mod adapter;
+
+#[cfg(target_os = "linux")]
+pub use adapter::{FuseAdapter, MusFuseFs};
+
+/// The shared mount lifecycle driving the FUSE adapter.
+#[cfg(target_os = "linux")]
+pub type FuseMountProvider = musfuse_core::AdapterMountProvider<FuseAdapter>;
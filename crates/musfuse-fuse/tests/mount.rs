@@ -0,0 +1,109 @@
+#![cfg(target_os = "linux")]
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use musfuse_core::config::{LosslessStrategy, PolicyConfig, SourceConfig};
+use musfuse_core::library::open_router;
+use musfuse_core::metadata::{AlbumId, TrackId};
+use musfuse_core::prelude::*;
+use musfuse_fuse::{FuseAdapter, FuseMountProvider};
+
+fn write_wav(path: &Path) {
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate: 44_100,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec).unwrap();
+    for frame in 0..44_100i32 {
+        let sample = ((frame % 200) - 100) as i16 * 300;
+        writer.write_sample(sample).unwrap();
+        writer.write_sample(sample / 2).unwrap();
+    }
+    writer.finalize().unwrap();
+}
+
+fn policy() -> PolicyConfig {
+    PolicyConfig {
+        lossless_strategy: LosslessStrategy::ConvertToFlac,
+        ..Default::default()
+    }
+}
+
+fn config(source: PathBuf, mount_point: PathBuf) -> MountConfig {
+    MountConfig {
+        sources: vec![SourceConfig {
+            path: source,
+            recursive: true,
+            watch: false,
+            follow_symlinks: true,
+        }],
+        mount_point,
+        policies: policy(),
+        ..Default::default()
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn mounted_library_lists_and_reads_a_track() {
+    if !Path::new("/dev/fuse").exists() {
+        eprintln!("skipping: /dev/fuse is not available");
+        return;
+    }
+
+    let library = tempfile::tempdir().unwrap();
+    let mount_point = tempfile::tempdir().unwrap();
+    std::fs::create_dir(library.path().join("Album")).unwrap();
+    write_wav(&library.path().join("Album").join("01.wav"));
+    let ctx = Arc::new(MountContext::new(config(
+        library.path().to_path_buf(),
+        mount_point.path().to_path_buf(),
+    )));
+
+    // A second router over the same context predicts what the mount serves.
+    let router = open_router(&ctx, AdapterCapabilities::READ_ONLY)
+        .await
+        .unwrap();
+    let album_dir = router.album_dir_name(&AlbumId("Album".into()));
+    let expected_albums = vec![album_dir.clone()];
+    let id = TrackId {
+        album: AlbumId("Album".into()),
+        disc: 1,
+        index: 1,
+    };
+    let expected = router.read_track(&id).await.unwrap();
+    let track_name = router.entry_name(&id);
+    let expected_track = track_name.clone();
+
+    let provider =
+        FuseMountProvider::with_adapter(FuseAdapter::new(tokio::runtime::Handle::current()));
+    provider.mount(ctx).await.expect("mount");
+    assert_eq!(provider.status(), MountStatus::Mounted);
+
+    let root = mount_point.path().to_path_buf();
+    let (albums, tracks, bytes) = tokio::task::spawn_blocking(move || {
+        let names = |dir: &Path| -> Vec<String> {
+            let mut names: Vec<String> = std::fs::read_dir(dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            names
+        };
+        let album = root.join(&album_dir);
+        let bytes = std::fs::read(album.join(&track_name)).unwrap();
+        (names(&root), names(&album), bytes)
+    })
+    .await
+    .unwrap();
+    let unmounted = provider.unmount().await;
+
+    assert_eq!(albums, expected_albums);
+    assert!(tracks.contains(&expected_track), "{tracks:?}");
+    assert!(bytes.starts_with(b"fLaC"));
+    assert_eq!(bytes, expected);
+    unmounted.expect("unmount");
+    assert_eq!(provider.status(), MountStatus::Unmounted);
+}
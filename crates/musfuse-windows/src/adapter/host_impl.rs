@@ -1,28 +1,113 @@
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use async_trait::async_trait;
 use parking_lot::Mutex;
-use tracing::{debug, error, info};
+use tokio::runtime::Handle;
+use tracing::{debug, error, info, warn};
+use winfsp::filesystem::FileSystemContext;
 use winfsp::host::{FileSystemHost, FileSystemParams, VolumeParams};
 use winfsp::{winfsp_init, FspInit};
 
+use musfuse_core::library::open_router;
 use musfuse_core::prelude::*;
+use musfuse_core::scanner::dir_size;
+use musfuse_core::timing::SlowOpThreshold;
 
+use super::musfuse::MusFuseFS;
 use super::passthrough::PassthroughFS;
 use super::winfsp::{WinFspHost, WinFspMountHandle};
 
-/// Handle to keep the filesystem mounted
-struct MountedHost {
-    host: FileSystemHost<PassthroughFS>,
+/// Entries walked per source when sizing the volume at mount
+const VOLUME_SIZE_MAX_ENTRIES: usize = 1_000_000;
+
+/// The running WinFSP host, over whichever filesystem the config selected
+enum MountedHost {
+    Library(FileSystemHost<MusFuseFS>),
+    Passthrough(FileSystemHost<PassthroughFS>),
+}
+
+impl MountedHost {
+    fn stop(&mut self) {
+        match self {
+            MountedHost::Library(host) => {
+                host.unmount();
+                host.stop();
+            }
+            MountedHost::Passthrough(host) => {
+                host.unmount();
+                host.stop();
+            }
+        }
+    }
 }
 
 impl Drop for MountedHost {
     fn drop(&mut self) {
         info!("stopping filesystem dispatcher");
-        self.host.stop();
-        self.host.unmount();
+        self.stop();
+    }
+}
+
+/// Volume parameters shared by both filesystems
+fn volume_params(read_only: bool) -> VolumeParams {
+    let mut volume_params = VolumeParams::new();
+    volume_params
+        .filesystem_name("MusFuse")
+        .prefix("")
+        .case_sensitive_search(false)
+        .case_preserved_names(true)
+        .unicode_on_disk(true)
+        .persistent_acls(false)
+        .reparse_points(false)
+        .named_streams(false)
+        .read_only_volume(read_only)
+        .post_cleanup_when_modified_only(true)
+        .pass_query_directory_pattern(true)
+        .sector_size(4096)
+        .sectors_per_allocation_unit(1)
+        .max_component_length(255);
+    volume_params
+}
+
+/// Start a dispatcher for `fs` and attach it at `mount_point`
+fn start_host<T: FileSystemContext>(
+    fs: T,
+    read_only: bool,
+    mount_point: &Path,
+) -> Result<FileSystemHost<T>> {
+    let options = FileSystemParams::default_params(volume_params(read_only));
+    let mut host = FileSystemHost::new_with_options(options, fs).map_err(|e| {
+        MusFuseError::Mount(format!("failed to create filesystem host: {:?}", e))
+    })?;
+
+    host.start().map_err(|e| {
+        MusFuseError::Mount(format!("failed to start filesystem dispatcher: {:?}", e))
+    })?;
+
+    let mount_point_str = mount_point.to_string_lossy();
+    info!("mounting to: {}", mount_point_str);
+    host.mount(mount_point_str.as_ref()).map_err(|e| {
+        error!("failed to mount filesystem: {:?}", e);
+        host.stop();
+        MusFuseError::Mount(format!("failed to mount filesystem: {:?}", e))
+    })?;
+    Ok(host)
+}
+
+/// Prepare the empty directory backing a mount that has no sources yet
+fn empty_root(config: &MountConfig) -> Result<PathBuf> {
+    let base = config.cache_dir.clone().unwrap_or_else(std::env::temp_dir);
+    let root = base.join("musfuse-empty-root");
+    fs::create_dir_all(&root)?;
+    if fs::read_dir(&root)?.next().is_some() {
+        return Err(MusFuseError::Mount(format!(
+            "empty mount root is not empty: {}",
+            root.display()
+        )));
     }
+    Ok(root)
 }
 
 /// Implementation of WinFspHost that manages the filesystem lifecycle
@@ -45,6 +130,59 @@ impl WinFspHostImpl {
     }
 }
 
+impl WinFspHostImpl {
+    /// Serve the virtual library tree of the context's sources, or an empty tree when
+    /// none are configured and the config allows it
+    async fn mount_library(ctx: &MountContext) -> Result<FileSystemHost<MusFuseFS>> {
+        let config = &ctx.config;
+        if config.sources.is_empty() && !config.allow_empty {
+            return Err(MusFuseError::Mount("no source directory configured".into()));
+        }
+
+        debug!("mounting library to {:?}", config.mount_point);
+        let router = open_router(ctx, AdapterCapabilities::READ_ONLY).await?;
+        let sources = config.sources.iter().map(|source| source.path.clone()).collect();
+        let fs = MusFuseFS::new(Arc::new(router), Handle::current())
+            .with_operations(ctx.operations.clone())
+            .with_volume_size(Self::library_size(sources));
+        start_host(fs, true, &config.mount_point)
+    }
+
+    /// Bytes below the given sources; a source that cannot be walked counts as empty
+    async fn library_size(sources: Vec<PathBuf>) -> u64 {
+        let mut total = 0;
+        for source in &sources {
+            match dir_size(source, VOLUME_SIZE_MAX_ENTRIES).await {
+                Ok(size) => total += size.bytes,
+                Err(e) => warn!("could not size source {:?}: {}", source, e),
+            }
+        }
+        total
+    }
+
+    /// Serve the first source byte-for-byte
+    fn mount_passthrough(config: &MountConfig) -> Result<FileSystemHost<PassthroughFS>> {
+        let (source_path, read_only, follow_symlinks) = match config.sources.first() {
+            Some(source) => (source.path.clone(), false, source.follow_symlinks),
+            None if config.allow_empty => (empty_root(config)?, true, false),
+            None => {
+                return Err(MusFuseError::Mount("no source directory configured".into()));
+            }
+        };
+
+        debug!("mounting source: {:?} to {:?}", source_path, config.mount_point);
+
+        let fs = PassthroughFS::new(source_path)
+            .map_err(|e| {
+                MusFuseError::Mount(format!("failed to create passthrough filesystem: {:?}", e))
+            })?
+            .with_follow_symlinks(follow_symlinks)
+            .with_directories_first(config.directories_first)
+            .with_slow_op_threshold(SlowOpThreshold::from_ms(config.slow_op_threshold_ms));
+        start_host(fs, read_only, &config.mount_point)
+    }
+}
+
 impl Default for WinFspHostImpl {
     fn default() -> Self {
         Self::new().expect("failed to initialize WinFSP")
@@ -59,68 +197,30 @@ impl WinFspHost for WinFspHostImpl {
         Ok(())
     }
 
-    async fn mount(&self, config: &MountConfig) -> Result<WinFspMountHandle> {
-        // Validate configuration
-        config.validate()?;
-
-        // Get source directory (we'll use the first one for M0)
-        let source = config
-            .sources
-            .first()
-            .ok_or_else(|| MusFuseError::Mount("no source directory configured".into()))?;
-
-        let source_path = source.path.clone();
-        debug!("mounting source: {:?} to {:?}", source_path, config.mount_point);
-
-        // Create passthrough filesystem
-        let fs = PassthroughFS::new(source_path.clone()).map_err(|e| {
-            MusFuseError::Mount(format!("failed to create passthrough filesystem: {:?}", e))
-        })?;
-
-        // Configure volume parameters
-        let mut volume_params = VolumeParams::new();
-        volume_params
-            .filesystem_name("MusFuse")
-            .prefix("")
-            .case_sensitive_search(false)
-            .case_preserved_names(true)
-            .unicode_on_disk(true)
-            .persistent_acls(false)
-            .reparse_points(false)
-            .named_streams(false)
-            .read_only_volume(false)
-            .post_cleanup_when_modified_only(true)
-            .pass_query_directory_pattern(true)
-            .sector_size(4096)
-            .sectors_per_allocation_unit(1)
-            .max_component_length(255);
-
-        // Create filesystem host
-        let options = FileSystemParams::default_params(volume_params);
-        let mut host = FileSystemHost::new_with_options(options, fs).map_err(|e| {
-            MusFuseError::Mount(format!("failed to create filesystem host: {:?}", e))
-        })?;
-
-        // Start the dispatcher
-        host.start().map_err(|e| {
-            MusFuseError::Mount(format!("failed to start filesystem dispatcher: {:?}", e))
-        })?;
-
-        // Mount the filesystem
-        let mount_point_str = config.mount_point.to_string_lossy();
-        info!("mounting to: {}", mount_point_str);
+    fn is_mount_point_in_use(&self, mount_point: &Path) -> bool {
+        // Drive letters are probed through their root; directory mount points must not exist yet.
+        let raw = mount_point.to_string_lossy();
+        if raw.len() == 2 && raw.ends_with(':') {
+            Path::new(&format!("{raw}\\")).exists()
+        } else {
+            mount_point.exists()
+        }
+    }
 
-        host.mount(mount_point_str.as_ref()).map_err(|e| {
-            error!("failed to mount filesystem: {:?}", e);
-            host.stop();
-            MusFuseError::Mount(format!("failed to mount filesystem: {:?}", e))
-        })?;
+    async fn mount(&self, ctx: &MountContext) -> Result<WinFspMountHandle> {
+        let config = &ctx.config;
+        config.validate()?;
 
-        info!("filesystem mounted successfully to {}", mount_point_str);
+        let host = if config.passthrough {
+            MountedHost::Passthrough(Self::mount_passthrough(config)?)
+        } else {
+            MountedHost::Library(Self::mount_library(ctx).await?)
+        };
+        info!("filesystem mounted successfully to {}", config.mount_point.display());
 
         // Store the host to keep it alive
         let mut mounted = self.mounted.lock();
-        *mounted = Some(MountedHost { host });
+        *mounted = Some(host);
 
         let mount_point = Arc::new(config.mount_point.clone());
         Ok(WinFspMountHandle { mount_point })
@@ -130,12 +230,65 @@ impl WinFspHost for WinFspHostImpl {
         info!("unmounting: {:?}", mount_point);
         
         let mut mounted = self.mounted.lock();
-        if let Some(mut host) = mounted.take() {
-            host.host.unmount();
-            host.host.stop();
+        // Dropping the host unmounts it and stops its dispatcher
+        if mounted.take().is_some() {
             info!("filesystem unmounted successfully");
         }
-        
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_mount_root_is_created_and_lists_nothing() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let config = MountConfig {
+            mount_point: PathBuf::from("M:"),
+            cache_dir: Some(dir.path().to_path_buf()),
+            allow_empty: true,
+            ..Default::default()
+        };
+
+        config.validate().expect("empty mount allowed");
+        let root = empty_root(&config).expect("empty root");
+        assert!(root.starts_with(dir.path()));
+        assert_eq!(fs::read_dir(&root).expect("read root").count(), 0);
+        PassthroughFS::new(root).expect("passthrough over empty root");
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn empty_sources_mount_an_empty_volume_in_either_mode() {
+        let Ok(host) = WinFspHostImpl::new() else {
+            eprintln!("skipping: WinFSP is not installed");
+            return;
+        };
+
+        for passthrough in [false, true] {
+            let dir = tempfile::tempdir().expect("tempdir");
+            let mount_point = dir.path().join("mnt");
+            let mut config = MountConfig {
+                mount_point: mount_point.clone(),
+                cache_dir: Some(dir.path().join("cache")),
+                passthrough,
+                ..Default::default()
+            };
+            let ctx = MountContext::new(config.clone());
+            host.mount(&ctx).await.expect_err("empty sources are refused by default");
+
+            config.allow_empty = true;
+            let ctx = MountContext::new(config);
+            host.mount(&ctx).await.expect("empty mount");
+            let listed = tokio::task::spawn_blocking(move || {
+                fs::read_dir(&mount_point).map(|entries| entries.count())
+            })
+            .await
+            .expect("listing task");
+            host.unmount(ctx.mount_point()).await.expect("unmount");
+            assert_eq!(listed.expect("list mount root"), 0, "passthrough: {passthrough}");
+        }
+    }
+}
@@ -1,7 +1,12 @@
 mod host_impl;
+mod musfuse;
 mod passthrough;
+mod stat_cache;
+mod status;
 mod winfsp;
 
 pub use host_impl::WinFspHostImpl;
+pub use musfuse::{MusFuseFS, VirtualContext};
 pub use passthrough::PassthroughFS;
+pub use status::{ntstatus_for, to_fsp_error};
 pub use winfsp::{WinFspAdapter, WinFspHost, WinFspMountHandle};
@@ -0,0 +1,672 @@
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use parking_lot::Mutex;
+use tokio::runtime::Handle;
+use tracing::{debug, trace, warn};
+
+use musfuse_core::filesystem::{FileRouter, VirtualEntry};
+use musfuse_core::metadata::{AlbumId, TrackId};
+use musfuse_core::mount::{OperationGuard, OperationTracker};
+use musfuse_core::ErrorClass;
+
+use super::passthrough::systemtime_to_filetime;
+use super::status::{ntstatus_for, to_fsp_error};
+use winfsp::filesystem::{
+    DirBuffer, DirInfo, DirMarker, FileInfo, FileSecurity, OpenFileInfo, VolumeInfo, WideNameInfo,
+};
+use winfsp::{FspError, Result, U16CStr};
+use windows::Win32::Foundation::{
+    STATUS_FILE_IS_A_DIRECTORY, STATUS_NOT_A_DIRECTORY, STATUS_OBJECT_NAME_NOT_FOUND,
+};
+use windows::Win32::Storage::FileSystem::{FILE_ATTRIBUTE_DIRECTORY, FILE_ATTRIBUTE_READONLY};
+
+/// Volume size reported when the mount did not measure its sources, or until it has
+const DEFAULT_VOLUME_SIZE: u64 = 1024 * 1024 * 1024 * 1024; // 1TB
+
+/// An opened entry of the virtual tree
+#[derive(Debug)]
+pub struct VirtualContext {
+    /// Router path of the entry, `/`-separated
+    pub path: String,
+    /// What the router resolved the path to when it was opened
+    pub entry: VirtualEntry,
+}
+
+/// Read-only filesystem serving the virtual tree of a `FileRouter`
+///
+/// Track files are read through the media engine, so they carry the converted bytes the
+/// policy asks for rather than the source file. Callbacks run on WinFSP dispatcher threads
+/// and block on `runtime` for the router's async reads.
+pub struct MusFuseFS {
+    /// Virtual tree and the media engine behind it
+    router: Arc<FileRouter>,
+    /// Runtime the async router calls are driven on
+    runtime: Handle,
+    /// In-flight opens and reads, which an unmount waits for
+    operations: Arc<OperationTracker>,
+    /// Timestamp reported for every entry, as a FILETIME
+    mounted_at: u64,
+    /// Total size reported for the volume, in bytes
+    volume_size: Arc<AtomicU64>,
+    /// Extracted cover of each album, or `None` for an album without art
+    covers: Mutex<HashMap<AlbumId, Option<Arc<Vec<u8>>>>>,
+}
+
+impl MusFuseFS {
+    /// Create a filesystem over `router`, driving its reads on `runtime`
+    pub fn new(router: Arc<FileRouter>, runtime: Handle) -> Self {
+        Self {
+            router,
+            runtime,
+            operations: Arc::new(OperationTracker::new()),
+            mounted_at: systemtime_to_filetime(SystemTime::now()),
+            volume_size: Arc::new(AtomicU64::new(DEFAULT_VOLUME_SIZE)),
+            covers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Report what `measure`, usually sizing the sources, resolves to as the volume size
+    ///
+    /// The measurement runs on `runtime` so a large library does not hold up the mount.
+    pub fn with_volume_size(self, measure: impl Future<Output = u64> + Send + 'static) -> Self {
+        let volume_size = self.volume_size.clone();
+        self.runtime.spawn(async move {
+            volume_size.store(measure.await, Ordering::Relaxed);
+        });
+        self
+    }
+
+    /// Count opens and reads against the mount's `operations`, so unmounting drains them
+    pub fn with_operations(mut self, operations: Arc<OperationTracker>) -> Self {
+        self.operations = operations;
+        self
+    }
+
+    /// Start an operation, or refuse it once the mount is shutting down
+    fn begin(&self) -> Result<OperationGuard> {
+        self.operations
+            .begin()
+            .ok_or(FspError::NTSTATUS(ntstatus_for(ErrorClass::Transient).0))
+    }
+
+    /// Convert a WinFSP path to the `/`-separated form the router resolves
+    fn virtual_path(file_name: &U16CStr) -> String {
+        let path = file_name.to_string_lossy().replace('\\', "/");
+        format!("/{}", path.trim_start_matches('/'))
+    }
+
+    /// Resolve a WinFSP path against the virtual tree
+    pub fn lookup(&self, file_name: &U16CStr) -> Result<VirtualContext> {
+        let path = Self::virtual_path(file_name);
+        match self.router.resolve(&path) {
+            Some(entry) => Ok(VirtualContext { path, entry }),
+            None => {
+                debug!("no virtual entry at {}", path);
+                Err(FspError::NTSTATUS(STATUS_OBJECT_NAME_NOT_FOUND.0))
+            }
+        }
+    }
+
+    /// Cover of the album `id` belongs to, extracted on first use and kept for the mount
+    ///
+    /// Explorer asks for a cover's size and bytes many times while it draws thumbnails, and
+    /// each extraction opens and parses the source file.
+    fn cover(&self, id: &TrackId) -> Result<Arc<Vec<u8>>> {
+        let cached = self.covers.lock().get(&id.album).cloned();
+        let cover = match cached {
+            Some(cover) => cover,
+            None => {
+                let cover = self
+                    .runtime
+                    .block_on(self.router.read_cover(id))
+                    .map_err(|e| to_fsp_error(&e))?
+                    .map(Arc::new);
+                self.covers.lock().insert(id.album.clone(), cover.clone());
+                cover
+            }
+        };
+        cover.ok_or(FspError::NTSTATUS(STATUS_OBJECT_NAME_NOT_FOUND.0))
+    }
+
+    /// Covers, lyrics, originals and album cue sheets are small enough, or cheap enough, to
+    /// load whole
+    fn whole_file(&self, entry: &VirtualEntry) -> Result<Arc<Vec<u8>>> {
+        let loaded = match entry {
+            VirtualEntry::CoverImage(id) => return self.cover(id),
+            VirtualEntry::Lyrics(id) => self
+                .runtime
+                .block_on(self.router.read_lyrics(id))
+                .map(|lyrics| lyrics.map(String::into_bytes)),
+            VirtualEntry::AlbumCue(album) => {
+                Ok(self.router.album_cue(album).map(String::into_bytes))
+            }
+            VirtualEntry::Directory(_) => {
+                return Err(FspError::NTSTATUS(STATUS_FILE_IS_A_DIRECTORY.0));
+            }
+            VirtualEntry::TrackFile(..)
+            | VirtualEntry::OriginalFile(_)
+            | VirtualEntry::AlbumStream(_) => {
+                return Err(FspError::NTSTATUS(STATUS_OBJECT_NAME_NOT_FOUND.0));
+            }
+        };
+        loaded
+            .map_err(|e| to_fsp_error(&e))?
+            .map(Arc::new)
+            .ok_or(FspError::NTSTATUS(STATUS_OBJECT_NAME_NOT_FOUND.0))
+    }
+
+    /// Size of an entry as it is served; converted tracks report their encoded length
+    fn size_of(&self, entry: &VirtualEntry) -> Result<u64> {
+        match entry {
+            VirtualEntry::Directory(_) => Ok(0),
+            VirtualEntry::TrackFile(id, format) => self
+                .runtime
+                .block_on(self.router.content_length(id, *format))
+                .map_err(|e| to_fsp_error(&e)),
+            VirtualEntry::AlbumStream(album) => self
+                .runtime
+                .block_on(self.router.album_stream_length(album))
+                .map_err(|e| to_fsp_error(&e)),
+            VirtualEntry::OriginalFile(id) => self
+                .runtime
+                .block_on(self.router.original_length(id))
+                .map_err(|e| to_fsp_error(&e)),
+            _ => self.whole_file(entry).map(|data| data.len() as u64),
+        }
+    }
+
+    /// Size of an entry as a directory listing shows it; converted tracks that have not been
+    /// encoded yet report an estimate, so listing a folder never transcodes it
+    fn listed_size(&self, entry: &VirtualEntry) -> Result<u64> {
+        match entry {
+            VirtualEntry::TrackFile(id, format) => self
+                .runtime
+                .block_on(self.router.listed_length(id, *format))
+                .map_err(|e| to_fsp_error(&e)),
+            VirtualEntry::AlbumStream(album) => self
+                .runtime
+                .block_on(self.router.listed_album_stream_length(album))
+                .map_err(|e| to_fsp_error(&e)),
+            _ => self.size_of(entry),
+        }
+    }
+
+    /// Attributes of a virtual entry; everything but directories is read-only
+    fn attributes(entry: &VirtualEntry) -> u32 {
+        match entry {
+            VirtualEntry::Directory(_) => FILE_ATTRIBUTE_DIRECTORY.0,
+            _ => FILE_ATTRIBUTE_READONLY.0,
+        }
+    }
+
+    /// Fill `file_info` for an opened virtual entry
+    fn entry_info(&self, entry: &VirtualEntry, file_info: &mut FileInfo) -> Result<()> {
+        let size = self.size_of(entry)?;
+        self.fill_info(entry, size, file_info);
+        Ok(())
+    }
+
+    /// Fill `file_info` for an entry of `size` bytes
+    fn fill_info(&self, entry: &VirtualEntry, size: u64, file_info: &mut FileInfo) {
+        file_info.file_attributes = Self::attributes(entry);
+        file_info.file_size = size;
+        file_info.allocation_size = size.div_ceil(4096) * 4096;
+        file_info.creation_time = self.mounted_at;
+        file_info.last_access_time = self.mounted_at;
+        file_info.last_write_time = self.mounted_at;
+        file_info.change_time = self.mounted_at;
+        file_info.index_number = 0;
+    }
+
+    /// Up to `len` bytes of an opened entry, starting at `offset`
+    fn read_entry(&self, entry: &VirtualEntry, offset: u64, len: usize) -> Result<Vec<u8>> {
+        if let VirtualEntry::TrackFile(id, format) = entry {
+            return self
+                .runtime
+                .block_on(self.router.read_track_range(id, *format, offset, len))
+                .map_err(|e| to_fsp_error(&e));
+        }
+        if let VirtualEntry::AlbumStream(album) = entry {
+            return self
+                .runtime
+                .block_on(self.router.read_album_range(album, offset, len))
+                .map_err(|e| to_fsp_error(&e));
+        }
+        if let VirtualEntry::OriginalFile(id) = entry {
+            return self
+                .runtime
+                .block_on(self.router.read_original_range(id, offset, len))
+                .map_err(|e| to_fsp_error(&e));
+        }
+        let data = self.whole_file(entry)?;
+        let start = usize::try_from(offset).unwrap_or(usize::MAX).min(data.len());
+        let end = start.saturating_add(len).min(data.len());
+        Ok(data[start..end].to_vec())
+    }
+
+    /// Names and file information of a directory's virtual entries, in router order
+    ///
+    /// Entries whose size cannot be determined, such as a cover for an album without art,
+    /// are left out rather than failing the whole listing.
+    pub fn directory_entries(&self, context: &VirtualContext) -> Result<Vec<(String, FileInfo)>> {
+        let Some(entries) = self.router.list_dir(&context.path) else {
+            return Err(FspError::NTSTATUS(STATUS_NOT_A_DIRECTORY.0));
+        };
+
+        let mut listing = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let name = self.router.virtual_name(&entry);
+            let mut file_info = FileInfo::default();
+            match self.listed_size(&entry) {
+                Ok(size) => {
+                    self.fill_info(&entry, size, &mut file_info);
+                    listing.push((name, file_info));
+                }
+                Err(e) => warn!("leaving {} out of {}: {:?}", name, context.path, e),
+            }
+        }
+        Ok(listing)
+    }
+}
+
+impl winfsp::filesystem::FileSystemContext for MusFuseFS {
+    type FileContext = Arc<VirtualContext>;
+
+    fn get_security_by_name(
+        &self,
+        file_name: &U16CStr,
+        _security_descriptor: Option<&mut [c_void]>,
+        _reparse_point_resolver: impl FnOnce(&U16CStr) -> Option<FileSecurity>,
+    ) -> Result<FileSecurity> {
+        let context = self.lookup(file_name)?;
+        trace!("get_security_by_name: {}", context.path);
+
+        Ok(FileSecurity {
+            reparse: false,
+            sz_security_descriptor: 0,
+            attributes: Self::attributes(&context.entry),
+        })
+    }
+
+    fn open(
+        &self,
+        file_name: &U16CStr,
+        _create_options: u32,
+        _granted_access: u32,
+        file_info: &mut OpenFileInfo,
+    ) -> Result<Self::FileContext> {
+        let _operation = self.begin()?;
+        let context = self.lookup(file_name)?;
+        trace!("open: {}", context.path);
+
+        self.entry_info(&context.entry, file_info.as_mut())?;
+        if let VirtualEntry::Directory(_) = context.entry {
+            // Opening an album folder usually means playback is about to start
+            let router = self.router.clone();
+            let path = context.path.clone();
+            self.runtime.spawn(async move {
+                if let Err(e) = router.prewarm_dir(&path).await {
+                    warn!("prewarming {} failed: {}", path, e);
+                }
+            });
+        }
+        if let VirtualEntry::TrackFile(..) | VirtualEntry::OriginalFile(_) = context.entry {
+            self.router.stats().record_track_open();
+        }
+        self.router.stats().handle_opened();
+        Ok(Arc::new(context))
+    }
+
+    fn close(&self, context: Self::FileContext) {
+        trace!("close: {}", context.path);
+        self.router.stats().handle_closed();
+    }
+
+    fn read(&self, context: &Self::FileContext, buffer: &mut [u8], offset: u64) -> Result<u32> {
+        trace!("read: {}, offset: {}, len: {}", context.path, offset, buffer.len());
+        let _operation = self.begin()?;
+
+        let data = self.read_entry(&context.entry, offset, buffer.len())?;
+        let n = data.len().min(buffer.len());
+        buffer[..n].copy_from_slice(&data[..n]);
+        Ok(n as u32)
+    }
+
+    fn get_file_info(&self, context: &Self::FileContext, file_info: &mut FileInfo) -> Result<()> {
+        trace!("get_file_info: {}", context.path);
+        let _operation = self.begin()?;
+
+        self.entry_info(&context.entry, file_info)
+    }
+
+    fn read_directory(
+        &self,
+        context: &Self::FileContext,
+        _pattern: Option<&U16CStr>,
+        marker: DirMarker,
+        buffer: &mut [u8],
+    ) -> Result<u32> {
+        trace!("read_directory: {}", context.path);
+
+        let dir_buffer = DirBuffer::new();
+        let lock = dir_buffer.acquire(marker.is_none(), None)?;
+
+        for (name, file_info) in self.directory_entries(context)? {
+            let mut dir_info: DirInfo<255> = DirInfo::new();
+            if let Err(e) = dir_info.set_name(&name) {
+                warn!("failed to set name for {}: {:?}", name, e);
+                continue;
+            }
+            *dir_info.file_info_mut() = file_info;
+
+            if let Err(e) = lock.write(&mut dir_info) {
+                trace!("buffer full, stopping directory enumeration: {:?}", e);
+                break;
+            }
+        }
+
+        drop(lock);
+        Ok(dir_buffer.read(marker, buffer))
+    }
+
+    fn get_volume_info(&self, out_volume_info: &mut VolumeInfo) -> Result<()> {
+        trace!("get_volume_info");
+
+        // Nothing can be written, so the whole volume is reported as used
+        out_volume_info.total_size = self.volume_size.load(Ordering::Relaxed);
+        out_volume_info.free_size = 0;
+        out_volume_info.set_volume_label("MusFuse");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    use async_trait::async_trait;
+    use mockall::mock;
+    use musfuse_core::filesystem::MediaEngine;
+    use musfuse_core::fixtures;
+    use musfuse_core::media::{
+        AudioChunk, AudioReader, CoverExtractor, FormatTranscoder, TranscodeRequest,
+        TranscodeResult,
+    };
+    use musfuse_core::metadata::{TagDelta, TrackId, TrackMetadata};
+    use musfuse_core::prelude::{LosslessStrategy, PolicyConfig};
+    use musfuse_core::tag::TagOverlayService;
+    use musfuse_core::track::{SourceTrack, TrackIndexEntry};
+    use windows::Win32::Foundation::STATUS_DEVICE_BUSY;
+    use winfsp::filesystem::FileSystemContext;
+    use winfsp::U16CString;
+
+    const CONVERTED: &[u8] = b"fLaC converted track bytes";
+    const COVER: &[u8] = b"\xff\xd8\xff cover";
+
+    mock! {
+        pub Reader {}
+
+        #[async_trait]
+        impl AudioReader for Reader {
+            async fn read(&self, track: &SourceTrack) -> musfuse_core::Result<Vec<AudioChunk>>;
+        }
+    }
+
+    mock! {
+        pub Transcoder {}
+
+        #[async_trait]
+        impl FormatTranscoder for Transcoder {
+            async fn transcode(&self, request: &TranscodeRequest) -> musfuse_core::Result<TranscodeResult>;
+        }
+    }
+
+    mock! {
+        pub Cover {}
+
+        #[async_trait]
+        impl CoverExtractor for Cover {
+            async fn extract(&self, track: &SourceTrack) -> musfuse_core::Result<Option<Vec<u8>>>;
+        }
+    }
+
+    mock! {
+        pub Tags {}
+
+        #[async_trait]
+        impl TagOverlayService for Tags {
+            async fn read(&self, track: &TrackId, source: &Path) -> musfuse_core::Result<TrackMetadata>;
+            async fn apply(
+                &self,
+                track: &TrackId,
+                source: &Path,
+                delta: &TagDelta,
+            ) -> musfuse_core::Result<TrackMetadata>;
+            async fn remove(&self, track: &TrackId) -> musfuse_core::Result<()>;
+        }
+    }
+
+    /// One second of 44.1 kHz stereo, so listings can estimate its size
+    fn entry() -> TrackIndexEntry {
+        let mut entry = fixtures::entry("Album", 1, "01.wav");
+        entry.metadata.duration_ms = 1_000;
+        entry
+    }
+
+    /// The size listed for `entry()` before it is first encoded
+    const ESTIMATE: u64 = 44_100 * 2 * 3 + 64 * 1024;
+
+    /// A router whose media engine converts every track to `CONVERTED` and finds `COVER`
+    fn router() -> Arc<FileRouter> {
+        let mut cover = MockCover::new();
+        cover.expect_extract().returning(|_| Ok(Some(COVER.to_vec())));
+        router_with_cover(cover)
+    }
+
+    fn router_with_cover(cover: MockCover) -> Arc<FileRouter> {
+        let mut transcoder = MockTranscoder::new();
+        transcoder.expect_transcode().returning(|request| {
+            Ok(TranscodeResult {
+                track_id: request.track.id.clone(),
+                format: "flac",
+                chunks: vec![AudioChunk {
+                    data: bytes::Bytes::from_static(CONVERTED),
+                    timestamp_ms: 0,
+                    is_end: true,
+                }],
+                artwork: None,
+                duration_ms: None,
+            })
+        });
+
+        let media = MediaEngine::new(
+            Arc::new(MockReader::new()),
+            Arc::new(transcoder),
+            Arc::new(cover),
+            PolicyConfig {
+                lossless_strategy: LosslessStrategy::ConvertToFlac,
+                ..Default::default()
+            },
+        );
+        Arc::new(FileRouter::new(
+            Arc::new(vec![entry()]),
+            Arc::new(media),
+            Arc::new(MockTags::new()),
+        ))
+    }
+
+    fn wide(path: &str) -> U16CString {
+        U16CString::from_str(path).expect("no interior nul")
+    }
+
+    /// The filesystem plus the runtime its callbacks block on; tests call it from outside
+    fn filesystem() -> (tokio::runtime::Runtime, Arc<FileRouter>, MusFuseFS) {
+        let runtime = tokio::runtime::Runtime::new().expect("runtime");
+        let router = router();
+        let fs = MusFuseFS::new(router.clone(), runtime.handle().clone());
+        (runtime, router, fs)
+    }
+
+    #[test]
+    fn open_resolves_virtual_paths() {
+        let (_runtime, router, fs) = filesystem();
+        let id = entry().id;
+        let album = router.album_dir_name(&id.album);
+        let track = router.entry_name(&id);
+
+        let root = fs.lookup(&wide("\\")).expect("root");
+        assert!(matches!(root.entry, VirtualEntry::Directory(_)));
+        assert_eq!(MusFuseFS::attributes(&root.entry), FILE_ATTRIBUTE_DIRECTORY.0);
+
+        let opened = fs
+            .lookup(&wide(&format!("\\{album}\\{track}")))
+            .expect("track");
+        assert_eq!(opened.path, format!("/{album}/{track}"));
+        assert_eq!(opened.entry, VirtualEntry::TrackFile(id, None));
+
+        let mut file_info = FileInfo::default();
+        fs.get_file_info(&Arc::new(opened), &mut file_info)
+            .expect("file info");
+        assert_eq!(file_info.file_size, CONVERTED.len() as u64);
+        assert_eq!(file_info.file_attributes, FILE_ATTRIBUTE_READONLY.0);
+
+        let err = fs
+            .lookup(&wide(&format!("\\{album}\\missing.flac")))
+            .expect_err("missing");
+        assert!(matches!(err, FspError::NTSTATUS(code) if code == STATUS_OBJECT_NAME_NOT_FOUND.0));
+    }
+
+    #[test]
+    fn read_serves_converted_bytes_in_ranges() {
+        let (_runtime, router, fs) = filesystem();
+        let id = entry().id;
+        let path = format!(
+            "\\{}\\{}",
+            router.album_dir_name(&id.album),
+            router.entry_name(&id)
+        );
+        let context = Arc::new(fs.lookup(&wide(&path)).expect("track"));
+
+        let mut buffer = vec![0u8; 4];
+        assert_eq!(fs.read(&context, &mut buffer, 0).expect("read"), 4);
+        assert_eq!(buffer, &CONVERTED[..4]);
+
+        let mut buffer = vec![0u8; 64];
+        let n = fs.read(&context, &mut buffer, 5).expect("read") as usize;
+        assert_eq!(&buffer[..n], &CONVERTED[5..]);
+
+        let n = fs
+            .read(&context, &mut buffer, CONVERTED.len() as u64)
+            .expect("read at end");
+        assert_eq!(n, 0);
+    }
+
+    #[test]
+    fn reads_are_refused_once_the_mount_drains() {
+        let runtime = tokio::runtime::Runtime::new().expect("runtime");
+        let router = router();
+        let operations = Arc::new(OperationTracker::new());
+        let fs = MusFuseFS::new(router.clone(), runtime.handle().clone())
+            .with_operations(operations.clone());
+        let id = entry().id;
+        let path = format!(
+            "\\{}\\{}",
+            router.album_dir_name(&id.album),
+            router.entry_name(&id)
+        );
+        let context = Arc::new(fs.lookup(&wide(&path)).expect("track"));
+
+        let mut buffer = vec![0u8; 4];
+        assert_eq!(fs.read(&context, &mut buffer, 0).expect("read"), 4);
+        assert_eq!(operations.in_flight(), 0);
+
+        operations.close();
+        let err = fs.read(&context, &mut buffer, 0).expect_err("draining");
+        assert!(matches!(err, FspError::NTSTATUS(code) if code == STATUS_DEVICE_BUSY.0));
+        let err = fs
+            .get_file_info(&context, &mut FileInfo::default())
+            .expect_err("draining");
+        assert!(matches!(err, FspError::NTSTATUS(code) if code == STATUS_DEVICE_BUSY.0));
+    }
+
+    #[test]
+    fn covers_are_extracted_once_per_album() {
+        let runtime = tokio::runtime::Runtime::new().expect("runtime");
+        let mut cover = MockCover::new();
+        cover
+            .expect_extract()
+            .times(1)
+            .returning(|_| Ok(Some(COVER.to_vec())));
+        let router = router_with_cover(cover);
+        let fs = MusFuseFS::new(router.clone(), runtime.handle().clone());
+        let album = router.album_dir_name(&entry().id.album);
+
+        let folder = fs.lookup(&wide(&format!("\\{album}"))).expect("album");
+        fs.directory_entries(&folder).expect("list album");
+        let context = Arc::new(
+            fs.lookup(&wide(&format!("\\{album}\\cover.jpg")))
+                .expect("cover"),
+        );
+        let mut file_info = FileInfo::default();
+        fs.get_file_info(&context, &mut file_info).expect("file info");
+        assert_eq!(file_info.file_size, COVER.len() as u64);
+
+        let mut buffer = vec![0u8; 64];
+        let n = fs.read(&context, &mut buffer, 0).expect("read") as usize;
+        assert_eq!(&buffer[..n], COVER);
+        let n = fs.read(&context, &mut buffer, 4).expect("read") as usize;
+        assert_eq!(&buffer[..n], &COVER[4..]);
+    }
+
+    #[test]
+    fn read_directory_enumerates_virtual_entries() {
+        let (_runtime, router, fs) = filesystem();
+        let id = entry().id;
+        let album = router.album_dir_name(&id.album);
+
+        let root = fs.lookup(&wide("\\")).expect("root");
+        let names: Vec<String> = fs
+            .directory_entries(&root)
+            .expect("list root")
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, vec![album.clone()]);
+
+        let context = fs.lookup(&wide(&format!("\\{album}"))).expect("album");
+        let listing = fs.directory_entries(&context).expect("list album");
+        let sizes: Vec<(String, u64)> = listing
+            .into_iter()
+            .map(|(name, info)| (name, info.file_size))
+            .collect();
+        assert_eq!(
+            sizes,
+            vec![
+                (router.entry_name(&id), ESTIMATE),
+                ("cover.jpg".to_string(), COVER.len() as u64),
+            ]
+        );
+
+        let track = fs
+            .lookup(&wide(&format!("\\{album}\\{}", router.entry_name(&id))))
+            .expect("track");
+        let mut file_info = FileInfo::default();
+        fs.get_file_info(&Arc::new(track), &mut file_info)
+            .expect("file info");
+        let listing = fs.directory_entries(&context).expect("list album again");
+        assert_eq!(listing[0].1.file_size, CONVERTED.len() as u64);
+
+        let track = fs
+            .lookup(&wide(&format!("\\{album}\\{}", router.entry_name(&id))))
+            .expect("track");
+        let err = fs.directory_entries(&track).expect_err("not a directory");
+        assert!(matches!(err, FspError::NTSTATUS(code) if code == STATUS_NOT_A_DIRECTORY.0));
+    }
+}
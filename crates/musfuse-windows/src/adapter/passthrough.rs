@@ -4,16 +4,27 @@ use std::io::{Read, Seek, SeekFrom, Write};
 use std::os::windows::fs::MetadataExt;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
+use musfuse_core::timing::SlowOpThreshold;
 use parking_lot::RwLock;
 use tracing::{debug, error, trace, warn};
+
+use super::stat_cache::{DEFAULT_METADATA_TTL, MetadataCache};
+use super::status::io_to_fsp_error;
 use winfsp::constants::FspCleanupFlags;
 use winfsp::filesystem::{
-    DirBuffer, DirInfo, DirMarker, FileInfo, FileSecurity, OpenFileInfo, VolumeInfo, WideNameInfo,
+    DirInfo, DirMarker, FileInfo, FileSecurity, OpenFileInfo, VolumeInfo, WideNameInfo,
 };
 use winfsp::{FspError, Result, U16CStr};
-use windows::Win32::Foundation::{STATUS_DIRECTORY_NOT_EMPTY, STATUS_OBJECT_NAME_COLLISION};
+use windows::Wdk::Storage::FileSystem::{
+    FILE_DELETE_ON_CLOSE, FILE_DIRECTORY_FILE, FILE_NON_DIRECTORY_FILE,
+};
+use windows::Win32::Foundation::{
+    STATUS_ACCESS_DENIED, STATUS_DIRECTORY_NOT_EMPTY, STATUS_INVALID_PARAMETER,
+    STATUS_OBJECT_NAME_COLLISION,
+};
+use windows::Win32::Storage::FileSystem::FILE_ATTRIBUTE_DIRECTORY;
 
 /// File context that holds the open file handle and metadata
 #[derive(Debug)]
@@ -36,10 +47,64 @@ impl FileContext {
     }
 }
 
+/// Kind of entry requested by a `create` call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CreateKind {
+    File,
+    Directory,
+}
+
+impl CreateKind {
+    /// Reconcile the create options with the requested attributes.
+    ///
+    /// A directory may be requested through `FILE_DIRECTORY_FILE` or the
+    /// `FILE_ATTRIBUTE_DIRECTORY` bit; combining either with
+    /// `FILE_NON_DIRECTORY_FILE` is contradictory and rejected.
+    fn from_request(create_options: u32, file_attributes: u32) -> Result<Self> {
+        let directory_option = create_options & FILE_DIRECTORY_FILE.0 != 0;
+        let non_directory_option = create_options & FILE_NON_DIRECTORY_FILE.0 != 0;
+        let directory_attribute = file_attributes & FILE_ATTRIBUTE_DIRECTORY.0 != 0;
+
+        match (directory_option || directory_attribute, non_directory_option) {
+            (true, true) => Err(FspError::NTSTATUS(STATUS_INVALID_PARAMETER.0)),
+            (true, false) => Ok(CreateKind::Directory),
+            (false, _) => Ok(CreateKind::File),
+        }
+    }
+
+    /// Create the entry on disk, failing if something already exists at `path`
+    fn create_at(self, path: &Path) -> Result<()> {
+        if path.exists() {
+            return Err(FspError::NTSTATUS(STATUS_OBJECT_NAME_COLLISION.0));
+        }
+
+        match self {
+            CreateKind::Directory => fs::create_dir(path)?,
+            CreateKind::File => {
+                fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
 /// Passthrough filesystem implementation that transparently maps to a source directory
 pub struct PassthroughFS {
     /// Source directory to pass through
     source: PathBuf,
+    /// Canonical form of `source`, used to contain reparse points
+    canonical_source: PathBuf,
+    /// Whether symlinks and junctions may lead outside the source directory
+    follow_symlinks: bool,
+    /// Whether directory listings put subdirectories before files
+    directories_first: bool,
+    /// Reads and directory listings slower than this are logged
+    slow_ops: SlowOpThreshold,
+    /// Recent stats, so browse bursts do not hit a slow source for every query
+    stat_cache: MetadataCache,
 }
 
 impl PassthroughFS {
@@ -51,7 +116,68 @@ impl PassthroughFS {
         if !source.is_dir() {
             return Err(FspError::IO(std::io::ErrorKind::NotADirectory));
         }
-        Ok(Self { source })
+        let canonical_source = fs::canonicalize(&source)?;
+        Ok(Self {
+            source,
+            canonical_source,
+            follow_symlinks: true,
+            directories_first: false,
+            slow_ops: SlowOpThreshold::disabled(),
+            stat_cache: MetadataCache::new(DEFAULT_METADATA_TTL),
+        })
+    }
+
+    /// Serve repeated stats of the same path from memory for `ttl`; zero disables it
+    pub fn with_metadata_ttl(mut self, ttl: Duration) -> Self {
+        self.stat_cache = MetadataCache::new(ttl);
+        self
+    }
+
+    /// Stat a path through the short-lived metadata cache
+    fn stat(&self, path: &Path) -> std::io::Result<fs::Metadata> {
+        self.stat_cache.get_or_stat(path, |path| fs::metadata(path))
+    }
+
+    /// Forget cached stats for a path that is about to change or just changed
+    fn invalidate(&self, path: &Path) {
+        self.stat_cache.invalidate(path);
+    }
+
+    /// Log reads and directory listings that take longer than the threshold
+    pub fn with_slow_op_threshold(mut self, threshold: SlowOpThreshold) -> Self {
+        self.slow_ops = threshold;
+        self
+    }
+
+    /// List subdirectories before files in directory enumerations
+    pub fn with_directories_first(mut self, directories_first: bool) -> Self {
+        self.directories_first = directories_first;
+        self
+    }
+
+    /// Refuse (or allow) reparse points whose target lies outside the source directory
+    pub fn with_follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// Reject paths that escape the source tree through a symlink or junction; a path
+    /// that does not exist yet is judged by the directory it would be created in
+    fn ensure_contained(&self, path: &Path) -> Result<()> {
+        if self.follow_symlinks {
+            return Ok(());
+        }
+        let target = fs::canonicalize(path).or_else(|err| match path.parent() {
+            Some(parent) => fs::canonicalize(parent),
+            None => Err(err),
+        });
+        match target {
+            Ok(target) if !target.starts_with(&self.canonical_source) => {
+                warn!("refusing to follow {:?} outside the source root", path);
+                Err(FspError::NTSTATUS(STATUS_ACCESS_DENIED.0))
+            }
+            _ => Ok(()),
+        }
     }
 
     /// Convert a WinFSP path to a real filesystem path
@@ -62,13 +188,65 @@ impl PassthroughFS {
         self.source.join(path_str)
     }
 
+    /// Read a directory in a stable order so repeated enumerations and marker
+    /// resumption see the same sequence regardless of what the OS yields.
+    fn sorted_entries(
+        &self,
+        path: &Path,
+    ) -> std::io::Result<Vec<(std::ffi::OsString, Option<fs::Metadata>)>> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(path)? {
+            match entry {
+                Ok(entry) => entries.push((entry.file_name(), entry.metadata().ok())),
+                Err(e) => warn!("failed to read directory entry: {}", e),
+            }
+        }
+
+        entries.sort_by_cached_key(|(name, metadata)| {
+            let is_dir = metadata.as_ref().is_some_and(|m| m.is_dir());
+            self.listing_key(&name.to_string_lossy(), is_dir)
+        });
+        Ok(entries)
+    }
+
+    /// Where an entry sorts in a listing: case-insensitively by name, optionally after
+    /// every directory, with the exact name breaking ties
+    fn listing_key(&self, name: &str, is_dir: bool) -> (bool, String, String) {
+        (self.directories_first && !is_dir, name.to_lowercase(), name.to_owned())
+    }
+
+    /// The part of a sorted listing that follows `marker`, the last name WinFSP was given.
+    /// A marker removed since then resumes where it would sort, as if it were a file.
+    fn entries_after(
+        &self,
+        mut entries: Vec<(std::ffi::OsString, Option<fs::Metadata>)>,
+        marker: Option<&[u16]>,
+    ) -> Vec<(std::ffi::OsString, Option<fs::Metadata>)> {
+        let Some(marker) = marker else {
+            return entries;
+        };
+        let marker = String::from_utf16_lossy(marker);
+        let found = entries.iter().position(|(name, _)| name.to_string_lossy() == marker);
+        let start = match found {
+            Some(found) => found + 1,
+            None => {
+                let key = self.listing_key(&marker, false);
+                entries.partition_point(|(name, metadata)| {
+                    let is_dir = metadata.as_ref().is_some_and(|m| m.is_dir());
+                    self.listing_key(&name.to_string_lossy(), is_dir) <= key
+                })
+            }
+        };
+        entries.split_off(start)
+    }
+
     /// Convert metadata to FileInfo
     fn metadata_to_file_info(metadata: &fs::Metadata, file_info: &mut FileInfo) {
         let attrs = metadata.file_attributes();
         file_info.file_attributes = attrs;
 
         file_info.file_size = metadata.len();
-        file_info.allocation_size = ((metadata.len() + 4095) / 4096) * 4096;
+        file_info.allocation_size = metadata.len().div_ceil(4096) * 4096;
 
         // Convert SystemTime to Windows FILETIME (100-nanosecond intervals since 1601-01-01)
         if let Ok(created) = metadata.created() {
@@ -110,8 +288,9 @@ impl winfsp::filesystem::FileSystemContext for PassthroughFS {
     ) -> Result<FileSecurity> {
         let path = self.resolve_path(file_name);
         trace!("get_security_by_name: {:?}", path);
+        self.ensure_contained(&path)?;
 
-        match fs::metadata(&path) {
+        match self.stat(&path) {
             Ok(metadata) => {
                 let attrs = metadata.file_attributes();
                 Ok(FileSecurity {
@@ -122,7 +301,7 @@ impl winfsp::filesystem::FileSystemContext for PassthroughFS {
             }
             Err(e) => {
                 debug!("get_security_by_name failed for {:?}: {}", path, e);
-                Err(FspError::from(e))
+                Err(io_to_fsp_error(e))
             }
         }
     }
@@ -136,15 +315,16 @@ impl winfsp::filesystem::FileSystemContext for PassthroughFS {
     ) -> Result<Self::FileContext> {
         let path = self.resolve_path(file_name);
         trace!("open: {:?}", path);
+        self.ensure_contained(&path)?;
 
-        match fs::metadata(&path) {
+        match self.stat(&path) {
             Ok(metadata) => {
                 Self::metadata_to_file_info(&metadata, file_info.as_mut());
                 Ok(Arc::new(FileContext::new(path)))
             }
             Err(e) => {
                 debug!("open failed for {:?}: {}", path, e);
-                Err(FspError::from(e))
+                Err(io_to_fsp_error(e))
             }
         }
     }
@@ -158,8 +338,8 @@ impl winfsp::filesystem::FileSystemContext for PassthroughFS {
     fn cleanup(&self, context: &Self::FileContext, file_name: Option<&U16CStr>, flags: u32) {
         trace!("cleanup: {:?}, flags: 0x{:x}", context.path, flags);
 
-        // Handle deletion
-        if FspCleanupFlags::FspCleanupDelete.is_flagged(flags) {
+        // Handle deletion, either requested now or at create time via FILE_DELETE_ON_CLOSE
+        if FspCleanupFlags::FspCleanupDelete.is_flagged(flags) || context.delete_on_close {
             let path = if let Some(name) = file_name {
                 self.resolve_path(name)
             } else {
@@ -167,6 +347,10 @@ impl winfsp::filesystem::FileSystemContext for PassthroughFS {
             };
 
             trace!("attempting to delete: {:?}", path);
+            if self.ensure_contained(&path).is_err() {
+                return;
+            }
+            self.invalidate(&path);
             if let Ok(metadata) = fs::metadata(&path) {
                 let result = if metadata.is_dir() {
                     fs::remove_dir(&path)
@@ -189,20 +373,22 @@ impl winfsp::filesystem::FileSystemContext for PassthroughFS {
             // Open file on demand
             match self.open_file_handle(&context.path, false) {
                 Ok(f) => *file_lock = Some(f),
-                Err(e) => return Err(FspError::from(e)),
+                Err(e) => return Err(io_to_fsp_error(e)),
             }
         }
 
         let file = file_lock.as_mut().unwrap();
-        
-        if let Err(e) = file.seek(SeekFrom::Start(offset)) {
-            return Err(FspError::from(e));
-        }
 
-        match file.read(buffer) {
-            Ok(n) => Ok(n as u32),
-            Err(e) => Err(FspError::from(e)),
-        }
+        self.slow_ops.time("read_file", context.path.display(), || {
+            if let Err(e) = file.seek(SeekFrom::Start(offset)) {
+                return Err(io_to_fsp_error(e));
+            }
+
+            match file.read(buffer) {
+                Ok(n) => Ok(n as u32),
+                Err(e) => Err(io_to_fsp_error(e)),
+            }
+        })
     }
 
     fn write(
@@ -221,16 +407,17 @@ impl winfsp::filesystem::FileSystemContext for PassthroughFS {
             // Open file on demand for writing
             match self.open_file_handle(&context.path, true) {
                 Ok(f) => *file_lock = Some(f),
-                Err(e) => return Err(FspError::from(e)),
+                Err(e) => return Err(io_to_fsp_error(e)),
             }
         }
 
         let file = file_lock.as_mut().unwrap();
 
         if let Err(e) = file.seek(SeekFrom::Start(offset)) {
-            return Err(FspError::from(e));
+            return Err(io_to_fsp_error(e));
         }
 
+        self.invalidate(&context.path);
         match file.write(buffer) {
             Ok(n) => {
                 // Update file info
@@ -244,19 +431,19 @@ impl winfsp::filesystem::FileSystemContext for PassthroughFS {
                 
                 Ok(n as u32)
             }
-            Err(e) => Err(FspError::from(e)),
+            Err(e) => Err(io_to_fsp_error(e)),
         }
     }
 
     fn get_file_info(&self, context: &Self::FileContext, file_info: &mut FileInfo) -> Result<()> {
         trace!("get_file_info: {:?}", context.path);
 
-        match fs::metadata(&context.path) {
+        match self.stat(&context.path) {
             Ok(metadata) => {
                 Self::metadata_to_file_info(&metadata, file_info);
                 Ok(())
             }
-            Err(e) => Err(FspError::from(e)),
+            Err(e) => Err(io_to_fsp_error(e)),
         }
     }
 
@@ -264,7 +451,7 @@ impl winfsp::filesystem::FileSystemContext for PassthroughFS {
         &self,
         context: &Self::FileContext,
         file_attributes: u32,
-        _creation_time: u64,
+        creation_time: u64,
         _last_access_time: u64,
         _last_write_time: u64,
         _last_change_time: u64,
@@ -286,6 +473,11 @@ impl winfsp::filesystem::FileSystemContext for PassthroughFS {
             }
         }
 
+        self.invalidate(&context.path);
+        if let Some(created) = filetime_to_systemtime(creation_time) {
+            set_creation_time(&context.path, created)?;
+        }
+
         // Refresh file info
         if let Ok(metadata) = fs::metadata(&context.path) {
             Self::metadata_to_file_info(&metadata, file_info);
@@ -307,6 +499,7 @@ impl winfsp::filesystem::FileSystemContext for PassthroughFS {
             .write(true)
             .open(&context.path)?;
 
+        self.invalidate(&context.path);
         file.set_len(new_size)?;
 
         if let Ok(metadata) = fs::metadata(&context.path) {
@@ -325,25 +518,18 @@ impl winfsp::filesystem::FileSystemContext for PassthroughFS {
     ) -> Result<u32> {
         trace!("read_directory: {:?}", context.path);
 
-        let dir_buffer = DirBuffer::new();
-        let _lock = dir_buffer.acquire(marker.is_none(), None)?;
-
-        // Read directory entries
-        let entries = match fs::read_dir(&context.path) {
+        // Read directory entries. They are written straight into WinFSP's buffer, since a
+        // `DirBuffer` would re-sort them by name and undo `directories_first`
+        let entries = self.slow_ops.time("read_dir", context.path.display(), || {
+            self.sorted_entries(&context.path)
+        });
+        let entries = match entries {
             Ok(entries) => entries,
-            Err(e) => return Err(FspError::from(e)),
+            Err(e) => return Err(io_to_fsp_error(e)),
         };
 
-        for entry in entries {
-            let entry = match entry {
-                Ok(e) => e,
-                Err(e) => {
-                    warn!("failed to read directory entry: {}", e);
-                    continue;
-                }
-            };
-
-            let file_name = entry.file_name();
+        let mut cursor = 0;
+        for (file_name, metadata) in self.entries_after(entries, marker.inner()) {
             let file_name_str = file_name.to_string_lossy();
 
             let mut dir_info: DirInfo<255> = DirInfo::new();
@@ -352,21 +538,19 @@ impl winfsp::filesystem::FileSystemContext for PassthroughFS {
                 continue;
             }
 
-            if let Ok(metadata) = entry.metadata() {
-                Self::metadata_to_file_info(&metadata, dir_info.file_info_mut());
+            if let Some(metadata) = &metadata {
+                Self::metadata_to_file_info(metadata, dir_info.file_info_mut());
             }
 
-            if dir_buffer.acquire(false, None).is_err() {
-                break;
-            }
-            if let Err(e) = _lock.write(&mut dir_info) {
-                trace!("buffer full, stopping directory enumeration: {:?}", e);
-                break;
+            // A full buffer is returned unterminated, so WinFSP asks again from the last name
+            if !dir_info.append_to_buffer(buffer, &mut cursor) {
+                trace!("buffer full, stopping directory enumeration");
+                return Ok(cursor);
             }
         }
 
-        drop(_lock);
-        Ok(dir_buffer.read(marker, buffer))
+        DirInfo::<255>::finalize_buffer(buffer, &mut cursor);
+        Ok(cursor)
     }
 
     fn get_volume_info(&self, out_volume_info: &mut VolumeInfo) -> Result<()> {
@@ -394,29 +578,23 @@ impl winfsp::filesystem::FileSystemContext for PassthroughFS {
     ) -> Result<Self::FileContext> {
         let path = self.resolve_path(file_name);
         trace!("create: {:?}", path);
+        self.ensure_contained(&path)?;
 
-        let is_directory = (create_options & 0x00000001) != 0; // FILE_DIRECTORY_FILE
-
-        if path.exists() {
-            return Err(FspError::NTSTATUS(STATUS_OBJECT_NAME_COLLISION.0));
-        }
+        let kind = CreateKind::from_request(create_options, file_attributes)?;
+        self.invalidate(&path);
+        kind.create_at(&path)?;
 
-        if is_directory {
-            fs::create_dir(&path)?;
-        } else {
-            let _attrs = file_attributes;
-            // Note: File attributes would be set here in a full implementation
-            
-            let file = fs::File::create(&path)?;
-            drop(file);
-        }
+        let delete_on_close = create_options & FILE_DELETE_ON_CLOSE.0 != 0;
 
         match fs::metadata(&path) {
             Ok(metadata) => {
                 Self::metadata_to_file_info(&metadata, file_info.as_mut());
-                Ok(Arc::new(FileContext::new(path)))
+                Ok(Arc::new(FileContext {
+                    delete_on_close,
+                    ..FileContext::new(path)
+                }))
             }
-            Err(e) => Err(FspError::from(e)),
+            Err(e) => Err(io_to_fsp_error(e)),
         }
     }
 
@@ -430,6 +608,8 @@ impl winfsp::filesystem::FileSystemContext for PassthroughFS {
         let old_path = self.resolve_path(file_name);
         let new_path = self.resolve_path(new_file_name);
         trace!("rename: {:?} -> {:?}", old_path, new_path);
+        self.ensure_contained(&old_path)?;
+        self.ensure_contained(&new_path)?;
 
         if new_path.exists() && !replace_if_exists {
             return Err(FspError::NTSTATUS(STATUS_OBJECT_NAME_COLLISION.0));
@@ -443,6 +623,8 @@ impl winfsp::filesystem::FileSystemContext for PassthroughFS {
             }
         }
 
+        self.invalidate(&old_path);
+        self.invalidate(&new_path);
         fs::rename(&old_path, &new_path)?;
         Ok(())
     }
@@ -464,7 +646,7 @@ impl winfsp::filesystem::FileSystemContext for PassthroughFS {
                             return Err(FspError::NTSTATUS(STATUS_DIRECTORY_NOT_EMPTY.0));
                         }
                     }
-                    Err(e) => return Err(FspError::from(e)),
+                    Err(e) => return Err(io_to_fsp_error(e)),
                 }
             }
         }
@@ -474,7 +656,7 @@ impl winfsp::filesystem::FileSystemContext for PassthroughFS {
 }
 
 /// Convert SystemTime to Windows FILETIME format
-fn systemtime_to_filetime(time: SystemTime) -> u64 {
+pub(super) fn systemtime_to_filetime(time: SystemTime) -> u64 {
     const UNIX_EPOCH_IN_FILETIME: u64 = 116444736000000000;
     const TICKS_PER_SECOND: u64 = 10_000_000;
 
@@ -487,3 +669,218 @@ fn systemtime_to_filetime(time: SystemTime) -> u64 {
         Err(_) => 0,
     }
 }
+
+/// Convert a Windows FILETIME to SystemTime; zero means "leave unchanged"
+fn filetime_to_systemtime(filetime: u64) -> Option<SystemTime> {
+    const UNIX_EPOCH_IN_FILETIME: u64 = 116444736000000000;
+
+    if filetime == 0 {
+        return None;
+    }
+    let unix_epoch = SystemTime::UNIX_EPOCH;
+    if filetime >= UNIX_EPOCH_IN_FILETIME {
+        unix_epoch.checked_add(filetime_ticks(filetime - UNIX_EPOCH_IN_FILETIME))
+    } else {
+        unix_epoch.checked_sub(filetime_ticks(UNIX_EPOCH_IN_FILETIME - filetime))
+    }
+}
+
+fn filetime_ticks(ticks: u64) -> std::time::Duration {
+    std::time::Duration::from_nanos(ticks.saturating_mul(100))
+}
+
+/// Apply a creation time to the real file; this ends up in `SetFileTime`
+fn set_creation_time(path: &Path, created: SystemTime) -> std::io::Result<()> {
+    use std::os::windows::fs::{FileTimesExt, OpenOptionsExt};
+    use windows::Win32::Storage::FileSystem::{FILE_FLAG_BACKUP_SEMANTICS, FILE_WRITE_ATTRIBUTES};
+
+    // Backup semantics are required to open directories
+    let file = fs::OpenOptions::new()
+        .access_mode(FILE_WRITE_ATTRIBUTES.0)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS.0)
+        .open(path)?;
+    file.set_times(fs::FileTimes::new().set_created(created))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use windows::Win32::Foundation::STATUS_OBJECT_NAME_NOT_FOUND;
+    use windows::Win32::Storage::FileSystem::FILE_ATTRIBUTE_NORMAL;
+    use winfsp::filesystem::FileSystemContext;
+
+    #[test]
+    fn directory_option_creates_directory() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("album");
+
+        let kind = CreateKind::from_request(FILE_DIRECTORY_FILE.0, FILE_ATTRIBUTE_NORMAL.0)
+            .expect("valid request");
+        assert_eq!(kind, CreateKind::Directory);
+        kind.create_at(&path).expect("create directory");
+        assert!(path.is_dir());
+    }
+
+    #[test]
+    fn directory_attribute_creates_directory() {
+        let kind = CreateKind::from_request(0, FILE_ATTRIBUTE_DIRECTORY.0).expect("valid request");
+        assert_eq!(kind, CreateKind::Directory);
+    }
+
+    #[test]
+    fn plain_request_creates_file() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("track.flac");
+
+        let kind = CreateKind::from_request(FILE_NON_DIRECTORY_FILE.0, FILE_ATTRIBUTE_NORMAL.0)
+            .expect("valid request");
+        assert_eq!(kind, CreateKind::File);
+        kind.create_at(&path).expect("create file");
+        assert!(path.is_file());
+
+        let err = kind.create_at(&path).expect_err("second create collides");
+        assert!(matches!(err, FspError::NTSTATUS(code) if code == STATUS_OBJECT_NAME_COLLISION.0));
+    }
+
+    #[test]
+    fn creation_time_is_applied_and_zero_is_ignored() {
+        use winfsp::filesystem::FileSystemContext;
+
+        let dir = tempfile::tempdir().expect("tempdir");
+        let path = dir.path().join("track.flac");
+        fs::write(&path, b"data").expect("write file");
+
+        let fs = PassthroughFS::new(dir.path().to_path_buf()).expect("passthrough");
+        let context = Arc::new(FileContext::new(path));
+        // 2001-01-01T00:00:00Z
+        let creation_time = 126227808000000000;
+
+        let mut file_info = FileInfo::default();
+        fs.set_basic_info(&context, 0, creation_time, 0, 0, 0, &mut file_info)
+            .expect("set basic info");
+
+        let mut read_back = FileInfo::default();
+        fs.get_file_info(&context, &mut read_back).expect("get file info");
+        assert_eq!(read_back.creation_time, creation_time);
+
+        fs.set_basic_info(&context, 0, 0, 0, 0, 0, &mut file_info)
+            .expect("zero leaves creation time");
+        fs.get_file_info(&context, &mut read_back).expect("get file info");
+        assert_eq!(read_back.creation_time, creation_time);
+    }
+
+    #[test]
+    fn links_outside_source_are_refused_unless_followed() {
+        let source = tempfile::tempdir().expect("tempdir");
+        let outside = tempfile::tempdir().expect("tempdir");
+        let link = source.path().join("external");
+        // Creating symlinks needs developer mode or elevation on Windows
+        if std::os::windows::fs::symlink_dir(outside.path(), &link).is_err() {
+            return;
+        }
+
+        let contained = PassthroughFS::new(source.path().to_path_buf())
+            .expect("passthrough")
+            .with_follow_symlinks(false);
+        let err = contained.ensure_contained(&link).expect_err("escapes root");
+        assert!(matches!(err, FspError::NTSTATUS(code) if code == STATUS_ACCESS_DENIED.0));
+        contained
+            .ensure_contained(&source.path().join("missing.flac"))
+            .expect("nonexistent paths are left to the caller");
+        contained
+            .ensure_contained(&link.join("new.flac"))
+            .expect_err("files created through the link escape too");
+
+        let following = PassthroughFS::new(source.path().to_path_buf()).expect("passthrough");
+        following.ensure_contained(&link).expect("following links");
+    }
+
+    #[test]
+    fn missing_paths_report_object_name_not_found() {
+        let source = tempfile::tempdir().expect("tempdir");
+        let fs = PassthroughFS::new(source.path().to_path_buf()).expect("passthrough");
+        let name = winfsp::U16CString::from_str("\\missing.flac").expect("no interior nul");
+
+        let err = fs
+            .get_security_by_name(&name, None, |_| None)
+            .expect_err("missing file");
+        assert!(matches!(err, FspError::NTSTATUS(code) if code == STATUS_OBJECT_NAME_NOT_FOUND.0));
+    }
+
+    #[test]
+    fn directory_listing_order_is_stable() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        for name in ["beta.flac", "Alpha.flac", "cover.jpg", "Zeta.flac"] {
+            fs::write(dir.path().join(name), b"").expect("write file");
+        }
+        fs::create_dir(dir.path().join("disc 2")).expect("create dir");
+
+        let names = |fs: &PassthroughFS| -> Vec<String> {
+            fs.sorted_entries(dir.path())
+                .expect("list")
+                .into_iter()
+                .map(|(name, _)| name.to_string_lossy().into_owned())
+                .collect()
+        };
+
+        let fs = PassthroughFS::new(dir.path().to_path_buf()).expect("passthrough");
+        let first = names(&fs);
+        assert_eq!(first, names(&fs));
+        assert_eq!(
+            first,
+            ["Alpha.flac", "beta.flac", "cover.jpg", "disc 2", "Zeta.flac"]
+        );
+
+        let fs = fs.with_directories_first(true);
+        assert_eq!(names(&fs)[0], "disc 2");
+    }
+
+    #[test]
+    fn listings_resume_after_the_marker_in_directories_first_order() {
+        let dir = tempfile::tempdir().expect("tempdir");
+        for name in ["beta.flac", "Alpha.flac", "Zeta.flac"] {
+            fs::write(dir.path().join(name), b"").expect("write file");
+        }
+        fs::create_dir(dir.path().join("disc 2")).expect("create dir");
+
+        let fs = PassthroughFS::new(dir.path().to_path_buf())
+            .expect("passthrough")
+            .with_directories_first(true);
+        let resumed = |marker: &str| -> Vec<String> {
+            let marker: Vec<u16> = marker.encode_utf16().collect();
+            let entries = fs.sorted_entries(dir.path()).expect("list");
+            fs.entries_after(entries, Some(&marker))
+                .into_iter()
+                .map(|(name, _)| name.to_string_lossy().into_owned())
+                .collect()
+        };
+
+        // "Alpha.flac" sorts before "disc 2" by name, yet still follows it
+        assert_eq!(resumed("disc 2"), ["Alpha.flac", "beta.flac", "Zeta.flac"]);
+        assert_eq!(resumed("beta.flac"), ["Zeta.flac"]);
+        assert_eq!(resumed("Gone.flac"), ["Zeta.flac"]);
+        assert!(resumed("Zeta.flac").is_empty());
+    }
+
+    #[test]
+    fn filetime_roundtrips_through_systemtime() {
+        let filetime = 126227808000000000;
+        let time = filetime_to_systemtime(filetime).expect("non-zero");
+        assert_eq!(systemtime_to_filetime(time), filetime);
+        assert_eq!(filetime_to_systemtime(0), None);
+    }
+
+    #[test]
+    fn contradictory_request_is_rejected() {
+        let err = CreateKind::from_request(
+            FILE_DIRECTORY_FILE.0 | FILE_NON_DIRECTORY_FILE.0,
+            FILE_ATTRIBUTE_NORMAL.0,
+        )
+        .expect_err("contradictory");
+        assert!(matches!(err, FspError::NTSTATUS(code) if code == STATUS_INVALID_PARAMETER.0));
+
+        let err = CreateKind::from_request(FILE_NON_DIRECTORY_FILE.0, FILE_ATTRIBUTE_DIRECTORY.0)
+            .expect_err("contradictory");
+        assert!(matches!(err, FspError::NTSTATUS(code) if code == STATUS_INVALID_PARAMETER.0));
+    }
+}
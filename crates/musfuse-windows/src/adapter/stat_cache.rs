@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+/// Default lifetime of a cached stat, long enough to cover one browse burst
+pub const DEFAULT_METADATA_TTL: Duration = Duration::from_secs(1);
+
+/// Expired entries are swept once the cache grows past this many paths
+const SWEEP_THRESHOLD: usize = 4096;
+
+/// Short-lived cache of `fs::metadata` results keyed by resolved path.
+///
+/// Only successful stats are cached, so a path that appears later is seen immediately;
+/// anything that mutates a path must call [`MetadataCache::invalidate`].
+pub struct MetadataCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<PathBuf, (Instant, fs::Metadata)>>,
+}
+
+impl MetadataCache {
+    /// Create a cache whose entries live for `ttl`; a zero TTL disables caching
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Return the cached metadata for `path`, or run `stat` and remember its result
+    pub fn get_or_stat(
+        &self,
+        path: &Path,
+        stat: impl FnOnce(&Path) -> io::Result<fs::Metadata>,
+    ) -> io::Result<fs::Metadata> {
+        if self.ttl.is_zero() {
+            return stat(path);
+        }
+
+        let now = Instant::now();
+        if let Some((stored, metadata)) = self.entries.lock().get(path)
+            && now.duration_since(*stored) < self.ttl
+        {
+            return Ok(metadata.clone());
+        }
+
+        let metadata = stat(path)?;
+        let mut entries = self.entries.lock();
+        if entries.len() >= SWEEP_THRESHOLD {
+            let ttl = self.ttl;
+            entries.retain(|_, (stored, _)| now.duration_since(*stored) < ttl);
+        }
+        entries.insert(path.to_path_buf(), (now, metadata.clone()));
+        Ok(metadata)
+    }
+
+    /// Drop `path`, everything below it, and its parent directory, whose timestamps
+    /// change when children are created, renamed or deleted
+    pub fn invalidate(&self, path: &Path) {
+        let mut entries = self.entries.lock();
+        entries.retain(|cached, _| !cached.starts_with(path));
+        if let Some(parent) = path.parent() {
+            entries.remove(parent);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn repeated_stats_are_served_until_invalidated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("01.flac");
+        fs::write(&path, b"fLaC").unwrap();
+
+        let cache = MetadataCache::new(Duration::from_secs(60));
+        let calls = Cell::new(0);
+        let counting = |path: &Path| {
+            calls.set(calls.get() + 1);
+            fs::metadata(path)
+        };
+
+        assert_eq!(cache.get_or_stat(&path, counting).unwrap().len(), 4);
+        assert_eq!(cache.get_or_stat(&path, counting).unwrap().len(), 4);
+        assert_eq!(calls.get(), 1);
+
+        fs::write(&path, b"fLaC and more").unwrap();
+        cache.invalidate(&path);
+        assert_eq!(cache.get_or_stat(&path, counting).unwrap().len(), 13);
+        assert_eq!(calls.get(), 2);
+    }
+}
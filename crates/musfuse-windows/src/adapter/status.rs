@@ -0,0 +1,52 @@
+use windows::Win32::Foundation::{
+    NTSTATUS, STATUS_ACCESS_DENIED, STATUS_DEVICE_BUSY, STATUS_FILE_CORRUPT_ERROR,
+    STATUS_IO_DEVICE_ERROR, STATUS_NOT_SUPPORTED, STATUS_OBJECT_NAME_NOT_FOUND,
+};
+use winfsp::FspError;
+
+use musfuse_core::{ErrorClass, MusFuseError};
+
+/// Translate a platform-neutral error class into the NTSTATUS reported to WinFSP
+pub fn ntstatus_for(class: ErrorClass) -> NTSTATUS {
+    match class {
+        ErrorClass::NotFound => STATUS_OBJECT_NAME_NOT_FOUND,
+        ErrorClass::PermissionDenied => STATUS_ACCESS_DENIED,
+        ErrorClass::NotSupported => STATUS_NOT_SUPPORTED,
+        ErrorClass::IoError => STATUS_IO_DEVICE_ERROR,
+        ErrorClass::Corrupt => STATUS_FILE_CORRUPT_ERROR,
+        ErrorClass::Transient => STATUS_DEVICE_BUSY,
+    }
+}
+
+/// Convert a core error into the `FspError` returned from filesystem callbacks
+pub fn to_fsp_error(err: &MusFuseError) -> FspError {
+    FspError::NTSTATUS(ntstatus_for(err.status_hint()).0)
+}
+
+/// Convert an I/O error on a source file through the same classes as core errors
+pub fn io_to_fsp_error(err: std::io::Error) -> FspError {
+    to_fsp_error(&MusFuseError::Io(err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn core_errors_map_through_their_class() {
+        let missing = MusFuseError::Io(std::io::Error::from(std::io::ErrorKind::NotFound));
+        assert!(matches!(
+            to_fsp_error(&missing),
+            FspError::NTSTATUS(code) if code == STATUS_OBJECT_NAME_NOT_FOUND.0
+        ));
+
+        let corrupt = MusFuseError::Media("bad frame".into());
+        assert!(matches!(
+            to_fsp_error(&corrupt),
+            FspError::NTSTATUS(code) if code == STATUS_FILE_CORRUPT_ERROR.0
+        ));
+
+        assert_eq!(ntstatus_for(ErrorClass::Transient), STATUS_DEVICE_BUSY);
+        assert_eq!(ntstatus_for(ErrorClass::NotSupported), STATUS_NOT_SUPPORTED);
+    }
+}
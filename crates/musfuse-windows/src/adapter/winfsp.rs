@@ -8,7 +8,8 @@ use musfuse_core::prelude::*;
 #[async_trait]
 pub trait WinFspHost: Send + Sync {
     async fn ensure_installed(&self) -> Result<()>;
-    async fn mount(&self, config: &MountConfig) -> Result<WinFspMountHandle>;
+    fn is_mount_point_in_use(&self, mount_point: &Path) -> bool;
+    async fn mount(&self, ctx: &MountContext) -> Result<WinFspMountHandle>;
     async fn unmount(&self, mount_point: &Path) -> Result<()>;
 }
 
@@ -19,11 +20,34 @@ pub struct WinFspMountHandle {
 
 pub struct WinFspAdapter<H: WinFspHost> {
     host: Arc<H>,
+    read_only: bool,
 }
 
 impl<H: WinFspHost> WinFspAdapter<H> {
     pub fn new(host: Arc<H>) -> Self {
-        Self { host }
+        Self {
+            host,
+            read_only: false,
+        }
+    }
+
+    /// Advertise the mount as read-only, withholding write and rename support.
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    /// Advertise what the filesystem `config` mounts supports: only passthrough mounts write
+    /// and rename; the virtual library is always read-only.
+    pub fn for_mount(mut self, config: &MountConfig) -> Self {
+        self.read_only = !config.passthrough;
+        self
+    }
+
+    fn next_free_drive(&self) -> Option<PathBuf> {
+        ('D'..='Z')
+            .map(|letter| PathBuf::from(format!("{letter}:")))
+            .find(|candidate| !self.host.is_mount_point_in_use(candidate))
     }
 }
 
@@ -36,8 +60,41 @@ impl<H: WinFspHost> PlatformAdapter for WinFspAdapter<H> {
         self.host.ensure_installed().await
     }
 
-    async fn mount(&self, config: &MountConfig) -> Result<()> {
-        self.host.mount(config).await.map(|_| ())
+    /// The passthrough filesystem writes and renames but does not surface reparse points
+    /// or alternate data streams.
+    fn capabilities(&self) -> AdapterCapabilities {
+        if self.read_only {
+            return AdapterCapabilities::READ_ONLY;
+        }
+        AdapterCapabilities {
+            writes: true,
+            renames: true,
+            reparse_points: false,
+            named_streams: false,
+        }
+    }
+
+    async fn resolve_mount_point(&self, config: &MountConfig) -> Result<PathBuf> {
+        let requested = &config.mount_point;
+        if !self.host.is_mount_point_in_use(requested) {
+            return Ok(requested.clone());
+        }
+        if !config.auto_select_free_drive {
+            return Err(MusFuseError::Mount(format!(
+                "mount point {} already in use",
+                requested.display()
+            )));
+        }
+        self.next_free_drive().ok_or_else(|| {
+            MusFuseError::Mount(format!(
+                "mount point {} already in use and no free drive letter is available",
+                requested.display()
+            ))
+        })
+    }
+
+    async fn mount(&self, ctx: &MountContext) -> Result<()> {
+        self.host.mount(ctx).await.map(|_| ())
     }
 
     async fn unmount(&self, mount_point: &Path) -> Result<()> {
@@ -56,7 +113,8 @@ mod tests {
         #[async_trait]
         impl WinFspHost for Host {
             async fn ensure_installed(&self) -> Result<()>;
-            async fn mount(&self, config: &MountConfig) -> Result<WinFspMountHandle>;
+            fn is_mount_point_in_use(&self, mount_point: &Path) -> bool;
+            async fn mount(&self, ctx: &MountContext) -> Result<WinFspMountHandle>;
             async fn unmount(&self, mount_point: &Path) -> Result<()>;
         }
     }
@@ -65,13 +123,11 @@ mod tests {
         MountConfig {
             sources: vec![],
             mount_point: PathBuf::from("M:"),
-            cache_dir: None,
-            kv_backend: KvBackendKind::Sled,
             policies: PolicyConfig {
                 lossless_strategy: LosslessStrategy::ConvertToFlac,
-                lossy_passthrough: true,
+                ..Default::default()
             },
-            scan_mode: ScanMode::Lazy,
+            ..Default::default()
         }
     }
 
@@ -99,6 +155,40 @@ mod tests {
         assert!(matches!(err, MusFuseError::Mount(_)));
     }
 
+    #[tokio::test]
+    async fn resolve_mount_point_rejects_occupied_drive() {
+        let mut mock_host = MockHost::new();
+        mock_host
+            .expect_is_mount_point_in_use()
+            .withf(|p| p.to_string_lossy() == "M:")
+            .return_const(true);
+        let adapter = WinFspAdapter::new(Arc::new(mock_host));
+        let err = adapter
+            .resolve_mount_point(&sample_config())
+            .await
+            .expect_err("should fail");
+        match err {
+            MusFuseError::Mount(reason) => assert_eq!(reason, "mount point M: already in use"),
+            other => panic!("unexpected error {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn resolve_mount_point_falls_back_to_free_drive() {
+        let mut mock_host = MockHost::new();
+        mock_host
+            .expect_is_mount_point_in_use()
+            .returning(|p| !matches!(p.to_string_lossy().as_ref(), "F:"));
+        let adapter = WinFspAdapter::new(Arc::new(mock_host));
+        let mut config = sample_config();
+        config.auto_select_free_drive = true;
+        let chosen = adapter
+            .resolve_mount_point(&config)
+            .await
+            .expect("free drive");
+        assert_eq!(chosen, PathBuf::from("F:"));
+    }
+
     #[tokio::test]
     async fn mount_calls_host_and_discards_handle() {
         let mut mock_host = MockHost::new();
@@ -109,11 +199,36 @@ mod tests {
         });
         let adapter = WinFspAdapter::new(Arc::new(mock_host));
         adapter
-            .mount(&sample_config())
+            .mount(&MountContext::new(sample_config()))
             .await
             .expect("mount should succeed");
     }
 
+    #[test]
+    fn capabilities_follow_the_read_only_mode() {
+        let adapter = WinFspAdapter::new(Arc::new(MockHost::new()));
+        let capabilities = adapter.capabilities();
+        assert!(capabilities.writes);
+        assert!(capabilities.renames);
+        assert!(!capabilities.is_read_only());
+
+        let read_only = WinFspAdapter::new(Arc::new(MockHost::new())).read_only();
+        assert_eq!(read_only.capabilities(), AdapterCapabilities::READ_ONLY);
+        assert!(read_only.capabilities().is_read_only());
+    }
+
+    #[test]
+    fn capabilities_follow_the_mounted_filesystem() {
+        let library = WinFspAdapter::new(Arc::new(MockHost::new())).for_mount(&sample_config());
+        assert_eq!(library.capabilities(), AdapterCapabilities::READ_ONLY);
+
+        let mut config = sample_config();
+        config.passthrough = true;
+        let passthrough = WinFspAdapter::new(Arc::new(MockHost::new())).for_mount(&config);
+        assert!(passthrough.capabilities().writes);
+        assert!(passthrough.capabilities().renames);
+    }
+
     #[tokio::test]
     async fn unmount_calls_host() {
         let mut mock_host = MockHost::new();
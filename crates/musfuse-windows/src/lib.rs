@@ -1,5 +1,6 @@
 pub mod adapter;
-pub mod provider;
 
-pub use adapter::{PassthroughFS, WinFspAdapter, WinFspHostImpl};
-pub use provider::WindowsMountProvider;
+pub use adapter::{MusFuseFS, PassthroughFS, WinFspAdapter, WinFspHostImpl};
+
+/// The shared mount lifecycle driving a WinFSP host.
+pub type WindowsMountProvider<H> = musfuse_core::AdapterMountProvider<WinFspAdapter<H>>;
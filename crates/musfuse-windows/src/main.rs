@@ -1,28 +1,45 @@
 use std::path::PathBuf;
 use std::sync::Arc;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use musfuse_core::kv::{open_backend, KvBackend, MemoryBackend};
 use musfuse_core::prelude::*;
-use musfuse_windows::{WindowsMountProvider, WinFspHostImpl};
+use musfuse_core::reload::LiveConfig;
+use musfuse_core::selftest::run_selftest;
+use musfuse_windows::{WindowsMountProvider, WinFspAdapter, WinFspHostImpl};
 use tracing::{error, info};
 
 #[derive(Parser, Debug)]
 #[command(name = "musfuse")]
 #[command(about = "MusFuse - Music Filesystem in Userspace", long_about = None)]
+#[command(subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Source directory to mount from
-    #[arg(short, long)]
-    source: PathBuf,
+    #[arg(short, long, required_unless_present = "config")]
+    source: Option<PathBuf>,
 
     /// Mount point (drive letter like M: or directory path)
-    #[arg(short, long)]
-    mount: PathBuf,
+    #[arg(short, long, required_unless_present = "config")]
+    mount: Option<PathBuf>,
+
+    /// TOML file holding a full mount configuration, used instead of --source and --mount
+    #[arg(short, long, conflicts_with_all = ["source", "mount"])]
+    config: Option<PathBuf>,
 
     /// Enable verbose logging
     #[arg(short, long)]
     verbose: bool,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Build a synthetic library and check scanning, cue mapping, transcoding and reads
+    Selftest,
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
@@ -39,79 +56,170 @@ async fn main() -> anyhow::Result<()> {
         .with_target(false)
         .init();
 
+    if let Some(Command::Selftest) = args.command {
+        return selftest().await;
+    }
+
     info!("MusFuse starting...");
-    info!("Source: {:?}", args.source);
-    info!("Mount point: {:?}", args.mount);
+    let config = match &args.config {
+        Some(path) => {
+            info!("Loading configuration from {:?}", path);
+            MountConfig::load(path)?
+        }
+        None => {
+            let (Some(source), Some(mount)) = (args.source, args.mount) else {
+                return Err(anyhow::anyhow!("--source and --mount are required"));
+            };
+            config_from_args(source, mount)?
+        }
+    };
+
+    // Create WinFSP host
+    let host = Arc::new(WinFspHostImpl::new()?);
+    
+    // Create mount provider
+    let adapter = WinFspAdapter::new(host).for_mount(&config);
+    let provider = WindowsMountProvider::with_adapter(adapter);
+
+    // Open the configured KV store and create mount context
+    let kv = open_kv(&config)?;
+    let live = LiveConfig::new(config.clone());
+    let mut context = Arc::new(MountContext::new(config).with_kv(kv));
+    let mut event_rx = context.signal.subscribe();
+
+    // Mount filesystem
+    info!("Mounting filesystem...");
+    provider.mount(context.clone()).await?;
+
+    info!("Filesystem mounted successfully!");
+    info!("Press Ctrl+C to unmount and exit...");
+    if args.config.is_some() {
+        info!("Press Ctrl+Break to reload the configuration...");
+    }
+
+    // Wait for Ctrl+C, reloading the config file on every Ctrl+Break
+    let mut ctrl_break = tokio::signal::windows::ctrl_break()?;
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {
+                info!("Received Ctrl+C, unmounting...");
+                break;
+            }
+            _ = ctrl_break.recv() => {
+                let Some(path) = &args.config else {
+                    continue;
+                };
+                match live.reload_mount(path, &provider, &context).await {
+                    Ok(next) => context = next,
+                    Err(e) => error!("Keeping the running configuration: {}", e),
+                }
+            }
+            event = event_rx.recv() => {
+                match event {
+                    Ok(MountEvent::Fault(reason)) => {
+                        error!("Filesystem fault: {}", reason);
+                        break;
+                    }
+                    Ok(MountEvent::Unmounted) => {
+                        info!("Filesystem unmounted");
+                        break;
+                    }
+                    Ok(MountEvent::LibraryChanged(change)) => {
+                        // The index is built once per mount, so a remount picks the change up.
+                        info!("Library changed ({:?}), rescanning...", change);
+                        if let Err(e) = provider.remount(context.clone()).await {
+                            error!("Could not rescan the library: {}", e);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    // Unmount filesystem
+    provider.unmount().await?;
+    info!("Filesystem unmounted successfully");
+
+    Ok(())
+}
+
+/// Builds a single-source configuration from --source and --mount.
+fn config_from_args(source: PathBuf, mount: PathBuf) -> anyhow::Result<MountConfig> {
+    info!("Source: {:?}", source);
+    info!("Mount point: {:?}", mount);
 
     // Validate source directory
-    if !args.source.exists() {
-        error!("Source directory does not exist: {:?}", args.source);
+    if !source.exists() {
+        error!("Source directory does not exist: {:?}", source);
         return Err(anyhow::anyhow!("Source directory does not exist"));
     }
 
-    if !args.source.is_dir() {
-        error!("Source path is not a directory: {:?}", args.source);
+    if !source.is_dir() {
+        error!("Source path is not a directory: {:?}", source);
         return Err(anyhow::anyhow!("Source path is not a directory"));
     }
 
     // Create mount configuration
     let config = MountConfig {
         sources: vec![SourceConfig {
-            path: args.source.clone(),
+            path: source.clone(),
             recursive: true,
             watch: false,
+            follow_symlinks: true,
         }],
-        mount_point: args.mount.clone(),
+        mount_point: mount.clone(),
         cache_dir: None,
         kv_backend: KvBackendKind::Sled,
         policies: PolicyConfig {
             lossless_strategy: LosslessStrategy::Passthrough,
             lossy_passthrough: true,
+            multi_value_separator: "; ".into(),
+            source_preference: SourcePreference::PreferPerTrack,
+            missing_files: MissingFilePolicy::Hide,
+            format_policies: Default::default(),
+            cue_track_order: Default::default(),
+            expose_originals: false,
+            write_through_tags: false,
+            resample_to: None,
+            target_bits: None,
         },
         scan_mode: ScanMode::Lazy,
+        allow_empty: false,
+        auto_select_free_drive: false,
+        smart_folders: Vec::new(),
+        slow_op_threshold_ms: None,
+        prewarm_tracks: 0,
+        max_concurrent_transcodes: None,
+        passthrough: false,
+        directories_first: false,
     };
 
     // Validate configuration
     config.validate()?;
+    Ok(config)
+}
 
-    // Create WinFSP host
-    let host = Arc::new(WinFspHostImpl::new()?);
-    
-    // Create mount provider
-    let provider = WindowsMountProvider::with_winfsp_host(host);
-
-    // Create mount context
-    let context = Arc::new(MountContext::new(config));
-    let mut event_rx = context.signal.subscribe();
-
-    // Mount filesystem
-    info!("Mounting filesystem...");
-    provider.mount(context.clone()).await?;
-
-    info!("Filesystem mounted successfully!");
-    info!("Press Ctrl+C to unmount and exit...");
+/// Opens the KV backend the config selects, kept under the cache directory.
+fn open_kv(config: &MountConfig) -> anyhow::Result<Arc<dyn KvBackend>> {
+    let base = config.cache_dir.clone().unwrap_or_else(std::env::temp_dir);
+    let dir = base.join("musfuse-kv");
+    info!("Opening {:?} KV store at {:?}", config.kv_backend, dir);
+    Ok(open_backend(&config.kv_backend, &dir)?)
+}
 
-    // Wait for Ctrl+C
-    tokio::select! {
-        _ = tokio::signal::ctrl_c() => {
-            info!("Received Ctrl+C, unmounting...");
-        }
-        event = event_rx.recv() => {
-            match event {
-                Ok(MountEvent::Fault(reason)) => {
-                    error!("Filesystem fault: {}", reason);
-                }
-                Ok(MountEvent::Unmounted) => {
-                    info!("Filesystem unmounted");
-                }
-                _ => {}
-            }
+/// Runs the pipeline self-test and prints one line per stage.
+async fn selftest() -> anyhow::Result<()> {
+    let report = run_selftest(Arc::new(MemoryBackend::new())).await;
+    for stage in &report.stages {
+        match &stage.error {
+            None => println!("{:<10} PASS", stage.stage.to_string()),
+            Some(error) => println!("{:<10} FAIL  {}", stage.stage.to_string(), error),
         }
     }
-
-    // Unmount filesystem
-    provider.unmount().await?;
-    info!("Filesystem unmounted successfully");
-
-    Ok(())
+    if report.passed() {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("self-test failed"))
+    }
 }